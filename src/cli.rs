@@ -0,0 +1,690 @@
+use crate::diagnostic;
+use crate::output::{self, OutputFormat};
+use crate::repl::DEFAULT_PROMPT;
+use math_parser::parse_math::context::{AngleMode, Context};
+use math_parser::parse_math::errors::ParseError;
+use math_parser::parse_math::eval::{DivisionByZero, EvalOptions, ZeroPower};
+use math_parser::parse_math::format::{Notation, Precision};
+use math_parser::parse_math::lines;
+use math_parser::parse_math::parser::{Parser, ParserOptions};
+use math_parser::parse_math::trace;
+use math_parser::parse_math::value::Value;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+
+pub const USAGE: &str = "\
+usage: math-parser [OPTION]... [--] [EXPRESSION]...
+
+Evaluates each expression, or the lines of --file, or starts the REPL.
+
+Options:
+  --json               print results and errors as JSON
+  --precision <n>      round results to n significant digits
+  --fixed <n>          print results with n decimal places
+  --exact              print results with every digit
+  --format <notation>  auto, sci, eng, human or human-bin
+  --tokens             print the tokens of each expression
+  --ast                print the parse tree of each expression
+  --file <path>        evaluate each line of a file
+  --check              only check the syntax, without evaluating
+  --check=strict       also check calls and names
+  --config <path>      read settings from this file
+  --replay <path>      replay a saved REPL transcript
+  --no-history         do not read or write the REPL history
+  --history-size <n>   keep at most n lines of history
+  -q, --quiet          print bare results
+  --no-quiet           echo the input, even if the config says quiet
+  --prompt <text>      the REPL prompt
+  -h, --help           show this help
+  --                   treat the rest as expressions, even with a -
+";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheckMode {
+    Syntax,
+    Strict,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub expressions: Vec<String>,
+    pub prompt: String,
+    pub ast: bool,
+    pub format: OutputFormat,
+    pub precision: Option<Precision>,
+    pub notation: Option<Notation>,
+    pub angle_mode: Option<AngleMode>,
+    pub zero_power: Option<ZeroPower>,
+    pub division_by_zero: Option<DivisionByZero>,
+    pub real_roots: Option<bool>,
+    pub bool_arithmetic: Option<bool>,
+    pub implicit_multiplication: Option<bool>,
+    // Variables every expression, file line and REPL session starts with.
+    pub variables: Vec<(String, f64)>,
+    pub config: Option<String>,
+    pub tokens: bool,
+    pub quiet: Option<bool>,
+    pub check: Option<CheckMode>,
+    pub no_history: bool,
+    pub history_size: Option<usize>,
+    pub file: Option<String>,
+    pub replay: Option<String>,
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            expressions: Vec::new(),
+            prompt: DEFAULT_PROMPT.to_string(),
+            ast: false,
+            format: OutputFormat::Text,
+            precision: None,
+            notation: None,
+            angle_mode: None,
+            zero_power: None,
+            division_by_zero: None,
+            real_roots: None,
+            bool_arithmetic: None,
+            implicit_multiplication: None,
+            variables: Vec::new(),
+            config: None,
+            tokens: false,
+            quiet: None,
+            check: None,
+            no_history: false,
+            history_size: None,
+            file: None,
+            replay: None,
+            help: false,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut options_ended = false;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if options_ended || !arg.starts_with('-') || arg == "-" {
+                parsed.expressions.push(arg);
+                continue;
+            }
+
+            match arg.as_str() {
+                "--" => options_ended = true,
+                "--help" | "-h" => parsed.help = true,
+                "--prompt" => parsed.prompt = value(&mut args, "--prompt")?,
+                "--ast" => parsed.ast = true,
+                "--json" => parsed.format = OutputFormat::Json,
+                "--tokens" => parsed.tokens = true,
+                "--quiet" | "-q" => parsed.quiet = Some(true),
+                "--no-quiet" => parsed.quiet = Some(false),
+                "--check" => parsed.check = Some(CheckMode::Syntax),
+                "--check=strict" => parsed.check = Some(CheckMode::Strict),
+                "--no-history" => parsed.no_history = true,
+                "--history-size" => {
+                    parsed.history_size = Some(number(&mut args, "--history-size")?)
+                }
+                "--file" => parsed.file = Some(value(&mut args, "--file")?),
+                "--replay" => parsed.replay = Some(value(&mut args, "--replay")?),
+                "--precision" => {
+                    let digits = number(&mut args, "--precision")?;
+                    if digits == 0 {
+                        return Err("option '--precision' must be at least 1".into());
+                    }
+                    parsed.precision = Some(Precision::Significant(digits));
+                }
+                "--format" => {
+                    let name = value(&mut args, "--format")?;
+                    let notation = output::parse_notation(&name).ok_or_else(|| {
+                        format!("option '--format' expects auto, sci, eng, human or human-bin, got '{}'", name)
+                    })?;
+                    parsed.notation = Some(notation);
+                }
+                "--config" => parsed.config = Some(value(&mut args, "--config")?),
+                "--exact" => parsed.precision = Some(Precision::Full),
+                "--fixed" => {
+                    parsed.precision = Some(Precision::Fixed(number(&mut args, "--fixed")?))
+                }
+                option => return Err(format!("unknown option '{}'", option)),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn notation(&self) -> Notation {
+        self.notation.unwrap_or_default()
+    }
+
+    pub fn quiet(&self) -> bool {
+        self.quiet.unwrap_or(false)
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+            .unwrap_or_else(|| self.format.default_precision())
+    }
+}
+
+fn value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("option '{}' requires a value", option))
+}
+
+fn number<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<usize, String> {
+    let value = value(args, option)?;
+    value
+        .parse()
+        .map_err(|_| format!("option '{}' expects a number, got '{}'", option, value))
+}
+
+// The settings the REPL takes from the same options and config.
+pub fn options(args: &Args) -> EvalOptions {
+    let defaults = EvalOptions::default();
+    EvalOptions {
+        zero_power: args.zero_power.unwrap_or(defaults.zero_power),
+        division_by_zero: args.division_by_zero.unwrap_or(defaults.division_by_zero),
+        real_roots: args.real_roots.unwrap_or(defaults.real_roots),
+        bool_arithmetic: args.bool_arithmetic.unwrap_or(defaults.bool_arithmetic),
+        angle_mode: args.angle_mode.unwrap_or(defaults.angle_mode),
+    }
+}
+
+pub fn parser_options(args: &Args) -> ParserOptions {
+    ParserOptions::default().implicit_multiplication(args.implicit_multiplication.unwrap_or(true))
+}
+
+pub fn context(args: &Args) -> Context {
+    let mut context = Context::new();
+    context.set_options(options(args));
+    for (name, value) in &args.variables {
+        context.set(name, *value);
+    }
+
+    context
+}
+
+pub fn evaluate_expressions<O: Write, E: Write>(
+    args: &Args,
+    mut output: O,
+    mut errors: E,
+) -> io::Result<bool> {
+    let mut success = true;
+    let context = context(args);
+
+    for expression in &args.expressions {
+        if args.tokens {
+            write!(output, "{}", output::format_tokens(expression))?;
+            continue;
+        }
+
+        // As in the REPL, names are looked up as they are read.
+        let result =
+            match Parser::with_context_and_options(expression, &context, parser_options(args))
+                .parse()
+            {
+                Ok(node) => {
+                    if args.ast && args.format == OutputFormat::Text {
+                        write!(output, "{}", node.to_tree_string())?;
+                    }
+                    // As the REPL does, with types, so that a list or matrix
+                    // has its value and a mistake is an error rather than NaN.
+                    trace::evaluate(&node, &context).map_err(ParseError::from)
+                }
+                Err(error) => Err(error),
+            };
+        success &= print_result(
+            args,
+            expression,
+            result,
+            "error",
+            true,
+            &mut output,
+            &mut errors,
+        )?;
+    }
+
+    Ok(success)
+}
+
+// --check only parses, so a call to an unknown function or with the wrong
+// number of arguments passes. --check=strict parses as evaluation does, with
+// the configured variables, and also catches those and unknown names.
+fn check(args: &Args, input: &str, context: &Context) -> Result<(), ParseError> {
+    let options = parser_options(args);
+    let mut parser = match args.check {
+        Some(CheckMode::Strict) => Parser::with_context_and_options(input, context, options),
+        _ => Parser::new_with_options(input, options.check_calls(false)),
+    };
+
+    parser.parse().map(drop)
+}
+
+pub fn check_expressions<E: Write>(args: &Args, mut errors: E) -> io::Result<bool> {
+    let mut success = true;
+    let context = context(args);
+
+    for expression in &args.expressions {
+        if let Err(error) = check(args, expression, &context) {
+            let message = format!("error: {}", error);
+            write!(
+                errors,
+                "{}",
+                diagnostic::render(&message, expression, error.span(expression), false)
+            )?;
+            success = false;
+        }
+    }
+
+    Ok(success)
+}
+
+pub fn check_file<E: Write>(args: &Args, path: &str, mut errors: E) -> io::Result<bool> {
+    let file = File::open(path)?;
+    let mut success = true;
+    let context = context(args);
+
+    for line in lines::source_lines(BufReader::new(file)) {
+        let (number, input) = line?;
+        if let Err(error) = check(args, &input, &context) {
+            let message = format!("{}:{}: error: {}", path, number, error);
+            write!(
+                errors,
+                "{}",
+                diagnostic::render(&message, &input, error.span(&input), false)
+            )?;
+            success = false;
+        }
+    }
+
+    Ok(success)
+}
+
+pub fn evaluate_file<O: Write, E: Write>(
+    args: &Args,
+    path: &str,
+    mut output: O,
+    mut errors: E,
+) -> io::Result<bool> {
+    let file = File::open(path)?;
+    let mut success = true;
+
+    let lines =
+        lines::evaluate_lines_with(BufReader::new(file), context(args), parser_options(args));
+    for line in lines {
+        let line = line?;
+        let prefix = format!("{}:{}: error", path, line.number);
+        success &= print_result(
+            args,
+            &line.input,
+            line.result.map(Value::Number),
+            &prefix,
+            false,
+            &mut output,
+            &mut errors,
+        )?;
+    }
+
+    Ok(success)
+}
+
+fn print_result<O: Write, E: Write>(
+    args: &Args,
+    input: &str,
+    result: Result<Value, ParseError>,
+    error_prefix: &str,
+    underline: bool,
+    output: &mut O,
+    errors: &mut E,
+) -> io::Result<bool> {
+    match (result, args.format) {
+        (Ok(value), OutputFormat::Text) => writeln!(
+            output,
+            "{}",
+            output::format_value(&value, args.precision(), args.notation())
+        )?,
+        (Ok(value), OutputFormat::Json) => writeln!(
+            output,
+            "{}",
+            output::json_value(input, &value, args.precision(), args.notation())
+        )?,
+        // An expression given on the command line is shown with the error
+        // underlined; a file's lines keep to one line each, for grep.
+        (Err(error), OutputFormat::Text) if underline => {
+            write!(errors, "{}: {}", error_prefix, error.render(input))?;
+            return Ok(false);
+        }
+        (Err(error), OutputFormat::Text) => {
+            writeln!(errors, "{}: {}", error_prefix, error)?;
+            return Ok(false);
+        }
+        (Err(error), OutputFormat::Json) => {
+            writeln!(
+                output,
+                "{}",
+                output::json_error(input, error.code(), &error.to_string(), error.span(input))
+            )?;
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Result<Args, String> {
+        Args::parse(values.iter().map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn expressions_in_order() {
+        let parsed = args(&["1+2", "3*4"]).unwrap();
+        assert_eq!(parsed.expressions, vec!["1+2", "3*4"]);
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let parsed = args(&["--", "-1+2", "--"]).unwrap();
+        assert_eq!(parsed.expressions, vec!["-1+2", "--"]);
+    }
+
+    #[test]
+    fn prompt_option() {
+        assert_eq!(args(&[]).unwrap().prompt, DEFAULT_PROMPT);
+        assert_eq!(args(&["--prompt", "> "]).unwrap().prompt, "> ");
+        assert_eq!(
+            args(&["--prompt"]),
+            Err("option '--prompt' requires a value".to_string())
+        );
+    }
+
+    #[test]
+    fn ast_option() {
+        assert!(!args(&["1"]).unwrap().ast);
+        let parsed = args(&["--ast", "1+2*3"]).unwrap();
+        assert!(parsed.ast);
+        assert_eq!(parsed.expressions, vec!["1+2*3"]);
+
+        let mut output = Vec::new();
+        evaluate_expressions(&parsed, &mut output, Vec::new()).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Sum\n├ Element(1)\n└ Multiply\n  ├ Element(2)\n  └ Element(3)\n7\n"
+        );
+    }
+
+    #[test]
+    fn json_option() {
+        assert_eq!(args(&[]).unwrap().format, OutputFormat::Text);
+        let parsed = args(&["--json", "1/0", "(1"]).unwrap();
+        assert_eq!(parsed.format, OutputFormat::Json);
+
+        let mut output = Vec::new();
+        let success = evaluate_expressions(&parsed, &mut output, Vec::new()).unwrap();
+        assert!(!success);
+        let lines: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["result"], "Infinity");
+        assert_eq!(lines[1]["error"]["code"], "unexpected_end_of_input");
+    }
+
+    #[test]
+    fn precision_options() {
+        assert_eq!(args(&[]).unwrap().precision(), Precision::Snapped);
+        assert_eq!(args(&["--exact"]).unwrap().precision(), Precision::Full);
+        assert_eq!(args(&["--json"]).unwrap().precision(), Precision::Full);
+        assert_eq!(
+            args(&["--precision", "4"]).unwrap().precision(),
+            Precision::Significant(4)
+        );
+        assert_eq!(
+            args(&["--json", "--fixed", "2"]).unwrap().precision(),
+            Precision::Fixed(2)
+        );
+        assert_eq!(
+            args(&["--precision", "0"]),
+            Err("option '--precision' must be at least 1".to_string())
+        );
+        assert_eq!(
+            args(&["--fixed", "two"]),
+            Err("option '--fixed' expects a number, got 'two'".to_string())
+        );
+
+        let parsed = args(&["--fixed", "3", "1/3"]).unwrap();
+        let mut output = Vec::new();
+        evaluate_expressions(&parsed, &mut output, Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "0.333\n");
+    }
+
+    #[test]
+    fn format_option() {
+        assert_eq!(args(&[]).unwrap().notation(), Notation::Auto);
+        assert_eq!(
+            args(&["--format", "sci"]).unwrap().notation(),
+            Notation::Scientific
+        );
+        assert_eq!(
+            args(&["--format", "hex"]),
+            Err(
+                "option '--format' expects auto, sci, eng, human or human-bin, got 'hex'"
+                    .to_string()
+            )
+        );
+
+        let parsed = args(&["--format", "eng", "12500"]).unwrap();
+        let mut output = Vec::new();
+        evaluate_expressions(&parsed, &mut output, Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "12.5e3\n");
+
+        let parsed = args(&["--format", "human", "--precision", "2", "1234567"]).unwrap();
+        let mut output = Vec::new();
+        evaluate_expressions(&parsed, &mut output, Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "1.2M\n");
+    }
+
+    #[test]
+    fn tokens_option() {
+        let parsed = args(&["--tokens", "1+$"]).unwrap();
+        assert!(parsed.tokens);
+
+        let mut output = Vec::new();
+        let success = evaluate_expressions(&parsed, &mut output, Vec::new()).unwrap();
+        assert!(success);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            output::format_tokens("1+$")
+        );
+    }
+
+    #[test]
+    fn quiet_option() {
+        assert!(!args(&[]).unwrap().quiet());
+        assert!(args(&["--quiet"]).unwrap().quiet());
+        assert!(args(&["-q", "1"]).unwrap().quiet());
+        assert_eq!(args(&["--no-quiet"]).unwrap().quiet, Some(false));
+        assert_eq!(args(&["-q", "--no-quiet"]).unwrap().quiet, Some(false));
+    }
+
+    #[test]
+    fn check_option() {
+        assert_eq!(args(&[]).unwrap().check, None);
+        assert_eq!(
+            args(&["--check", "1"]).unwrap().check,
+            Some(CheckMode::Syntax)
+        );
+        assert_eq!(
+            args(&["--check=strict"]).unwrap().check,
+            Some(CheckMode::Strict)
+        );
+
+        let parsed = args(&["--check", "1/0", "(1", "2*"]).unwrap();
+        let mut errors = Vec::new();
+        assert!(!check_expressions(&parsed, &mut errors).unwrap());
+        assert_eq!(
+            String::from_utf8(errors).unwrap(),
+            "error: Unexpected end of input at position 2, expected ')'\n  (1\n    ^\nerror: Unexpected end of input at position 2, expected a number or '('\n  2*\n    ^\n"
+        );
+    }
+
+    #[test]
+    fn check_modes() {
+        let expressions = ["foo(1)", "sqrt(1, 2)", "x + 1", "pi * 2"];
+
+        let mut syntax = args(&["--check"]).unwrap();
+        syntax.expressions = expressions
+            .iter()
+            .map(|expression| expression.to_string())
+            .collect();
+        let mut errors = Vec::new();
+        assert!(check_expressions(&syntax, &mut errors).unwrap());
+        assert!(errors.is_empty());
+
+        let mut strict = Args {
+            check: Some(CheckMode::Strict),
+            ..syntax
+        };
+        let mut errors = Vec::new();
+        assert!(!check_expressions(&strict, &mut errors).unwrap());
+        assert_eq!(
+            String::from_utf8(errors).unwrap(),
+            "error: Unknown function: foo\n  foo(1)\n  ^~~\n\
+             error: Wrong number of arguments: sqrt takes 1, got 2\n  sqrt(1, 2)\n  ^~~~~~~~~~\n\
+             error: Unknown identifier: x\n  x + 1\n  ^\n"
+        );
+
+        // Configured variables are known to a strict check.
+        strict.expressions = vec!["x + 1".into()];
+        strict.variables = vec![("x".into(), 2.)];
+        assert!(check_expressions(&strict, Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn history_options() {
+        let parsed = args(&[]).unwrap();
+        assert!(!parsed.no_history);
+        assert_eq!(parsed.history_size, None);
+
+        let parsed = args(&["--no-history", "--history-size", "50"]).unwrap();
+        assert!(parsed.no_history);
+        assert_eq!(parsed.history_size, Some(50));
+    }
+
+    #[test]
+    fn help_option() {
+        assert!(!args(&[]).unwrap().help);
+        assert!(args(&["--help"]).unwrap().help);
+        assert!(args(&["-q", "-h"]).unwrap().help);
+        assert!(!args(&["--", "--help"]).unwrap().help);
+    }
+
+    #[test]
+    fn file_option() {
+        assert_eq!(args(&[]).unwrap().file, None);
+        assert_eq!(
+            args(&["--file", "formulas.txt"]).unwrap().file,
+            Some("formulas.txt".to_string())
+        );
+        assert_eq!(
+            args(&["--file"]),
+            Err("option '--file' requires a value".to_string())
+        );
+    }
+
+    #[test]
+    fn replay_option() {
+        assert_eq!(
+            args(&["--replay", "session.txt"]).unwrap().replay,
+            Some("session.txt".to_string())
+        );
+        assert_eq!(
+            args(&["--replay"]),
+            Err("option '--replay' requires a value".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert_eq!(args(&["-1"]), Err("unknown option '-1'".to_string()));
+    }
+
+    #[test]
+    fn errors_do_not_stop_evaluation() {
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let args = args(&["1+", "2*3"]).unwrap();
+        let success = evaluate_expressions(&args, &mut output, &mut errors).unwrap();
+
+        assert!(!success);
+        assert_eq!(String::from_utf8(output).unwrap(), "6\n");
+        assert_eq!(
+            String::from_utf8(errors).unwrap(),
+            "error: Unexpected end of input at position 2, expected a number or '('\n  1+\n    ^\n"
+        );
+    }
+
+    #[cfg(feature = "trace")]
+    mod trace {
+        use super::*;
+        use crate::repl::{self, Mode, Session};
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::Arc;
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // Counts the evaluate spans, which is all the front ends add to
+        // the library's own.
+        #[derive(Clone, Default)]
+        struct Evaluations(Arc<AtomicU64>);
+
+        impl Subscriber for Evaluations {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                if span.metadata().name() == "evaluate" {
+                    self.0.fetch_add(1, Ordering::SeqCst);
+                }
+                Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+            fn event(&self, _event: &Event<'_>) {}
+
+            fn enter(&self, _span: &Id) {}
+
+            fn exit(&self, _span: &Id) {}
+        }
+
+        #[test]
+        fn front_ends_trace_evaluation() {
+            let evaluations = Evaluations::default();
+            tracing::subscriber::with_default(evaluations.clone(), || {
+                let args = args(&["1+2", "2*3"]).unwrap();
+                evaluate_expressions(&args, Vec::new(), Vec::new()).unwrap();
+            });
+            assert_eq!(evaluations.0.load(Ordering::SeqCst), 2);
+
+            let evaluations = Evaluations::default();
+            tracing::subscriber::with_default(evaluations.clone(), || {
+                let mut session = Session::new(Vec::new(), Vec::new(), Mode::Pipe);
+                repl::run("x = 2\nx + 1\n".as_bytes(), &mut session).unwrap();
+            });
+            assert_eq!(evaluations.0.load(Ordering::SeqCst), 2);
+        }
+    }
+}
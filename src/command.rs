@@ -0,0 +1,312 @@
+use crate::output;
+use math_parser::parse_math::context::AngleMode;
+use math_parser::parse_math::eval::{DivisionByZero, ZeroPower};
+use math_parser::parse_math::format::Notation;
+
+pub const HELP: &str = "\
+Commands:
+  :help            show this help
+  :vars            list variables
+  :clear [vars]    clear variables and results, or only variables
+  :ast [on|off]    show or toggle printing the parse tree
+  :tokens <expr>   print the tokens of an expression
+  :echo on|off     echo the input and prefix results
+  :mode [deg|rad]  show or switch the angle mode
+  :zeropower [one|error|nan]
+                   show or switch what 0^0 evaluates to
+  :divzero [inf|error|nan]
+                   show or switch what dividing by zero gives; inf
+                   keeps the sign, so 1/(-0) is -inf
+  :roots [real|principal]
+                   show or switch whether (-8)^(1/3) is the real root -2
+                   or the principal one, which is NaN for real numbers
+  :format [auto|sci|eng|human|human-bin]
+                   show or switch the result notation
+  :output [dec|hex|bin|oct]
+                   show or switch the base of integer results;
+                   negative values print as -0xff, not two's complement
+  :plot <expr>, <var>, <from>..<to>
+                   draw an ASCII plot of expr over the range
+  :save <file>     write a transcript of the session, see --replay
+  :q, exit, quit   leave the REPL
+
+Syntax: numbers like 2.5, 6.02e23, 1_000, 0xff, 0o755, 0b101, inf or nan,
++ - * / ^ (or **), parentheses or braces {}, unary minus,
+implicit multiplication like 2(3+4), (1+1)2 or 2x, which binds tighter
+than * and /, so 1/2x is 1/(2x) and 6/2(1+2) is 1.
+A trailing % divides by 100, as in 120 * (1 + 8.5%); 5! is a factorial.
+10 % 3 is a remainder, with the sign of the number divided, and 7 // 2
+a quotient rounded down.
+Functions: sqrt, abs, sin, cos, tan, asin, acos, atan, atan2(y, x), ln,
+log(x) or log(x, base), exp, max and min, as in max(1, sqrt(abs(-16)));
+sin, cos and tan take and asin, acos, atan and atan2 give angles in the
+:mode unit. SUM, AVERAGE, POWER and PI() work as in spreadsheets.
+if(c, a, b) is a when c holds and b otherwise; only that one is evaluated.
+sum(i, 1, 100, i^2) adds and prod(k, 1, 5, k) multiplies the terms for
+each integer from the first bound to the second.
+== != < <= > >= compare two numbers, giving true or false; equality is exact.
+&&, || and a leading ! combine them; the right side of && and || is only
+evaluated when the left one does not decide the result.
+|x - 3| is an absolute value; ||x| - 1| nests.
+Lists [1, 2, 3] and matrices [[1, 2], [3, 4]] support + - * with shape
+checks; a number scales every element.
+pi, e and tau are constants unless assigned; names are case-sensitive.
+ans is the last result, $1, $2, ... the numbered results of the session.
+x = <expr> assigns a variable for the rest of the session, and
+f(x, y) = <expr> defines a function, which may not call itself.
+; separates statements, as in a = 3; b = 4; sqrt(a^2 + b^2), and only the
+last one's result is shown.
+2*x + 3 = 7 solves a linear equation for its one unknown.
+# starts a comment that runs to the end of the line.
+";
+
+#[derive(Debug, PartialEq)]
+pub enum Command<'a> {
+    Help,
+    Vars,
+    Clear {
+        only_variables: bool,
+    },
+    Ast(Option<bool>),
+    Tokens(&'a str),
+    Mode(Option<AngleMode>),
+    ZeroPower(Option<ZeroPower>),
+    Roots(Option<bool>),
+    DivZero(Option<DivisionByZero>),
+    Format(Option<Notation>),
+    Output(Option<u32>),
+    Echo(bool),
+    Plot {
+        expression: &'a str,
+        variable: &'a str,
+        start: f64,
+        end: f64,
+    },
+    Save(&'a str),
+    Exit,
+    Invalid(String),
+    Unknown(&'a str),
+}
+
+impl<'a> Command<'a> {
+    pub fn parse(line: &'a str) -> Option<Self> {
+        if ["exit", "quit"].contains(&line) {
+            return Some(Command::Exit);
+        }
+
+        let line = line.strip_prefix(':')?;
+        let (name, argument) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+
+        let command = match (name, argument) {
+            ("help", "") => Command::Help,
+            ("vars", "") => Command::Vars,
+            ("clear", "") => Command::Clear {
+                only_variables: false,
+            },
+            ("clear", "vars") => Command::Clear {
+                only_variables: true,
+            },
+            ("clear", _) => Command::Invalid("usage: :clear [vars]".into()),
+            ("ast", "") => Command::Ast(None),
+            ("ast", "on") => Command::Ast(Some(true)),
+            ("ast", "off") => Command::Ast(Some(false)),
+            ("ast", _) => Command::Invalid("usage: :ast on|off".into()),
+            ("tokens", expression) => Command::Tokens(expression),
+            ("echo", "on") => Command::Echo(true),
+            ("echo", "off") => Command::Echo(false),
+            ("echo", _) => Command::Invalid("usage: :echo on|off".into()),
+            ("mode", "") => Command::Mode(None),
+            ("mode", "deg") => Command::Mode(Some(AngleMode::Degrees)),
+            ("mode", "rad") => Command::Mode(Some(AngleMode::Radians)),
+            ("mode", _) => Command::Invalid("usage: :mode deg|rad".into()),
+            ("zeropower", "") => Command::ZeroPower(None),
+            ("zeropower", "one") => Command::ZeroPower(Some(ZeroPower::One)),
+            ("zeropower", "error") => Command::ZeroPower(Some(ZeroPower::Error)),
+            ("zeropower", "nan") => Command::ZeroPower(Some(ZeroPower::NaN)),
+            ("zeropower", _) => Command::Invalid("usage: :zeropower one|error|nan".into()),
+            ("divzero", "") => Command::DivZero(None),
+            ("divzero", "inf") => Command::DivZero(Some(DivisionByZero::Infinity)),
+            ("divzero", "error") => Command::DivZero(Some(DivisionByZero::Error)),
+            ("divzero", "nan") => Command::DivZero(Some(DivisionByZero::NaN)),
+            ("divzero", _) => Command::Invalid("usage: :divzero inf|error|nan".into()),
+            ("roots", "") => Command::Roots(None),
+            ("roots", "real") => Command::Roots(Some(true)),
+            ("roots", "principal") => Command::Roots(Some(false)),
+            ("roots", _) => Command::Invalid("usage: :roots real|principal".into()),
+            ("format", "") => Command::Format(None),
+            ("format", name) => match output::parse_notation(name) {
+                Some(notation) => Command::Format(Some(notation)),
+                None => Command::Invalid("usage: :format auto|sci|eng|human|human-bin".into()),
+            },
+            ("output", "") => Command::Output(None),
+            ("output", name) => match output::parse_radix(name) {
+                Some(radix) => Command::Output(Some(radix)),
+                None => Command::Invalid("usage: :output dec|hex|bin|oct".into()),
+            },
+            ("plot", argument) => plot(argument).unwrap_or_else(|| {
+                Command::Invalid("usage: :plot <expr>, <var>, <from>..<to>".into())
+            }),
+            ("save", "") => Command::Invalid("usage: :save <file>".into()),
+            ("save", path) => Command::Save(path),
+            ("q", "") | ("quit", "") => Command::Exit,
+            (name, _) => Command::Unknown(name),
+        };
+
+        Some(command)
+    }
+}
+
+fn plot(argument: &str) -> Option<Command<'_>> {
+    let mut parts = argument.rsplitn(3, ',').map(str::trim);
+    let range = parts.next()?;
+    let variable = parts.next()?;
+    let expression = parts.next()?;
+
+    let (start, end) = range.split_once("..")?;
+    let (start, end): (f64, f64) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    let identifier = variable.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && variable
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if expression.is_empty() || !identifier || start >= end || start.is_nan() {
+        return None;
+    }
+
+    Some(Command::Plot {
+        expression,
+        variable,
+        start,
+        end,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expressions_are_not_commands() {
+        assert_eq!(Command::parse("1+2"), None);
+        assert_eq!(Command::parse(""), None);
+    }
+
+    #[test]
+    fn exit() {
+        for line in ["exit", "quit", ":q", ":quit"].iter() {
+            assert_eq!(Command::parse(line), Some(Command::Exit));
+        }
+    }
+
+    #[test]
+    fn commands_with_arguments() {
+        assert_eq!(Command::parse(":help"), Some(Command::Help));
+        assert_eq!(Command::parse(":vars"), Some(Command::Vars));
+        assert_eq!(
+            Command::parse(":clear"),
+            Some(Command::Clear {
+                only_variables: false
+            })
+        );
+        assert_eq!(
+            Command::parse(":clear   vars"),
+            Some(Command::Clear {
+                only_variables: true
+            })
+        );
+        assert_eq!(Command::parse(":ast on"), Some(Command::Ast(Some(true))));
+        assert_eq!(Command::parse(":ast"), Some(Command::Ast(None)));
+        assert_eq!(
+            Command::parse(":tokens 1 + 2"),
+            Some(Command::Tokens("1 + 2"))
+        );
+        assert_eq!(Command::parse(":mode"), Some(Command::Mode(None)));
+        assert_eq!(
+            Command::parse(":save session.txt"),
+            Some(Command::Save("session.txt"))
+        );
+        assert_eq!(
+            Command::parse(":mode deg"),
+            Some(Command::Mode(Some(AngleMode::Degrees)))
+        );
+        assert_eq!(
+            Command::parse(":zeropower nan"),
+            Some(Command::ZeroPower(Some(ZeroPower::NaN)))
+        );
+        assert_eq!(Command::parse(":roots"), Some(Command::Roots(None)));
+        assert_eq!(
+            Command::parse(":divzero error"),
+            Some(Command::DivZero(Some(DivisionByZero::Error)))
+        );
+        assert_eq!(
+            Command::parse(":roots real"),
+            Some(Command::Roots(Some(true)))
+        );
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert_eq!(
+            Command::parse(":ast maybe"),
+            Some(Command::Invalid("usage: :ast on|off".into()))
+        );
+        assert_eq!(
+            Command::parse(":clear all"),
+            Some(Command::Invalid("usage: :clear [vars]".into()))
+        );
+        assert_eq!(
+            Command::parse(":mode grad"),
+            Some(Command::Invalid("usage: :mode deg|rad".into()))
+        );
+        assert_eq!(
+            Command::parse(":zeropower 1"),
+            Some(Command::Invalid("usage: :zeropower one|error|nan".into()))
+        );
+        assert_eq!(
+            Command::parse(":divzero 0"),
+            Some(Command::Invalid("usage: :divzero inf|error|nan".into()))
+        );
+        assert_eq!(
+            Command::parse(":roots complex"),
+            Some(Command::Invalid("usage: :roots real|principal".into()))
+        );
+        assert_eq!(
+            Command::parse(":save"),
+            Some(Command::Invalid("usage: :save <file>".into()))
+        );
+    }
+
+    #[test]
+    fn plot_arguments() {
+        assert_eq!(
+            Command::parse(":plot x^2 - 2, x, -3..3"),
+            Some(Command::Plot {
+                expression: "x^2 - 2",
+                variable: "x",
+                start: -3.,
+                end: 3.,
+            })
+        );
+
+        let usage = Some(Command::Invalid(
+            "usage: :plot <expr>, <var>, <from>..<to>".into(),
+        ));
+        for line in [
+            ":plot x",
+            ":plot x, x, 3..1",
+            ":plot x, 1x, 0..1",
+            ":plot , x, 0..1",
+        ]
+        .iter()
+        {
+            assert_eq!(Command::parse(line), usage, "{}", line);
+        }
+    }
+
+    #[test]
+    fn unknown() {
+        assert_eq!(Command::parse(":foo 1"), Some(Command::Unknown("foo")));
+    }
+}
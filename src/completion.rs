@@ -0,0 +1,151 @@
+use crate::repl::Session;
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::Helper;
+use std::io::Write;
+
+const COMMANDS: &[&str] = &[
+    ":ast", ":clear", ":echo", ":format", ":help", ":mode", ":output", ":quit", ":save", ":tokens",
+    ":vars",
+];
+
+#[derive(Default)]
+pub struct CompletionHelper {
+    names: Vec<String>,
+}
+
+impl CompletionHelper {
+    pub fn refresh<W: Write, E: Write>(&mut self, session: &Session<W, E>) {
+        self.names = session.completion_names();
+    }
+}
+
+pub fn candidates(line: &str, cursor: usize, names: &[String]) -> (usize, Vec<String>) {
+    let before = &line[..cursor];
+
+    if before.starts_with(':') {
+        if before.contains(char::is_whitespace) {
+            return (cursor, Vec::new());
+        }
+
+        let matches = COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(before))
+            .map(|command| command.to_string())
+            .collect();
+        return (0, matches);
+    }
+
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+        .last()
+        .map_or(cursor, |(index, _)| index);
+    let word = &before[start..];
+    if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+        return (cursor, Vec::new());
+    }
+
+    let matches = names
+        .iter()
+        .filter(|name| name.starts_with(word))
+        .cloned()
+        .collect();
+    (start, matches)
+}
+
+impl Completer for CompletionHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(candidates(line, pos, &self.names))
+    }
+}
+
+impl Hinter for CompletionHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CompletionHelper {}
+
+impl Validator for CompletionHelper {}
+
+impl Helper for CompletionHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::repl::{Action, Mode};
+
+    fn names() -> Vec<String> {
+        vec!["ans".into(), "answer".into(), "sqrt(".into()]
+    }
+
+    #[test]
+    fn completes_names_mid_line() {
+        assert_eq!(
+            candidates("2 * an", 6, &names()),
+            (4, vec!["ans".to_string(), "answer".to_string()])
+        );
+        assert_eq!(
+            candidates("1 + sq + 2", 6, &names()),
+            (4, vec!["sqrt(".to_string()])
+        );
+        assert_eq!(
+            candidates("(answ", 5, &names()),
+            (1, vec!["answer".to_string()])
+        );
+    }
+
+    #[test]
+    fn no_candidates_outside_identifiers() {
+        assert_eq!(candidates("1 + ", 4, &names()), (4, vec![]));
+        assert_eq!(candidates("12", 2, &names()), (2, vec![]));
+        assert_eq!(candidates("x", 1, &names()), (0, vec![]));
+    }
+
+    #[test]
+    fn completes_commands() {
+        assert_eq!(
+            candidates(":o", 2, &names()),
+            (0, vec![":output".to_string()])
+        );
+        assert_eq!(candidates(":", 1, &names()).1.len(), COMMANDS.len());
+        assert_eq!(candidates(":mode d", 7, &names()), (7, vec![]));
+    }
+
+    #[test]
+    fn commands_are_known() {
+        for command in COMMANDS {
+            assert!(
+                !matches!(Command::parse(command), Some(Command::Unknown(_))),
+                "{}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn names_follow_session() {
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Pipe);
+        let mut helper = CompletionHelper::default();
+        helper.refresh(&session);
+        assert_eq!(candidates("a", 1, &helper.names), (0, vec![]));
+
+        assert_eq!(session.handle("1+2").unwrap(), Action::Continue);
+        helper.refresh(&session);
+        assert_eq!(
+            candidates("a", 1, &helper.names),
+            (0, vec!["ans".to_string()])
+        );
+    }
+}
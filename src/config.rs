@@ -0,0 +1,262 @@
+use crate::cli::Args;
+use crate::output;
+use math_parser::parse_math::context::AngleMode;
+use math_parser::parse_math::eval::{DivisionByZero, ZeroPower};
+use math_parser::parse_math::format::Precision;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    angle_mode: Option<String>,
+    zero_power: Option<String>,
+    division_by_zero: Option<String>,
+    real_roots: Option<bool>,
+    bool_arithmetic: Option<bool>,
+    format: Option<String>,
+    precision: Option<usize>,
+    fixed: Option<usize>,
+    quiet: Option<bool>,
+    history_size: Option<usize>,
+    implicit_multiplication: Option<bool>,
+    // A table of name = number, set before the first expression.
+    variables: Option<BTreeMap<String, f64>>,
+}
+
+pub fn default_path<F: Fn(&str) -> Option<OsString>>(var: F) -> Option<PathBuf> {
+    let config = match var("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var("HOME")?).join(".config"),
+    };
+    Some(config.join("mathparser").join("config.toml"))
+}
+
+// An explicitly requested file has to exist; the default one is optional.
+pub fn load(path: &Path, required: bool) -> Result<Config, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) if !required && !path.exists() => return Ok(Config::default()),
+        Err(error) => return Err(format!("cannot read '{}': {}", path.display(), error)),
+    };
+
+    toml::from_str(&contents)
+        .map_err(|error| format!("invalid config '{}': {}", path.display(), error.message()))
+}
+
+impl Config {
+    // Values already set on the command line win over the config file.
+    pub fn merge_into(self, args: &mut Args) -> Result<(), String> {
+        if args.angle_mode.is_none() {
+            args.angle_mode = match self.angle_mode.as_deref() {
+                None => None,
+                Some("deg") => Some(AngleMode::Degrees),
+                Some("rad") => Some(AngleMode::Radians),
+                Some(value) => return Err(invalid("angle_mode", value, "deg or rad")),
+            };
+        }
+
+        if args.zero_power.is_none() {
+            args.zero_power = match self.zero_power.as_deref() {
+                None => None,
+                Some("one") => Some(ZeroPower::One),
+                Some("error") => Some(ZeroPower::Error),
+                Some("nan") => Some(ZeroPower::NaN),
+                Some(value) => return Err(invalid("zero_power", value, "one, error or nan")),
+            };
+        }
+        args.real_roots = args.real_roots.or(self.real_roots);
+        args.bool_arithmetic = args.bool_arithmetic.or(self.bool_arithmetic);
+
+        if args.division_by_zero.is_none() {
+            args.division_by_zero = match self.division_by_zero.as_deref() {
+                None => None,
+                Some("inf") => Some(DivisionByZero::Infinity),
+                Some("error") => Some(DivisionByZero::Error),
+                Some("nan") => Some(DivisionByZero::NaN),
+                Some(value) => return Err(invalid("division_by_zero", value, "inf, error or nan")),
+            };
+        }
+
+        if args.notation.is_none() {
+            if let Some(name) = self.format {
+                args.notation = Some(output::parse_notation(&name).ok_or_else(|| {
+                    invalid("format", &name, "auto, sci, eng, human or human-bin")
+                })?);
+            }
+        }
+
+        if args.precision.is_none() {
+            args.precision = match (self.precision, self.fixed) {
+                (Some(_), Some(_)) => {
+                    return Err("config keys 'precision' and 'fixed' cannot both be set".into())
+                }
+                (Some(0), None) => return Err(invalid("precision", "0", "at least 1")),
+                (Some(digits), None) => Some(Precision::Significant(digits)),
+                (None, Some(decimals)) => Some(Precision::Fixed(decimals)),
+                (None, None) => None,
+            };
+        }
+
+        args.quiet = args.quiet.or(self.quiet);
+        args.history_size = args.history_size.or(self.history_size);
+        args.implicit_multiplication = args
+            .implicit_multiplication
+            .or(self.implicit_multiplication);
+
+        for (name, value) in self.variables.unwrap_or_default() {
+            if !is_identifier(&name) {
+                return Err(format!(
+                    "invalid variable name '{}' in config table 'variables'",
+                    name
+                ));
+            }
+            args.variables.push((name, value));
+        }
+
+        Ok(())
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn invalid(key: &str, value: &str, expected: &str) -> String {
+    format!(
+        "invalid value '{}' for config key '{}', expected {}",
+        value, key, expected
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math_parser::parse_math::format::Notation;
+
+    fn temp_config(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "math-parser-config-{}-{}.toml",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn args(values: &[&str]) -> Args {
+        Args::parse(values.iter().map(|value| value.to_string())).unwrap()
+    }
+
+    #[test]
+    fn parses_all_keys() {
+        let path = temp_config(
+            "all",
+            "angle_mode = \"deg\"\nzero_power = \"error\"\nreal_roots = true\nbool_arithmetic = true\ndivision_by_zero = \"nan\"\nformat = \"eng\"\nprecision = 6\nquiet = true\nhistory_size = 50\nimplicit_multiplication = false\n\n[variables]\nrate = 0.2\ng = 9.81\n",
+        );
+        let config = load(&path, true).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut merged = args(&[]);
+        config.merge_into(&mut merged).unwrap();
+        assert_eq!(merged.angle_mode, Some(AngleMode::Degrees));
+        assert_eq!(merged.zero_power, Some(ZeroPower::Error));
+        assert_eq!(merged.real_roots, Some(true));
+        assert_eq!(merged.bool_arithmetic, Some(true));
+        assert_eq!(merged.division_by_zero, Some(DivisionByZero::NaN));
+        assert_eq!(merged.notation(), Notation::Engineering);
+        assert_eq!(merged.precision(), Precision::Significant(6));
+        assert!(merged.quiet());
+        assert_eq!(merged.history_size, Some(50));
+        assert_eq!(merged.implicit_multiplication, Some(false));
+        assert_eq!(
+            merged.variables,
+            vec![("g".to_string(), 9.81), ("rate".to_string(), 0.2)]
+        );
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let path = temp_config(
+            "override",
+            "format = \"sci\"\nfixed = 2\nhistory_size = 50\nquiet = true\n",
+        );
+        let config = load(&path, true).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut merged = args(&["--format", "auto", "--precision", "4", "--no-quiet"]);
+        config.merge_into(&mut merged).unwrap();
+        assert!(!merged.quiet());
+        assert_eq!(merged.notation(), Notation::Auto);
+        assert_eq!(merged.precision(), Precision::Significant(4));
+        assert_eq!(merged.history_size, Some(50));
+
+        let mut defaults = args(&[]);
+        Config::default().merge_into(&mut defaults).unwrap();
+        assert_eq!(defaults, args(&[]));
+    }
+
+    #[test]
+    fn errors_name_the_bad_key() {
+        let path = temp_config("unknown", "precison = 6\n");
+        let error = load(&path, true).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(error.contains("precison"), "{}", error);
+
+        let path = temp_config("type", "precision = \"six\"\n");
+        let error = load(&path, true).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(error.starts_with("invalid config"), "{}", error);
+
+        let path = temp_config("value", "angle_mode = \"grad\"\n");
+        let config = load(&path, true).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            config.merge_into(&mut args(&[])),
+            Err("invalid value 'grad' for config key 'angle_mode', expected deg or rad".into())
+        );
+
+        let path = temp_config("zero_power", "zero_power = \"zero\"\n");
+        let config = load(&path, true).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            config.merge_into(&mut args(&[])),
+            Err(
+                "invalid value 'zero' for config key 'zero_power', expected one, error or nan"
+                    .into()
+            )
+        );
+
+        let path = temp_config("variables", "[variables]\n\"2x\" = 1\n");
+        let config = load(&path, true).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            config.merge_into(&mut args(&[])),
+            Err("invalid variable name '2x' in config table 'variables'".into())
+        );
+    }
+
+    #[test]
+    fn missing_files() {
+        let path = Path::new("/nonexistent/mathparser/config.toml");
+        assert_eq!(load(path, false), Ok(Config::default()));
+        assert!(load(path, true).unwrap_err().starts_with("cannot read"));
+    }
+
+    #[test]
+    fn path_lookup() {
+        let home = |name: &str| match name {
+            "HOME" => Some(OsString::from("/home/me")),
+            _ => None,
+        };
+        assert_eq!(
+            default_path(home),
+            Some(PathBuf::from("/home/me/.config/mathparser/config.toml"))
+        );
+        assert_eq!(default_path(|_| None), None);
+    }
+}
@@ -0,0 +1,92 @@
+use std::ffi::OsStr;
+use std::ops::Range;
+
+const RED: &str = "\x1b[31m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+pub fn use_color(stdout_is_terminal: bool, no_color: Option<&OsStr>) -> bool {
+    let no_color = no_color.is_some_and(|value| !value.is_empty());
+    stdout_is_terminal && !no_color
+}
+
+fn paint(text: &str, style: &str, color: bool) -> String {
+    if color {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn render(message: &str, source: &str, span: Option<Range<usize>>, color: bool) -> String {
+    let mut rendered = paint(message, RED, color);
+    rendered.push('\n');
+
+    if let Some(span) = span {
+        let start = source[..span.start.min(source.len())].chars().count();
+        let width = source
+            .get(span.clone())
+            .map_or(1, |text| text.chars().count().max(1));
+        let underline = format!("^{}", "~".repeat(width - 1));
+
+        rendered.push_str(&format!("  {}\n", source));
+        rendered.push_str(&format!(
+            "  {}{}\n",
+            " ".repeat(start),
+            paint(&underline, BOLD_RED, color)
+        ));
+    }
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_decision() {
+        assert!(use_color(true, None));
+        assert!(use_color(true, Some(OsStr::new(""))));
+        assert!(!use_color(true, Some(OsStr::new("1"))));
+        assert!(!use_color(false, None));
+    }
+
+    #[test]
+    fn plain_without_span() {
+        assert_eq!(
+            render("Parse error: Balance parenthesis error", "(1", None, false),
+            "Parse error: Balance parenthesis error\n"
+        );
+    }
+
+    #[test]
+    fn plain_with_span() {
+        assert_eq!(
+            render("unexpected '$$'", "1 + $$ 2", Some(4..6), false),
+            "unexpected '$$'\n  1 + $$ 2\n      ^~\n"
+        );
+    }
+
+    #[test]
+    fn span_after_multibyte_characters() {
+        assert_eq!(
+            render("bad", "× $", Some(3..4), false),
+            "bad\n  × $\n    ^\n"
+        );
+    }
+
+    #[test]
+    fn empty_span_at_end() {
+        assert_eq!(render("eof", "1+", Some(2..2), false), "eof\n  1+\n    ^\n");
+    }
+
+    #[test]
+    fn colored() {
+        assert_eq!(
+            render("bad", "1 $", Some(2..3), true),
+            "\x1b[31mbad\x1b[0m\n  1 $\n    \x1b[1;31m^\x1b[0m\n"
+        );
+        assert_eq!(render("bad", "1", None, true), "\x1b[31mbad\x1b[0m\n");
+    }
+}
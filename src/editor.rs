@@ -0,0 +1,85 @@
+use crate::completion::CompletionHelper;
+use crate::history::History;
+use crate::repl::{Action, Session};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use std::io::{self, Write};
+
+const CONTINUATION_PROMPT: &str = "...> ";
+
+pub fn run<W: Write, E: Write>(
+    session: &mut Session<W, E>,
+    prompt: &str,
+    mut history: Option<History>,
+) -> io::Result<()> {
+    let mut editor: Editor<CompletionHelper, DefaultHistory> =
+        Editor::new().map_err(into_io_error)?;
+    editor.set_helper(Some(CompletionHelper::default()));
+
+    if let Some(history) = &history {
+        for entry in history.load() {
+            editor.add_history_entry(entry).map_err(into_io_error)?;
+        }
+    }
+
+    let result = read_lines(&mut editor, session, prompt, &mut history);
+    if let Some(history) = &history {
+        let _ = history.trim();
+    }
+
+    result
+}
+
+fn read_lines<W: Write, E: Write>(
+    editor: &mut Editor<CompletionHelper, DefaultHistory>,
+    session: &mut Session<W, E>,
+    prompt: &str,
+    history: &mut Option<History>,
+) -> io::Result<()> {
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            helper.refresh(session);
+        }
+
+        let current_prompt = if session.is_pending() {
+            CONTINUATION_PROMPT.to_string()
+        } else {
+            session.prompt(prompt)
+        };
+
+        match editor.readline(&current_prompt) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    editor
+                        .add_history_entry(line.as_str())
+                        .map_err(into_io_error)?;
+                    save(history, &line);
+                }
+
+                if session.handle(&line)? == Action::Exit {
+                    return Ok(());
+                }
+            }
+            Err(ReadlineError::Interrupted) => session.cancel_pending(),
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(error) => return Err(into_io_error(error)),
+        }
+    }
+}
+
+fn save(history: &mut Option<History>, line: &str) {
+    if let Some(file) = history {
+        if let Err(error) = file.append(line) {
+            eprintln!("warning: history will not be saved: {}", error);
+            *history = None;
+        }
+    }
+}
+
+fn into_io_error(error: ReadlineError) -> io::Error {
+    match error {
+        ReadlineError::Io(error) => error,
+        error => io::Error::other(error),
+    }
+}
@@ -0,0 +1,161 @@
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub const DEFAULT_SIZE: usize = 1000;
+pub const PATH_VARIABLE: &str = "MATHPARSER_HISTORY";
+
+pub struct History {
+    path: PathBuf,
+    size: usize,
+}
+
+// `MATHPARSER_HISTORY` wins and an empty value turns history off; otherwise
+// the file lives in the XDG state directory.
+pub fn default_path<F: Fn(&str) -> Option<OsString>>(var: F) -> Option<PathBuf> {
+    if let Some(path) = var(PATH_VARIABLE) {
+        return if path.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(path))
+        };
+    }
+
+    let state = match var("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var("HOME")?).join(".local").join("state"),
+    };
+    Some(state.join("mathparser").join("history"))
+}
+
+impl History {
+    pub fn new(path: PathBuf, size: usize) -> Self {
+        History { path, size }
+    }
+
+    // Missing, unreadable and non-UTF-8 files all start a fresh history.
+    pub fn load(&self) -> Vec<String> {
+        let contents = fs::read_to_string(&self.path).unwrap_or_default();
+        let entries: Vec<String> = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let skip = entries.len().saturating_sub(self.size);
+        entries.into_iter().skip(skip).collect()
+    }
+
+    pub fn append(&self, entry: &str) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", entry)
+    }
+
+    pub fn trim(&self) -> io::Result<()> {
+        let entries = self.load();
+        let mut contents = entries.join("\n");
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+
+        fs::write(&self.path, contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "math-parser-history-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn vars<'a>(values: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        move |name| {
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| OsString::from(value))
+        }
+    }
+
+    #[test]
+    fn path_lookup() {
+        assert_eq!(
+            default_path(vars(&[("HOME", "/home/me")])),
+            Some(PathBuf::from("/home/me/.local/state/mathparser/history"))
+        );
+        assert_eq!(
+            default_path(vars(&[("HOME", "/home/me"), ("XDG_STATE_HOME", "/state")])),
+            Some(PathBuf::from("/state/mathparser/history"))
+        );
+        assert_eq!(
+            default_path(vars(&[("HOME", "/home/me"), (PATH_VARIABLE, "/tmp/h")])),
+            Some(PathBuf::from("/tmp/h"))
+        );
+        assert_eq!(
+            default_path(vars(&[("HOME", "/home/me"), (PATH_VARIABLE, "")])),
+            None
+        );
+        assert_eq!(default_path(vars(&[])), None);
+    }
+
+    #[test]
+    fn append_creates_directories_and_load_reads_back() {
+        let dir = temp_dir("append");
+        let history = History::new(dir.join("nested").join("history"), 10);
+        assert!(history.load().is_empty());
+
+        history.append("1+2").unwrap();
+        history.append(":ast on").unwrap();
+        assert_eq!(history.load(), vec!["1+2", ":ast on"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn trim_keeps_newest_entries() {
+        let dir = temp_dir("trim");
+        let history = History::new(dir.join("history"), 2);
+        for entry in ["1", "2", "3", "4"].iter() {
+            history.append(entry).unwrap();
+        }
+
+        assert_eq!(history.load(), vec!["3", "4"]);
+        history.trim().unwrap();
+        assert_eq!(fs::read_to_string(dir.join("history")).unwrap(), "3\n4\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_or_unreadable_file_starts_fresh() {
+        let dir = temp_dir("corrupt");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history");
+        fs::write(&path, [0xff, 0xfe, b'\n', 0x80]).unwrap();
+        assert!(History::new(path.clone(), 10).load().is_empty());
+
+        assert!(History::new(dir.clone(), 10).load().is_empty());
+        assert!(History::new(Path::new("/nonexistent/history").into(), 10)
+            .load()
+            .is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
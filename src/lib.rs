@@ -0,0 +1 @@
+pub mod parse_math;
@@ -1,21 +1,159 @@
-mod parse_math;
-use parse_math::parser::Parser;
-use std::io;
+mod cli;
+mod command;
+#[cfg(feature = "repl")]
+mod completion;
+#[cfg(feature = "config")]
+mod config;
+mod diagnostic;
+#[cfg(feature = "repl")]
+mod editor;
+#[cfg(feature = "repl")]
+mod history;
+mod output;
+mod plot;
+mod repl;
+mod transcript;
+
+use cli::Args;
+use repl::{Mode, Session};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::process;
 
 fn main() {
-    loop {
-        let mut input = String::new();
-
-        match io::stdin().read_line(&mut input) {
-            Ok(_) => {
-                println!("Your input: {}", input);
-                let mut parser = Parser::new(&input);
-                match parser.evaluate() {
-                    Ok(result) => println!("Result: {}", result),
-                    Err(error) => println!("Parse error: {}", error),
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            eprint!("{}", cli::USAGE);
+            process::exit(2);
+        }
+    };
+    if args.help {
+        print!("{}", cli::USAGE);
+        return;
+    }
+
+    #[cfg(feature = "config")]
+    let args = apply_config(args);
+
+    if let Some(path) = &args.file {
+        let result = match args.check {
+            Some(_) => cli::check_file(&args, path, io::stderr()),
+            None => cli::evaluate_file(&args, path, io::stdout(), io::stderr()),
+        };
+        match result {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(error) => {
+                eprintln!("error: cannot read '{}': {}", path, error);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = &args.replay {
+        let divergences = fs::read_to_string(path)
+            .and_then(|text| transcript::replay(&text, |session| configure(session, &args)));
+        match divergences {
+            Ok(divergences) if divergences.is_empty() => return,
+            Ok(divergences) => {
+                for divergence in &divergences {
+                    eprintln!("{}", divergence);
                 }
+                eprintln!("{} line(s) diverged from '{}'", divergences.len(), path);
+                process::exit(1);
+            }
+            Err(error) => {
+                eprintln!("error: cannot replay '{}': {}", path, error);
+                process::exit(1);
+            }
+        }
+    }
+
+    if args.check.is_some() {
+        match cli::check_expressions(&args, io::stderr()) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                process::exit(1);
             }
-            Err(error) => println!("error: {}", error),
         }
     }
+
+    if !args.expressions.is_empty() {
+        match cli::evaluate_expressions(&args, io::stdout(), io::stderr()) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                process::exit(1);
+            }
+        }
+    }
+
+    let stdin = io::stdin();
+    let mode = if stdin.is_terminal() {
+        Mode::Interactive
+    } else {
+        Mode::Pipe
+    };
+    let mut session = Session::new(io::stdout(), io::stderr(), mode);
+    configure(&mut session, &args);
+    session.set_color(diagnostic::use_color(
+        io::stdout().is_terminal(),
+        env::var_os("NO_COLOR").as_deref(),
+    ));
+
+    let result = match mode {
+        #[cfg(feature = "repl")]
+        Mode::Interactive => {
+            let path = if args.no_history {
+                None
+            } else {
+                history::default_path(|name| env::var_os(name))
+            };
+            let size = args.history_size.unwrap_or(history::DEFAULT_SIZE);
+            let history = path.map(|path| history::History::new(path, size));
+            editor::run(&mut session, &args.prompt, history)
+        }
+        _ => repl::run(stdin.lock(), &mut session),
+    };
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn configure<W: Write, E: Write>(session: &mut Session<W, E>, args: &Args) {
+    session.set_show_ast(args.ast);
+    session.set_format(args.format);
+    session.set_precision(args.precision);
+    session.set_notation(args.notation());
+    session.set_context(cli::context(args));
+    session.set_parser_options(cli::parser_options(args));
+    if args.quiet() {
+        session.set_echo(false);
+    }
+}
+
+#[cfg(feature = "config")]
+fn apply_config(mut args: Args) -> Args {
+    let config = match &args.config {
+        Some(path) => config::load(path.as_ref(), true),
+        None => match config::default_path(|name| env::var_os(name)) {
+            Some(path) => config::load(&path, false),
+            None => Ok(config::Config::default()),
+        },
+    };
+
+    if let Err(error) = config.and_then(|config| config.merge_into(&mut args)) {
+        eprintln!("error: {}", error);
+        process::exit(2);
+    }
+
+    args
 }
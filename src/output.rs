@@ -0,0 +1,432 @@
+use math_parser::parse_math::equation::Solution;
+use math_parser::parse_math::format::{self, Notation, Precision, Prefixes};
+use math_parser::parse_math::token::{Token, Tokenizer};
+use math_parser::parse_math::value::Value;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn default_precision(self) -> Precision {
+        match self {
+            OutputFormat::Text => Precision::default(),
+            OutputFormat::Json => Precision::Full,
+        }
+    }
+}
+
+pub fn parse_notation(name: &str) -> Option<Notation> {
+    match name {
+        "auto" => Some(Notation::Auto),
+        "sci" => Some(Notation::Scientific),
+        "eng" => Some(Notation::Engineering),
+        "human" => Some(Notation::Humanized(Prefixes::Si)),
+        "human-bin" => Some(Notation::Humanized(Prefixes::Binary)),
+        _ => None,
+    }
+}
+
+pub fn notation_name(notation: Notation) -> &'static str {
+    match notation {
+        Notation::Auto => "auto",
+        Notation::Scientific => "sci",
+        Notation::Engineering => "eng",
+        Notation::Humanized(Prefixes::Si) => "human",
+        Notation::Humanized(Prefixes::Binary) => "human-bin",
+    }
+}
+
+pub fn parse_radix(name: &str) -> Option<u32> {
+    match name {
+        "dec" => Some(10),
+        "hex" => Some(16),
+        "bin" => Some(2),
+        "oct" => Some(8),
+        _ => None,
+    }
+}
+
+pub fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        16 => "hex",
+        2 => "bin",
+        8 => "oct",
+        _ => "dec",
+    }
+}
+
+pub fn json_result(input: &str, result: f64, precision: Precision, notation: Notation) -> String {
+    format!(
+        "{{\"input\":{},\"ok\":true,\"result\":{}}}",
+        json_string(input),
+        json_number(result, precision, notation)
+    )
+}
+
+// Lists and matrices come out as (nested) arrays.
+pub fn json_value(input: &str, value: &Value, precision: Precision, notation: Notation) -> String {
+    let numbers = |numbers: &[f64]| {
+        let numbers: Vec<String> = numbers
+            .iter()
+            .map(|number| json_number(*number, precision, notation))
+            .collect();
+        format!("[{}]", numbers.join(","))
+    };
+    let result = match value {
+        Value::Number(number) => json_number(*number, precision, notation),
+        Value::Bool(value) => value.to_string(),
+        Value::List(list) => numbers(list),
+        Value::Matrix(matrix) => {
+            let rows: Vec<String> = (0..matrix.rows())
+                .map(|row| numbers(matrix.row(row)))
+                .collect();
+            format!("[{}]", rows.join(","))
+        }
+    };
+
+    format!(
+        "{{\"input\":{},\"ok\":true,\"result\":{}}}",
+        json_string(input),
+        result
+    )
+}
+
+// "solutions" is "one", "all" or "none"; only a unique solution has a result.
+pub fn json_solution(
+    input: &str,
+    variable: &str,
+    solution: Solution,
+    precision: Precision,
+    notation: Notation,
+) -> String {
+    let (solutions, result) = match solution {
+        Solution::Unique(value) => ("one", json_number(value, precision, notation)),
+        Solution::AllValues => ("all", "null".to_string()),
+        Solution::NoSolution => ("none", "null".to_string()),
+    };
+
+    format!(
+        "{{\"input\":{},\"ok\":true,\"variable\":{},\"solutions\":\"{}\",\"result\":{}}}",
+        json_string(input),
+        json_string(variable),
+        solutions,
+        result
+    )
+}
+
+// The span is in bytes of the input, as [start, end].
+pub fn json_error(input: &str, code: &str, message: &str, span: Option<Range<usize>>) -> String {
+    let span = match span {
+        Some(span) => format!("[{},{}]", span.start, span.end),
+        None => "null".to_string(),
+    };
+
+    format!(
+        "{{\"input\":{},\"ok\":false,\"error\":{{\"code\":{},\"message\":{},\"span\":{}}}}}",
+        json_string(input),
+        json_string(code),
+        json_string(message),
+        span
+    )
+}
+
+// Matrices print one row per line.
+pub fn format_value(value: &Value, precision: Precision, notation: Notation) -> String {
+    let numbers = |numbers: &[f64]| {
+        let numbers: Vec<String> = numbers
+            .iter()
+            .map(|number| format::format_with_notation(*number, precision, notation))
+            .collect();
+        format!("[{}]", numbers.join(", "))
+    };
+
+    match value {
+        Value::Number(number) => format::format_with_notation(*number, precision, notation),
+        Value::Bool(value) => value.to_string(),
+        Value::List(list) => numbers(list),
+        Value::Matrix(matrix) => {
+            let rows: Vec<String> = (0..matrix.rows())
+                .map(|row| numbers(matrix.row(row)))
+                .collect();
+            rows.join("\n")
+        }
+    }
+}
+
+pub fn format_tokens(input: &str) -> String {
+    let mut lines = String::new();
+
+    for spanned in Tokenizer::new(input).spanned() {
+        let kind = match spanned.token {
+            Token::Invalid(_) | Token::InvalidNumber(_) => "error",
+            ref token => token.kind(),
+        };
+        let literal = format!("{:?}", &input[spanned.span.clone()]);
+        lines.push_str(&format!(
+            "{:<16} {:<10} {}..{}\n",
+            kind, literal, spanned.span.start, spanned.span.end
+        ));
+    }
+
+    lines
+}
+
+fn json_number(value: f64, precision: Precision, notation: Notation) -> String {
+    if value.is_nan() {
+        json_string("NaN")
+    } else if value.is_infinite() {
+        json_string(if value > 0. { "Infinity" } else { "-Infinity" })
+    } else if precision == Precision::Full && notation == Notation::Auto {
+        format!("{:?}", value)
+    } else if let Notation::Humanized(_) = notation {
+        json_string(&format::format_with_notation(value, precision, notation))
+    } else {
+        format::format_with_notation(value, precision, notation)
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+
+    for char in value.chars() {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            char if (char as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", char as u32)),
+            char => escaped.push(char),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math_parser::parse_math::errors::ParseError;
+    use serde_json::json;
+
+    fn parse(line: &str) -> serde_json::Value {
+        serde_json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn negative_zero_round_trips() {
+        let line = json_result("-0", -0., Precision::Full, Notation::Auto);
+        assert_eq!(line, "{\"input\":\"-0\",\"ok\":true,\"result\":-0.0}");
+        let result = parse(&line)["result"].as_f64().unwrap();
+        assert!(result == 0. && result.is_sign_negative());
+    }
+
+    #[test]
+    fn result_object() {
+        let value = parse(&json_result("1+2", 3., Precision::Full, Notation::Auto));
+        assert_eq!(value, json!({"input": "1+2", "ok": true, "result": 3.0}));
+    }
+
+    #[test]
+    fn non_finite_results() {
+        assert_eq!(
+            parse(&json_result(
+                "1/0",
+                f64::INFINITY,
+                Precision::Full,
+                Notation::Auto
+            ))["result"],
+            "Infinity"
+        );
+        assert_eq!(
+            parse(&json_result(
+                "-1/0",
+                f64::NEG_INFINITY,
+                Precision::Full,
+                Notation::Auto
+            ))["result"],
+            "-Infinity"
+        );
+        assert_eq!(
+            parse(&json_result(
+                "0/0",
+                f64::NAN,
+                Precision::Full,
+                Notation::Auto
+            ))["result"],
+            "NaN"
+        );
+    }
+
+    #[test]
+    fn result_precision() {
+        let full = parse(&json_result(
+            "0.1+0.2",
+            0.1 + 0.2,
+            Precision::Full,
+            Notation::Auto,
+        ));
+        assert_eq!(full["result"], 0.30000000000000004);
+        let nice = parse(&json_result(
+            "0.1+0.2",
+            0.1 + 0.2,
+            Precision::default(),
+            Notation::Auto,
+        ));
+        assert_eq!(nice["result"], 0.3);
+        let fixed = parse(&json_result(
+            "1e20",
+            1e20,
+            Precision::Fixed(1),
+            Notation::Auto,
+        ));
+        assert_eq!(fixed["result"], 1e20);
+    }
+
+    #[test]
+    fn error_object() {
+        let error = ParseError::ParenthesisNotBalanced;
+        let value = parse(&json_error("(1", error.code(), &error.to_string(), None));
+        assert_eq!(
+            value,
+            json!({
+                "input": "(1",
+                "ok": false,
+                "error": {
+                    "code": "parenthesis_not_balanced",
+                    "message": "Balance parenthesis error",
+                    "span": null
+                }
+            })
+        );
+
+        let error = ParseError::UnexpectedCharacter {
+            character: '$',
+            position: 4,
+        };
+        let value = parse(&json_error(
+            "1 + $ 2",
+            error.code(),
+            &error.to_string(),
+            error.span("1 + $ 2"),
+        ));
+        assert_eq!(value["error"]["span"], json!([4, 5]));
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            format_tokens("1.5 * (2-3)"),
+            "\
+Number           \"1.5\"      0..3
+Asterisk         \"*\"        4..5
+LeftParenthesis  \"(\"        6..7
+Number           \"2\"        7..8
+Minus            \"-\"        8..9
+Number           \"3\"        9..10
+RightParenthesis \")\"        10..11
+EOF              \"\"         11..11
+"
+        );
+    }
+
+    #[test]
+    fn tokens_with_invalid_character() {
+        assert_eq!(
+            format_tokens("2 $ 3"),
+            "\
+Number           \"2\"        0..1
+error            \"$\"        2..3
+Number           \"3\"        4..5
+EOF              \"\"         5..5
+"
+        );
+    }
+
+    #[test]
+    fn result_notation() {
+        let value = parse(&json_result(
+            "6.022e23",
+            6.022e23,
+            Precision::Full,
+            Notation::Engineering,
+        ));
+        assert_eq!(value["result"], 6.022e23);
+        assert_eq!(
+            json_result("1000", 1000., Precision::default(), Notation::Scientific),
+            "{\"input\":\"1000\",\"ok\":true,\"result\":1e3}"
+        );
+        assert_eq!(
+            json_result(
+                "2^20",
+                1048576.,
+                Precision::Full,
+                Notation::Humanized(Prefixes::Binary)
+            ),
+            "{\"input\":\"2^20\",\"ok\":true,\"result\":\"1Mi\"}"
+        );
+    }
+
+    #[test]
+    fn matrix_values() {
+        let matrix =
+            Value::list(vec![Value::List(vec![0.5, 2.]), Value::List(vec![3., -4.])]).unwrap();
+        assert_eq!(
+            parse(&json_value("m", &matrix, Precision::Full, Notation::Auto))["result"],
+            json!([[0.5, 2.0], [3.0, -4.0]])
+        );
+        assert_eq!(
+            format_value(&matrix, Precision::default(), Notation::Scientific),
+            "[5e-1, 2e0]\n[3e0, -4e0]"
+        );
+        assert_eq!(
+            format_value(
+                &Value::List(vec![0.1 + 0.2, 1.]),
+                Precision::default(),
+                Notation::Auto
+            ),
+            "[0.3, 1]"
+        );
+    }
+
+    #[test]
+    fn solutions() {
+        let unique = parse(&json_solution(
+            "2*x = 1",
+            "x",
+            Solution::Unique(0.5),
+            Precision::Full,
+            Notation::Auto,
+        ));
+        assert_eq!(unique["variable"], "x");
+        assert_eq!(unique["solutions"], "one");
+        assert_eq!(unique["result"], json!(0.5));
+
+        let none = parse(&json_solution(
+            "x = x + 1",
+            "x",
+            Solution::NoSolution,
+            Precision::Full,
+            Notation::Auto,
+        ));
+        assert_eq!(none["solutions"], "none");
+        assert!(none["result"].is_null());
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let value = parse(&json_result(
+            "\"a\\\\b\"\t\u{1}",
+            1.,
+            Precision::Full,
+            Notation::Auto,
+        ));
+        assert_eq!(value["input"], "\"a\\\\b\"\t\u{1}");
+    }
+}
@@ -1,24 +1,852 @@
-#[derive(PartialEq, Debug)]
+use super::context::{self, AngleMode, Context};
+use super::equation::Equation;
+use super::errors::EvalError;
+use super::eval::{self, EvalOptions};
+use super::function::{self, Arity};
+use super::operator::{BinaryOperator, Fixity, UnaryOperator};
+use super::value::Value;
+use std::convert::Infallible;
+use std::mem;
+
+#[derive(Clone, PartialEq, Debug)]
 pub enum Node {
     Element(f64),
+    Variable(String),
+    // A spreadsheet cell such as A1 or AB12, whose value eval_with_cells
+    // asks the sheet for.
+    CellRef(String),
     Negative(Box<Node>),
+    Percent(Box<Node>),
+    Factorial(Box<Node>),
     Sum(Box<Node>, Box<Node>),
     Subtract(Box<Node>, Box<Node>),
     Multiply(Box<Node>, Box<Node>),
     Divide(Box<Node>, Box<Node>),
+    // The quotient rounded down, so -7 // 2 is -4.
+    FloorDivide(Box<Node>, Box<Node>),
+    // The remainder of truncating division, as f64's %: it has the sign of
+    // the dividend and is NaN for a zero divisor.
+    Modulo(Box<Node>, Box<Node>),
     Power(Box<Node>, Box<Node>),
+    // An operator defined in the context the text was parsed with, named
+    // by its symbol and applied through the context it is evaluated in.
+    CustomBinary(String, Box<Node>, Box<Node>),
+    CustomUnary(Fixity, String, Box<Node>),
+    // 1 when the comparison holds and 0 otherwise, or a boolean when
+    // evaluated with types. Equality is exact, so 0.1 + 0.2 == 0.3 is 0.
+    Compare(Comparison, Box<Node>, Box<Node>),
+    // Logical operators, true or false like comparisons, which take any
+    // number but 0 as true. The right side of && and || is only evaluated
+    // when the left one does not already decide the result.
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    List(Vec<Node>),
+    // A call of a built-in function, looked up by name when evaluated.
+    Function(String, Vec<Node>),
+    // if(condition, then, otherwise), of which only the branch the
+    // condition picks is evaluated.
+    Conditional(Box<Node>, Box<Node>, Box<Node>),
+    // sum(i, from, to, body) or prod(i, from, to, body): the body is
+    // evaluated once for each integer i from `from` to `to`, with i bound
+    // to it, and the results added or multiplied.
+    Series(Series, String, Box<Node>, Box<Node>, Box<Node>),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Series {
+    Sum,
+    Product,
+}
+
+// More terms than this are an error rather than a wait, since
+// sum(i, 1, 1e18, i) would not end.
+pub const MAX_TERMS: u64 = 1_000_000;
+
+impl Series {
+    pub fn name(self) -> &'static str {
+        match self {
+            Series::Sum => "sum",
+            Series::Product => "prod",
+        }
+    }
+
+    // What a series of no terms gives, as sum(i, 1, 0, i) does.
+    pub fn empty(self) -> f64 {
+        match self {
+            Series::Sum => 0.,
+            Series::Product => 1.,
+        }
+    }
+
+    pub fn combine(self, total: f64, term: f64) -> f64 {
+        match self {
+            Series::Sum => total + term,
+            Series::Product => total * term,
+        }
+    }
+
+    // The values the bound variable takes, none when `to` is below `from`.
+    pub fn indices(self, from: f64, to: f64) -> Result<impl Iterator<Item = f64>, EvalError> {
+        for bound in [from, to].iter() {
+            if !(bound.is_finite() && bound.fract() == 0.) {
+                return Err(EvalError::Bound {
+                    function: self.name(),
+                    value: *bound,
+                });
+            }
+        }
+        let count = if to < from { 0. } else { to - from + 1. };
+        if count > MAX_TERMS as f64 {
+            return Err(EvalError::TooManyTerms {
+                function: self.name(),
+                limit: MAX_TERMS,
+            });
+        }
+
+        Ok((0..count as u64).map(move |index| from + index as f64))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    pub fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+
+    // 1 or 0, for the evaluations without booleans.
+    pub fn value(self, left: f64, right: f64) -> f64 {
+        truth(self.holds(left, right))
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Statement {
+    Expression(Node),
+    Assignment(String, Node),
+    Equation(Equation),
+    // f(x, y) = <body>, which makes f callable once it is in the context.
+    Definition(String, UserFunction),
+}
+
+// A function defined in the expression language, as in f(x) = x^2 + 1.
+#[derive(Clone, PartialEq, Debug)]
+pub struct UserFunction {
+    pub parameters: Vec<String>,
+    pub body: Node,
+}
+
+impl UserFunction {
+    // The arguments are bound to the parameters in a scope of their own:
+    // a parameter hides a variable of the same name, and the body sees
+    // nothing of its caller's parameters.
+    pub fn call(&self, name: &str, arguments: &[f64], context: &Context) -> Result<f64, EvalError> {
+        if arguments.len() != self.parameters.len() {
+            return Err(EvalError::ArgumentCount {
+                function: name.to_string(),
+                expected: Arity::Exactly(self.parameters.len()),
+                found: arguments.len(),
+            });
+        }
+
+        let scope: Vec<(&str, f64)> = self
+            .parameters
+            .iter()
+            .map(String::as_str)
+            .zip(arguments.iter().copied())
+            .collect();
+        self.body.eval_scoped(context, &scope, &no_cells)
+    }
+}
+
+fn no_cells(_: &str) -> Option<f64> {
+    None
+}
+
+// `scope` with the variable of a series in front, where it hides any
+// outer one of the same name.
+fn bind<'s>(variable: &'s str, scope: &[(&'s str, f64)]) -> Vec<(&'s str, f64)> {
+    let mut inner = Vec::with_capacity(scope.len() + 1);
+    inner.push((variable, 0.));
+    inner.extend_from_slice(scope);
+
+    inner
+}
+
+// The operator a CustomBinary node names, as the context defines it.
+pub(crate) fn operator<'c>(
+    symbol: &str,
+    context: &'c Context,
+) -> Result<&'c BinaryOperator, EvalError> {
+    character(symbol)
+        .and_then(|character| context.operators().binary(character))
+        .ok_or_else(|| EvalError::UnknownOperator(symbol.to_string()))
+}
+
+pub(crate) fn unary_operator<'c>(
+    fixity: Fixity,
+    symbol: &str,
+    context: &'c Context,
+) -> Result<&'c UnaryOperator, EvalError> {
+    character(symbol)
+        .and_then(|character| context.operators().unary(fixity, character))
+        .ok_or_else(|| EvalError::UnknownOperator(symbol.to_string()))
+}
+
+fn character(symbol: &str) -> Option<char> {
+    let mut chars = symbol.chars();
+    match (chars.next(), chars.next()) {
+        (Some(character), None) => Some(character),
+        _ => None,
+    }
+}
+
+// Any number but 0 is true.
+fn truthy<E>(_: &Node, condition: f64) -> Result<bool, E> {
+    Ok(condition != 0.)
+}
+
+pub(crate) fn truth(holds: bool) -> f64 {
+    if holds {
+        1.
+    } else {
+        0.
+    }
+}
+
+// Built-in functions come first; the parser does not let a definition take
+// their names.
+fn call(name: &str, arguments: &[f64], context: &Context) -> Result<f64, EvalError> {
+    match (function::lookup(name), context.function(name)) {
+        (None, Some(function)) => function.call(name, arguments, context),
+        _ => function::call(name, arguments, context.angle_mode()),
+    }
 }
 
 impl Node {
+    // A free variable other than a constant such as pi, a list, the
+    // factorial of anything but a non-negative integer or a call that does
+    // not fit its function has no number here and evaluates to NaN;
+    // eval_with reports it instead. Angles are in radians, and any condition
+    // but 0 holds, NaN included.
     pub fn eval(&self) -> f64 {
+        let result = self.fold(truthy, truth, |node, operands: Vec<f64>| {
+            Ok::<_, Infallible>(match node {
+                Self::Element(number) => *number,
+                Self::Variable(name) => context::constant(name).unwrap_or(f64::NAN),
+                Self::CellRef(_) | Self::List(_) => f64::NAN,
+                Self::Negative(_) => -operands[0],
+                Self::Percent(_) => operands[0] / 100.,
+                Self::Factorial(_) => eval::factorial(operands[0]).unwrap_or(f64::NAN),
+                Self::Sum(_, _) => operands[0] + operands[1],
+                Self::Subtract(_, _) => operands[0] - operands[1],
+                Self::Multiply(_, _) => operands[0] * operands[1],
+                Self::Divide(_, _) => operands[0] / operands[1],
+                Self::FloorDivide(_, _) => (operands[0] / operands[1]).floor(),
+                Self::Modulo(_, _) => operands[0] % operands[1],
+                Self::Power(_, _) => operands[0].powf(operands[1]),
+                Self::Compare(comparison, _, _) => comparison.value(operands[0], operands[1]),
+                Self::Not(_) => truth(operands[0] == 0.),
+                Self::Function(name, _) => {
+                    function::call(name, &operands, AngleMode::Radians).unwrap_or(f64::NAN)
+                }
+                Self::CustomBinary(..) | Self::CustomUnary(..) => f64::NAN,
+                // The body needs its variable bound, which is what a
+                // context is for.
+                Self::Series(..) => node.eval_with(&Context::new()).unwrap_or(f64::NAN),
+                Self::And(..) | Self::Or(..) | Self::Conditional(..) => {
+                    unreachable!("fold evaluates a branch instead")
+                }
+            })
+        });
+
+        match result {
+            Ok(number) => number,
+            Err(never) => match never {},
+        }
+    }
+
+    pub fn eval_with_options(&self, options: &EvalOptions) -> Result<f64, EvalError> {
+        self.eval_with_options_scoped(options, &[])
+    }
+
+    // The only names with a value here are constants and those in `scope`,
+    // the variables of the series the node is in.
+    fn eval_with_options_scoped(
+        &self,
+        options: &EvalOptions,
+        scope: &[(&str, f64)],
+    ) -> Result<f64, EvalError> {
+        self.fold(truthy, truth, |node, operands: Vec<f64>| {
+            Ok(match node {
+                Self::Element(number) => *number,
+                Self::Variable(name) => match scope.iter().find(|(local, _)| local == name) {
+                    Some((_, value)) => *value,
+                    None => context::constant(name)
+                        .ok_or_else(|| EvalError::UnknownVariable(name.clone()))?,
+                },
+                Self::CellRef(cell) => return Err(EvalError::UnknownCell(cell.clone())),
+                Self::Negative(_) => -operands[0],
+                Self::Percent(_) => operands[0] / 100.,
+                Self::Factorial(_) => eval::factorial(operands[0])?,
+                Self::Sum(_, _) => operands[0] + operands[1],
+                Self::Subtract(_, _) => operands[0] - operands[1],
+                Self::Multiply(_, _) => operands[0] * operands[1],
+                Self::Divide(_, _) => operands[0] / options.divisor(operands[1])?,
+                Self::FloorDivide(_, _) => (operands[0] / options.divisor(operands[1])?).floor(),
+                Self::Modulo(_, _) => operands[0] % options.divisor(operands[1])?,
+                Self::Power(_, _) => options.power(operands[0], operands[1])?,
+                Self::Compare(comparison, _, _) => comparison.value(operands[0], operands[1]),
+                Self::Not(_) => truth(operands[0] == 0.),
+                Self::List(_) => return Err(self.not_a_number()),
+                Self::Function(name, _) => function::call(name, &operands, options.angle_mode)?,
+                Self::CustomBinary(symbol, _, _) | Self::CustomUnary(_, symbol, _) => {
+                    return Err(EvalError::UnknownOperator(symbol.clone()))
+                }
+                Self::Series(series, variable, _, _, body) => {
+                    let mut inner = bind(variable, scope);
+                    let mut total = series.empty();
+                    for index in series.indices(operands[0], operands[1])? {
+                        inner[0].1 = index;
+                        let term = body.eval_with_options_scoped(options, &inner)?;
+                        total = series.combine(total, term);
+                    }
+                    total
+                }
+                Self::And(..) | Self::Or(..) | Self::Conditional(..) => {
+                    unreachable!("fold evaluates a branch instead")
+                }
+            })
+        })
+    }
+
+    pub fn eval_with(&self, context: &Context) -> Result<f64, EvalError> {
+        self.eval_scoped(context, &[], &no_cells)
+    }
+
+    // `cells` gives the value of a cell of the sheet, or None for one that
+    // cannot be read, such as an empty or out-of-range cell.
+    pub fn eval_with_cells(
+        &self,
+        context: &Context,
+        cells: &dyn Fn(&str) -> Option<f64>,
+    ) -> Result<f64, EvalError> {
+        self.eval_scoped(context, &[], cells)
+    }
+
+    // Names in `scope` hide those in the context.
+    fn eval_scoped(
+        &self,
+        context: &Context,
+        scope: &[(&str, f64)],
+        cells: &dyn Fn(&str) -> Option<f64>,
+    ) -> Result<f64, EvalError> {
+        self.fold(truthy, truth, |node, operands: Vec<f64>| {
+            Ok(match node {
+                Self::Element(number) => *number,
+                Self::Variable(name) => match scope.iter().find(|(local, _)| local == name) {
+                    Some((_, value)) => *value,
+                    None => context
+                        .get(name)
+                        .ok_or_else(|| EvalError::UnknownVariable(name.clone()))?,
+                },
+                Self::CellRef(cell) => {
+                    cells(cell).ok_or_else(|| EvalError::UnknownCell(cell.clone()))?
+                }
+                Self::Negative(_) => -operands[0],
+                Self::Percent(_) => operands[0] / 100.,
+                Self::Factorial(_) => eval::factorial(operands[0])?,
+                Self::Sum(_, _) => operands[0] + operands[1],
+                Self::Subtract(_, _) => operands[0] - operands[1],
+                Self::Multiply(_, _) => operands[0] * operands[1],
+                Self::Divide(_, _) => operands[0] / context.options().divisor(operands[1])?,
+                Self::FloorDivide(_, _) => {
+                    (operands[0] / context.options().divisor(operands[1])?).floor()
+                }
+                Self::Modulo(_, _) => operands[0] % context.options().divisor(operands[1])?,
+                Self::Power(_, _) => context.options().power(operands[0], operands[1])?,
+                Self::Compare(comparison, _, _) => comparison.value(operands[0], operands[1]),
+                Self::Not(_) => truth(operands[0] == 0.),
+                Self::List(_) => return Err(self.not_a_number()),
+                Self::Function(name, _) => call(name, &operands, context)?,
+                Self::CustomBinary(symbol, _, _) => {
+                    operator(symbol, context)?.apply(operands[0], operands[1])
+                }
+                Self::CustomUnary(fixity, symbol, _) => {
+                    unary_operator(*fixity, symbol, context)?.apply(operands[0])
+                }
+                Self::Series(series, variable, _, _, body) => {
+                    let mut inner = bind(variable, scope);
+                    let mut total = series.empty();
+                    for index in series.indices(operands[0], operands[1])? {
+                        inner[0].1 = index;
+                        let term = body.eval_scoped(context, &inner, cells)?;
+                        total = series.combine(total, term);
+                    }
+                    total
+                }
+                Self::And(..) | Self::Or(..) | Self::Conditional(..) => {
+                    unreachable!("fold evaluates a branch instead")
+                }
+            })
+        })
+    }
+
+    // Applies `apply` to every node, children before their parent and left
+    // before right, handing it the results for the node's children. A
+    // conditional is not applied: `choose` tells from its condition's
+    // result which branch to take, and that branch's result is the
+    // conditional's, so the other one is never visited. Neither are && and
+    // ||, whose sides `choose` tells the truth of in turn, and `truth` gives
+    // the result once one decides it. A series is applied to its bounds
+    // alone, since `apply` evaluates the body once for each term itself.
+    // The nodes waiting are kept on the heap rather than the stack, so that
+    // trees of any depth can be evaluated; the first error ends the walk.
+    fn fold<T, E>(
+        &self,
+        mut choose: impl FnMut(&Node, T) -> Result<bool, E>,
+        truth: impl Fn(bool) -> T,
+        mut apply: impl FnMut(&Node, Vec<T>) -> Result<T, E>,
+    ) -> Result<T, E> {
+        enum Visit<'a> {
+            Enter(&'a Node),
+            Exit(&'a Node),
+            Branch(&'a Node, &'a Node, &'a Node),
+            // The left side of && or || is evaluated, and then the right
+            // one if need be.
+            Left(&'a Node, &'a Node),
+            Right(&'a Node),
+            Bounds(&'a Node),
+        }
+        let mut pending = vec![Visit::Enter(self)];
+        let mut results = Vec::new();
+
+        while let Some(visit) = pending.pop() {
+            match visit {
+                Visit::Enter(node @ Self::Conditional(condition, then, otherwise)) => {
+                    pending.push(Visit::Branch(node, then, otherwise));
+                    pending.push(Visit::Enter(condition));
+                }
+                Visit::Enter(node @ (Self::And(left, right) | Self::Or(left, right))) => {
+                    pending.push(Visit::Left(node, right));
+                    pending.push(Visit::Enter(left));
+                }
+                Visit::Enter(node @ Self::Series(_, _, from, to, _)) => {
+                    pending.push(Visit::Bounds(node));
+                    pending.push(Visit::Enter(to));
+                    pending.push(Visit::Enter(from));
+                }
+                Visit::Enter(node) => {
+                    pending.push(Visit::Exit(node));
+                    pending.extend(node.children().into_iter().rev().map(Visit::Enter));
+                }
+                Visit::Exit(node) => {
+                    let operands = results.split_off(results.len() - node.children().len());
+                    results.push(apply(node, operands)?);
+                }
+                Visit::Branch(node, then, otherwise) => {
+                    let condition = results.pop().expect("a condition leaves one result");
+                    let branch = if choose(node, condition)? {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    pending.push(Visit::Enter(branch));
+                }
+                Visit::Left(node, right) => {
+                    let left = results.pop().expect("a side leaves one result");
+                    let holds = choose(node, left)?;
+                    if holds == matches!(node, Self::Or(..)) {
+                        results.push(truth(holds));
+                    } else {
+                        pending.push(Visit::Right(node));
+                        pending.push(Visit::Enter(right));
+                    }
+                }
+                Visit::Right(node) => {
+                    let right = results.pop().expect("a side leaves one result");
+                    results.push(truth(choose(node, right)?));
+                }
+                Visit::Bounds(node) => {
+                    let bounds = results.split_off(results.len() - 2);
+                    results.push(apply(node, bounds)?);
+                }
+            }
+        }
+
+        Ok(results.pop().expect("the root leaves one result"))
+    }
+
+    pub(crate) fn not_a_number(&self) -> EvalError {
+        EvalError::TypeMismatch {
+            operation: "scalar evaluation",
+            operands: vec!["list"],
+        }
+    }
+
+    // Sorted and without duplicates. The variable of a series is not one
+    // in its body, where the series gives it its values.
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables = Vec::new();
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            match node {
+                Self::Variable(name) => variables.push(name.clone()),
+                Self::Series(_, variable, from, to, body) => {
+                    pending.push(from);
+                    pending.push(to);
+                    let free = body.variables().into_iter();
+                    variables.extend(free.filter(|name| name != variable));
+                }
+                _ => pending.extend(node.children()),
+            }
+        }
+        variables.sort();
+        variables.dedup();
+
+        variables
+    }
+
+    // The cells a formula reads, sorted and without duplicates, for a sheet
+    // to know what to recalculate it after.
+    pub fn cells(&self) -> Vec<String> {
+        let mut cells = Vec::new();
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            if let Self::CellRef(cell) = node {
+                cells.push(cell.clone());
+            }
+            pending.extend(node.children());
+        }
+        cells.sort();
+        cells.dedup();
+
+        cells
+    }
+
+    pub fn eval_checked(&self) -> Result<Value, EvalError> {
+        self.eval_checked_in(AngleMode::Radians)
+    }
+
+    pub fn eval_checked_in(&self, angle_mode: AngleMode) -> Result<Value, EvalError> {
+        let mut context = Context::new();
+        context.set_angle_mode(angle_mode);
+
+        self.eval_checked_with(&context)
+    }
+
+    // Conditions and the operands of logical operators have to be booleans
+    // here, unless the options' bool_arithmetic lets booleans count as 1
+    // and 0 and numbers as conditions.
+    pub fn eval_checked_with(&self, context: &Context) -> Result<Value, EvalError> {
+        let options = context.options();
+        let loose = options.bool_arithmetic;
+        let condition = move |value: Value, operation| match value {
+            Value::Number(number) if loose => Ok(number != 0.),
+            value => value.condition(operation),
+        };
+        let choose = |node: &Node, value: Value| {
+            condition(
+                value,
+                match node {
+                    Self::And(..) => "&&",
+                    Self::Or(..) => "||",
+                    _ => "if",
+                },
+            )
+        };
+        self.fold(choose, Value::Bool, |node, operands: Vec<Value>| {
+            let mut operands = operands.into_iter().map(|value| match value {
+                Value::Bool(value) if loose => Value::Number(truth(value)),
+                value => value,
+            });
+            let mut operand = || operands.next().expect("one result per child");
+
+            match node {
+                Self::Element(number) => Ok(Value::Number(*number)),
+                Self::Variable(name) => context
+                    .get(name)
+                    .map(Value::Number)
+                    .ok_or_else(|| EvalError::UnknownVariable(name.clone())),
+                Self::CellRef(cell) => Err(EvalError::UnknownCell(cell.clone())),
+                Self::Negative(_) => operand().negate(),
+                Self::Percent(_) => Value::quotient(operand(), Value::Number(100.), &options),
+                Self::Factorial(_) => Ok(Value::Number(eval::factorial(
+                    operand().number("factorial")?,
+                )?)),
+                Self::Sum(_, _) => Value::sum(operand(), operand()),
+                Self::Subtract(_, _) => Value::difference(operand(), operand()),
+                Self::Multiply(_, _) => Value::product(operand(), operand()),
+                Self::Divide(_, _) => Value::quotient(operand(), operand(), &options),
+                Self::FloorDivide(_, _) => {
+                    Value::divided("floor division", operand(), operand(), &options, |a, b| {
+                        (a / b).floor()
+                    })
+                }
+                Self::Modulo(_, _) => {
+                    Value::divided("modulo", operand(), operand(), &options, |a, b| a % b)
+                }
+                Self::Power(_, _) => Value::power(operand(), operand(), &options),
+                Self::Compare(comparison, _, _) => match (operand(), operand()) {
+                    (Value::Number(left), Value::Number(right)) => {
+                        Ok(Value::Bool(comparison.holds(left, right)))
+                    }
+                    (left, right) => Err(EvalError::TypeMismatch {
+                        operation: "comparison",
+                        operands: vec![left.type_name(), right.type_name()],
+                    }),
+                },
+                Self::Not(_) => Ok(Value::Bool(!condition(operand(), "!")?)),
+                Self::List(_) => Value::list(operands.collect()),
+                Self::Function(name, _) => {
+                    if let Some(function) = function::lookup_list(name) {
+                        return function.call(operands.collect());
+                    }
+                    let operation = match function::lookup(name) {
+                        Some(function) => function.name,
+                        None if context.function(name).is_some() => "function call",
+                        None => return Err(EvalError::UnknownFunction(name.clone())),
+                    };
+                    let arguments = operands
+                        .map(|argument| argument.number(operation))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok(Value::Number(call(name, &arguments, context)?))
+                }
+                Self::CustomBinary(symbol, _, _) => {
+                    let operator = operator(symbol, context)?;
+                    Ok(Value::Number(operator.apply(
+                        operand().number("custom operator")?,
+                        operand().number("custom operator")?,
+                    )))
+                }
+                Self::CustomUnary(fixity, symbol, _) => {
+                    let operator = unary_operator(*fixity, symbol, context)?;
+                    Ok(Value::Number(
+                        operator.apply(operand().number("custom operator")?),
+                    ))
+                }
+                // Terms may be vectors or matrices as well as numbers.
+                Self::Series(series, variable, _, _, body) => {
+                    let from = operand().number(series.name())?;
+                    let to = operand().number(series.name())?;
+                    let mut inner = context.clone();
+                    let mut total = Value::Number(series.empty());
+                    for index in series.indices(from, to)? {
+                        inner.set(variable, index);
+                        let term = body.eval_checked_with(&inner)?;
+                        total = match series {
+                            Series::Sum => Value::sum(total, term)?,
+                            Series::Product => Value::product(total, term)?,
+                        };
+                    }
+                    Ok(total)
+                }
+                Self::And(..) | Self::Or(..) | Self::Conditional(..) => {
+                    unreachable!("fold evaluates a branch instead")
+                }
+            }
+        })
+    }
+
+    pub fn to_tree_string(&self) -> String {
+        let mut tree = format!("{}\n", self.label());
+        self.write_children(&mut tree, "");
+
+        tree
+    }
+
+    fn write_children(&self, tree: &mut String, prefix: &str) {
+        let children = self.children();
+
+        for (index, child) in children.iter().enumerate() {
+            let last = index + 1 == children.len();
+            let (branch, indent) = if last {
+                ("└ ", "  ")
+            } else {
+                ("├ ", "│ ")
+            };
+
+            tree.push_str(&format!("{}{}{}\n", prefix, branch, child.label()));
+            child.write_children(tree, &format!("{}{}", prefix, indent));
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Self::Element(number) => format!("Element({})", number),
+            Self::Variable(name) => format!("Variable({})", name),
+            Self::CellRef(cell) => format!("CellRef({})", cell),
+            Self::Negative(_) => "Negative".into(),
+            Self::Percent(_) => "Percent".into(),
+            Self::Factorial(_) => "Factorial".into(),
+            Self::Sum(_, _) => "Sum".into(),
+            Self::Subtract(_, _) => "Subtract".into(),
+            Self::Multiply(_, _) => "Multiply".into(),
+            Self::Divide(_, _) => "Divide".into(),
+            Self::FloorDivide(_, _) => "FloorDivide".into(),
+            Self::Modulo(_, _) => "Modulo".into(),
+            Self::Power(_, _) => "Power".into(),
+            Self::CustomBinary(symbol, _, _) => format!("CustomBinary({})", symbol),
+            Self::CustomUnary(fixity, symbol, _) => format!("Custom{:?}({})", fixity, symbol),
+            Self::Compare(comparison, _, _) => format!("Compare({})", comparison.symbol()),
+            Self::And(_, _) => "And".into(),
+            Self::Or(_, _) => "Or".into(),
+            Self::Not(_) => "Not".into(),
+            Self::List(_) => "List".into(),
+            Self::Function(name, _) => format!("Function({})", name),
+            Self::Conditional(..) => "Conditional".into(),
+            Self::Series(series, variable, ..) => {
+                format!("Series({}, {})", series.name(), variable)
+            }
+        }
+    }
+
+    pub(crate) fn children(&self) -> Vec<&Node> {
+        match self {
+            Self::Element(_) | Self::Variable(_) | Self::CellRef(_) => vec![],
+            Self::Negative(node)
+            | Self::Percent(node)
+            | Self::Factorial(node)
+            | Self::CustomUnary(_, _, node)
+            | Self::Not(node) => vec![node],
+            Self::Sum(left, right)
+            | Self::Subtract(left, right)
+            | Self::Multiply(left, right)
+            | Self::Divide(left, right)
+            | Self::FloorDivide(left, right)
+            | Self::Modulo(left, right)
+            | Self::Power(left, right)
+            | Self::Compare(_, left, right)
+            | Self::CustomBinary(_, left, right)
+            | Self::And(left, right)
+            | Self::Or(left, right) => vec![left, right],
+            Self::List(items) | Self::Function(_, items) => items.iter().collect(),
+            Self::Conditional(condition, then, otherwise) => vec![condition, then, otherwise],
+            Self::Series(_, _, from, to, body) => vec![from, to, body],
+        }
+    }
+
+    pub(crate) fn children_mut(&mut self) -> Vec<&mut Node> {
         match self {
-            Self::Element(number) => *number,
-            Self::Negative(node) => -node.eval(),
-            Self::Sum(left, right) => left.eval() + right.eval(),
-            Self::Subtract(left, right) => left.eval() - right.eval(),
-            Self::Multiply(left, right) => left.eval() * right.eval(),
-            Self::Divide(left, right) => left.eval() / right.eval(),
-            Self::Power(left, right) => left.eval().powf(right.eval()),
+            Self::Element(_) | Self::Variable(_) | Self::CellRef(_) => vec![],
+            Self::Negative(node)
+            | Self::Percent(node)
+            | Self::Factorial(node)
+            | Self::CustomUnary(_, _, node)
+            | Self::Not(node) => vec![node],
+            Self::Sum(left, right)
+            | Self::Subtract(left, right)
+            | Self::Multiply(left, right)
+            | Self::Divide(left, right)
+            | Self::FloorDivide(left, right)
+            | Self::Modulo(left, right)
+            | Self::Power(left, right)
+            | Self::Compare(_, left, right)
+            | Self::CustomBinary(_, left, right)
+            | Self::And(left, right)
+            | Self::Or(left, right) => vec![left, right],
+            Self::List(items) | Self::Function(_, items) => items.iter_mut().collect(),
+            Self::Conditional(condition, then, otherwise) => vec![condition, then, otherwise],
+            Self::Series(_, _, from, to, body) => vec![from, to, body],
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        let mut count = 0;
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            count += 1;
+            pending.extend(node.children());
+        }
+
+        count
+    }
+}
+
+// Dropping a tree frees its nodes from a stack of its own as well, since
+// the derived drop would recurse once per level.
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_children(&mut pending);
+        while let Some(mut node) = pending.pop() {
+            node.take_children(&mut pending);
+        }
+    }
+}
+
+impl Node {
+    // A node's children cannot be moved out of it by a pattern now that it
+    // has a Drop of its own, so they are taken out instead.
+    pub(crate) fn into_children(mut self) -> Vec<Node> {
+        let mut children = Vec::new();
+        self.take_children(&mut children);
+
+        children
+    }
+
+    fn take_children(&mut self, taken: &mut Vec<Node>) {
+        let mut take = |child: &mut Box<Node>| taken.push(mem::replace(child, Self::Element(0.)));
+
+        match self {
+            Self::Element(_) | Self::Variable(_) | Self::CellRef(_) => {}
+            Self::Negative(node)
+            | Self::Percent(node)
+            | Self::Factorial(node)
+            | Self::CustomUnary(_, _, node)
+            | Self::Not(node) => take(node),
+            Self::Sum(left, right)
+            | Self::Subtract(left, right)
+            | Self::Multiply(left, right)
+            | Self::Divide(left, right)
+            | Self::FloorDivide(left, right)
+            | Self::Modulo(left, right)
+            | Self::Power(left, right)
+            | Self::Compare(_, left, right)
+            | Self::CustomBinary(_, left, right)
+            | Self::And(left, right)
+            | Self::Or(left, right) => {
+                take(left);
+                take(right);
+            }
+            Self::List(items) | Self::Function(_, items) => taken.append(items),
+            Self::Conditional(condition, then, otherwise) => {
+                take(condition);
+                take(then);
+                take(otherwise);
+            }
+            Self::Series(_, _, from, to, body) => {
+                take(from);
+                take(to);
+                take(body);
+            }
         }
     }
 }
@@ -26,6 +854,7 @@ impl Node {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parse_math::eval::{DivisionByZero, ZeroPower};
 
     #[test]
     fn number() {
@@ -68,4 +897,403 @@ mod tests {
         let node = Node::Power(Box::new(Node::Element(3.)), Box::new(Node::Element(4.)));
         assert_eq!(node.eval(), 81.);
     }
+
+    #[test]
+    fn checked_evaluation() {
+        let node = Node::Power(Box::new(Node::Element(2.)), Box::new(Node::Element(10.)));
+        assert_eq!(node.eval_checked(), Ok(Value::Number(1024.)));
+
+        let node = Node::Negative(Box::new(Node::Sum(
+            Box::new(Node::Element(1.)),
+            Box::new(Node::Element(2.)),
+        )));
+        assert_eq!(node.eval_checked(), Ok(Value::Number(-3.)));
+    }
+
+    #[test]
+    fn evaluation_options() {
+        let cube_root = Node::Power(
+            Box::new(Node::Negative(Box::new(Node::Element(8.)))),
+            Box::new(Node::Divide(
+                Box::new(Node::Element(1.)),
+                Box::new(Node::Element(3.)),
+            )),
+        );
+        assert!(cube_root.eval().is_nan());
+        assert!(cube_root
+            .eval_with_options(&EvalOptions::default())
+            .unwrap()
+            .is_nan());
+
+        let options = EvalOptions {
+            real_roots: true,
+            ..EvalOptions::default()
+        };
+        let root = cube_root.eval_with_options(&options).unwrap();
+        assert!((root + 2.).abs() < 1e-12);
+
+        // A context carries the options to the other evaluators.
+        let mut context = Context::new();
+        assert!(cube_root.eval_with(&context).unwrap().is_nan());
+        context.set_options(options);
+        assert!((cube_root.eval_with(&context).unwrap() + 2.).abs() < 1e-12);
+        let root = cube_root.eval_checked_with(&context).unwrap();
+        assert!(matches!(root, Value::Number(root) if (root + 2.).abs() < 1e-12));
+
+        let zero = Node::Power(Box::new(Node::Element(0.)), Box::new(Node::Element(0.)));
+        assert_eq!(zero.eval_with(&context), Ok(1.));
+        context.set_options(EvalOptions {
+            zero_power: ZeroPower::Error,
+            ..context.options()
+        });
+        assert_eq!(zero.eval_with(&context), Err(EvalError::ZeroToZeroPower));
+        assert_eq!(
+            zero.eval_checked_with(&context),
+            Err(EvalError::ZeroToZeroPower)
+        );
+        // Setting the angle mode keeps the other options.
+        context.set_angle_mode(AngleMode::Degrees);
+        assert!(context.options().real_roots);
+    }
+
+    #[test]
+    fn division_by_negative_zero() {
+        let divide = |left: Node| {
+            Node::Divide(
+                Box::new(left),
+                Box::new(Node::Negative(Box::new(Node::Element(0.)))),
+            )
+        };
+        let one = divide(Node::Element(1.));
+        let list = divide(Node::List(vec![Node::Element(1.), Node::Element(-2.)]));
+        let mut context = Context::new();
+
+        // IEEE 754 by default, where the sign of the zero counts.
+        assert_eq!(one.eval(), f64::NEG_INFINITY);
+        assert_eq!(one.eval_with(&context), Ok(f64::NEG_INFINITY));
+        assert_eq!(
+            list.eval_checked_with(&context),
+            Ok(Value::List(vec![f64::NEG_INFINITY, f64::INFINITY]))
+        );
+
+        let options = EvalOptions {
+            division_by_zero: DivisionByZero::Error,
+            ..EvalOptions::default()
+        };
+        context.set_options(options);
+        assert_eq!(
+            one.eval_with_options(&options),
+            Err(EvalError::DivisionByZero)
+        );
+        assert_eq!(one.eval_with(&context), Err(EvalError::DivisionByZero));
+        assert_eq!(
+            list.eval_checked_with(&context),
+            Err(EvalError::DivisionByZero)
+        );
+
+        context.set_options(EvalOptions {
+            division_by_zero: DivisionByZero::NaN,
+            ..options
+        });
+        assert!(one.eval_with(&context).unwrap().is_nan());
+        assert!(matches!(
+            one.eval_checked_with(&context),
+            Ok(Value::Number(number)) if number.is_nan()
+        ));
+        assert!(matches!(
+            list.eval_checked_with(&context),
+            Ok(Value::List(list)) if list.iter().all(|number| number.is_nan())
+        ));
+    }
+
+    #[test]
+    fn angle_modes() {
+        let call =
+            |name: &str, argument| Node::Function(name.into(), vec![Node::Element(argument)]);
+        let degrees = EvalOptions {
+            angle_mode: AngleMode::Degrees,
+            ..EvalOptions::default()
+        };
+        let sine = call("sin", 90.).eval_with_options(&degrees).unwrap();
+        assert!((sine - 1.).abs() < 1e-12);
+        assert_eq!(
+            call("sin", 90.).eval_with_options(&EvalOptions::default()),
+            Ok(90f64.sin())
+        );
+        assert_eq!(call("sin", 90.).eval(), 90f64.sin());
+        assert_eq!(call("asin", 1.).eval_with_options(&degrees), Ok(90.));
+
+        let mut context = Context::new();
+        assert_eq!(
+            call("asin", 1.).eval_with(&context),
+            Ok(std::f64::consts::FRAC_PI_2)
+        );
+        context.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(call("asin", 1.).eval_with(&context), Ok(90.));
+        assert_eq!(
+            call("acos", 0.).eval_checked_in(AngleMode::Degrees),
+            Ok(Value::Number(90.))
+        );
+    }
+
+    #[test]
+    fn matrix_evaluation() {
+        let matrix = |rows: &[[f64; 2]]| {
+            Node::List(
+                rows.iter()
+                    .map(|row| Node::List(row.iter().map(|x| Node::Element(*x)).collect()))
+                    .collect(),
+            )
+        };
+        let a = matrix(&[[1., 2.], [3., 4.]]);
+        let product = Node::Multiply(Box::new(a.clone()), Box::new(a.clone()));
+        assert_eq!(
+            product.eval_checked().unwrap().to_string(),
+            "[7, 10]\n[15, 22]"
+        );
+
+        let scaled = Node::Multiply(Box::new(Node::Element(2.)), Box::new(a.clone()));
+        assert_eq!(scaled.eval_checked().unwrap().to_string(), "[2, 4]\n[6, 8]");
+
+        let row = Node::List(vec![Node::List(vec![Node::Element(1.), Node::Element(2.)])]);
+        let mismatch = Node::Multiply(Box::new(row.clone()), Box::new(row));
+        assert_eq!(
+            mismatch.eval_checked().unwrap_err().to_string(),
+            "Shape mismatch: cannot apply matrix product to 1x2 and 1x2"
+        );
+
+        let mixed = Node::List(vec![Node::Element(1.), a]);
+        assert_eq!(
+            mixed.eval_checked().unwrap_err().to_string(),
+            "Type mismatch: cannot apply list element to matrix"
+        );
+        assert!(product.eval().is_nan());
+        assert_eq!(
+            product.eval_with(&Context::new()),
+            Err(product.not_a_number())
+        );
+    }
+
+    #[test]
+    fn variables() {
+        let x = || Box::new(Node::Variable("x".into()));
+        let node = Node::Sum(
+            Box::new(Node::Multiply(x(), Box::new(Node::Variable("y".into())))),
+            x(),
+        );
+        assert_eq!(node.variables(), vec!["x", "y"]);
+        assert!(node.eval().is_nan());
+        assert_eq!(
+            node.eval_checked(),
+            Err(EvalError::UnknownVariable("x".into()))
+        );
+
+        let mut context = Context::new();
+        context.set("x", 2.);
+        assert_eq!(
+            node.eval_with(&context),
+            Err(EvalError::UnknownVariable("y".into()))
+        );
+        context.set("y", 5.);
+        assert_eq!(node.eval_with(&context), Ok(12.));
+        assert_eq!(
+            node.to_tree_string(),
+            "Sum\n├ Multiply\n│ ├ Variable(x)\n│ └ Variable(y)\n└ Variable(x)\n"
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let element = |number| Box::new(Node::Element(number));
+        let unknown = || Box::new(Node::Variable("y".into()));
+        let failing = || Box::new(Node::Factorial(element(-1.)));
+        let conditional =
+            |condition, then, otherwise| Node::Conditional(condition, then, otherwise);
+
+        // The branch not taken is not evaluated, so neither the unknown
+        // variable nor the factorial of -1 fails.
+        let context = Context::new();
+        let node = conditional(element(0.), unknown(), element(2.));
+        assert_eq!(node.eval_with(&context), Ok(2.));
+        assert_eq!(node.eval_with_options(&EvalOptions::default()), Ok(2.));
+        assert_eq!(node.eval(), 2.);
+        let node = conditional(element(-3.), element(1.), failing());
+        assert_eq!(node.eval_with(&context), Ok(1.));
+        assert_eq!(
+            conditional(element(0.), element(1.), failing()).eval_with(&context),
+            Err(EvalError::Domain {
+                operation: "factorial",
+                value: -1.,
+            })
+        );
+        assert_eq!(
+            conditional(element(f64::NAN), element(1.), element(2.)).eval(),
+            1.
+        );
+
+        // A condition has to be a boolean when evaluating with types.
+        let error = conditional(element(1.), element(1.), element(2.))
+            .eval_checked()
+            .unwrap_err();
+        assert_eq!(error.code(), "type_mismatch");
+
+        let nested = conditional(
+            element(1.),
+            Box::new(conditional(element(0.), failing(), element(5.))),
+            failing(),
+        );
+        assert_eq!(nested.eval_with(&context), Ok(5.));
+        assert_eq!(nested.node_count(), 9);
+        assert_eq!(nested.to_tree_string().lines().next(), Some("Conditional"));
+    }
+
+    #[test]
+    fn comparisons() {
+        let compare = |comparison, left, right| {
+            Node::Compare(
+                comparison,
+                Box::new(Node::Element(left)),
+                Box::new(Node::Element(right)),
+            )
+        };
+        let context = Context::new();
+        assert_eq!(compare(Comparison::Less, 1., 2.).eval(), 1.);
+        assert_eq!(compare(Comparison::GreaterOrEqual, 1., 2.).eval(), 0.);
+        assert_eq!(
+            compare(Comparison::Equal, 2., 2.).eval_with(&context),
+            Ok(1.)
+        );
+        assert_eq!(
+            compare(Comparison::NotEqual, 2., 2.).eval_with_options(&EvalOptions::default()),
+            Ok(0.)
+        );
+        assert_eq!(compare(Comparison::Less, f64::NAN, 1.).eval(), 0.);
+
+        // With types, a comparison gives a boolean, which a condition needs
+        // and arithmetic rejects.
+        assert_eq!(
+            compare(Comparison::Greater, 3., 2.).eval_checked(),
+            Ok(Value::Bool(true))
+        );
+        let conditional = Node::Conditional(
+            Box::new(compare(Comparison::LessOrEqual, 3., 2.)),
+            Box::new(Node::Element(1.)),
+            Box::new(Node::Element(2.)),
+        );
+        assert_eq!(conditional.eval_checked(), Ok(Value::Number(2.)));
+        let sum = Node::Sum(
+            Box::new(compare(Comparison::Less, 1., 2.)),
+            Box::new(Node::Element(1.)),
+        );
+        assert_eq!(sum.eval_checked().unwrap_err().code(), "type_mismatch");
+        let list = Node::Compare(
+            Comparison::Equal,
+            Box::new(Node::List(vec![Node::Element(1.)])),
+            Box::new(Node::Element(1.)),
+        );
+        assert_eq!(list.eval_checked().unwrap_err().code(), "type_mismatch");
+        assert_eq!(list.to_tree_string().lines().next(), Some("Compare(==)"));
+    }
+
+    #[test]
+    fn logical_operators() {
+        let element = |number| Box::new(Node::Element(number));
+        let less = |left, right| Box::new(Node::Compare(Comparison::Less, left, right));
+        let failing = || Box::new(Node::Factorial(element(-1.)));
+
+        let context = Context::new();
+        assert_eq!(
+            Node::And(element(0.), failing()).eval_with(&context),
+            Ok(0.)
+        );
+        assert_eq!(Node::Or(element(2.), failing()).eval_with(&context), Ok(1.));
+        assert_eq!(Node::And(element(2.), element(-3.)).eval(), 1.);
+        assert_eq!(Node::Or(element(0.), element(0.)).eval(), 0.);
+        assert_eq!(Node::Not(element(0.)).eval(), 1.);
+        assert!(Node::Or(element(0.), failing())
+            .eval_with(&context)
+            .is_err());
+
+        // With types, the operands have to be booleans.
+        let both = Node::And(
+            less(element(1.), element(2.)),
+            less(element(3.), element(2.)),
+        );
+        assert_eq!(both.eval_checked(), Ok(Value::Bool(false)));
+        assert_eq!(
+            Node::Not(Box::new(both)).eval_checked(),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            Node::Or(less(element(1.), element(2.)), element(7.)).eval_checked(),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            Node::Or(element(1.), less(element(1.), element(2.))).eval_checked(),
+            Err(EvalError::TypeMismatch {
+                operation: "||",
+                operands: vec!["number"],
+            })
+        );
+        assert_eq!(
+            Node::Not(element(1.)).eval_checked().unwrap_err().code(),
+            "type_mismatch"
+        );
+
+        let node = Node::Or(element(0.), Box::new(Node::Not(element(1.))));
+        assert_eq!(node.node_count(), 4);
+        assert_eq!(
+            node.to_tree_string(),
+            "Or\n├ Element(0)\n└ Not\n  └ Element(1)\n"
+        );
+    }
+
+    #[test]
+    fn deep_trees() {
+        let mut sum = Node::Element(0.);
+        let mut negation = Node::Element(1.);
+        for _ in 0..1_000_000 {
+            sum = Node::Sum(Box::new(sum), Box::new(Node::Element(1.)));
+            negation = Node::Negative(Box::new(negation));
+        }
+
+        assert_eq!(sum.eval(), 1_000_000.);
+        assert_eq!(sum.eval_with(&Context::new()), Ok(1_000_000.));
+        assert_eq!(sum.eval_checked(), Ok(Value::Number(1_000_000.)));
+        assert_eq!(negation.eval(), 1.);
+        assert_eq!(negation.eval_with_options(&EvalOptions::default()), Ok(1.));
+    }
+
+    #[test]
+    fn node_count() {
+        let left = Node::Negative(Box::new(Node::Element(3.)));
+        let node = Node::Power(Box::new(left), Box::new(Node::Element(4.)));
+        assert_eq!(node.node_count(), 4);
+    }
+
+    #[test]
+    fn tree_string() {
+        let right = Node::Multiply(Box::new(Node::Element(2.)), Box::new(Node::Element(3.)));
+        let node = Node::Sum(Box::new(Node::Element(1.)), Box::new(right));
+        assert_eq!(
+            node.to_tree_string(),
+            "Sum\n├ Element(1)\n└ Multiply\n  ├ Element(2)\n  └ Element(3)\n"
+        );
+    }
+
+    #[test]
+    fn tree_string_nested_left() {
+        let negative = Node::Negative(Box::new(Node::Element(2.5)));
+        let left = Node::Power(Box::new(negative), Box::new(Node::Element(2.)));
+        let node = Node::Divide(Box::new(left), Box::new(Node::Element(4.)));
+        assert_eq!(
+            node.to_tree_string(),
+            "Divide\n├ Power\n│ ├ Negative\n│ │ └ Element(2.5)\n│ └ Element(2)\n└ Element(4)\n"
+        );
+    }
+
+    #[test]
+    fn tree_string_single_element() {
+        assert_eq!(Node::Element(7.).to_tree_string(), "Element(7)\n");
+    }
 }
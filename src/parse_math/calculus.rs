@@ -0,0 +1,1072 @@
+use super::ast::{self, Node, Series};
+use super::context::{self, AngleMode, Context};
+use super::errors::{CalculusError, EvalError};
+use super::eval::{self, EvalOptions};
+use super::function;
+use std::collections::HashMap;
+
+pub const MAX_NODE_COUNT: usize = 10_000;
+const MAX_POLYNOMIAL_POWER: f64 = 64.;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TaylorForm {
+    // Powers of (x - x0).
+    Centered,
+    // Powers of x.
+    Expanded,
+}
+
+impl Node {
+    pub fn differentiate(&self, variable: &str) -> Result<Node, CalculusError> {
+        Ok(self.derivative(variable)?.simplify())
+    }
+
+    pub fn differentiate_n(&self, variable: &str, n: usize) -> Result<Node, CalculusError> {
+        (0..n).try_fold(self.simplify(), |node, _| node.checked_derivative(variable))
+    }
+
+    // Products double in size with every derivative, so each step is
+    // simplified and then held to MAX_NODE_COUNT.
+    fn checked_derivative(&self, variable: &str) -> Result<Node, CalculusError> {
+        let derivative = self.differentiate(variable)?;
+        if derivative.node_count() > MAX_NODE_COUNT {
+            return Err(CalculusError::TooLarge(MAX_NODE_COUNT));
+        }
+
+        Ok(derivative)
+    }
+
+    fn derivative(&self, variable: &str) -> Result<Node, CalculusError> {
+        let derivative = match self {
+            Node::Element(_) => Node::Element(0.),
+            Node::Variable(name) if name == variable => Node::Element(1.),
+            Node::Variable(_) | Node::CellRef(_) => Node::Element(0.),
+            Node::Negative(node) => Node::Negative(Box::new(node.derivative(variable)?)),
+            Node::Percent(node) => Node::Percent(Box::new(node.derivative(variable)?)),
+            Node::Factorial(node) if !node.depends_on(variable) => Node::Element(0.),
+            Node::Factorial(_) => {
+                return Err(CalculusError::Unsupported(
+                    "a factorial of the variable".into(),
+                ))
+            }
+            Node::Sum(left, right) => Node::Sum(
+                Box::new(left.derivative(variable)?),
+                Box::new(right.derivative(variable)?),
+            ),
+            Node::Subtract(left, right) => Node::Subtract(
+                Box::new(left.derivative(variable)?),
+                Box::new(right.derivative(variable)?),
+            ),
+            Node::Multiply(left, right) => Node::Sum(
+                Box::new(Node::Multiply(
+                    Box::new(left.derivative(variable)?),
+                    right.clone(),
+                )),
+                Box::new(Node::Multiply(
+                    left.clone(),
+                    Box::new(right.derivative(variable)?),
+                )),
+            ),
+            Node::Divide(left, right) => Node::Divide(
+                Box::new(Node::Subtract(
+                    Box::new(Node::Multiply(
+                        Box::new(left.derivative(variable)?),
+                        right.clone(),
+                    )),
+                    Box::new(Node::Multiply(
+                        left.clone(),
+                        Box::new(right.derivative(variable)?),
+                    )),
+                )),
+                Box::new(Node::Power(right.clone(), Box::new(Node::Element(2.)))),
+            ),
+            // A floored quotient is constant between its jumps, as is a
+            // comparison or a logical operator.
+            Node::FloorDivide(_, _)
+            | Node::Compare(..)
+            | Node::And(..)
+            | Node::Or(..)
+            | Node::Not(_) => Node::Element(0.),
+            // u % c = u - c * trunc(u / c) has the slope of u away from the
+            // jumps; a varying divisor would need trunc as a node.
+            Node::Modulo(left, right) if !right.depends_on(variable) => {
+                left.derivative(variable)?
+            }
+            Node::Modulo(_, _) => {
+                return Err(CalculusError::Unsupported(
+                    "a remainder with a varying divisor".into(),
+                ))
+            }
+            Node::Power(base, exponent) if !exponent.depends_on(variable) => Node::Multiply(
+                Box::new(Node::Multiply(
+                    exponent.clone(),
+                    Box::new(Node::Power(
+                        base.clone(),
+                        Box::new(Node::Subtract(
+                            exponent.clone(),
+                            Box::new(Node::Element(1.)),
+                        )),
+                    )),
+                )),
+                Box::new(base.derivative(variable)?),
+            ),
+            // d/dx c^v = c^v * ln(c) * v' for a positive constant c. There is
+            // no logarithm node, so other bases cannot be expressed.
+            Node::Power(base, exponent) => match base.as_ref() {
+                Node::Element(constant) if *constant > 0. => Node::Multiply(
+                    Box::new(Node::Multiply(
+                        Box::new(self.clone()),
+                        Box::new(Node::Element(constant.ln())),
+                    )),
+                    Box::new(exponent.derivative(variable)?),
+                ),
+                _ => {
+                    return Err(CalculusError::Unsupported(
+                        "a power whose base and exponent both vary".into(),
+                    ))
+                }
+            },
+            Node::List(_) => return Err(CalculusError::Unsupported("a list".into())),
+            Node::Function(_, _)
+            | Node::CustomBinary(..)
+            | Node::CustomUnary(..)
+            | Node::Series(..)
+                if !self.depends_on(variable) =>
+            {
+                Node::Element(0.)
+            }
+            Node::CustomBinary(symbol, _, _) | Node::CustomUnary(_, symbol, _) => {
+                return Err(unsupported_operator(symbol))
+            }
+            Node::Function(name, arguments) => call_derivative(name, arguments, variable)?,
+            // Each branch's derivative where that branch is taken, which
+            // leaves out where the condition changes.
+            Node::Conditional(condition, then, otherwise) => Node::Conditional(
+                condition.clone(),
+                Box::new(then.derivative(variable)?),
+                Box::new(otherwise.derivative(variable)?),
+            ),
+            // The derivative of a sum is the sum of the derivatives of its
+            // terms, as long as how many there are does not vary.
+            Node::Series(Series::Sum, index, from, to, body)
+                if !from.depends_on(variable) && !to.depends_on(variable) =>
+            {
+                Node::Series(
+                    Series::Sum,
+                    index.clone(),
+                    from.clone(),
+                    to.clone(),
+                    Box::new(body.derivative(variable)?),
+                )
+            }
+            Node::Series(series, _, _, _, _) => {
+                return Err(CalculusError::Unsupported(format!(
+                    "{} of the variable",
+                    series.name()
+                )))
+            }
+        };
+
+        Ok(derivative)
+    }
+
+    pub fn gradient(&self, variables: &[&str]) -> Result<Vec<Node>, CalculusError> {
+        variables
+            .iter()
+            .map(|variable| self.checked_derivative(variable))
+            .collect()
+    }
+
+    // One forward-mode pass per variable over (value, derivative) pairs, so
+    // no derivative trees are built.
+    pub fn gradient_at(
+        &self,
+        variables: &[&str],
+        context: &Context,
+    ) -> Result<Vec<f64>, CalculusError> {
+        variables
+            .iter()
+            .map(|variable| Ok(self.dual(variable, context)?.1))
+            .collect()
+    }
+
+    // What has no derivative is an error, as it is for derivative, rather
+    // than a NaN slope.
+    fn dual(&self, variable: &str, context: &Context) -> Result<(f64, f64), CalculusError> {
+        let dual = match self {
+            Node::Element(number) => (*number, 0.),
+            Node::Variable(name) => {
+                let value = context
+                    .get(name)
+                    .ok_or_else(|| EvalError::UnknownVariable(name.clone()))?;
+                (value, if name == variable { 1. } else { 0. })
+            }
+            Node::CellRef(cell) => return Err(EvalError::UnknownCell(cell.clone()).into()),
+            Node::Negative(node) => {
+                let (u, du) = node.dual(variable, context)?;
+                (-u, -du)
+            }
+            Node::Percent(node) => {
+                let (u, du) = node.dual(variable, context)?;
+                (u / 100., du / 100.)
+            }
+            // Factorials only exist at integers, so one that varies has no
+            // derivative.
+            Node::Factorial(node) => match node.dual(variable, context)? {
+                (u, 0.) => (eval::factorial(u)?, 0.),
+                _ => {
+                    return Err(CalculusError::Unsupported(
+                        "a factorial of the variable".into(),
+                    ))
+                }
+            },
+            Node::Sum(left, right) => {
+                let ((u, du), (v, dv)) = (
+                    left.dual(variable, context)?,
+                    right.dual(variable, context)?,
+                );
+                (u + v, du + dv)
+            }
+            Node::Subtract(left, right) => {
+                let ((u, du), (v, dv)) = (
+                    left.dual(variable, context)?,
+                    right.dual(variable, context)?,
+                );
+                (u - v, du - dv)
+            }
+            Node::Multiply(left, right) => {
+                let ((u, du), (v, dv)) = (
+                    left.dual(variable, context)?,
+                    right.dual(variable, context)?,
+                );
+                (u * v, du * v + u * dv)
+            }
+            Node::Divide(left, right) => {
+                let ((u, du), (v, dv)) = (
+                    left.dual(variable, context)?,
+                    right.dual(variable, context)?,
+                );
+                (u / v, (du * v - u * dv) / (v * v))
+            }
+            Node::FloorDivide(left, right) => {
+                let (u, v) = (
+                    left.dual(variable, context)?.0,
+                    right.dual(variable, context)?.0,
+                );
+                ((u / v).floor(), 0.)
+            }
+            Node::Compare(comparison, left, right) => {
+                let (u, v) = (
+                    left.dual(variable, context)?.0,
+                    right.dual(variable, context)?.0,
+                );
+                (comparison.value(u, v), 0.)
+            }
+            Node::And(left, right) | Node::Or(left, right) => {
+                let or = matches!(self, Node::Or(..));
+                let mut holds = left.dual(variable, context)?.0 != 0.;
+                if holds != or {
+                    holds = right.dual(variable, context)?.0 != 0.;
+                }
+                (ast::truth(holds), 0.)
+            }
+            Node::Not(node) => (ast::truth(node.dual(variable, context)?.0 == 0.), 0.),
+            Node::Modulo(left, right) => {
+                let ((u, du), (v, dv)) = (
+                    left.dual(variable, context)?,
+                    right.dual(variable, context)?,
+                );
+                (u % v, du - dv * (u / v).trunc())
+            }
+            Node::Power(left, right) => {
+                let ((u, du), (v, dv)) = (
+                    left.dual(variable, context)?,
+                    right.dual(variable, context)?,
+                );
+                let power = u.powf(v);
+                let derivative = if dv == 0. {
+                    v * u.powf(v - 1.) * du
+                } else {
+                    power * (dv * u.ln() + v * du / u)
+                };
+                (power, derivative)
+            }
+            Node::List(_) => return Err(self.not_a_number().into()),
+            // The chain rule, summed over the arguments that vary.
+            Node::Function(name, arguments) => {
+                let duals = arguments
+                    .iter()
+                    .map(|argument| argument.dual(variable, context))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let values: Vec<f64> = duals.iter().map(|(u, _)| *u).collect();
+                let value = function::call(name, &values, context.angle_mode())?;
+                if duals.iter().all(|(_, du)| *du == 0.) {
+                    (value, 0.)
+                } else {
+                    let partials = function::lookup(name)
+                        .and_then(|function| function.partials(&values, context.angle_mode()))
+                        .ok_or_else(|| {
+                            CalculusError::Unsupported(format!("{} of the variable", name))
+                        })?;
+                    let slope = partials
+                        .iter()
+                        .zip(&duals)
+                        .filter(|(_, (_, du))| *du != 0.)
+                        .map(|(partial, (_, du))| partial * du)
+                        .sum();
+                    (value, slope)
+                }
+            }
+            Node::CustomBinary(symbol, left, right) => {
+                let (u, du) = left.dual(variable, context)?;
+                let (v, dv) = right.dual(variable, context)?;
+                let value = ast::operator(symbol, context)?.apply(u, v);
+                if du != 0. || dv != 0. {
+                    return Err(unsupported_operator(symbol));
+                }
+                (value, 0.)
+            }
+            Node::CustomUnary(fixity, symbol, node) => {
+                let (u, du) = node.dual(variable, context)?;
+                let value = ast::unary_operator(*fixity, symbol, context)?.apply(u);
+                if du != 0. {
+                    return Err(unsupported_operator(symbol));
+                }
+                (value, 0.)
+            }
+            Node::Conditional(condition, then, otherwise) => {
+                if condition.dual(variable, context)?.0 != 0. {
+                    then.dual(variable, context)?
+                } else {
+                    otherwise.dual(variable, context)?
+                }
+            }
+            // Bounds are integers, so as with floored quotients the number
+            // of terms is constant between its jumps.
+            Node::Series(series, index, from, to, body) => {
+                let (from, to) = (
+                    from.dual(variable, context)?.0,
+                    to.dual(variable, context)?.0,
+                );
+                // The index hides the variable in the body, where nothing
+                // then varies with it.
+                let variable = if index == variable { "" } else { variable };
+                let mut inner = context.clone();
+                let (mut total, mut slope) = (series.empty(), 0.);
+                for value in series.indices(from, to)? {
+                    inner.set(index, value);
+                    let (u, du) = body.dual(variable, &inner)?;
+                    slope = match series {
+                        Series::Sum => slope + du,
+                        Series::Product => slope * u + total * du,
+                    };
+                    total = series.combine(total, u);
+                }
+                (total, slope)
+            }
+        };
+
+        Ok(dual)
+    }
+
+    fn depends_on(&self, variable: &str) -> bool {
+        self.variables().iter().any(|name| name == variable)
+    }
+}
+
+fn unsupported_operator(symbol: &str) -> CalculusError {
+    CalculusError::Unsupported(format!("the operator {} of the variable", symbol))
+}
+
+// The chain rule for a call of a built-in, with angles in radians as eval
+// has them. max and min, which have none where their arguments cross,
+// would need a condition and are left out.
+fn call_derivative(name: &str, arguments: &[Node], variable: &str) -> Result<Node, CalculusError> {
+    let boxed = |node: Node| Box::new(node);
+    let call =
+        |name: &str, argument: &Node| Node::Function(name.to_string(), vec![argument.clone()]);
+    let square = |node: &Node| Node::Power(boxed(node.clone()), boxed(Node::Element(2.)));
+    let reciprocal = |node: Node| Node::Divide(boxed(Node::Element(1.)), boxed(node));
+    let derivatives = || {
+        arguments
+            .iter()
+            .map(|argument| argument.derivative(variable))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let total = |terms: Vec<Node>| {
+        terms
+            .into_iter()
+            .reduce(|sum, term| Node::Sum(boxed(sum), boxed(term)))
+            .unwrap_or(Node::Element(0.))
+    };
+
+    let derivative = match (name, arguments) {
+        ("SUM", _) => total(derivatives()?),
+        ("AVERAGE", _) => Node::Divide(
+            boxed(total(derivatives()?)),
+            boxed(Node::Element(arguments.len() as f64)),
+        ),
+        ("POWER", [base, exponent]) => {
+            Node::Power(boxed(base.clone()), boxed(exponent.clone())).derivative(variable)?
+        }
+        // d atan2(y, x) = (x dy - y dx) / (x^2 + y^2)
+        ("atan2", [y, x]) => Node::Divide(
+            boxed(Node::Subtract(
+                boxed(Node::Multiply(
+                    boxed(x.clone()),
+                    boxed(y.derivative(variable)?),
+                )),
+                boxed(Node::Multiply(
+                    boxed(y.clone()),
+                    boxed(x.derivative(variable)?),
+                )),
+            )),
+            boxed(Node::Sum(boxed(square(x)), boxed(square(y)))),
+        ),
+        ("log", [u, base]) => {
+            Node::Divide(boxed(call("ln", u)), boxed(call("ln", base))).derivative(variable)?
+        }
+        (_, [u]) => {
+            let outer = match name {
+                "sin" => call("cos", u),
+                "cos" => Node::Negative(boxed(call("sin", u))),
+                "tan" => reciprocal(square(&call("cos", u))),
+                "asin" | "acos" => {
+                    let one_minus_square =
+                        Node::Subtract(boxed(Node::Element(1.)), boxed(square(u)));
+                    let slope = reciprocal(call("sqrt", &one_minus_square));
+                    match name {
+                        "asin" => slope,
+                        _ => Node::Negative(boxed(slope)),
+                    }
+                }
+                "atan" => reciprocal(Node::Sum(boxed(Node::Element(1.)), boxed(square(u)))),
+                "exp" => call("exp", u),
+                "ln" => reciprocal(u.clone()),
+                "log" => reciprocal(Node::Multiply(
+                    boxed(u.clone()),
+                    boxed(Node::Element(std::f64::consts::LN_10)),
+                )),
+                "sqrt" => Node::Divide(boxed(Node::Element(0.5)), boxed(call("sqrt", u))),
+                "abs" => Node::Divide(boxed(u.clone()), boxed(call("abs", u))),
+                _ => {
+                    return Err(CalculusError::Unsupported(format!(
+                        "{} of the variable",
+                        name
+                    )))
+                }
+            };
+            Node::Multiply(boxed(outer), boxed(u.derivative(variable)?))
+        }
+        _ => {
+            return Err(CalculusError::Unsupported(format!(
+                "{} of the variable",
+                name
+            )))
+        }
+    };
+
+    Ok(derivative)
+}
+
+// Remembers every derivative of one expression it has computed. Mixed
+// partial derivatives are assumed to commute, so the order of the variables
+// does not matter and d/dx d/dy reuses d/dy d/dx.
+pub struct DerivativeCache {
+    node: Node,
+    derivatives: HashMap<Vec<String>, Node>,
+}
+
+impl DerivativeCache {
+    pub fn new(node: &Node) -> Self {
+        DerivativeCache {
+            node: node.simplify(),
+            derivatives: HashMap::new(),
+        }
+    }
+
+    pub fn derivative(&mut self, variables: &[&str]) -> Result<&Node, CalculusError> {
+        let mut key: Vec<String> = variables.iter().map(|name| name.to_string()).collect();
+        key.sort();
+
+        for length in 1..=key.len() {
+            let prefix = &key[..length];
+            if self.derivatives.contains_key(prefix) {
+                continue;
+            }
+
+            let previous = match length {
+                1 => &self.node,
+                _ => &self.derivatives[&key[..length - 1]],
+            };
+            let derivative = previous.checked_derivative(&key[length - 1])?;
+            self.derivatives.insert(prefix.to_vec(), derivative);
+        }
+
+        Ok(self.derivatives.get(&key).unwrap_or(&self.node))
+    }
+
+    pub fn computed(&self) -> usize {
+        self.derivatives.len()
+    }
+}
+
+// Builds sum f^(k)(x0) / k! * (x - x0)^k for k up to `order`.
+pub fn taylor(
+    node: &Node,
+    variable: &str,
+    center: f64,
+    order: usize,
+    form: TaylorForm,
+) -> Result<Node, CalculusError> {
+    let mut context = Context::new();
+    context.set(variable, center);
+
+    let mut coefficients = Vec::with_capacity(order + 1);
+    let mut derivative = node.simplify();
+    let mut factorial = 1.;
+    for k in 0..=order {
+        if k > 0 {
+            derivative = derivative.checked_derivative(variable)?;
+            factorial *= k as f64;
+        }
+        coefficients.push(derivative.eval_with(&context)? / factorial);
+    }
+
+    let polynomial = match form {
+        TaylorForm::Centered => {
+            let base = Node::Subtract(
+                Box::new(Node::Variable(variable.to_string())),
+                Box::new(Node::Element(center)),
+            );
+            build_polynomial(&coefficients, base)
+        }
+        TaylorForm::Expanded => build_polynomial(
+            &shift(&coefficients, center),
+            Node::Variable(variable.to_string()),
+        ),
+    };
+
+    Ok(polynomial.simplify())
+}
+
+// Coefficients of `node` as a polynomial in `variable`, lowest power first
+// and without trailing zeros. None when the expression is not a polynomial
+// in that variable or mentions another variable.
+pub fn as_polynomial(node: &Node, variable: &str) -> Option<Vec<f64>> {
+    let mut coefficients = polynomial(node, variable)?;
+    while coefficients.len() > 1 && coefficients.last() == Some(&0.) {
+        coefficients.pop();
+    }
+
+    Some(coefficients)
+}
+
+fn polynomial(node: &Node, variable: &str) -> Option<Vec<f64>> {
+    let coefficients = match node {
+        Node::Element(number) => vec![*number],
+        Node::Variable(name) if name == variable => vec![0., 1.],
+        Node::Variable(name) => vec![context::constant(name)?],
+        Node::CellRef(_) => return None,
+        Node::Negative(node) => polynomial(node, variable)?
+            .into_iter()
+            .map(|c| -c)
+            .collect(),
+        Node::Percent(node) => polynomial(node, variable)?
+            .into_iter()
+            .map(|c| c / 100.)
+            .collect(),
+        Node::Modulo(left, right) => {
+            match (
+                as_polynomial(left, variable)?.as_slice(),
+                as_polynomial(right, variable)?.as_slice(),
+            ) {
+                ([a], [b]) => vec![a % b],
+                _ => return None,
+            }
+        }
+        Node::FloorDivide(left, right) => {
+            match (
+                as_polynomial(left, variable)?.as_slice(),
+                as_polynomial(right, variable)?.as_slice(),
+            ) {
+                ([a], [b]) => vec![(a / b).floor()],
+                _ => return None,
+            }
+        }
+        Node::Factorial(node) => match as_polynomial(node, variable)?.as_slice() {
+            [constant] => vec![eval::factorial(*constant).ok()?],
+            _ => return None,
+        },
+        Node::Sum(left, right) => add(&polynomial(left, variable)?, &polynomial(right, variable)?),
+        Node::Subtract(left, right) => {
+            let right: Vec<f64> = polynomial(right, variable)?.iter().map(|c| -c).collect();
+            add(&polynomial(left, variable)?, &right)
+        }
+        Node::Multiply(left, right) => {
+            multiply(&polynomial(left, variable)?, &polynomial(right, variable)?)
+        }
+        Node::Divide(left, right) => match as_polynomial(right, variable)?.as_slice() {
+            [divisor] => polynomial(left, variable)?
+                .into_iter()
+                .map(|c| c / divisor)
+                .collect(),
+            _ => return None,
+        },
+        Node::Power(base, exponent) => {
+            let exponent = match as_polynomial(exponent, variable)?.as_slice() {
+                [exponent] => *exponent,
+                _ => return None,
+            };
+            if exponent < 0. || exponent.fract() != 0. || exponent > MAX_POLYNOMIAL_POWER {
+                return None;
+            }
+
+            let base = polynomial(base, variable)?;
+            (0..exponent as usize).fold(vec![1.], |power, _| multiply(&power, &base))
+        }
+        Node::List(_) | Node::CustomBinary(..) | Node::CustomUnary(..) => return None,
+        // A series that does not vary is a number like any other constant.
+        Node::Series(..) if !node.depends_on(variable) => {
+            vec![node.eval_with_options(&EvalOptions::default()).ok()?]
+        }
+        Node::Series(..) => return None,
+        Node::Function(name, arguments) => {
+            let arguments = arguments
+                .iter()
+                .map(
+                    |argument| match as_polynomial(argument, variable)?.as_slice() {
+                        [constant] => Some(*constant),
+                        _ => None,
+                    },
+                )
+                .collect::<Option<Vec<_>>>()?;
+            vec![function::call(name, &arguments, AngleMode::Radians).ok()?]
+        }
+        Node::Compare(comparison, left, right) => {
+            match (
+                polynomial(left, variable)?.as_slice(),
+                polynomial(right, variable)?.as_slice(),
+            ) {
+                ([u], [v]) => vec![comparison.value(*u, *v)],
+                _ => return None,
+            }
+        }
+        Node::And(left, right) | Node::Or(left, right) => {
+            match (
+                polynomial(left, variable)?.as_slice(),
+                polynomial(right, variable)?.as_slice(),
+            ) {
+                ([u], [v]) if matches!(node, Node::And(..)) => {
+                    vec![ast::truth(*u != 0. && *v != 0.)]
+                }
+                ([u], [v]) => vec![ast::truth(*u != 0. || *v != 0.)],
+                _ => return None,
+            }
+        }
+        Node::Not(node) => match polynomial(node, variable)?.as_slice() {
+            [u] => vec![ast::truth(*u == 0.)],
+            _ => return None,
+        },
+        Node::Conditional(condition, then, otherwise) => {
+            match as_polynomial(condition, variable)?.as_slice() {
+                [0.] => polynomial(otherwise, variable)?,
+                [_] => polynomial(then, variable)?,
+                _ => return None,
+            }
+        }
+    };
+
+    Some(coefficients)
+}
+
+fn add(left: &[f64], right: &[f64]) -> Vec<f64> {
+    (0..left.len().max(right.len()))
+        .map(|i| left.get(i).unwrap_or(&0.) + right.get(i).unwrap_or(&0.))
+        .collect()
+}
+
+fn multiply(left: &[f64], right: &[f64]) -> Vec<f64> {
+    let mut product = vec![0.; left.len() + right.len() - 1];
+    for (i, a) in left.iter().enumerate() {
+        for (j, b) in right.iter().enumerate() {
+            product[i + j] += a * b;
+        }
+    }
+
+    product
+}
+
+// Rewrites sum c_k (x - x0)^k as a polynomial in x.
+fn shift(coefficients: &[f64], center: f64) -> Vec<f64> {
+    let base = [-center, 1.];
+    coefficients
+        .iter()
+        .enumerate()
+        .fold(vec![0.], |sum, (k, coefficient)| {
+            let power = (0..k).fold(vec![1.], |power, _| multiply(&power, &base));
+            let term: Vec<f64> = power.iter().map(|c| c * coefficient).collect();
+            add(&sum, &term)
+        })
+}
+
+fn build_polynomial(coefficients: &[f64], base: Node) -> Node {
+    let terms = coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, coefficient)| **coefficient != 0.)
+        .map(|(k, coefficient)| {
+            let power = Node::Power(Box::new(base.clone()), Box::new(Node::Element(k as f64)));
+            Node::Multiply(Box::new(Node::Element(*coefficient)), Box::new(power))
+        });
+
+    terms
+        .reduce(|sum, term| Node::Sum(Box::new(sum), Box::new(term)))
+        .unwrap_or(Node::Element(0.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_math::errors::EvalError;
+    use crate::parse_math::parser::Parser;
+
+    fn parse(input: &str) -> Node {
+        Parser::new(input).parse().unwrap()
+    }
+
+    fn at(node: &Node, x: f64) -> f64 {
+        let mut context = Context::new();
+        context.set("x", x);
+        context.set("y", 2.);
+        node.eval_with(&context).unwrap()
+    }
+
+    #[test]
+    fn derivatives() {
+        let cases = [
+            ("x^3", "3*x^2"),
+            ("2*x + 5", "2"),
+            ("x*y", "y"),
+            ("1/x", "-1/x^2"),
+            ("(x+1)^0.5", "0.5*(x+1)^-0.5"),
+            ("-x", "-1"),
+            ("2^x", "2^x*0.6931471805599453"),
+            ("sin(x^2)", "cos(x^2)*2*x"),
+            ("cos(2*x)", "-2*sin(2*x)"),
+            ("tan(x)", "1/cos(x)^2"),
+            ("asin(x/4)", "1/(4*sqrt(1-x^2/16))"),
+            ("acos(x/4)", "-1/(4*sqrt(1-x^2/16))"),
+            ("atan(x)", "1/(1+x^2)"),
+            ("exp(3*x)", "3*exp(3*x)"),
+            ("ln(x*y)", "1/x"),
+            ("log(x)", "1/(x*ln(10))"),
+            ("log(x, 2)", "1/(x*ln(2))"),
+            ("log(2, x)", "-ln(2)/(x*ln(x)^2)"),
+            ("sqrt(x)", "0.5/sqrt(x)"),
+            ("abs(1-x)", "(1-x)/abs(1-x)*-1"),
+            ("atan2(y, x)", "-y/(x^2+y^2)"),
+            ("SUM(x, x^2, y)", "1+2*x"),
+            ("AVERAGE(x, 3*x)", "2"),
+            ("POWER(x, 3)", "3*x^2"),
+        ];
+
+        for (input, expected) in cases.iter() {
+            let derivative = parse(input).differentiate("x").unwrap();
+            for x in [0.5, 1.5, 3.].iter() {
+                let expected = at(&parse(expected), *x);
+                assert!(
+                    (at(&derivative, *x) - expected).abs() < 1e-12,
+                    "{} at {}",
+                    input,
+                    x
+                );
+            }
+        }
+
+        assert_eq!(
+            parse("x^3").differentiate("x").unwrap(),
+            Node::Multiply(
+                Box::new(Node::Element(3.)),
+                Box::new(Node::Power(
+                    Box::new(Node::Variable("x".into())),
+                    Box::new(Node::Element(2.))
+                ))
+            )
+        );
+        assert_eq!(parse("y^2").differentiate("x").unwrap(), Node::Element(0.));
+        assert!(matches!(
+            parse("x^x").differentiate("x"),
+            Err(CalculusError::Unsupported(_))
+        ));
+        for input in ["max(x, 1)", "min(x, y)"].iter() {
+            assert!(
+                matches!(
+                    parse(input).differentiate("x"),
+                    Err(CalculusError::Unsupported(_))
+                ),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            parse("max(y, 1) * x").differentiate("x").unwrap(),
+            parse("max(y, 1)").simplify()
+        );
+    }
+
+    #[test]
+    fn higher_order_derivatives() {
+        assert_eq!(
+            parse("x^5").differentiate_n("x", 3).unwrap(),
+            parse("60*x^2").simplify()
+        );
+        assert_eq!(
+            parse("x^5").differentiate_n("x", 6).unwrap(),
+            Node::Element(0.)
+        );
+        assert_eq!(parse("x+1").differentiate_n("x", 0).unwrap(), parse("x+1"));
+
+        let second = parse("1/x").differentiate_n("x", 2).unwrap();
+        for x in [0.5, 2., -3.].iter() {
+            assert!((at(&second, *x) - 2. / x.powi(3)).abs() < 1e-12, "{}", x);
+        }
+
+        // The second derivative of sin is -sin.
+        let second = parse("sin(x)").differentiate_n("x", 2).unwrap();
+        assert_eq!(second, parse("-sin(x)").simplify());
+        for x in [0.5, 2., -3.].iter() {
+            assert!((at(&second, *x) + x.sin()).abs() < 1e-12, "{}", x);
+        }
+    }
+
+    #[test]
+    fn size_limit() {
+        let product = (1..=24)
+            .map(|k| format!("(x+{})", k))
+            .collect::<Vec<_>>()
+            .join("*");
+        assert_eq!(
+            parse(&product).differentiate_n("x", 8),
+            Err(CalculusError::TooLarge(MAX_NODE_COUNT))
+        );
+    }
+
+    #[test]
+    fn cached_and_mixed_derivatives() {
+        let mut cache = DerivativeCache::new(&parse("x^2*y^3"));
+        let mixed = cache.derivative(&["x", "y"]).unwrap().clone();
+        assert_eq!(cache.computed(), 2);
+        assert!((at(&mixed, 3.) - 6. * 3. * 4.).abs() < 1e-12);
+
+        assert_eq!(cache.derivative(&["y", "x"]).unwrap(), &mixed);
+        assert_eq!(cache.computed(), 2);
+
+        let third = cache.derivative(&["y", "y", "y"]).unwrap().clone();
+        assert_eq!(third, parse("6*x^2").simplify());
+        assert_eq!(cache.computed(), 5);
+        assert_eq!(cache.derivative(&[]).unwrap(), &parse("x^2*y^3"));
+    }
+
+    #[test]
+    fn gradient() {
+        let node = parse("x*y + y^2");
+        let gradient = node.gradient(&["x", "y", "z"]).unwrap();
+        assert_eq!(gradient[0], Node::Variable("y".into()));
+        assert_eq!(gradient[1], parse("x + 2*y").simplify());
+        assert_eq!(gradient[2], Node::Element(0.));
+
+        let mut context = Context::new();
+        context.set("x", 3.);
+        context.set("y", 2.);
+        assert_eq!(
+            node.gradient_at(&["x", "y", "z"], &context),
+            Ok(vec![2., 7., 0.])
+        );
+        assert_eq!(node.gradient_at(&["y", "x"], &context), Ok(vec![7., 2.]));
+
+        // Calls follow the chain rule, in the context's angle mode.
+        let node = parse("sin(x)*y + max(x, y^2)");
+        assert_eq!(
+            node.gradient_at(&["x", "y"], &context),
+            Ok(vec![3f64.cos() * 2., 3f64.sin() + 4.])
+        );
+        context.set_angle_mode(AngleMode::Degrees);
+        let slope = parse("sin(x)").gradient_at(&["x"], &context).unwrap()[0];
+        assert!((slope - 3f64.to_radians().cos().to_radians()).abs() < 1e-15);
+        context.set_angle_mode(AngleMode::Radians);
+
+        // What has no derivative is an error, not a NaN slope.
+        for input in ["x!", "max(x, 3)", "min(x, 3, x)"].iter() {
+            assert!(
+                matches!(
+                    parse(input).gradient_at(&["x"], &context),
+                    Err(CalculusError::Unsupported(_))
+                ),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(parse("y! * x").gradient_at(&["x"], &context), Ok(vec![2.]));
+    }
+
+    #[test]
+    fn series() {
+        // d/dx of x + 2x^2 + 3x^3, and of x^2 * 2x^2 * 3x^2 = 6x^6.
+        let sum = parse("sum(k, 1, 3, k * x^k)");
+        let derivative = sum.differentiate("x").unwrap();
+        assert!(matches!(derivative, Node::Series(Series::Sum, ..)));
+        assert_eq!(at(&derivative, 2.), 1. + 8. + 36.);
+        let product = parse("prod(k, 1, 3, k * x^2)");
+        let mut context = Context::new();
+        context.set("x", 2.);
+        assert_eq!(sum.gradient_at(&["x", "k"], &context), Ok(vec![45., 0.]));
+        assert_eq!(product.gradient_at(&["x"], &context), Ok(vec![36. * 32.]));
+
+        assert_eq!(
+            parse("sum(x, 1, 3, x^2) * y").differentiate("x").unwrap(),
+            Node::Element(0.)
+        );
+        for input in ["prod(k, 1, 3, k * x)", "sum(k, 1, x, k)"].iter() {
+            assert!(matches!(
+                parse(input).differentiate("x"),
+                Err(CalculusError::Unsupported(_))
+            ));
+        }
+
+        assert_eq!(
+            as_polynomial(&parse("sum(k, 1, 3, k) * x - 12"), "x"),
+            Some(vec![-12., 6.])
+        );
+        assert_eq!(as_polynomial(&parse("sum(k, 1, 3, k * x)"), "x"), None);
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        let names: Vec<String> = (0..10).map(|i| format!("x{}", i)).collect();
+        let variables: Vec<&str> = names.iter().map(String::as_str).collect();
+        let node = parse(
+            "x0*x1 + x2^3/x3 - (x4 + 2*x5)^2 + 3^x6 + x7*x8*x9 + (x0 + x9)^0.5 \
+             + sin(x1*x2) + exp(x3)/ln(x4) + atan2(x5, x6)",
+        );
+
+        let mut context = Context::new();
+        for (i, name) in variables.iter().enumerate() {
+            context.set(name, 1. + i as f64 / 4.);
+        }
+        let gradient = node.gradient_at(&variables, &context).unwrap();
+
+        let h = 1e-6;
+        for (i, name) in variables.iter().enumerate() {
+            let value = context.get(name).unwrap();
+            let mut shifted = context.clone();
+            shifted.set(name, value + h);
+            let above = node.eval_with(&shifted).unwrap();
+            shifted.set(name, value - h);
+            let below = node.eval_with(&shifted).unwrap();
+
+            let estimate = (above - below) / (2. * h);
+            assert!(
+                (gradient[i] - estimate).abs() < 1e-5 * estimate.abs().max(1.),
+                "{}: {} vs {}",
+                name,
+                gradient[i],
+                estimate
+            );
+        }
+
+        let symbolic = node.gradient(&variables).unwrap();
+        for (derivative, expected) in symbolic.iter().zip(gradient.iter()) {
+            assert!((derivative.eval_with(&context).unwrap() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn geometric_series() {
+        let series = taylor(&parse("1/(1-x)"), "x", 0., 3, TaylorForm::Centered).unwrap();
+        assert_eq!(as_polynomial(&series, "x"), Some(vec![1., 1., 1., 1.]));
+    }
+
+    #[test]
+    fn odd_function_has_zero_even_coefficients() {
+        let series = taylor(&parse("x/(1+x^2)"), "x", 0., 5, TaylorForm::Centered).unwrap();
+        assert_eq!(
+            as_polynomial(&series, "x"),
+            Some(vec![0., 1., 0., -1., 0., 1.])
+        );
+    }
+
+    #[test]
+    fn exponential_series() {
+        let series = taylor(&parse("exp(x)"), "x", 0., 3, TaylorForm::Centered).unwrap();
+        let coefficients = as_polynomial(&series, "x").unwrap();
+        let expected = [1., 1., 1. / 2., 1. / 6.];
+        assert_eq!(coefficients.len(), expected.len());
+        for (coefficient, expected) in coefficients.iter().zip(expected.iter()) {
+            assert!((coefficient - expected).abs() < 1e-12, "{:?}", coefficients);
+        }
+    }
+
+    #[test]
+    fn sine_series_has_zero_even_coefficients() {
+        let series = taylor(&parse("sin(x)"), "x", 0., 7, TaylorForm::Centered).unwrap();
+        let coefficients = as_polynomial(&series, "x").unwrap();
+        assert_eq!(coefficients.len(), 8);
+        for (k, coefficient) in coefficients.iter().enumerate() {
+            if k % 2 == 0 {
+                assert_eq!(*coefficient, 0., "{}", k);
+            }
+        }
+        assert!((coefficients[3] + 1. / 6.).abs() < 1e-12);
+        assert!((coefficients[7] + 1. / 5040.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn centered_and_expanded_forms() {
+        let cube = parse("x^3");
+        let centered = taylor(&cube, "x", 1., 3, TaylorForm::Centered).unwrap();
+        assert_eq!(
+            centered,
+            parse("1 + 3*(x-1) + 3*(x-1)^2 + (x-1)^3").simplify()
+        );
+
+        let expanded = taylor(&cube, "x", 1., 3, TaylorForm::Expanded).unwrap();
+        assert_eq!(as_polynomial(&expanded, "x"), Some(vec![0., 0., 0., 1.]));
+        assert_eq!(expanded, parse("x^3").simplify());
+    }
+
+    #[test]
+    fn agrees_with_the_function_near_the_center() {
+        let root = parse("(1+x)^0.5");
+        let series = taylor(&root, "x", 0., 4, TaylorForm::Centered).unwrap();
+        for x in [-0.1, 0.05, 0.1].iter() {
+            assert!((at(&series, *x) - at(&root, *x)).abs() < 1e-5, "{}", x);
+        }
+
+        let series = taylor(&root, "x", 3., 2, TaylorForm::Expanded).unwrap();
+        assert!((at(&series, 3.2) - at(&root, 3.2)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn other_variables_cannot_be_evaluated() {
+        assert_eq!(
+            taylor(&parse("x*y"), "x", 0., 2, TaylorForm::Centered),
+            Err(CalculusError::Evaluation(EvalError::UnknownVariable(
+                "y".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn polynomials() {
+        assert_eq!(
+            as_polynomial(&parse("(x+1)^2 - 1"), "x"),
+            Some(vec![0., 2., 1.])
+        );
+        assert_eq!(as_polynomial(&parse("x*x/2"), "x"), Some(vec![0., 0., 0.5]));
+        assert_eq!(as_polynomial(&parse("x - x"), "x"), Some(vec![0.]));
+        assert_eq!(as_polynomial(&parse("1/x"), "x"), None);
+        assert_eq!(as_polynomial(&parse("x^0.5"), "x"), None);
+        assert_eq!(as_polynomial(&parse("x*y"), "x"), None);
+    }
+}
@@ -0,0 +1,248 @@
+use super::ast::UserFunction;
+use super::errors::OperatorError;
+use super::eval::EvalOptions;
+use super::operator::{Associativity, Fixity, Operators};
+use super::token::OperationPrecedence;
+use std::collections::HashMap;
+use std::f64::consts;
+
+// Names that have a value without being set. Like every other name they
+// are case-sensitive, so PI is not pi, and a variable of the same name
+// hides them.
+static CONSTANTS: [(&str, f64); 3] = [("pi", consts::PI), ("e", consts::E), ("tau", consts::TAU)];
+
+pub fn constant(name: &str) -> Option<f64> {
+    CONSTANTS
+        .iter()
+        .find(|(constant, _)| *constant == name)
+        .map(|(_, value)| *value)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Context {
+    variables: HashMap<String, f64>,
+    functions: HashMap<String, UserFunction>,
+    results: Vec<f64>,
+    // How powers and angles evaluate, the angle mode included.
+    options: EvalOptions,
+    operators: Operators,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Context::default()
+    }
+
+    pub fn angle_mode(&self) -> AngleMode {
+        self.options.angle_mode
+    }
+
+    pub fn set_angle_mode(&mut self, angle_mode: AngleMode) {
+        self.options.angle_mode = angle_mode;
+    }
+
+    pub fn options(&self) -> EvalOptions {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: EvalOptions) {
+        self.options = options;
+    }
+
+    pub fn set(&mut self, name: &str, value: f64) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        match self.variables.get(name) {
+            Some(value) => Some(*value),
+            None if name == "ans" => self.ans(),
+            None => constant(name),
+        }
+    }
+
+    pub fn variables(&self) -> Vec<(&str, f64)> {
+        let mut variables: Vec<(&str, f64)> = self
+            .variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+
+    // A definition replaces one of the same name. Whether the body calls
+    // the function back is checked when it is parsed, not here.
+    pub fn define(&mut self, name: &str, function: UserFunction) {
+        self.functions.insert(name.to_string(), function);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&UserFunction> {
+        self.functions.get(name)
+    }
+
+    pub fn functions(&self) -> Vec<(&str, &UserFunction)> {
+        let mut functions: Vec<(&str, &UserFunction)> = self
+            .functions
+            .iter()
+            .map(|(name, function)| (name.as_str(), function))
+            .collect();
+        functions.sort_by(|a, b| a.0.cmp(b.0));
+        functions
+    }
+
+    // Parsing with the context reads `symbol` between two operands as this
+    // operator, and evaluating with it applies `apply` to them.
+    pub fn define_operator(
+        &mut self,
+        symbol: char,
+        precedence: OperationPrecedence,
+        associativity: Associativity,
+        apply: fn(f64, f64) -> f64,
+    ) -> Result<(), OperatorError> {
+        self.operators
+            .define_binary(symbol, precedence, associativity, apply)
+    }
+
+    pub fn define_unary_operator(
+        &mut self,
+        symbol: char,
+        fixity: Fixity,
+        precedence: OperationPrecedence,
+        apply: fn(f64) -> f64,
+    ) -> Result<(), OperatorError> {
+        self.operators
+            .define_unary(symbol, fixity, precedence, apply)
+    }
+
+    pub fn operators(&self) -> &Operators {
+        &self.operators
+    }
+
+    // Functions go with the variables, since their bodies may hold values
+    // taken from them.
+    pub fn clear_variables(&mut self) {
+        self.variables.clear();
+        self.functions.clear();
+    }
+
+    // Keeps the evaluation options and the operators, which are settings
+    // rather than session state.
+    pub fn clear(&mut self) {
+        self.clear_variables();
+        self.results.clear();
+    }
+
+    pub fn push_result(&mut self, result: f64) -> usize {
+        self.results.push(result);
+        self.results.len()
+    }
+
+    pub fn ans(&self) -> Option<f64> {
+        self.results.last().copied()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.variables.keys().cloned().collect();
+        if self.ans().is_some() && !self.variables.contains_key("ans") {
+            names.push("ans".to_string());
+        }
+        for (name, _) in CONSTANTS.iter() {
+            if !self.variables.contains_key(*name) {
+                names.push(name.to_string());
+            }
+        }
+
+        names.sort();
+        names
+    }
+
+    pub fn history(&self, number: usize) -> Option<f64> {
+        number
+            .checked_sub(1)
+            .and_then(|index| self.results.get(index))
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_results() {
+        let mut context = Context::new();
+        assert_eq!(context.ans(), None);
+        assert_eq!(context.names(), vec!["e", "pi", "tau"]);
+        assert_eq!(context.push_result(3.), 1);
+        assert_eq!(context.push_result(5.), 2);
+
+        assert_eq!(context.ans(), Some(5.));
+        assert_eq!(context.names(), vec!["ans", "e", "pi", "tau"]);
+        assert_eq!(context.history(1), Some(3.));
+        assert_eq!(context.history(2), Some(5.));
+        assert_eq!(context.history(0), None);
+        assert_eq!(context.history(3), None);
+    }
+
+    #[test]
+    fn variables() {
+        let mut context = Context::new();
+        context.push_result(1.);
+        context.set("x", 2.);
+        context.set("X", 3.);
+
+        assert_eq!(context.get("x"), Some(2.));
+        assert_eq!(context.get("X"), Some(3.));
+        assert_eq!(context.get("ans"), Some(1.));
+        assert_eq!(context.get("y"), None);
+        assert_eq!(context.names(), vec!["X", "ans", "e", "pi", "tau", "x"]);
+
+        context.set("ans", 5.);
+        assert_eq!(context.get("ans"), Some(5.));
+        assert_eq!(context.names(), vec!["X", "ans", "e", "pi", "tau", "x"]);
+        assert_eq!(context.variables(), vec![("X", 3.), ("ans", 5.), ("x", 2.)]);
+    }
+
+    #[test]
+    fn clear() {
+        let mut context = Context::new();
+        context.set_angle_mode(AngleMode::Degrees);
+        context.push_result(1.);
+        context.set("x", 2.);
+
+        context.clear_variables();
+        assert_eq!(context.get("x"), None);
+        assert_eq!(context.ans(), Some(1.));
+
+        context.set("x", 2.);
+        context.clear();
+        assert_eq!(context.get("x"), None);
+        assert_eq!(context.ans(), None);
+        assert_eq!(context.angle_mode(), AngleMode::Degrees);
+    }
+
+    #[test]
+    fn constants() {
+        let mut context = Context::new();
+        assert_eq!(context.get("pi"), Some(consts::PI));
+        assert_eq!(context.get("e"), Some(consts::E));
+        assert_eq!(context.get("tau"), Some(consts::TAU));
+        assert_eq!(context.get("PI"), None);
+        assert_eq!(context.get("pie"), None);
+        assert!(context.variables().is_empty());
+
+        context.set("e", 2.);
+        assert_eq!(context.get("e"), Some(2.));
+        assert_eq!(context.names(), vec!["e", "pi", "tau"]);
+        context.clear();
+        assert_eq!(context.get("e"), Some(consts::E));
+    }
+}
@@ -0,0 +1,400 @@
+// Differential testing of `Parser` against a deliberately naive reference
+// evaluator. The reference shares no code with the engine and follows the
+// grammar below directly:
+//
+//   expr    := term (('+' | '-') term)*
+//   term    := factor (('*' | '/') factor)*
+//   factor  := unary ('(' expr ')' ('^' unary)?)*
+//   unary   := ('+' | '-') unary | power
+//   power   := primary ('^' unary)?
+//   primary := number | '(' expr ')'
+//
+// Random expressions are generated from a seed, evaluated by both sides and
+// compared within `EPSILON`. Divergent expressions are shrunk before being
+// reported so failures are readable.
+use super::parser::Parser;
+use super::random::Random;
+use std::fmt;
+
+const EPSILON: f64 = 1e-9;
+const MAX_DEPTH: u32 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+impl Op {
+    fn symbol(self) -> char {
+        match self {
+            Self::Add => '+',
+            Self::Sub => '-',
+            Self::Mul => '*',
+            Self::Div => '/',
+            Self::Pow => '^',
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(String),
+    Plus(Box<Expr>),
+    Minus(Box<Expr>),
+    Group(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Implicit(Box<Expr>, Box<Expr>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Number(literal) => write!(f, "{}", literal),
+            Self::Plus(expr) => write!(f, "+{}", expr),
+            Self::Minus(expr) => write!(f, "-{}", expr),
+            Self::Group(expr) => write!(f, "({})", expr),
+            Self::Binary(op, left, right) => write!(f, "{}{}{}", left, op.symbol(), right),
+            Self::Implicit(left, right) => write!(f, "{}({})", left, right),
+        }
+    }
+}
+
+impl Expr {
+    fn generate(rng: &mut Random, depth: u32) -> Self {
+        if depth == 0 || rng.below(4) == 0 {
+            return Self::number(rng);
+        }
+
+        let child = |rng: &mut Random| Box::new(Self::generate(rng, depth - 1));
+        match rng.below(10) {
+            0 => Self::Plus(child(rng)),
+            1 => Self::Minus(child(rng)),
+            2 => Self::Group(child(rng)),
+            3 => Self::Implicit(child(rng), child(rng)),
+            _ => {
+                let op = [Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Pow][rng.below(5) as usize];
+                Self::Binary(op, child(rng), child(rng))
+            }
+        }
+    }
+
+    fn number(rng: &mut Random) -> Self {
+        let integer = rng.below(12);
+        let literal = if rng.below(3) == 0 {
+            format!("{}.{}", integer, rng.below(100))
+        } else {
+            integer.to_string()
+        };
+
+        Self::Number(literal)
+    }
+
+    fn shrink_candidates(&self) -> Vec<Expr> {
+        let mut candidates = Vec::new();
+
+        match self {
+            Self::Number(literal) => {
+                if literal.len() > 1 {
+                    candidates.push(Self::Number("1".into()));
+                }
+            }
+            Self::Plus(expr) | Self::Minus(expr) | Self::Group(expr) => {
+                candidates.push((**expr).clone());
+                for smaller in expr.shrink_candidates() {
+                    candidates.push(self.with_children(smaller, None));
+                }
+            }
+            Self::Binary(_, left, right) | Self::Implicit(left, right) => {
+                candidates.push((**left).clone());
+                candidates.push((**right).clone());
+                for smaller in left.shrink_candidates() {
+                    candidates.push(self.with_children(smaller, Some((**right).clone())));
+                }
+                for smaller in right.shrink_candidates() {
+                    candidates.push(self.with_children((**left).clone(), Some(smaller)));
+                }
+            }
+        }
+
+        candidates
+    }
+
+    fn with_children(&self, first: Expr, second: Option<Expr>) -> Expr {
+        match (self, second) {
+            (Self::Plus(_), _) => Self::Plus(Box::new(first)),
+            (Self::Minus(_), _) => Self::Minus(Box::new(first)),
+            (Self::Group(_), _) => Self::Group(Box::new(first)),
+            (Self::Binary(op, _, _), Some(second)) => {
+                Self::Binary(*op, Box::new(first), Box::new(second))
+            }
+            (Self::Implicit(_, _), Some(second)) => {
+                Self::Implicit(Box::new(first), Box::new(second))
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+fn shrink(mut expr: Expr, still_fails: impl Fn(&Expr) -> bool) -> Expr {
+    'outer: loop {
+        for candidate in expr.shrink_candidates() {
+            if still_fails(&candidate) {
+                expr = candidate;
+                continue 'outer;
+            }
+        }
+
+        return expr;
+    }
+}
+
+struct Reference {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Reference {
+    fn evaluate(input: &str) -> Result<f64, String> {
+        let chars = input.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut reference = Reference { chars, position: 0 };
+        let value = reference.expr()?;
+
+        match reference.peek() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected '{}' at {}", c, reference.position)),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+
+        loop {
+            if self.eat('*') {
+                value *= self.factor()?;
+            } else if self.eat('/') {
+                value /= self.factor()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+
+        while self.eat('(') {
+            let mut group = self.group()?;
+            if self.eat('^') {
+                group = group.powf(self.unary()?);
+            }
+            value *= group;
+        }
+
+        Ok(value)
+    }
+
+    fn power(&mut self) -> Result<f64, String> {
+        let value = self.primary()?;
+
+        if self.eat('^') {
+            return Ok(value.powf(self.unary()?));
+        }
+
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.eat('+') {
+            self.unary()
+        } else if self.eat('-') {
+            Ok(-self.unary()?)
+        } else {
+            self.power()
+        }
+    }
+
+    fn primary(&mut self) -> Result<f64, String> {
+        if self.eat('(') {
+            return self.group();
+        }
+
+        let start = self.position;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || c == '.') {
+            self.position += 1;
+        }
+
+        let literal: String = self.chars[start..self.position].iter().collect();
+        literal
+            .parse::<f64>()
+            .map_err(|_| format!("expected a number at {}", start))
+    }
+
+    fn group(&mut self) -> Result<f64, String> {
+        let value = self.expr()?;
+
+        if self.eat(')') {
+            Ok(value)
+        } else {
+            Err(format!("expected ')' at {}", self.position))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Divergence {
+    input: String,
+    engine: Result<f64, String>,
+    reference: Result<f64, String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?}: engine {:?}, reference {:?}",
+            self.input, self.engine, self.reference
+        )
+    }
+}
+
+fn agree(engine: f64, reference: f64) -> bool {
+    if engine.is_nan() || reference.is_nan() {
+        return engine.is_nan() && reference.is_nan();
+    }
+    if engine.is_infinite() || reference.is_infinite() {
+        return engine == reference;
+    }
+
+    let scale = engine.abs().max(reference.abs()).max(1.);
+    (engine - reference).abs() <= EPSILON * scale
+}
+
+fn check(input: &str) -> Option<Divergence> {
+    let engine = Parser::new(input).evaluate().map_err(|e| e.to_string());
+    let reference = Reference::evaluate(input);
+
+    let agrees = match (&engine, &reference) {
+        (Ok(engine), Ok(reference)) => agree(*engine, *reference),
+        (Err(_), Err(_)) => true,
+        _ => false,
+    };
+
+    if agrees {
+        None
+    } else {
+        Some(Divergence {
+            input: input.into(),
+            engine,
+            reference,
+        })
+    }
+}
+
+fn run(seed: u64, cases: usize) -> Result<(), Divergence> {
+    let mut rng = Random::new(seed);
+
+    for _ in 0..cases {
+        let expr = Expr::generate(&mut rng, MAX_DEPTH);
+        if check(&expr.to_string()).is_some() {
+            let minimal = shrink(expr, |candidate| check(&candidate.to_string()).is_some());
+            return Err(check(&minimal.to_string()).expect("shrinking keeps the divergence"));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regression_cases() {
+        let cases = [
+            "2(3)^2",
+            "(2)(3)(4)^2",
+            "-2^2",
+            "2^-3^2",
+            "10^2^3",
+            "8/2(2+2)",
+            "1-2-3+4",
+            "--3*-+2",
+            "1/0-1/0",
+            "0.5(4)/2",
+        ];
+
+        for case in cases.iter() {
+            if let Some(divergence) = check(case) {
+                panic!("{}", divergence);
+            }
+        }
+    }
+
+    #[test]
+    fn random_seeds() {
+        for seed in [1, 7, 42, 2021, 0xDEAD_BEEF].iter() {
+            if let Err(divergence) = run(*seed, 2000) {
+                panic!("seed {}: {}", seed, divergence);
+            }
+        }
+    }
+
+    #[test]
+    fn reference_rejects_malformed_input() {
+        assert!(Reference::evaluate("1+").is_err());
+        assert!(Reference::evaluate("(1").is_err());
+        assert!(Reference::evaluate("1)").is_err());
+    }
+
+    #[test]
+    fn shrink_minimizes_expression() {
+        let mut rng = Random::new(3);
+        let expr = loop {
+            let expr = Expr::generate(&mut rng, MAX_DEPTH);
+            let rendered = expr.to_string();
+            if rendered.contains('/') && rendered.len() > 10 {
+                break expr;
+            }
+        };
+
+        let minimal = shrink(expr, |candidate| candidate.to_string().contains('/'));
+        match minimal {
+            Expr::Binary(Op::Div, left, right) => {
+                assert!(matches!(*left, Expr::Number(_)));
+                assert!(matches!(*right, Expr::Number(_)));
+            }
+            other => panic!("not minimal: {}", other),
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use super::ast::Node;
+use super::operator::Fixity;
+use std::fmt;
+
+// Negation and logical not bind looser than ^ and the postfix % and !, so
+// -x^2 is -(x^2) and -5% is -(5%), but tighter than * and /. Postfix
+// operators bind tighter than ^, so 5%^2 is (5%)^2. Comparisons bind
+// looser than arithmetic, and && and || looser still. Implicit
+// multiplication is only printed where it reads the same whichever way
+// ParserOptions::juxtaposition_binds_tight is set.
+// How tightly a custom operator binds is up to the context, so one is
+// parenthesized wherever it is an operand, and so are its operands.
+const CUSTOM: u8 = 0;
+const OR: u8 = 1;
+const AND: u8 = 2;
+const COMPARISON: u8 = 3;
+const SUM: u8 = 4;
+const PRODUCT: u8 = 5;
+const PREFIX: u8 = 6;
+const POWER: u8 = 7;
+const POSTFIX: u8 = 8;
+const PRIMARY: u8 = 9;
+
+impl Node {
+    // The shortest string that parses back to this tree: no spaces, only
+    // the parentheses the precedence requires, and juxtaposition instead of
+    // * wherever the right factor starts with a parenthesis. NaN and
+    // infinite elements print as NaN and inf, which parse back as well.
+    pub fn to_minified(&self) -> String {
+        self.render(true)
+    }
+
+    fn render(&self, compact: bool) -> String {
+        match self {
+            Node::Element(number) => number.to_string(),
+            Node::Variable(name) | Node::CellRef(name) => name.clone(),
+            Node::Negative(node) => format!("-{}", node.operand(PREFIX, false, compact)),
+            // 50%% does not parse, so a percent of a percent keeps its
+            // parentheses; 3!! does.
+            Node::Percent(node) => {
+                let nested = matches!(node.as_ref(), Node::Percent(_));
+                format!("{}%", node.operand(POSTFIX, nested, compact))
+            }
+            Node::Factorial(node) => format!("{}!", node.operand(POSTFIX, false, compact)),
+            Node::Sum(left, right) => Node::binary(left, "+", right, SUM, compact),
+            Node::Subtract(left, right) => Node::binary(left, "-", right, SUM, compact),
+            Node::Multiply(left, right) => Node::binary(left, "*", right, PRODUCT, compact),
+            Node::Divide(left, right) => Node::binary(left, "/", right, PRODUCT, compact),
+            Node::FloorDivide(left, right) => Node::binary(left, "//", right, PRODUCT, compact),
+            Node::Modulo(left, right) => Node::binary(left, "%", right, PRODUCT, compact),
+            Node::Power(left, right) => Node::binary(left, "^", right, POWER, compact),
+            Node::CustomBinary(symbol, left, right) => {
+                let left = left.operand(PRIMARY, false, compact);
+                let right = right.operand(PRIMARY, false, compact);
+                match compact {
+                    true => format!("{}{}{}", left, symbol, right),
+                    false => format!("{} {} {}", left, symbol, right),
+                }
+            }
+            Node::CustomUnary(fixity, symbol, node) => {
+                let node = node.operand(PRIMARY, false, compact);
+                match fixity {
+                    Fixity::Prefix => format!("{}{}", symbol, node),
+                    Fixity::Postfix => format!("{}{}", node, symbol),
+                }
+            }
+            Node::Compare(comparison, left, right) => {
+                Node::binary(left, comparison.symbol(), right, COMPARISON, compact)
+            }
+            Node::And(left, right) => Node::binary(left, "&&", right, AND, compact),
+            Node::Or(left, right) => Node::binary(left, "||", right, OR, compact),
+            Node::Not(node) => format!("!{}", node.operand(PREFIX, false, compact)),
+            Node::List(items) => format!("[{}]", Node::items(items, compact)),
+            Node::Function(name, arguments) => {
+                format!("{}({})", name, Node::items(arguments, compact))
+            }
+            Node::Conditional(condition, then, otherwise) => {
+                let parts = [
+                    condition.render(compact),
+                    then.render(compact),
+                    otherwise.render(compact),
+                ];
+                format!("if({})", parts.join(if compact { "," } else { ", " }))
+            }
+            Node::Series(series, variable, from, to, body) => {
+                let parts = [
+                    variable.clone(),
+                    from.render(compact),
+                    to.render(compact),
+                    body.render(compact),
+                ];
+                let parts = parts.join(if compact { "," } else { ", " });
+                format!("{}({})", series.name(), parts)
+            }
+        }
+    }
+
+    fn items(items: &[Node], compact: bool) -> String {
+        let items: Vec<String> = items.iter().map(|item| item.render(compact)).collect();
+        items.join(if compact { "," } else { ", " })
+    }
+
+    fn binary(left: &Node, operator: &str, right: &Node, precedence: u8, compact: bool) -> String {
+        // ^ is right-associative, comparisons do not chain and every other
+        // operator is left-associative, so an operand of the same precedence
+        // on the other side keeps its parentheses.
+        let power = operator == "^";
+        // a/b(c) would read as a/(b(c)) by default.
+        let juxtaposable = left.precedence() != PRODUCT;
+        let mut left = left.operand(precedence, power || precedence == COMPARISON, compact);
+        // A sign after ^ starts a negation that takes in the rest of the
+        // power, just as the right operand does.
+        let mut right = match right {
+            Node::Negative(_) if power => right.render(compact),
+            _ => right.operand(precedence, !power, compact),
+        };
+        // A % followed by a sign is a percent, and one followed by a
+        // parenthesis is a remainder, not a percent times a group.
+        if operator == "%" && right.starts_with('-') {
+            right = format!("({})", right);
+        }
+        // 3!==6 would read as 3 != = 6.
+        if compact && left.ends_with('!') && operator.starts_with('=') {
+            left = format!("({})", left);
+        }
+
+        match (compact, operator) {
+            (true, "*")
+                if juxtaposable
+                    && right.starts_with('(')
+                    && !left.ends_with('%')
+                    && !ends_with_name(&left) =>
+            {
+                format!("{}{}", left, right)
+            }
+            (true, _) => format!("{}{}{}", left, operator, right),
+            (false, _) => format!("{} {} {}", left, operator, right),
+        }
+    }
+
+    fn operand(&self, precedence: u8, strict: bool, compact: bool) -> String {
+        let own = self.precedence();
+        if own < precedence || (strict && own == precedence) {
+            format!("({})", self.render(compact))
+        } else {
+            self.render(compact)
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Node::CustomBinary(..) | Node::CustomUnary(..) => CUSTOM,
+            Node::Or(_, _) => OR,
+            Node::And(_, _) => AND,
+            Node::Compare(..) => COMPARISON,
+            Node::Sum(_, _) | Node::Subtract(_, _) => SUM,
+            Node::Multiply(_, _)
+            | Node::Divide(_, _)
+            | Node::FloorDivide(_, _)
+            | Node::Modulo(_, _) => PRODUCT,
+            Node::Power(_, _) => POWER,
+            Node::Negative(_) | Node::Not(_) => PREFIX,
+            Node::Percent(_) | Node::Factorial(_) => POSTFIX,
+            Node::Element(_)
+            | Node::Variable(_)
+            | Node::CellRef(_)
+            | Node::List(_)
+            | Node::Function(_, _)
+            | Node::Conditional(..)
+            | Node::Series(..) => PRIMARY,
+        }
+    }
+}
+
+// A name straight before a parenthesis would call it. inf and NaN read as
+// numbers, so those can still be juxtaposed.
+fn ends_with_name(text: &str) -> bool {
+    let rest = text.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+    let word = &text[rest.len()..];
+    word.starts_with(|c: char| !c.is_ascii_digit()) && word != "inf" && word != "NaN"
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_math::parser::Parser;
+    use crate::parse_math::testing::Trees;
+
+    fn parse(input: &str) -> Node {
+        Parser::new(input).parse().unwrap()
+    }
+
+    fn minified(input: &str) -> String {
+        parse(input).to_minified()
+    }
+
+    #[test]
+    fn minifications() {
+        assert_eq!(minified("(2)*(x + 1)"), "2(x+1)");
+        assert_eq!(minified("2 * (x + 1)^2"), "2(x+1)^2");
+        assert_eq!(minified("(a+b) * (c-d) * (e)"), "(a+b)(c-d)*e");
+        assert_eq!(minified("(1/2) * (x+1)"), "1/2*(x+1)");
+        assert_eq!(minified("1 / (2 * (x+1))"), "1/(2(x+1))");
+        assert_eq!(minified("(-2) * (x+1)"), "-2(x+1)");
+        assert_eq!(minified("1.50 + +0.5"), "1.5+0.5");
+        assert_eq!(minified("((a - b)) - c"), "a-b-c");
+        assert_eq!(minified("a - (b - c)"), "a-(b-c)");
+        assert_eq!(minified("(-x)^2"), "(-x)^2");
+        assert_eq!(minified("-(x^2)"), "-x^2");
+        assert_eq!(minified("2^(-x)"), "2^-x");
+        assert_eq!(minified("-(2*x)"), "-(2*x)");
+        assert_eq!(minified("- - x"), "--x");
+        assert_eq!(minified("2^(3^4)"), "2^3^4");
+        assert_eq!(minified("(2^3)^4"), "(2^3)^4");
+        assert_eq!(minified("x / (2 * y)"), "x/(2*y)");
+        assert_eq!(minified("[ [1, 2], [3, (4)] ]"), "[[1,2],[3,4]]");
+        assert_eq!(minified("(5%)^2 * (-(5))%"), "5%^2(-5)%");
+        assert_eq!(minified("-(5%)"), "-5%");
+        assert_eq!(minified("((x + 1)%)%"), "((x+1)%)%");
+        assert_eq!(minified("((3)!)! + (2%)! + (x!)%"), "3!!+2%!+x!%");
+        assert_eq!(minified("10 % (0 - 3) % (-x)"), "10%(0-3)%(-x)");
+        assert_eq!(minified("10 % 3 % -x"), "10%3%-x");
+        assert_eq!(minified("(5%) * (x + 1)"), "5%*(x+1)");
+        assert_eq!(minified("(5%) % 2"), "5%%2");
+        assert_eq!(minified("20 // (3 // 2) / 1"), "20//(3//2)/1");
+        assert_eq!(minified("sqrt((x + 1)) * (2)"), "sqrt(x+1)*2");
+        assert_eq!(minified("2 * sin(x) ^ 2"), "2*sin(x)^2");
+        assert_eq!(minified("abs(x) * (y - 1)"), "abs(x)(y-1)");
+        assert_eq!(minified("x_1 * (y - 1)"), "x_1*(y-1)");
+        assert_eq!(minified("inf * (y - 1)"), "inf(y-1)");
+        assert_eq!(minified("(1 + 2) < (4 * 1)"), "1+2<4*1");
+        assert_eq!(minified("(1 < 2) < 3"), "(1<2)<3");
+        assert_eq!(minified("1 < (2 < 3)"), "1<(2<3)");
+        assert_eq!(minified("3! == 6"), "(3!)==6");
+        assert_eq!(minified("x! >= 6"), "x!>=6");
+        assert_eq!(minified("(a && b) || (c && !d)"), "a&&b||c&&!d");
+        assert_eq!(minified("(a || b) && (!c)^2"), "(a||b)&&(!c)^2");
+        assert_eq!(minified("!(a < b) * -!c"), "!(a<b)*-!c");
+    }
+
+    #[test]
+    fn infinity_and_nan() {
+        assert_eq!(parse("1/inf").to_string(), "1 / inf");
+        assert_eq!(parse("-Infinity").to_minified(), "-inf");
+        assert_eq!(parse("inf").to_minified(), "inf");
+        assert_eq!(parse("1/inf").eval(), 0.);
+        assert_eq!(parse("-inf").eval(), f64::NEG_INFINITY);
+        assert!(parse("inf - inf").eval().is_nan());
+
+        let nan = parse("2 * nan");
+        assert_eq!(nan.to_string(), "2 * NaN");
+        assert!(nan.eval().is_nan());
+        // A tree holding NaN is not equal even to itself, as f64 is not, so
+        // it round-trips through its text rather than by comparison.
+        assert_ne!(nan, nan.clone());
+        assert_eq!(parse(&nan.to_string()).to_string(), nan.to_string());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(parse("(2)*(x+1)").to_string(), "2 * (x + 1)");
+        assert_eq!(parse("-(x^2)/[1,2]").to_string(), "-x ^ 2 / [1, 2]");
+        assert_eq!(parse("2*sqrt( ln(x) )").to_string(), "2 * sqrt(ln(x))");
+    }
+
+    #[test]
+    fn shorter_than_display() {
+        for input in [
+            "2 * (x + 1)",
+            "(a + b) * (a - b) / 4",
+            "-(1 - x) ^ 2",
+            "1 + 2 * 3 - 4 / 5",
+            "x",
+        ]
+        .iter()
+        {
+            let node = parse(input);
+            let minified = node.to_minified();
+            assert!(minified.len() <= node.to_string().len(), "{}", input);
+            assert!(!minified.contains(' '), "{}", minified);
+        }
+        assert_eq!(parse("2*(x+1)").to_minified().len(), 6);
+        assert_eq!(parse("2*(x+1)").to_string().len(), 11);
+    }
+
+    #[test]
+    fn generated_trees_round_trip() {
+        let mut trees = Trees::new(0x7e57);
+        for _ in 0..2000 {
+            let node = trees.node(5);
+            let minified = node.to_minified();
+            assert_eq!(
+                Parser::new(&minified).parse(),
+                Ok(node.clone()),
+                "{}",
+                minified
+            );
+
+            let display = node.to_string();
+            assert_eq!(
+                Parser::new(&display).parse(),
+                Ok(node.clone()),
+                "{}",
+                display
+            );
+            assert!(minified.len() <= display.len());
+        }
+    }
+}
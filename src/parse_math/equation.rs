@@ -0,0 +1,184 @@
+use super::ast::Node;
+use super::calculus;
+use super::context;
+use super::errors::SolveError;
+use super::parser::Parser;
+
+// Coefficients closer than this, relative to their size, count as equal,
+// so 0.1 + 0.2 = 0.3 is an identity rather than a contradiction.
+const EPSILON: f64 = 1e-12;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Equation {
+    pub left: Node,
+    pub right: Node,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Solution {
+    Unique(f64),
+    AllValues,
+    NoSolution,
+}
+
+impl Equation {
+    // Sorted and without duplicates, over both sides. Constants such as pi
+    // are not unknowns.
+    pub fn unknowns(&self) -> Vec<String> {
+        let mut unknowns = self.left.variables();
+        unknowns.extend(self.right.variables());
+        unknowns.retain(|name| context::constant(name).is_none());
+        unknowns.sort();
+        unknowns.dedup();
+
+        unknowns
+    }
+
+    pub fn unknown(&self) -> Result<String, SolveError> {
+        let mut unknowns = self.unknowns();
+        match unknowns.len() {
+            0 => Err(SolveError::NoUnknown),
+            1 => Ok(unknowns.remove(0)),
+            _ => Err(SolveError::TooManyUnknowns(unknowns)),
+        }
+    }
+
+    // Moves everything to one side as a polynomial in `variable` and solves
+    // it if its degree is at most one.
+    pub fn solve_linear(&self, variable: &str) -> Result<Solution, SolveError> {
+        let polynomial = |node| {
+            calculus::as_polynomial(node, variable)
+                .ok_or_else(|| SolveError::NotPolynomial(variable.to_string()))
+        };
+        let (left, right) = (polynomial(&self.left)?, polynomial(&self.right)?);
+
+        let coefficients: Vec<f64> = (0..left.len().max(right.len()))
+            .map(|power| {
+                let a = left.get(power).copied().unwrap_or(0.);
+                let b = right.get(power).copied().unwrap_or(0.);
+                if (a - b).abs() <= EPSILON * a.abs().max(b.abs()) {
+                    0.
+                } else {
+                    a - b
+                }
+            })
+            .collect();
+
+        let solution = match coefficients.iter().rposition(|c| *c != 0.) {
+            None => Solution::AllValues,
+            Some(0) => Solution::NoSolution,
+            // Adding 0 turns a -0 solution into 0.
+            Some(1) => Solution::Unique(-coefficients[0] / coefficients[1] + 0.),
+            Some(degree) => {
+                return Err(SolveError::Nonlinear {
+                    variable: variable.to_string(),
+                    degree,
+                })
+            }
+        };
+
+        Ok(solution)
+    }
+}
+
+// Solves text such as 2x + 3 = 11 for its one unknown, giving x and 4.
+// Only a single solution is an answer here, so an equation that every
+// value or no value solves is an error too.
+pub fn solve(input: &str) -> Result<(String, f64), SolveError> {
+    let equation = Parser::new(input).parse_equation()?;
+    let unknown = equation.unknown()?;
+
+    match equation.solve_linear(&unknown)? {
+        Solution::Unique(value) => Ok((unknown, value)),
+        Solution::AllValues => Err(SolveError::Identity(unknown)),
+        Solution::NoSolution => Err(SolveError::Contradiction(unknown)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_math::errors::ParseError;
+
+    fn solve(input: &str) -> Result<Solution, SolveError> {
+        let equation = Parser::new(input).parse_equation().unwrap();
+        equation.solve_linear(&equation.unknown()?)
+    }
+
+    #[test]
+    fn linear_equations() {
+        assert_eq!(solve("2*x + 3 = 7"), Ok(Solution::Unique(2.)));
+        assert_eq!(solve("3*x - 5 = x + 1"), Ok(Solution::Unique(3.)));
+        assert_eq!(solve("(x + 1) / 4 = 2 - x"), Ok(Solution::Unique(1.4)));
+        assert_eq!(solve("5 = -x"), Ok(Solution::Unique(-5.)));
+        assert_eq!(solve("2*x = 0"), Ok(Solution::Unique(0.)));
+    }
+
+    #[test]
+    fn identities_and_contradictions() {
+        assert_eq!(solve("2*(x + 1) = 2*x + 2"), Ok(Solution::AllValues));
+        assert_eq!(solve("x + 0.1 + 0.2 = x + 0.3"), Ok(Solution::AllValues));
+        assert_eq!(solve("x + 1 = x + 2"), Ok(Solution::NoSolution));
+        assert_eq!(solve("x^2 - x = x^2 + 4"), Ok(Solution::Unique(-4.)));
+    }
+
+    #[test]
+    fn unsupported_equations() {
+        let error = solve("x^2 = 4").unwrap_err();
+        assert_eq!(
+            error,
+            SolveError::Nonlinear {
+                variable: "x".into(),
+                degree: 2
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Cannot solve: the equation has degree 2 in x, only linear equations can be solved"
+        );
+        assert_eq!(error.code(), "nonlinear");
+
+        assert_eq!(solve("1/x = 2"), Err(SolveError::NotPolynomial("x".into())));
+        assert_eq!(
+            solve("x + y = 2").unwrap_err().to_string(),
+            "Cannot solve for several unknowns at once: x, y"
+        );
+        assert_eq!(solve("1 + 1 = 2"), Err(SolveError::NoUnknown));
+    }
+
+    #[test]
+    fn solving_text() {
+        assert_eq!(super::solve("2x + 3 = 11"), Ok(("x".into(), 4.)));
+        // The unknown on both sides.
+        assert_eq!(super::solve("5y - 2 = 3y + 8"), Ok(("y".into(), 5.)));
+        assert_eq!(super::solve("4(t + 1) = 2t - 6"), Ok(("t".into(), -5.)));
+        assert_eq!(
+            super::solve("x / 2 + pi = x - pi"),
+            Ok(("x".into(), 4. * std::f64::consts::PI))
+        );
+
+        let error = super::solve("x + 1 = x").unwrap_err();
+        assert_eq!(error, SolveError::Contradiction("x".into()));
+        assert_eq!(
+            error.to_string(),
+            "Cannot solve: the equation holds for no value of x"
+        );
+        assert_eq!(
+            super::solve("2(n - 1) = 2n - 2").unwrap_err().to_string(),
+            "Cannot solve: the equation holds for every value of n"
+        );
+        assert_eq!(super::solve("x * x = 4").unwrap_err().code(), "nonlinear");
+        assert_eq!(
+            super::solve("a + b = 2").unwrap_err().code(),
+            "too_many_unknowns"
+        );
+        assert_eq!(
+            super::solve("2x + 3"),
+            Err(SolveError::Parse(ParseError::UnexpectedToken {
+                found: crate::parse_math::token::Token::EOF,
+                expected: vec!["'='"],
+                position: 6,
+            }))
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use super::ast::Node;
+use super::context::Context;
+use super::random::Random;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    Uniform { low: f64, high: f64 },
+    Normal { mean: f64, std_dev: f64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EquivalenceOptions {
+    pub samples: usize,
+    pub max_attempts: usize,
+    pub distribution: Distribution,
+    pub seed: u64,
+    pub epsilon: f64,
+}
+
+impl Default for EquivalenceOptions {
+    fn default() -> Self {
+        EquivalenceOptions {
+            samples: 32,
+            max_attempts: 256,
+            distribution: Distribution::Uniform {
+                low: -10.,
+                high: 10.,
+            },
+            seed: 0x5eed,
+            epsilon: 1e-9,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EquivalenceResult {
+    Equivalent,
+    NotEquivalent(Vec<(String, f64)>),
+    Inconclusive,
+}
+
+// Samples both expressions at random points over the union of their
+// variables. Points where either side fails to evaluate or is not finite are
+// skipped. Sampling cannot see differences on a set of measure zero: x/x and
+// 1 only disagree at x = 0 and come out as Equivalent.
+pub fn are_equivalent(a: &Node, b: &Node, options: &EquivalenceOptions) -> EquivalenceResult {
+    let mut variables = a.variables();
+    variables.extend(b.variables());
+    variables.sort();
+    variables.dedup();
+
+    let mut random = Random::new(options.seed);
+    let mut valid = 0;
+
+    for _ in 0..options.max_attempts {
+        if valid == options.samples {
+            return EquivalenceResult::Equivalent;
+        }
+
+        let mut context = Context::new();
+        let point: Vec<(String, f64)> = variables
+            .iter()
+            .map(|name| (name.clone(), sample(&mut random, options.distribution)))
+            .collect();
+        for (name, value) in &point {
+            context.set(name, *value);
+        }
+
+        let (left, right) = match (a.eval_with(&context), b.eval_with(&context)) {
+            (Ok(left), Ok(right)) if left.is_finite() && right.is_finite() => (left, right),
+            _ => continue,
+        };
+
+        let scale = left.abs().max(right.abs()).max(1.);
+        if (left - right).abs() > options.epsilon * scale {
+            return EquivalenceResult::NotEquivalent(point);
+        }
+        valid += 1;
+    }
+
+    if valid == options.samples {
+        EquivalenceResult::Equivalent
+    } else {
+        EquivalenceResult::Inconclusive
+    }
+}
+
+fn sample(random: &mut Random, distribution: Distribution) -> f64 {
+    match distribution {
+        Distribution::Uniform { low, high } => low + (high - low) * random.unit(),
+        Distribution::Normal { mean, std_dev } => {
+            let radius = (-2. * (1. - random.unit()).ln()).sqrt();
+            let angle = 2. * std::f64::consts::PI * random.unit();
+            mean + std_dev * radius * angle.cos()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_math::parser::Parser;
+
+    fn equivalent(a: &str, b: &str, options: &EquivalenceOptions) -> EquivalenceResult {
+        let a = Parser::new(a).parse().unwrap();
+        let b = Parser::new(b).parse().unwrap();
+        are_equivalent(&a, &b, options)
+    }
+
+    #[test]
+    fn equivalent_expressions() {
+        let options = EquivalenceOptions::default();
+        assert_eq!(
+            equivalent("2(x+1)", "2*x+2", &options),
+            EquivalenceResult::Equivalent
+        );
+        assert_eq!(
+            equivalent("(x+y)^2", "x^2 + 2*x*y + y^2", &options),
+            EquivalenceResult::Equivalent
+        );
+        assert_eq!(
+            equivalent("1+2", "3", &options),
+            EquivalenceResult::Equivalent
+        );
+        assert_eq!(
+            equivalent("-0*x", "0", &options),
+            EquivalenceResult::Equivalent
+        );
+    }
+
+    #[test]
+    fn counterexample() {
+        let options = EquivalenceOptions::default();
+        match equivalent("(x^2)^0.5", "x", &options) {
+            EquivalenceResult::NotEquivalent(point) => {
+                assert_eq!(point.len(), 1);
+                assert_eq!(point[0].0, "x");
+                assert!(point[0].1 < 0.);
+            }
+            result => panic!("{:?}", result),
+        }
+
+        match equivalent("x", "y", &options) {
+            EquivalenceResult::NotEquivalent(point) => {
+                let names: Vec<&str> = point.iter().map(|(name, _)| name.as_str()).collect();
+                assert_eq!(names, vec!["x", "y"]);
+            }
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn removable_singularity_is_not_detected() {
+        let options = EquivalenceOptions::default();
+        assert_eq!(
+            equivalent("x/x", "1", &options),
+            EquivalenceResult::Equivalent
+        );
+    }
+
+    #[test]
+    fn too_many_invalid_points() {
+        let options = EquivalenceOptions {
+            distribution: Distribution::Uniform {
+                low: -10.,
+                high: -1.,
+            },
+            ..EquivalenceOptions::default()
+        };
+        assert_eq!(
+            equivalent("x^0.5", "(x^0.25)^2", &options),
+            EquivalenceResult::Inconclusive
+        );
+    }
+
+    #[test]
+    fn seeded_sampling_is_reproducible() {
+        let options = EquivalenceOptions {
+            distribution: Distribution::Normal {
+                mean: 0.,
+                std_dev: 3.,
+            },
+            seed: 7,
+            ..EquivalenceOptions::default()
+        };
+        let first = equivalent("x", "-x", &options);
+        assert_eq!(first, equivalent("x", "-x", &options));
+        assert_ne!(first, EquivalenceResult::Equivalent);
+    }
+}
@@ -1,11 +1,174 @@
+use super::function::Arity;
+use super::token::{Token, Tokenizer};
 use std::fmt;
+use std::ops::Range;
 
 #[derive(PartialEq, Debug)]
 pub enum ParseError {
     UnableToParse(String),
     ParenthesisNotBalanced,
+    BarNotBalanced,
+    MismatchedGroup {
+        open: char,
+        close: char,
+        position: usize,
+    },
     InvalidOperator(String),
-    InvalidNumber(String),
+    InvalidNumber {
+        literal: String,
+        span: Range<usize>,
+    },
+    UnknownReference(String),
+    UnknownIdentifier {
+        name: String,
+        span: Range<usize>,
+    },
+    UnknownFunction {
+        name: String,
+        span: Range<usize>,
+    },
+    // The span is the whole call, from the name to the closing parenthesis.
+    ArgumentCount {
+        function: String,
+        expected: Arity,
+        found: usize,
+        span: Range<usize>,
+    },
+    UnexpectedCharacter {
+        character: char,
+        position: usize,
+    },
+    Io(String),
+    UnexpectedComma(usize),
+    MissingArgument(usize),
+    MissingOperator(usize),
+    TrailingInput {
+        token: Token,
+        position: usize,
+    },
+    UnexpectedEndOfInput {
+        expected: &'static str,
+        position: usize,
+    },
+    // Groups, signs and powers nested past ParserOptions::max_depth.
+    TooDeep {
+        limit: usize,
+        position: usize,
+    },
+    // A second comparison straight after one, as in 1 < x < 3.
+    ChainedComparison(usize),
+    // A token where none of the `expected` kinds of token may be.
+    UnexpectedToken {
+        found: Token,
+        expected: Vec<&'static str>,
+        position: usize,
+    },
+    // A definition whose body calls the function being defined.
+    RecursiveFunction(String),
+    DuplicateParameter(String),
+    // From Parser::evaluate_with, which evaluates what it parsed.
+    Evaluation(EvalError),
+}
+
+impl ParseError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnableToParse(_) => "unable_to_parse",
+            ParseError::ParenthesisNotBalanced => "parenthesis_not_balanced",
+            ParseError::BarNotBalanced => "bar_not_balanced",
+            ParseError::MismatchedGroup { .. } => "mismatched_group",
+            ParseError::InvalidOperator(_) => "invalid_operator",
+            ParseError::InvalidNumber { .. } => "invalid_number",
+            ParseError::UnknownReference(_) => "unknown_reference",
+            ParseError::UnknownIdentifier { .. } => "unknown_identifier",
+            ParseError::UnknownFunction { .. } => "unknown_function",
+            ParseError::ArgumentCount { .. } => "argument_count",
+            ParseError::UnexpectedCharacter { .. } => "unexpected_character",
+            ParseError::Io(_) => "io",
+            ParseError::UnexpectedComma(_) => "unexpected_comma",
+            ParseError::MissingArgument(_) => "missing_argument",
+            ParseError::MissingOperator(_) => "missing_operator",
+            ParseError::TrailingInput { .. } => "trailing_input",
+            ParseError::UnexpectedEndOfInput { .. } => "unexpected_end_of_input",
+            ParseError::TooDeep { .. } => "too_deep",
+            ParseError::ChainedComparison(_) => "chained_comparison",
+            ParseError::UnexpectedToken { .. } => "unexpected_token",
+            ParseError::RecursiveFunction(_) => "recursive_function",
+            ParseError::DuplicateParameter(_) => "duplicate_parameter",
+            ParseError::Evaluation(error) => error.code(),
+        }
+    }
+
+    // Where in the input the error is, for the errors that know; the
+    // others are about the input as a whole or about a name in it.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            ParseError::MismatchedGroup { position, .. }
+            | ParseError::UnexpectedCharacter { position, .. }
+            | ParseError::TrailingInput { position, .. }
+            | ParseError::UnexpectedEndOfInput { position, .. }
+            | ParseError::TooDeep { position, .. }
+            | ParseError::UnexpectedToken { position, .. }
+            | ParseError::UnexpectedComma(position)
+            | ParseError::MissingArgument(position)
+            | ParseError::MissingOperator(position)
+            | ParseError::ChainedComparison(position) => Some(*position),
+            _ => self.stored_span().map(|span| span.start),
+        }
+    }
+
+    // The span of the errors the parser records one for, since a name or a
+    // call is more than the token at its start.
+    fn stored_span(&self) -> Option<Range<usize>> {
+        match self {
+            ParseError::InvalidNumber { span, .. }
+            | ParseError::UnknownIdentifier { span, .. }
+            | ParseError::UnknownFunction { span, .. }
+            | ParseError::ArgumentCount { span, .. } => Some(span.clone()),
+            _ => None,
+        }
+    }
+
+    // The bytes of `source`, the input that gave this error, of the token
+    // at the error's position; empty at the end of the input.
+    pub fn span(&self, source: &str) -> Option<Range<usize>> {
+        if let Some(span) = self.stored_span() {
+            return Some(span);
+        }
+        let position = self.position()?;
+        let rest = source.get(position..).unwrap_or("");
+        let spanned = Tokenizer::new(rest).spanned_token();
+        Some(match spanned.token {
+            Token::EOF => position..position,
+            _ => position + spanned.span.start..position + spanned.span.end,
+        })
+    }
+
+    // The message, then the line of `source` the error is on with the span
+    // underlined beneath it. Columns count characters, not bytes.
+    pub fn render(&self, source: &str) -> String {
+        let mut rendered = format!("{}\n", self);
+        if let Some(span) = self.span(source) {
+            let start = span.start.min(source.len());
+            let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = source[start..]
+                .find('\n')
+                .map_or(source.len(), |i| start + i);
+            let column = source[line_start..start].chars().count();
+            let width = source
+                .get(span)
+                .map_or(1, |text| text.chars().count().max(1));
+
+            rendered.push_str(&format!(
+                "  {}\n  {}^{}\n",
+                source[line_start..line_end].trim_end_matches('\r'),
+                " ".repeat(column),
+                "~".repeat(width - 1)
+            ));
+        }
+
+        rendered
+    }
 }
 
 impl fmt::Display for ParseError {
@@ -13,8 +176,392 @@ impl fmt::Display for ParseError {
         match &self {
             ParseError::UnableToParse(e) => write!(f, "Error in evaluating {}", e),
             ParseError::ParenthesisNotBalanced => write!(f, "Balance parenthesis error"),
+            ParseError::BarNotBalanced => write!(f, "Balance absolute value bar error"),
+            ParseError::MismatchedGroup {
+                open,
+                close,
+                position,
+            } => write!(
+                f,
+                "Mismatched '{}' closed by '{}' at position {}",
+                open, close, position
+            ),
             ParseError::InvalidOperator(e) => write!(f, "Invalid operator: {}", e),
-            ParseError::InvalidNumber(e) => write!(f, "Invalid number: {}", e),
+            ParseError::InvalidNumber { literal, .. } => write!(f, "Invalid number: {}", literal),
+            ParseError::UnknownReference(e) => write!(f, "Unknown reference: {}", e),
+            ParseError::UnknownIdentifier { name, .. } => write!(f, "Unknown identifier: {}", name),
+            ParseError::UnknownFunction { name, .. } => write!(f, "Unknown function: {}", name),
+            ParseError::ArgumentCount {
+                function,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "Wrong number of arguments: {} takes {}, got {}",
+                function, expected, found
+            ),
+            ParseError::UnexpectedCharacter {
+                character,
+                position,
+            } => write!(
+                f,
+                "Unexpected character '{}' at position {}",
+                character, position
+            ),
+            ParseError::Io(e) => write!(f, "Read error: {}", e),
+            ParseError::UnexpectedComma(position) => {
+                write!(f, "Unexpected ',' at position {}", position)
+            }
+            ParseError::MissingArgument(position) => {
+                write!(f, "Missing argument at position {}", position)
+            }
+            ParseError::MissingOperator(position) => {
+                write!(f, "Missing operator at position {}", position)
+            }
+            ParseError::TrailingInput { token, position } => {
+                write!(f, "Unexpected '{}' at position {}", token, position)
+            }
+            ParseError::UnexpectedEndOfInput { expected, position } => write!(
+                f,
+                "Unexpected end of input at position {}, expected {}",
+                position, expected
+            ),
+            ParseError::TooDeep { limit, position } => write!(
+                f,
+                "Nested more than {} levels deep at position {}",
+                limit, position
+            ),
+            ParseError::ChainedComparison(position) => write!(
+                f,
+                "Chained comparison at position {}; compare one pair at a time",
+                position
+            ),
+            ParseError::UnexpectedToken {
+                found,
+                expected,
+                position,
+            } => {
+                let expected = match expected.as_slice() {
+                    [] => String::new(),
+                    [only] => only.to_string(),
+                    [first, second] => format!("{} or {}", first, second),
+                    [rest @ .., last] => format!("{}, or {}", rest.join(", "), last),
+                };
+                match found {
+                    Token::EOF => write!(f, "expected {} but found the end of input", expected)?,
+                    found => write!(f, "expected {} but found '{}'", expected, found)?,
+                }
+                write!(f, " at position {}", position)
+            }
+            ParseError::RecursiveFunction(e) => write!(f, "Function calls itself: {}", e),
+            ParseError::DuplicateParameter(e) => write!(f, "Parameter named twice: {}", e),
+            ParseError::Evaluation(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<EvalError> for ParseError {
+    fn from(error: EvalError) -> Self {
+        ParseError::Evaluation(error)
+    }
+}
+
+// One of the errors Parser::parse_with_recovery found, and the bytes of the
+// input it is about.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Range<usize>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum EvalError {
+    TypeMismatch {
+        operation: &'static str,
+        operands: Vec<&'static str>,
+    },
+    ZeroToZeroPower,
+    DivisionByZero,
+    UnknownVariable(String),
+    UnknownCell(String),
+    // A custom operator the context does not define.
+    UnknownOperator(String),
+    // Only for trees built by hand; the parser rejects unknown names.
+    UnknownFunction(String),
+    ArgumentCount {
+        function: String,
+        expected: Arity,
+        found: usize,
+    },
+    ShapeMismatch {
+        operation: &'static str,
+        left: Vec<usize>,
+        right: Vec<usize>,
+    },
+    RaggedMatrix {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    NotSquare(usize, usize),
+    WrongLength {
+        operation: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    Domain {
+        operation: &'static str,
+        value: f64,
+    },
+    // A bound of sum or prod that is not an integer.
+    Bound {
+        function: &'static str,
+        value: f64,
+    },
+    TooManyTerms {
+        function: &'static str,
+        limit: u64,
+    },
+}
+
+impl EvalError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvalError::TypeMismatch { .. } => "type_mismatch",
+            EvalError::ZeroToZeroPower => "zero_to_zero_power",
+            EvalError::DivisionByZero => "division_by_zero",
+            EvalError::UnknownVariable(_) => "unknown_variable",
+            EvalError::UnknownCell(_) => "unknown_cell",
+            EvalError::UnknownOperator(_) => "unknown_operator",
+            EvalError::UnknownFunction(_) => "unknown_function",
+            EvalError::ArgumentCount { .. } => "argument_count",
+            EvalError::ShapeMismatch { .. } => "shape_mismatch",
+            EvalError::RaggedMatrix { .. } => "ragged_matrix",
+            EvalError::NotSquare(_, _) => "not_square",
+            EvalError::WrongLength { .. } => "wrong_length",
+            EvalError::Domain { .. } => "domain",
+            EvalError::Bound { .. } => "bound",
+            EvalError::TooManyTerms { .. } => "too_many_terms",
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            EvalError::TypeMismatch {
+                operation,
+                operands,
+            } => write!(
+                f,
+                "Type mismatch: cannot apply {} to {}",
+                operation,
+                operands.join(" and ")
+            ),
+            EvalError::ZeroToZeroPower => write!(f, "0^0 is undefined"),
+            EvalError::DivisionByZero => write!(f, "Division by zero"),
+            EvalError::UnknownVariable(name) => write!(f, "Unknown variable: {}", name),
+            EvalError::UnknownCell(cell) => write!(f, "Unknown cell: {}", cell),
+            EvalError::UnknownOperator(symbol) => write!(f, "Unknown operator: {}", symbol),
+            EvalError::UnknownFunction(name) => write!(f, "Unknown function: {}", name),
+            EvalError::ArgumentCount {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Wrong number of arguments: {} takes {}, got {}",
+                function, expected, found
+            ),
+            EvalError::ShapeMismatch {
+                operation,
+                left,
+                right,
+            } => write!(
+                f,
+                "Shape mismatch: cannot apply {} to {} and {}",
+                operation,
+                shape(left),
+                shape(right)
+            ),
+            EvalError::RaggedMatrix {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Ragged matrix: row {} has {} columns, expected {}",
+                row, found, expected
+            ),
+            EvalError::NotSquare(rows, columns) => {
+                write!(f, "Matrix is not square: {}x{}", rows, columns)
+            }
+            EvalError::WrongLength {
+                operation,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Wrong length: {} needs {} elements, got {}",
+                operation, expected, found
+            ),
+            EvalError::Domain { operation, value } => {
+                write!(
+                    f,
+                    "Domain error: {} is not defined for {}",
+                    operation, value
+                )
+            }
+            EvalError::Bound { function, value } => write!(
+                f,
+                "The bounds of {} have to be integers, not {}",
+                function, value
+            ),
+            EvalError::TooManyTerms { function, limit } => {
+                write!(f, "Too many terms: {} takes at most {}", function, limit)
+            }
+        }
+    }
+}
+
+fn shape(dimensions: &[usize]) -> String {
+    let dimensions: Vec<String> = dimensions.iter().map(usize::to_string).collect();
+    dimensions.join("x")
+}
+
+// Why Operators would not take an operator.
+#[derive(Clone, PartialEq, Debug)]
+pub enum OperatorError {
+    Reserved(char),
+    AlreadyDefined(char),
+    Ambiguous(char),
+    Precedence(char),
+}
+
+impl fmt::Display for OperatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            OperatorError::Reserved(symbol) => write!(
+                f,
+                "Cannot define an operator '{}': the character already has a meaning",
+                symbol
+            ),
+            OperatorError::AlreadyDefined(symbol) => {
+                write!(f, "The operator '{}' is already defined", symbol)
+            }
+            OperatorError::Ambiguous(symbol) => write!(
+                f,
+                "The operator '{}' cannot be postfix as well as prefix or binary",
+                symbol
+            ),
+            OperatorError::Precedence(symbol) => write!(
+                f,
+                "Cannot define an operator '{}' with that precedence",
+                symbol
+            ),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum CalculusError {
+    Unsupported(String),
+    Evaluation(EvalError),
+    TooLarge(usize),
+}
+
+impl CalculusError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CalculusError::Unsupported(_) => "unsupported",
+            CalculusError::Evaluation(error) => error.code(),
+            CalculusError::TooLarge(_) => "too_large",
+        }
+    }
+}
+
+impl From<EvalError> for CalculusError {
+    fn from(error: EvalError) -> Self {
+        CalculusError::Evaluation(error)
+    }
+}
+
+impl fmt::Display for CalculusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            CalculusError::Unsupported(e) => write!(f, "Cannot differentiate {}", e),
+            CalculusError::Evaluation(e) => write!(f, "{}", e),
+            CalculusError::TooLarge(limit) => {
+                write!(f, "Expression grew beyond {} nodes", limit)
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum SolveError {
+    Parse(ParseError),
+    NoUnknown,
+    TooManyUnknowns(Vec<String>),
+    Nonlinear { variable: String, degree: usize },
+    NotPolynomial(String),
+    // What solve makes of an equation every value of the unknown solves,
+    // or none does.
+    Identity(String),
+    Contradiction(String),
+}
+
+impl SolveError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SolveError::Parse(error) => error.code(),
+            SolveError::NoUnknown => "no_unknown",
+            SolveError::TooManyUnknowns(_) => "too_many_unknowns",
+            SolveError::Nonlinear { .. } => "nonlinear",
+            SolveError::NotPolynomial(_) => "not_polynomial",
+            SolveError::Identity(_) => "identity",
+            SolveError::Contradiction(_) => "contradiction",
+        }
+    }
+}
+
+impl From<ParseError> for SolveError {
+    fn from(error: ParseError) -> Self {
+        SolveError::Parse(error)
+    }
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            SolveError::Parse(e) => write!(f, "{}", e),
+            SolveError::NoUnknown => write!(f, "Nothing to solve: the equation has no unknown"),
+            SolveError::TooManyUnknowns(names) => write!(
+                f,
+                "Cannot solve for several unknowns at once: {}",
+                names.join(", ")
+            ),
+            SolveError::Nonlinear { variable, degree } => write!(
+                f,
+                "Cannot solve: the equation has degree {} in {}, only linear equations can be solved",
+                degree, variable
+            ),
+            SolveError::NotPolynomial(variable) => write!(
+                f,
+                "Cannot solve: the equation is not a polynomial in {}",
+                variable
+            ),
+            SolveError::Identity(variable) => write!(
+                f,
+                "Cannot solve: the equation holds for every value of {}",
+                variable
+            ),
+            SolveError::Contradiction(variable) => write!(
+                f,
+                "Cannot solve: the equation holds for no value of {}",
+                variable
+            ),
         }
     }
 }
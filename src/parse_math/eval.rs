@@ -0,0 +1,201 @@
+use super::context::AngleMode;
+use super::errors::EvalError;
+
+const ROOT_TOLERANCE: f64 = 1e-9;
+const MAX_ROOT_DENOMINATOR: i64 = 99;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ZeroPower {
+    #[default]
+    One,
+    Error,
+    NaN,
+}
+
+// What dividing by zero gives, for /, // and %. Infinity is IEEE 754
+// arithmetic, where the sign of a zero divisor counts: 1/(-0) is -inf.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DivisionByZero {
+    #[default]
+    Infinity,
+    Error,
+    NaN,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EvalOptions {
+    pub zero_power: ZeroPower,
+    pub division_by_zero: DivisionByZero,
+    pub real_roots: bool,
+    // Whether evaluating with types lets booleans count as 1 and 0 in
+    // arithmetic and numbers as conditions, so that (score >= 90) * bonus
+    // works there too. Plain Node::eval always does.
+    pub bool_arithmetic: bool,
+    // The unit trigonometric functions take and their inverses give.
+    pub angle_mode: AngleMode,
+}
+
+impl EvalOptions {
+    pub fn power(&self, base: f64, exponent: f64) -> Result<f64, EvalError> {
+        if base == 0. && exponent == 0. {
+            return match self.zero_power {
+                ZeroPower::One => Ok(1.),
+                ZeroPower::Error => Err(EvalError::ZeroToZeroPower),
+                ZeroPower::NaN => Ok(f64::NAN),
+            };
+        }
+
+        if self.real_roots && base < 0. && exponent.fract() != 0. {
+            if let Some((numerator, _)) = odd_root(exponent) {
+                let magnitude = (-base).powf(exponent);
+                return Ok(if numerator % 2 == 0 {
+                    magnitude
+                } else {
+                    -magnitude
+                });
+            }
+        }
+
+        Ok(base.powf(exponent))
+    }
+
+    // The number to divide by instead of `divisor`. The policy does not
+    // depend on the dividend, so a list or matrix is checked once.
+    pub fn divisor(&self, divisor: f64) -> Result<f64, EvalError> {
+        match self.division_by_zero {
+            DivisionByZero::Error if divisor == 0. => Err(EvalError::DivisionByZero),
+            DivisionByZero::NaN if divisor == 0. => Ok(f64::NAN),
+            _ => Ok(divisor),
+        }
+    }
+}
+
+// Only for non-negative integers. Every factorial past 170! overflows f64,
+// so those are infinite without multiplying them out.
+pub fn factorial(value: f64) -> Result<f64, EvalError> {
+    if !(value >= 0. && value.fract() == 0.) {
+        return Err(EvalError::Domain {
+            operation: "factorial",
+            value,
+        });
+    }
+    if value > 170. {
+        return Ok(f64::INFINITY);
+    }
+
+    Ok((2..=value as u32).fold(1., |product, k| product * k as f64))
+}
+
+// Finds p/q with an odd q that the exponent is within ROOT_TOLERANCE of, so
+// 0.3333333333 counts as 1/3 but 0.3333 does not.
+fn odd_root(exponent: f64) -> Option<(i64, i64)> {
+    if !exponent.is_finite() {
+        return None;
+    }
+
+    (1..=MAX_ROOT_DENOMINATOR)
+        .map(|denominator| ((exponent * denominator as f64).round() as i64, denominator))
+        .find(|(numerator, denominator)| {
+            let rational = *numerator as f64 / *denominator as f64;
+            (exponent - rational).abs() <= ROOT_TOLERANCE * exponent.abs().max(1.)
+        })
+        .filter(|(_, denominator)| denominator % 2 == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn real_roots() -> EvalOptions {
+        EvalOptions {
+            real_roots: true,
+            ..EvalOptions::default()
+        }
+    }
+
+    #[test]
+    fn zero_to_the_zero() {
+        assert_eq!(EvalOptions::default().power(0., 0.), Ok(1.));
+        assert_eq!(EvalOptions::default().power(-0., 0.), Ok(1.));
+
+        let options = EvalOptions {
+            zero_power: ZeroPower::Error,
+            ..EvalOptions::default()
+        };
+        assert_eq!(options.power(0., 0.), Err(EvalError::ZeroToZeroPower));
+        assert_eq!(options.power(0., 2.), Ok(0.));
+
+        let options = EvalOptions {
+            zero_power: ZeroPower::NaN,
+            ..EvalOptions::default()
+        };
+        assert!(options.power(0., 0.).unwrap().is_nan());
+    }
+
+    #[test]
+    fn zero_divisors() {
+        assert_eq!(
+            EvalOptions::default().divisor(-0.).map(f64::signum),
+            Ok(-1.)
+        );
+
+        let options = EvalOptions {
+            division_by_zero: DivisionByZero::Error,
+            ..EvalOptions::default()
+        };
+        assert_eq!(options.divisor(-0.), Err(EvalError::DivisionByZero));
+        assert_eq!(options.divisor(2.), Ok(2.));
+
+        let options = EvalOptions {
+            division_by_zero: DivisionByZero::NaN,
+            ..EvalOptions::default()
+        };
+        assert!(options.divisor(0.).unwrap().is_nan());
+        assert_eq!(options.divisor(-1.), Ok(-1.));
+    }
+
+    #[test]
+    fn odd_roots_of_negative_bases() {
+        assert!(EvalOptions::default().power(-8., 1. / 3.).unwrap().is_nan());
+
+        let cube_root = real_roots().power(-8., 1. / 3.).unwrap();
+        assert!((cube_root + 2.).abs() < 1e-12, "{}", cube_root);
+        let fifth_root = real_roots().power(-32., 0.2).unwrap();
+        assert!((fifth_root + 2.).abs() < 1e-12, "{}", fifth_root);
+        let squared = real_roots().power(-8., 2. / 3.).unwrap();
+        assert!((squared - 4.).abs() < 1e-12, "{}", squared);
+        let close = real_roots().power(-8., 0.3333333333).unwrap();
+        assert!((close + 2.).abs() < 1e-8, "{}", close);
+    }
+
+    #[test]
+    fn real_roots_are_conservative() {
+        assert!(real_roots().power(-8., 0.5).unwrap().is_nan());
+        assert!(real_roots().power(-8., 0.3333).unwrap().is_nan());
+        assert!(real_roots()
+            .power(-8., std::f64::consts::PI)
+            .unwrap()
+            .is_nan());
+        assert_eq!(real_roots().power(-2., 3.), Ok(-8.));
+        assert_eq!(real_roots().power(8., 1. / 3.), Ok(2.));
+    }
+
+    #[test]
+    fn factorials() {
+        assert_eq!(factorial(0.), Ok(1.));
+        assert_eq!(factorial(5.), Ok(120.));
+        assert!(factorial(170.).unwrap().is_finite());
+        assert_eq!(factorial(171.), Ok(f64::INFINITY));
+        assert_eq!(factorial(1e300), Ok(f64::INFINITY));
+
+        let error = factorial(2.5).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Domain error: factorial is not defined for 2.5"
+        );
+        assert_eq!(error.code(), "domain");
+        for value in [-2., -0.5, f64::NAN, f64::INFINITY].iter() {
+            assert!(factorial(*value).is_err(), "{}", value);
+        }
+    }
+}
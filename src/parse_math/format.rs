@@ -0,0 +1,599 @@
+pub const SNAP_ULPS: u64 = 2;
+const INTEGER_EPSILON: f64 = 1e-9;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Precision {
+    #[default]
+    Snapped,
+    Full,
+    Significant(usize),
+    Fixed(usize),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Notation {
+    #[default]
+    Auto,
+    Scientific,
+    Engineering,
+    Humanized(Prefixes),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Prefixes {
+    #[default]
+    Si,
+    Binary,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HumanizeOptions {
+    pub digits: usize,
+    pub prefixes: Prefixes,
+}
+
+impl Default for HumanizeOptions {
+    fn default() -> Self {
+        HumanizeOptions {
+            digits: 3,
+            prefixes: Prefixes::Si,
+        }
+    }
+}
+
+const SI_PREFIXES: [&str; 7] = ["n", "µ", "m", "", "k", "M", "G"];
+const SI_UNPREFIXED: usize = 3;
+const BINARY_PREFIXES: [&str; 4] = ["", "Ki", "Mi", "Gi"];
+
+pub fn format_result(value: f64) -> String {
+    format_with_precision(value, Precision::default())
+}
+
+pub fn format_with_precision(value: f64, precision: Precision) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+
+    match precision {
+        Precision::Snapped => layout(snap(value)),
+        Precision::Full => value.to_string(),
+        Precision::Significant(digits) => ResultFormat(value).significant(digits),
+        Precision::Fixed(decimals) => ResultFormat(value).fixed(decimals),
+    }
+}
+
+// Rounding works on the exact binary value with ties going to the even
+// digit, so 2.5 becomes 2 but 1.005, stored as 1.00499..., becomes 1.00.
+// Output never depends on the locale and always uses '.'.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResultFormat(pub f64);
+
+impl ResultFormat {
+    pub fn fixed(self, decimals: usize) -> String {
+        if !self.0.is_finite() {
+            return self.0.to_string();
+        }
+
+        let formatted = format!("{:.*}", decimals, self.0);
+        match formatted.strip_prefix('-') {
+            Some(magnitude) if magnitude.chars().all(|c| c == '0' || c == '.') => {
+                magnitude.to_string()
+            }
+            _ => formatted,
+        }
+    }
+
+    // Trailing zeros are dropped. Exponents below -7 or above 15 switch to
+    // scientific notation, like the default display.
+    pub fn significant(self, digits: usize) -> String {
+        if !self.0.is_finite() {
+            return self.0.to_string();
+        }
+
+        let scientific = format!("{:.*e}", digits.max(1) - 1, self.0);
+        layout(scientific.parse().unwrap_or(self.0))
+    }
+
+    pub fn percent(self, decimals: usize) -> String {
+        if !self.0.is_finite() {
+            return format!("{}%", self.0);
+        }
+
+        format!("{}%", ResultFormat(self.0 * 100.).fixed(decimals))
+    }
+}
+
+pub fn format_with_notation(value: f64, precision: Precision, notation: Notation) -> String {
+    if let Notation::Humanized(prefixes) = notation {
+        let digits = match precision {
+            Precision::Significant(digits) => digits,
+            _ => HumanizeOptions::default().digits,
+        };
+        return format_humanized(value, &HumanizeOptions { digits, prefixes });
+    }
+    if notation == Notation::Auto || !value.is_finite() {
+        return format_with_precision(value, precision);
+    }
+
+    let scientific = match precision {
+        Precision::Snapped => format!("{:e}", snap(value)),
+        Precision::Full => format!("{:e}", value),
+        Precision::Significant(digits) => format!("{:.*e}", digits.max(1) - 1, value),
+        Precision::Fixed(decimals) => format!("{:.*e}", decimals, value),
+    };
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+    let mut exponent: i32 = exponent[1..].parse().unwrap();
+    let (mut sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(magnitude) => ("-", magnitude),
+        None => ("", mantissa),
+    };
+
+    let mut digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    if let Precision::Significant(_) = precision {
+        digits.truncate(digits.trim_end_matches('0').len().max(1));
+    }
+    if digits.chars().all(|c| c == '0') {
+        sign = "";
+        exponent = 0;
+    }
+
+    let mut integer_digits = 1;
+    if notation == Notation::Engineering {
+        let shift = exponent.rem_euclid(3);
+        exponent -= shift;
+        integer_digits += shift as usize;
+        while digits.len() < integer_digits {
+            digits.push('0');
+        }
+    }
+
+    let (integer, fraction) = digits.split_at(integer_digits);
+    if fraction.is_empty() {
+        format!("{}{}e{}", sign, integer, exponent)
+    } else {
+        format!("{}{}.{}e{}", sign, integer, fraction, exponent)
+    }
+}
+
+// Scales the value to the largest prefix that keeps the rounded mantissa at
+// or above one, so 1234000 reads 1.23M. Binary prefixes have no fractional
+// counterparts and leave values below 1024 unprefixed. Values beyond the
+// prefix table fall back to scientific notation.
+pub fn format_humanized(value: f64, options: &HumanizeOptions) -> String {
+    let precision = Precision::Significant(options.digits.max(1));
+    if !value.is_finite() || value == 0. {
+        return format_with_precision(value, precision);
+    }
+
+    let (base, prefixes, unprefixed): (f64, &[&str], usize) = match options.prefixes {
+        Prefixes::Si => (1000., &SI_PREFIXES, SI_UNPREFIXED),
+        Prefixes::Binary => (1024., &BINARY_PREFIXES, 0),
+    };
+    let round = |scaled: f64| -> f64 {
+        format!("{:.*e}", options.digits.max(1) - 1, scaled)
+            .parse()
+            .unwrap_or(scaled)
+    };
+
+    let scale = |index: usize| value / base.powi(index as i32 - unprefixed as i32);
+    let mut index = unprefixed;
+    while scale(index).abs() >= base && index + 1 < prefixes.len() {
+        index += 1;
+    }
+    while scale(index).abs() < 1. && index > 0 {
+        index -= 1;
+    }
+
+    let mut scaled = round(scale(index));
+    if scaled.abs() >= base && index + 1 < prefixes.len() {
+        index += 1;
+        scaled = round(scale(index));
+    }
+
+    let below_table = scaled.abs() < 1. && prefixes == SI_PREFIXES;
+    if scale(index).abs() >= base || scaled.abs() >= base || below_table {
+        return format_with_notation(value, precision, Notation::Scientific);
+    }
+
+    format!(
+        "{}{}",
+        format_with_precision(scaled, precision),
+        prefixes[index]
+    )
+}
+
+// Returns the value with the fewest significant digits that lies within
+// SNAP_ULPS units in the last place of `value`, so 0.1 + 0.2 becomes 0.3.
+// Values with no shorter neighbour come back unchanged.
+pub fn snap(value: f64) -> f64 {
+    if !value.is_finite() {
+        return value;
+    }
+
+    (1..17)
+        .filter_map(|digits| format!("{:.*e}", digits - 1, value).parse().ok())
+        .find(|candidate: &f64| {
+            candidate.is_finite() && ulps_between(*candidate, value) <= SNAP_ULPS
+        })
+        .unwrap_or(value)
+}
+
+// Negative values are written as a sign followed by the magnitude, never in
+// two's complement. Returns `None` when the value is not an integer.
+pub fn format_radix(value: f64, radix: u32) -> Option<String> {
+    let rounded = value.round();
+    if !value.is_finite() || (value - rounded).abs() > INTEGER_EPSILON * rounded.abs().max(1.) {
+        return None;
+    }
+
+    let magnitude = rounded.abs();
+    if magnitude >= u64::MAX as f64 {
+        return None;
+    }
+
+    let sign = if rounded < 0. { "-" } else { "" };
+    let magnitude = magnitude as u64;
+    let digits = match radix {
+        2 => format!("0b{:b}", magnitude),
+        8 => format!("0o{:o}", magnitude),
+        16 => format!("0x{:x}", magnitude),
+        _ => magnitude.to_string(),
+    };
+
+    Some(format!("{}{}", sign, digits))
+}
+
+fn layout(rounded: f64) -> String {
+    if rounded == 0. {
+        return "0".into();
+    }
+
+    let scientific = format!("{:e}", rounded);
+    let exponent: i32 = scientific
+        .rsplit('e')
+        .next()
+        .and_then(|exponent| exponent.parse().ok())
+        .unwrap_or(0);
+
+    if (-7..16).contains(&exponent) {
+        rounded.to_string()
+    } else {
+        format!("{:e}", rounded)
+    }
+}
+
+fn ulps_between(a: f64, b: f64) -> u64 {
+    // Maps the bit patterns onto a line where adjacent floats differ by one
+    // and both zeros meet at 0.
+    fn ordered(value: f64) -> i64 {
+        let bits = value.to_bits() as i64;
+        if bits < 0 {
+            i64::MIN - bits
+        } else {
+            bits
+        }
+    }
+
+    ordered(a).abs_diff(ordered(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nice_rounds_float_artifacts() {
+        let cases = [
+            (0.1 + 0.2, "0.3"),
+            (1.1 * 3., "3.3"),
+            (0.1 * 3., "0.3"),
+            (0.7 + 0.1, "0.8"),
+            (1. - 0.9, "0.1"),
+            (0.3 - 0.1, "0.2"),
+            (4.35 * 100., "435"),
+            (1.15 * 100., "115"),
+            (-(0.1 + 0.2), "-0.3"),
+            (1e22 * 1.1, "1.1e22"),
+        ];
+
+        for (value, expected) in cases.iter() {
+            assert_eq!(format_result(*value), *expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn nice_keeps_values_without_short_neighbour() {
+        let cases = [
+            (1. / 3., "0.3333333333333333"),
+            (2. / 3., "0.6666666666666666"),
+            (std::f64::consts::PI, "3.141592653589793"),
+            (0.3 + 5e-16, "0.3000000000000005"),
+            (5e-324, "5e-324"),
+            (f64::MAX, "1.7976931348623157e308"),
+        ];
+
+        for (value, expected) in cases.iter() {
+            assert_eq!(format_result(*value), *expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn snapping_stays_within_tolerance() {
+        for value in [
+            0.1 + 0.2,
+            1.1 * 3.,
+            1. - 0.9,
+            1. / 3.,
+            1e-310,
+            -7.000000000000001,
+        ]
+        .iter()
+        {
+            assert!(
+                ulps_between(snap(*value), *value) <= SNAP_ULPS,
+                "{:?}",
+                value
+            );
+        }
+        assert_eq!(ulps_between(0., -0.), 0);
+        assert_eq!(ulps_between(1., 1. + f64::EPSILON), 1);
+        assert_eq!(ulps_between(-5e-324, 5e-324), 2);
+        assert!(snap(f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn nice_integers_have_no_decimal_point() {
+        assert_eq!(format_result(3.), "3");
+        assert_eq!(format_result(-1024.), "-1024");
+        assert_eq!(format_result(123456789012.), "123456789012");
+    }
+
+    #[test]
+    fn nice_large_and_small_use_exponent() {
+        assert_eq!(format_result(1e20), "1e20");
+        assert_eq!(format_result(-2.5e-9), "-2.5e-9");
+        assert_eq!(format_result(1e15), "1000000000000000");
+        assert_eq!(format_result(0.0000001), "0.0000001");
+    }
+
+    #[test]
+    fn nice_negative_zero() {
+        assert_eq!(format_result(-0.0), "0");
+        assert_eq!(format_result(-1e-17), "-1e-17");
+        assert_eq!(format_humanized(-0.0, &HumanizeOptions::default()), "0");
+        assert_eq!(format_result(-1e-300 * 1e-300), "0");
+    }
+
+    #[test]
+    fn non_finite() {
+        assert_eq!(format_result(f64::INFINITY), "inf");
+        assert_eq!(format_result(f64::NEG_INFINITY), "-inf");
+        assert_eq!(format_result(f64::NAN), "NaN");
+    }
+
+    #[test]
+    fn significant_digits() {
+        let precision = Precision::Significant(3);
+        assert_eq!(format_with_precision(1. / 3., precision), "0.333");
+        assert_eq!(format_with_precision(123456., precision), "123000");
+        assert_eq!(format_with_precision(2., precision), "2");
+        assert_eq!(
+            format_with_precision(0.1 + 0.2, Precision::Significant(17)),
+            "0.30000000000000004"
+        );
+    }
+
+    #[test]
+    fn fixed_decimals() {
+        assert_eq!(format_with_precision(1. / 3., Precision::Fixed(2)), "0.33");
+        assert_eq!(format_with_precision(2., Precision::Fixed(3)), "2.000");
+        assert_eq!(
+            format_with_precision(1e20, Precision::Fixed(1)),
+            "100000000000000000000.0"
+        );
+        assert_eq!(format_with_precision(-0.001, Precision::Fixed(2)), "0.00");
+        assert_eq!(format_with_precision(-1.005, Precision::Fixed(0)), "-1");
+    }
+
+    #[test]
+    fn result_format_rounding() {
+        let cases = [
+            (ResultFormat(2.5).fixed(0), "2"),
+            (ResultFormat(3.5).fixed(0), "4"),
+            (ResultFormat(-2.5).fixed(0), "-2"),
+            (ResultFormat(0.125).fixed(2), "0.12"),
+            (ResultFormat(0.375).fixed(2), "0.38"),
+            (ResultFormat(1.005).fixed(2), "1.00"),
+            (ResultFormat(-0.4).fixed(0), "0"),
+            (ResultFormat(1e21).fixed(0), "1000000000000000000000"),
+            (ResultFormat(123456789.125).fixed(2), "123456789.12"),
+            (ResultFormat(2.5).significant(1), "2"),
+            (ResultFormat(0.0012345).significant(3), "0.00123"),
+            (ResultFormat(1e15).significant(3), "1000000000000000"),
+            (ResultFormat(1.5e16).significant(3), "1.5e16"),
+            (ResultFormat(f64::NAN).fixed(2), "NaN"),
+        ];
+
+        for (formatted, expected) in cases.iter() {
+            assert_eq!(formatted, expected);
+        }
+    }
+
+    #[test]
+    fn result_format_percent() {
+        assert_eq!(ResultFormat(0.25).percent(0), "25%");
+        assert_eq!(ResultFormat(0.1234).percent(1), "12.3%");
+        assert_eq!(ResultFormat(0.00042).percent(2), "0.04%");
+        assert_eq!(ResultFormat(0.000042).percent(2), "0.00%");
+        assert_eq!(ResultFormat(-0.000042).percent(2), "0.00%");
+        assert_eq!(ResultFormat(1.5).percent(0), "150%");
+        assert_eq!(ResultFormat(f64::INFINITY).percent(1), "inf%");
+    }
+
+    #[test]
+    fn scientific_notation() {
+        let cases = [
+            (6.022e23, "6.022e23"),
+            (1000., "1e3"),
+            (999.9999999999999, "1e3"),
+            (12500., "1.25e4"),
+            (-0.00042, "-4.2e-4"),
+            (1., "1e0"),
+            (0., "0e0"),
+            (-0., "0e0"),
+            (5e-324, "5e-324"),
+            (f64::INFINITY, "inf"),
+        ];
+
+        for (value, expected) in cases.iter() {
+            let formatted =
+                format_with_notation(*value, Precision::default(), Notation::Scientific);
+            assert_eq!(formatted, *expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn engineering_notation() {
+        let cases = [
+            (12500., "12.5e3"),
+            (1000., "1e3"),
+            (999., "999e0"),
+            (100000., "100e3"),
+            (6.022e23, "602.2e21"),
+            (-0.00042, "-420e-6"),
+            (0.0001234, "123.4e-6"),
+            (0., "0e0"),
+            (5e-324, "5e-324"),
+            (f64::NAN, "NaN"),
+        ];
+
+        for (value, expected) in cases.iter() {
+            let formatted =
+                format_with_notation(*value, Precision::default(), Notation::Engineering);
+            assert_eq!(formatted, *expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn notation_with_precision() {
+        assert_eq!(
+            format_with_notation(1. / 3., Precision::Significant(3), Notation::Scientific),
+            "3.33e-1"
+        );
+        assert_eq!(
+            format_with_notation(1000., Precision::Fixed(3), Notation::Scientific),
+            "1.000e3"
+        );
+        assert_eq!(
+            format_with_notation(0.1 + 0.2, Precision::Full, Notation::Engineering),
+            "300.00000000000004e-3"
+        );
+        assert_eq!(
+            format_with_notation(0.1 + 0.2, Precision::default(), Notation::Auto),
+            "0.3"
+        );
+    }
+
+    #[test]
+    fn humanized_si() {
+        let options = HumanizeOptions::default();
+        let cases = [
+            (1234000., "1.23M"),
+            (0.00042, "420µ"),
+            (1000., "1k"),
+            (999., "999"),
+            (999.9, "1k"),
+            (999_999., "1M"),
+            (-1500., "-1.5k"),
+            (0., "0"),
+            (-0., "0"),
+            (1., "1"),
+            (0.5, "500m"),
+            (2.5e-9, "2.5n"),
+            (4.2e9, "4.2G"),
+            (1.5e12, "1.5e12"),
+            (3e-12, "3e-12"),
+            (f64::INFINITY, "inf"),
+        ];
+
+        for (value, expected) in cases.iter() {
+            assert_eq!(format_humanized(*value, &options), *expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn humanized_binary() {
+        let options = HumanizeOptions {
+            digits: 3,
+            prefixes: Prefixes::Binary,
+        };
+        let cases = [
+            (1024., "1Ki"),
+            (1000., "1000"),
+            (1536., "1.5Ki"),
+            (1048576. * 3., "3Mi"),
+            (-2048., "-2Ki"),
+            (1073741824., "1Gi"),
+            (0.25, "0.25"),
+            (0., "0"),
+            (1024f64.powi(4), "1.1e12"),
+        ];
+
+        for (value, expected) in cases.iter() {
+            assert_eq!(format_humanized(*value, &options), *expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn humanized_digits() {
+        let options = HumanizeOptions {
+            digits: 5,
+            ..HumanizeOptions::default()
+        };
+        assert_eq!(format_humanized(1234567., &options), "1.2346M");
+        assert_eq!(
+            format_with_notation(
+                1234567.,
+                Precision::Significant(2),
+                Notation::Humanized(Prefixes::Si)
+            ),
+            "1.2M"
+        );
+        assert_eq!(
+            format_with_notation(
+                1234567.,
+                Precision::default(),
+                Notation::Humanized(Prefixes::Si)
+            ),
+            "1.23M"
+        );
+    }
+
+    #[test]
+    fn radix() {
+        assert_eq!(format_radix(255., 16), Some("0xff".into()));
+        assert_eq!(format_radix(255., 2), Some("0b11111111".into()));
+        assert_eq!(format_radix(255., 8), Some("0o377".into()));
+        assert_eq!(format_radix(255., 10), Some("255".into()));
+        assert_eq!(format_radix(-255., 16), Some("-0xff".into()));
+        assert_eq!(format_radix(0.1 * 3. * 10., 16), Some("0x3".into()));
+        assert_eq!(format_radix(-0., 2), Some("0b0".into()));
+    }
+
+    #[test]
+    fn radix_requires_integers() {
+        assert_eq!(format_radix(2.5, 16), None);
+        assert_eq!(format_radix(f64::INFINITY, 16), None);
+        assert_eq!(format_radix(f64::NAN, 2), None);
+        assert_eq!(format_radix(1e30, 16), None);
+    }
+
+    #[test]
+    fn full_precision() {
+        assert_eq!(
+            format_with_precision(0.1 + 0.2, Precision::Full),
+            "0.30000000000000004"
+        );
+        assert_eq!(format_with_precision(-0.0, Precision::Full), "-0");
+    }
+}
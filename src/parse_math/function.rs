@@ -0,0 +1,628 @@
+use super::context::AngleMode;
+use super::errors::EvalError;
+use super::matrix::Matrix;
+use super::value::Value;
+use super::vector;
+use std::fmt;
+
+// How many arguments a function takes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    Between(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exactly(expected) => count == expected,
+            Arity::AtLeast(minimum) => count >= minimum,
+            Arity::Between(minimum, maximum) => (minimum..=maximum).contains(&count),
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arity::Exactly(expected) => write!(f, "{}", expected),
+            Arity::AtLeast(minimum) => write!(f, "at least {}", minimum),
+            Arity::Between(minimum, maximum) => write!(f, "{} to {}", minimum, maximum),
+        }
+    }
+}
+
+// Whether a function reads or gives an angle, which is in the angle mode
+// it is evaluated in.
+#[derive(Clone, Copy, PartialEq)]
+enum Angle {
+    Neither,
+    Argument,
+    Result,
+}
+
+// A built-in a call can name. Apart from angles, functions follow f64, so
+// sqrt(-1) is NaN, ln(0) is -inf and max skips NaN arguments.
+pub struct Function {
+    pub name: &'static str,
+    pub arity: Arity,
+    apply: fn(&[f64]) -> f64,
+    // The partial derivatives, in radians, or None where there are none.
+    partials: fn(&[f64]) -> Option<Vec<f64>>,
+    angle: Angle,
+}
+
+static FUNCTIONS: [Function; 18] = [
+    Function {
+        name: "sqrt",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].sqrt(),
+        partials: |x| Some(vec![0.5 / x[0].sqrt()]),
+        angle: Angle::Neither,
+    },
+    Function {
+        name: "abs",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].abs(),
+        partials: |x| Some(vec![x[0] / x[0].abs()]),
+        angle: Angle::Neither,
+    },
+    Function {
+        name: "sin",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].sin(),
+        partials: |x| Some(vec![x[0].cos()]),
+        angle: Angle::Argument,
+    },
+    Function {
+        name: "cos",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].cos(),
+        partials: |x| Some(vec![-x[0].sin()]),
+        angle: Angle::Argument,
+    },
+    Function {
+        name: "tan",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].tan(),
+        partials: |x| Some(vec![1. / x[0].cos().powi(2)]),
+        angle: Angle::Argument,
+    },
+    Function {
+        name: "asin",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].asin(),
+        partials: |x| Some(vec![1. / (1. - x[0] * x[0]).sqrt()]),
+        angle: Angle::Result,
+    },
+    Function {
+        name: "acos",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].acos(),
+        partials: |x| Some(vec![-1. / (1. - x[0] * x[0]).sqrt()]),
+        angle: Angle::Result,
+    },
+    Function {
+        name: "atan",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].atan(),
+        partials: |x| Some(vec![1. / (1. + x[0] * x[0])]),
+        angle: Angle::Result,
+    },
+    // atan2(y, x), the angle of the point (x, y).
+    Function {
+        name: "atan2",
+        arity: Arity::Exactly(2),
+        apply: |x| x[0].atan2(x[1]),
+        partials: |x| {
+            let square = x[0] * x[0] + x[1] * x[1];
+            Some(vec![x[1] / square, -x[0] / square])
+        },
+        angle: Angle::Result,
+    },
+    Function {
+        name: "ln",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].ln(),
+        partials: |x| Some(vec![1. / x[0]]),
+        angle: Angle::Neither,
+    },
+    // log(x) is the common logarithm and log(x, b) the one to base b.
+    // Bases 2 and 10 have their own, exact for powers of the base.
+    Function {
+        name: "log",
+        arity: Arity::Between(1, 2),
+        apply: |x| match x {
+            [x] | [x, 10.] => x.log10(),
+            [x, 2.] => x.log2(),
+            [x, base] => x.ln() / base.ln(),
+            _ => f64::NAN,
+        },
+        partials: |x| match x {
+            [x] => Some(vec![1. / (x * std::f64::consts::LN_10)]),
+            [x, base] => Some(vec![
+                1. / (x * base.ln()),
+                -x.ln() / (base * base.ln().powi(2)),
+            ]),
+            _ => None,
+        },
+        angle: Angle::Neither,
+    },
+    Function {
+        name: "exp",
+        arity: Arity::Exactly(1),
+        apply: |x| x[0].exp(),
+        partials: |x| Some(vec![x[0].exp()]),
+        angle: Angle::Neither,
+    },
+    Function {
+        name: "max",
+        arity: Arity::AtLeast(1),
+        apply: |x| x.iter().copied().fold(f64::NAN, f64::max),
+        partials: |x| selected(x, x.iter().copied().fold(f64::NAN, f64::max)),
+        angle: Angle::Neither,
+    },
+    Function {
+        name: "min",
+        arity: Arity::AtLeast(1),
+        apply: |x| x.iter().copied().fold(f64::NAN, f64::min),
+        partials: |x| selected(x, x.iter().copied().fold(f64::NAN, f64::min)),
+        angle: Angle::Neither,
+    },
+    // Spreadsheet functions, named as spreadsheets write them, for formulas
+    // pasted from one.
+    Function {
+        name: "SUM",
+        arity: Arity::AtLeast(1),
+        apply: |x| x.iter().sum(),
+        partials: |x| Some(vec![1.; x.len()]),
+        angle: Angle::Neither,
+    },
+    Function {
+        name: "AVERAGE",
+        arity: Arity::AtLeast(1),
+        apply: |x| x.iter().sum::<f64>() / x.len() as f64,
+        partials: |x| Some(vec![1. / x.len() as f64; x.len()]),
+        angle: Angle::Neither,
+    },
+    Function {
+        name: "POWER",
+        arity: Arity::Exactly(2),
+        apply: |x| x[0].powf(x[1]),
+        partials: |x| {
+            let power = x[0].powf(x[1]);
+            Some(vec![x[1] * x[0].powf(x[1] - 1.), power * x[0].ln()])
+        },
+        angle: Angle::Neither,
+    },
+    Function {
+        name: "PI",
+        arity: Arity::Exactly(0),
+        apply: |_| std::f64::consts::PI,
+        partials: |_| Some(Vec::new()),
+        angle: Angle::Neither,
+    },
+];
+
+// 1 for the argument max or min gives, when only one gives it; where two
+// cross there is no derivative.
+fn selected(arguments: &[f64], result: f64) -> Option<Vec<f64>> {
+    if arguments.iter().filter(|x| **x == result).count() != 1 {
+        return None;
+    }
+
+    Some(
+        arguments
+            .iter()
+            .map(|x| if *x == result { 1. } else { 0. })
+            .collect(),
+    )
+}
+
+// A built-in of lists and matrices, which takes and gives values rather
+// than numbers. Only the evaluation with types has such values; elsewhere a
+// call of one has a number only when the function gives one.
+pub struct ListFunction {
+    pub name: &'static str,
+    pub arity: Arity,
+    apply: fn(Vec<Value>) -> Result<Value, EvalError>,
+}
+
+// The largest identity matrix, so that a typo cannot ask for terabytes.
+const LARGEST_IDENTITY: f64 = 1000.;
+
+static LIST_FUNCTIONS: [ListFunction; 6] = [
+    ListFunction {
+        name: "dot",
+        arity: Arity::Exactly(2),
+        apply: |x| match &x[..] {
+            [a, b] => vector::dot(list("dot", a)?, list("dot", b)?).map(Value::Number),
+            _ => unreachable!("the arity is checked"),
+        },
+    },
+    ListFunction {
+        name: "cross",
+        arity: Arity::Exactly(2),
+        apply: |x| match &x[..] {
+            [a, b] => vector::cross(list("cross", a)?, list("cross", b)?).map(Value::List),
+            _ => unreachable!("the arity is checked"),
+        },
+    },
+    ListFunction {
+        name: "norm",
+        arity: Arity::Exactly(1),
+        apply: |x| Ok(Value::Number(vector::norm(list("norm", &x[0])?))),
+    },
+    ListFunction {
+        name: "det",
+        arity: Arity::Exactly(1),
+        apply: |mut x| {
+            let matrix = matrix("det", x.remove(0))?;
+            matrix.determinant().map(Value::Number)
+        },
+    },
+    ListFunction {
+        name: "transpose",
+        arity: Arity::Exactly(1),
+        apply: |mut x| Ok(Value::Matrix(matrix("transpose", x.remove(0))?.transpose())),
+    },
+    ListFunction {
+        name: "identity",
+        arity: Arity::Exactly(1),
+        apply: |mut x| {
+            let size = x.remove(0).number("identity")?;
+            if !(size.fract() == 0. && (1. ..=LARGEST_IDENTITY).contains(&size)) {
+                return Err(EvalError::Domain {
+                    operation: "identity",
+                    value: size,
+                });
+            }
+            Ok(Value::Matrix(Matrix::identity(size as usize)))
+        },
+    },
+];
+
+fn list<'a>(operation: &'static str, value: &'a Value) -> Result<&'a [f64], EvalError> {
+    match value {
+        Value::List(list) => Ok(list),
+        value => Err(EvalError::TypeMismatch {
+            operation,
+            operands: vec![value.type_name()],
+        }),
+    }
+}
+
+// A list is a matrix of one row.
+fn matrix(operation: &'static str, value: Value) -> Result<Matrix, EvalError> {
+    match value {
+        Value::Matrix(matrix) => Ok(matrix),
+        Value::List(list) => Matrix::from_rows(vec![list]),
+        value => Err(EvalError::TypeMismatch {
+            operation,
+            operands: vec![value.type_name()],
+        }),
+    }
+}
+
+// Names are case-sensitive, as variables are.
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|function| function.name == name)
+}
+
+// For spreadsheet formulas, where SQRT and Sqrt are sqrt too.
+pub fn lookup_ignoring_case(name: &str) -> Option<&'static Function> {
+    FUNCTIONS
+        .iter()
+        .find(|function| function.name.eq_ignore_ascii_case(name))
+}
+
+pub fn lookup_list(name: &str) -> Option<&'static ListFunction> {
+    LIST_FUNCTIONS.iter().find(|function| function.name == name)
+}
+
+// The arity of any built-in function of the name.
+pub fn arity(name: &str) -> Option<Arity> {
+    lookup(name)
+        .map(|function| function.arity)
+        .or_else(|| lookup_list(name).map(|function| function.arity))
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    FUNCTIONS
+        .iter()
+        .map(|function| function.name)
+        .chain(LIST_FUNCTIONS.iter().map(|function| function.name))
+}
+
+pub fn call(name: &str, arguments: &[f64], angle_mode: AngleMode) -> Result<f64, EvalError> {
+    if let Some(function) = lookup_list(name) {
+        let arguments = arguments.iter().copied().map(Value::Number).collect();
+        return function.call(arguments)?.number("scalar evaluation");
+    }
+
+    lookup(name)
+        .ok_or_else(|| EvalError::UnknownFunction(name.to_string()))?
+        .call(arguments, angle_mode)
+}
+
+impl Function {
+    pub fn call(&self, arguments: &[f64], angle_mode: AngleMode) -> Result<f64, EvalError> {
+        if !self.arity.accepts(arguments.len()) {
+            return Err(EvalError::ArgumentCount {
+                function: self.name.to_string(),
+                expected: self.arity,
+                found: arguments.len(),
+            });
+        }
+
+        Ok(match (angle_mode, self.angle) {
+            (AngleMode::Degrees, Angle::Argument) => {
+                let radians: Vec<f64> = arguments.iter().map(|x| x.to_radians()).collect();
+                (self.apply)(&radians)
+            }
+            (AngleMode::Degrees, Angle::Result) => (self.apply)(arguments).to_degrees(),
+            _ => (self.apply)(arguments),
+        })
+    }
+
+    // The partial derivatives at `arguments`, per unit of the angle mode.
+    pub fn partials(&self, arguments: &[f64], angle_mode: AngleMode) -> Option<Vec<f64>> {
+        let (arguments, scale) = match (angle_mode, self.angle) {
+            (AngleMode::Degrees, Angle::Argument) => (
+                arguments.iter().map(|x| x.to_radians()).collect(),
+                1f64.to_radians(),
+            ),
+            (AngleMode::Degrees, Angle::Result) => (arguments.to_vec(), 1f64.to_degrees()),
+            _ => (arguments.to_vec(), 1.),
+        };
+
+        let partials = (self.partials)(&arguments)?;
+        Some(
+            partials
+                .into_iter()
+                .map(|partial| partial * scale)
+                .collect(),
+        )
+    }
+}
+
+impl ListFunction {
+    pub fn call(&self, arguments: Vec<Value>) -> Result<Value, EvalError> {
+        if !self.arity.accepts(arguments.len()) {
+            return Err(EvalError::ArgumentCount {
+                function: self.name.to_string(),
+                expected: self.arity,
+                found: arguments.len(),
+            });
+        }
+
+        (self.apply)(arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry() {
+        assert_eq!(call("sqrt", &[16.], AngleMode::Radians), Ok(4.));
+        assert_eq!(call("abs", &[-2.5], AngleMode::Radians), Ok(2.5));
+        assert_eq!(call("exp", &[0.], AngleMode::Radians), Ok(1.));
+        assert_eq!(call("ln", &[0.], AngleMode::Radians), Ok(f64::NEG_INFINITY));
+        assert!(call("sqrt", &[-1.], AngleMode::Radians).unwrap().is_nan());
+        assert!(lookup("Sqrt").is_none());
+        assert_eq!(names().count(), FUNCTIONS.len() + LIST_FUNCTIONS.len());
+
+        assert_eq!(
+            call("sinh", &[1.], AngleMode::Radians),
+            Err(EvalError::UnknownFunction("sinh".into()))
+        );
+        let error = call("cos", &[1., 2.], AngleMode::Radians).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Wrong number of arguments: cos takes 1, got 2"
+        );
+        assert_eq!(error.code(), "argument_count");
+    }
+
+    #[test]
+    fn multiple_arguments() {
+        assert_eq!(call("max", &[1., 3., 2.], AngleMode::Radians), Ok(3.));
+        assert_eq!(call("min", &[4.], AngleMode::Radians), Ok(4.));
+        assert_eq!(call("max", &[f64::NAN, 1.], AngleMode::Radians), Ok(1.));
+        assert_eq!(call("log", &[8., 2.], AngleMode::Radians), Ok(3.));
+        assert_eq!(call("log", &[1000.], AngleMode::Radians), Ok(3.));
+        assert_eq!(call("log", &[1000., 10.], AngleMode::Radians), Ok(3.));
+        assert!((call("log", &[81., 3.], AngleMode::Radians).unwrap() - 4.).abs() < 1e-12);
+        assert_eq!(
+            call("atan2", &[1., 1.], AngleMode::Radians),
+            Ok(std::f64::consts::FRAC_PI_4)
+        );
+
+        for (name, arguments) in [("atan2", &[1.][..]), ("max", &[]), ("log", &[1., 2., 3.])].iter()
+        {
+            let error = call(name, arguments, AngleMode::Radians).unwrap_err();
+            assert_eq!(error.code(), "argument_count", "{}", name);
+        }
+        assert_eq!(
+            call("atan2", &[1.], AngleMode::Radians)
+                .unwrap_err()
+                .to_string(),
+            "Wrong number of arguments: atan2 takes 2, got 1"
+        );
+        assert_eq!(
+            call("max", &[], AngleMode::Radians)
+                .unwrap_err()
+                .to_string(),
+            "Wrong number of arguments: max takes at least 1, got 0"
+        );
+        assert_eq!(Arity::Between(1, 2).to_string(), "1 to 2");
+    }
+
+    #[test]
+    fn spreadsheet_functions() {
+        assert_eq!(call("SUM", &[1., 2., 3.5], AngleMode::Radians), Ok(6.5));
+        assert_eq!(call("AVERAGE", &[2., 4., 9.], AngleMode::Radians), Ok(5.));
+        assert_eq!(call("POWER", &[5., 2.], AngleMode::Radians), Ok(25.));
+        assert_eq!(
+            call("PI", &[], AngleMode::Degrees),
+            Ok(std::f64::consts::PI)
+        );
+        assert!(lookup("sum").is_none());
+        assert_eq!(lookup_ignoring_case("Sqrt").map(|f| f.name), Some("sqrt"));
+        assert_eq!(
+            lookup_ignoring_case("average").map(|f| f.name),
+            Some("AVERAGE")
+        );
+        assert!(lookup_ignoring_case("sinh").is_none());
+    }
+
+    #[test]
+    fn list_functions() {
+        let matrix = |rows: &[&[f64]]| {
+            Value::Matrix(Matrix::from_rows(rows.iter().map(|row| row.to_vec()).collect()).unwrap())
+        };
+        let det = lookup_list("det").unwrap();
+        assert_eq!(
+            det.call(vec![matrix(&[&[1., 2.], &[3., 4.]])]),
+            Ok(Value::Number(-2.))
+        );
+        assert_eq!(
+            det.call(vec![matrix(&[&[1., 2., 3.], &[4., 5., 6.]])]),
+            Err(EvalError::NotSquare(2, 3))
+        );
+        assert_eq!(det.call(vec![Value::List(vec![4.])]), Ok(Value::Number(4.)));
+        assert_eq!(
+            lookup_list("transpose")
+                .unwrap()
+                .call(vec![matrix(&[&[1., 2.], &[3., 4.]])]),
+            Ok(matrix(&[&[1., 3.], &[2., 4.]]))
+        );
+        assert_eq!(
+            lookup_list("identity")
+                .unwrap()
+                .call(vec![Value::Number(2.)]),
+            Ok(matrix(&[&[1., 0.], &[0., 1.]]))
+        );
+        for size in [0., 2.5, -1., 1e9, f64::NAN].iter() {
+            let error = lookup_list("identity")
+                .unwrap()
+                .call(vec![Value::Number(*size)])
+                .unwrap_err();
+            assert_eq!(error.code(), "domain", "{}", size);
+        }
+        assert_eq!(
+            det.call(vec![Value::Number(5.)]).unwrap_err().to_string(),
+            "Type mismatch: cannot apply det to number"
+        );
+        assert_eq!(det.call(vec![]).unwrap_err().code(), "argument_count");
+        let list = |values: &[f64]| Value::List(values.to_vec());
+        let dot = lookup_list("dot").unwrap();
+        assert_eq!(
+            dot.call(vec![list(&[1., 2., 3.]), list(&[4., 5., 6.])]),
+            Ok(Value::Number(32.))
+        );
+        assert_eq!(
+            dot.call(vec![list(&[1., 2.]), list(&[1., 2., 3.])])
+                .unwrap_err()
+                .code(),
+            "shape_mismatch"
+        );
+        assert_eq!(
+            lookup_list("cross")
+                .unwrap()
+                .call(vec![list(&[1., 0., 0.]), list(&[0., 1., 0.])]),
+            Ok(list(&[0., 0., 1.]))
+        );
+        assert_eq!(
+            lookup_list("norm").unwrap().call(vec![list(&[3., 4.])]),
+            Ok(Value::Number(5.))
+        );
+        assert_eq!(
+            lookup_list("norm")
+                .unwrap()
+                .call(vec![Value::Number(3.)])
+                .unwrap_err()
+                .to_string(),
+            "Type mismatch: cannot apply norm to number"
+        );
+
+        assert!(lookup("det").is_none());
+        assert_eq!(arity("transpose"), Some(Arity::Exactly(1)));
+        assert_eq!(arity("sqrt"), Some(Arity::Exactly(1)));
+
+        // Without values of their own, they have a number only where the
+        // function gives one.
+        assert_eq!(
+            call("identity", &[2.], AngleMode::Radians)
+                .unwrap_err()
+                .to_string(),
+            "Type mismatch: cannot apply scalar evaluation to matrix"
+        );
+        assert_eq!(
+            call("det", &[2.], AngleMode::Radians).unwrap_err().code(),
+            "type_mismatch"
+        );
+    }
+
+    #[test]
+    fn partial_derivatives() {
+        let partials = |name, arguments: &[f64], angle_mode| {
+            lookup(name).unwrap().partials(arguments, angle_mode)
+        };
+        assert_eq!(partials("sin", &[0.], AngleMode::Radians), Some(vec![1.]));
+        assert_eq!(partials("exp", &[0.], AngleMode::Radians), Some(vec![1.]));
+        assert_eq!(
+            partials("atan2", &[1., 1.], AngleMode::Radians),
+            Some(vec![0.5, -0.5])
+        );
+        assert_eq!(
+            partials("POWER", &[2., 3.], AngleMode::Radians),
+            Some(vec![12., 8. * 2f64.ln()])
+        );
+        assert_eq!(
+            partials("max", &[1., 3., 2.], AngleMode::Radians),
+            Some(vec![0., 1., 0.])
+        );
+        assert_eq!(partials("min", &[1., 1.], AngleMode::Radians), None);
+        assert_eq!(partials("PI", &[], AngleMode::Radians), Some(vec![]));
+
+        // In degrees, a slope is per degree.
+        let slope = partials("sin", &[60.], AngleMode::Degrees).unwrap()[0];
+        assert!((slope - 0.5f64.to_radians()).abs() < 1e-15);
+        assert_eq!(
+            partials("atan", &[0.], AngleMode::Degrees),
+            Some(vec![1f64.to_degrees()])
+        );
+    }
+
+    #[test]
+    fn angle_modes() {
+        let sine = lookup("sin").unwrap();
+        assert!((sine.call(&[30.], AngleMode::Degrees).unwrap() - 0.5).abs() < 1e-12);
+        assert_eq!(sine.call(&[0.5], AngleMode::Radians), Ok(0.5f64.sin()));
+        assert_eq!(call("atan2", &[1., 1.], AngleMode::Degrees), Ok(45.));
+        assert!((call("sin", &[90.], AngleMode::Degrees).unwrap() - 1.).abs() < 1e-12);
+        assert!((call("tan", &[45.], AngleMode::Degrees).unwrap() - 1.).abs() < 1e-12);
+
+        // Inverses give their angle in the mode's unit.
+        let asin = lookup("asin").unwrap();
+        assert!((asin.call(&[0.5], AngleMode::Degrees).unwrap() - 30.).abs() < 1e-12);
+        assert!(
+            (asin.call(&[0.5], AngleMode::Radians).unwrap() - std::f64::consts::FRAC_PI_6).abs()
+                < 1e-12
+        );
+        assert_eq!(call("acos", &[-1.], AngleMode::Degrees), Ok(180.));
+        assert_eq!(
+            call("acos", &[-1.], AngleMode::Radians),
+            Ok(std::f64::consts::PI)
+        );
+        assert_eq!(call("atan", &[1.], AngleMode::Degrees), Ok(45.));
+        assert!(call("asin", &[2.], AngleMode::Degrees).unwrap().is_nan());
+        // Only functions of angles read or give degrees.
+        assert_eq!(call("sqrt", &[4.], AngleMode::Degrees), Ok(2.));
+        assert_eq!(call("max", &[90., 180.], AngleMode::Degrees), Ok(180.));
+    }
+}
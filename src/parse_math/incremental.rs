@@ -0,0 +1,370 @@
+use super::ast::Node;
+use super::errors::ParseError;
+use super::parser::{Parser, SpannedAst};
+use std::ops::Range;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl Edit {
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = source.to_string();
+        edited.replace_range(self.range.clone(), &self.text);
+
+        edited
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Reparse {
+    // Only the text in `reparsed`, between a pair of delimiters, was parsed
+    // again; everything else was reused and shifted.
+    Incremental {
+        ast: SpannedAst,
+        reparsed: Range<usize>,
+    },
+    // The edit could not be confined to one delimited subtree, or the text
+    // there no longer parses on its own, so the whole source was parsed.
+    Full(Result<SpannedAst, ParseError>),
+}
+
+impl Reparse {
+    pub fn into_result(self) -> Result<SpannedAst, ParseError> {
+        match self {
+            Reparse::Incremental { ast, .. } => Ok(ast),
+            Reparse::Full(result) => result,
+        }
+    }
+}
+
+struct Region {
+    index: usize,
+    open: usize,
+    close: usize,
+}
+
+impl Parser<'_> {
+    // `previous` must be the parse_spanned result for `source`, the text
+    // before the edit. The content of a group or list item is parsed the
+    // same wherever it stands, so an edit inside one only needs that
+    // content parsed again, as long as it still parses completely.
+    pub fn reparse(source: &str, previous: SpannedAst, edit: &Edit) -> Reparse {
+        let edited = edit.apply(source);
+        let region = match localize(source, &previous, edit) {
+            Some(region) => region,
+            None => return Reparse::Full(Parser::new(&edited).parse_spanned()),
+        };
+
+        let delta = edit.text.len() as isize - edit.range.len() as isize;
+        let shifted = |position: usize| (position as isize + delta) as usize;
+        let reparsed = region.open + 1..shifted(region.close);
+        let mut parser = Parser::new(&edited[reparsed.clone()]);
+        let replacement = match parser.parse_spanned() {
+            Ok(replacement) if parser.at_end() => replacement,
+            _ => return Reparse::Full(Parser::new(&edited).parse_spanned()),
+        };
+
+        let mut node = previous.node;
+        let replaced = replace(&mut node, &previous.spans, region.index, replacement.node);
+
+        let shift = |span: &Range<usize>| {
+            let start = if span.start >= region.close {
+                shifted(span.start)
+            } else {
+                span.start
+            };
+            let end = if span.end > region.open {
+                shifted(span.end)
+            } else {
+                span.end
+            };
+            start..end
+        };
+        let first = region.index + 1 - replaced;
+        let mut spans = Vec::with_capacity(previous.spans.len() + replacement.spans.len());
+        spans.extend(previous.spans[..first].iter().map(shift));
+        spans.extend(
+            replacement
+                .spans
+                .iter()
+                .map(|span| span.start + reparsed.start..span.end + reparsed.start),
+        );
+        spans.extend(previous.spans[region.index + 1..].iter().map(shift));
+
+        Reparse::Incremental {
+            ast: SpannedAst { node, spans },
+            reparsed,
+        }
+    }
+}
+
+// The innermost node containing the edit whose text is exactly the content
+// of a group or a list item.
+fn localize(source: &str, previous: &SpannedAst, edit: &Edit) -> Option<Region> {
+    let whitespace = |c: char| c.is_ascii_whitespace();
+
+    previous
+        .spans
+        .iter()
+        .enumerate()
+        .filter(|(_, span)| span.start <= edit.range.start && edit.range.end <= span.end)
+        .filter_map(|(index, span)| {
+            let before = source[..span.start].trim_end_matches(whitespace);
+            let after = span.end + source[span.end..].len()
+                - source[span.end..].trim_start_matches(whitespace).len();
+            let delimiters = (before.chars().last()?, source[after..].chars().next()?);
+
+            match delimiters {
+                ('(', ')')
+                | ('[', ']')
+                | ('{', '}')
+                | ('[', ',')
+                | ('(', ',')
+                | (',', ',')
+                | (',', ']')
+                | (',', ')') => Some(Region {
+                    index,
+                    open: before.len() - 1,
+                    close: after,
+                }),
+                _ => None,
+            }
+        })
+        .min_by_key(|region| region.close - region.open)
+}
+
+// Swaps in the replacement for the node at post-order position `target`
+// and returns how many nodes the old subtree had. `spans` are those of
+// `node`'s subtree, so only the path down to the target is visited.
+fn replace(node: &mut Node, spans: &[Range<usize>], target: usize, replacement: Node) -> usize {
+    let root = spans.len() - 1;
+    if target == root {
+        *node = replacement;
+        return spans.len();
+    }
+
+    match node {
+        Node::Element(_) | Node::Variable(_) | Node::CellRef(_) => 0,
+        Node::Negative(child)
+        | Node::Percent(child)
+        | Node::Factorial(child)
+        | Node::CustomUnary(_, _, child)
+        | Node::Not(child) => replace(child, &spans[..root], target, replacement),
+        Node::Sum(left, right)
+        | Node::Subtract(left, right)
+        | Node::Multiply(left, right)
+        | Node::Divide(left, right)
+        | Node::FloorDivide(left, right)
+        | Node::Modulo(left, right)
+        | Node::Power(left, right)
+        | Node::Compare(_, left, right)
+        | Node::CustomBinary(_, left, right)
+        | Node::And(left, right)
+        | Node::Or(left, right) => {
+            let split = subtree_start(spans, root - 1);
+            if target >= split {
+                replace(right, &spans[split..root], target - split, replacement)
+            } else {
+                replace(left, &spans[..split], target, replacement)
+            }
+        }
+        Node::List(items) | Node::Function(_, items) => {
+            replace_among(items.iter_mut().collect(), spans, target, replacement)
+        }
+        Node::Conditional(condition, then, otherwise) => replace_among(
+            vec![&mut **condition, &mut **then, &mut **otherwise],
+            spans,
+            target,
+            replacement,
+        ),
+        Node::Series(_, _, from, to, body) => replace_among(
+            vec![&mut **from, &mut **to, &mut **body],
+            spans,
+            target,
+            replacement,
+        ),
+    }
+}
+
+// Replaces in whichever of the children of the node at the end of `spans`
+// the target is in.
+fn replace_among(
+    children: Vec<&mut Node>,
+    spans: &[Range<usize>],
+    target: usize,
+    replacement: Node,
+) -> usize {
+    let mut end = spans.len() - 1;
+    for (index, child) in children.into_iter().enumerate().rev() {
+        let first = if index == 0 {
+            0
+        } else {
+            subtree_start(spans, end - 1)
+        };
+        if target >= first {
+            return replace(child, &spans[first..end], target - first, replacement);
+        }
+        end = first;
+    }
+    0
+}
+
+// Every node of a subtree starts at or after its root's start, and the
+// node before the subtree in post-order ends before it.
+fn subtree_start(spans: &[Range<usize>], root: usize) -> usize {
+    let start = spans[root].start;
+    let mut first = root;
+    while first > 0 && spans[first - 1].start >= start {
+        first -= 1;
+    }
+
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_math::testing::Trees;
+    use std::time::Instant;
+
+    fn parse(source: &str) -> SpannedAst {
+        Parser::new(source).parse_spanned().unwrap()
+    }
+
+    fn edit(range: Range<usize>, text: &str) -> Edit {
+        Edit {
+            range,
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn spans() {
+        let ast = parse("-(1 + x) * 2");
+        assert_eq!(ast.spans, vec![2..3, 6..7, 2..7, 0..8, 11..12, 0..12]);
+        assert_eq!(ast.span(), 0..12);
+
+        let ast = parse("3(x)^2");
+        assert_eq!(ast.spans, vec![0..1, 2..3, 5..6, 1..6, 0..6]);
+    }
+
+    #[test]
+    fn edits_inside_a_group_are_incremental() {
+        let source = "1 + (2 * 3) - [4, 5]";
+        let edit = edit(9..10, "30");
+        let reparse = Parser::reparse(source, parse(source), &edit);
+        let expected = parse("1 + (2 * 30) - [4, 5]");
+        assert_eq!(
+            reparse,
+            Reparse::Incremental {
+                ast: expected,
+                reparsed: 5..11,
+            }
+        );
+
+        let edit = Edit {
+            range: 18..19,
+            text: "x+1".into(),
+        };
+        match Parser::reparse(source, parse(source), &edit) {
+            Reparse::Incremental { ast, reparsed } => {
+                assert_eq!(reparsed, 17..21);
+                assert_eq!(ast, parse("1 + (2 * 3) - [4, x+1]"));
+            }
+            reparse => panic!("{:?}", reparse),
+        }
+
+        let source = "max(1, 2 * 3, x)";
+        for (range, reparsed) in [(4..5, 4..5), (7..8, 6..12), (14..15, 13..15)].iter() {
+            let argument = Edit {
+                range: range.clone(),
+                text: "y".into(),
+            };
+            let expected = parse(&argument.apply(source));
+            assert_eq!(
+                Parser::reparse(source, parse(source), &argument),
+                Reparse::Incremental {
+                    ast: expected,
+                    reparsed: reparsed.clone(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn structural_edits_fall_back_to_a_full_parse() {
+        let source = "1 + (2 * 3)";
+        for (range, text) in [(0..1, "5"), (10..11, ""), (6..7, ")+("), (4..5, "")].iter() {
+            let edit = edit(range.clone(), text);
+            let edited = edit.apply(source);
+            let reparse = Parser::reparse(source, parse(source), &edit);
+            assert_eq!(
+                reparse,
+                Reparse::Full(Parser::new(&edited).parse_spanned()),
+                "{}",
+                edited
+            );
+        }
+    }
+
+    #[test]
+    fn matches_a_full_parse_after_random_edits() {
+        let pieces = [
+            "", "", "x", "7", "+", "-", "*", "^", "%", "(", ")", "[", "]", ",", " ", "(y+1)",
+        ];
+        let mut trees = Trees::new(0x0bad_5eed);
+        trees.decimals = false;
+        let mut incremental = 0;
+
+        for _ in 0..5000 {
+            let source = trees.node(5).to_string();
+            let start = trees.next(source.len() as u64 + 1) as usize;
+            let end = start + trees.next((source.len() - start).min(3) as u64 + 1) as usize;
+            let edit = edit(start..end, pieces[trees.next(pieces.len() as u64) as usize]);
+
+            let expected = Parser::new(&edit.apply(&source)).parse_spanned();
+            let reparse = Parser::reparse(&source, parse(&source), &edit);
+            if let Reparse::Incremental { .. } = reparse {
+                incremental += 1;
+            }
+            assert_eq!(reparse.into_result(), expected, "{:?} in {}", edit, source);
+        }
+        assert!(incremental > 800, "{}", incremental);
+    }
+
+    // cargo test --release -- --ignored --nocapture reparse_benchmark
+    #[test]
+    #[ignore]
+    fn reparse_benchmark() {
+        let terms: Vec<String> = (0..3200)
+            .map(|i| format!("({} + x) * {}", i, i % 7))
+            .collect();
+        let source = terms.join(" + ");
+        assert!(source.len() > 50_000);
+        let position = source.len() / 2;
+        let position = position + source[position..].find('x').unwrap();
+        let edit = edit(position..position + 1, "y");
+        let edited = edit.apply(&source);
+
+        let start = Instant::now();
+        let full = Parser::new(&edited).parse_spanned();
+        let full_time = start.elapsed();
+
+        let previous = parse(&source);
+        let start = Instant::now();
+        let reparse = Parser::reparse(&source, previous, &edit);
+        let incremental_time = start.elapsed();
+
+        assert!(matches!(reparse, Reparse::Incremental { .. }));
+        assert_eq!(reparse.into_result(), full);
+        println!(
+            "{} bytes: full parse {:?}, incremental {:?}",
+            source.len(),
+            full_time,
+            incremental_time
+        );
+    }
+}
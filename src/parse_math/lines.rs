@@ -0,0 +1,234 @@
+use super::ast::Node;
+use super::context::Context;
+use super::errors::ParseError;
+use super::parser::{Parser, ParserOptions};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::iter::Enumerate;
+use std::str::Lines;
+
+#[derive(PartialEq, Debug)]
+pub struct Line {
+    pub number: usize,
+    pub input: String,
+    pub result: Result<f64, ParseError>,
+}
+
+// An error in one line of a document, numbered from 1. Its position is
+// the column in that line.
+#[derive(PartialEq, Debug)]
+pub struct LineError {
+    pub line: usize,
+    pub error: ParseError,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+pub struct SourceLines<R> {
+    reader: R,
+    number: usize,
+}
+
+pub fn source_lines<R: BufRead>(reader: R) -> SourceLines<R> {
+    SourceLines { reader, number: 0 }
+}
+
+impl<R: BufRead> Iterator for SourceLines<R> {
+    type Item = io::Result<(usize, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut buffer = String::new();
+            match self.reader.read_line(&mut buffer) {
+                Ok(0) => return None,
+                Ok(_) => self.number += 1,
+                Err(error) => return Some(Err(error)),
+            }
+
+            let input = buffer.trim();
+            if is_skipped(input) {
+                continue;
+            }
+
+            return Some(Ok((self.number, input.to_string())));
+        }
+    }
+}
+
+fn is_skipped(input: &str) -> bool {
+    input.is_empty() || input.starts_with('#')
+}
+
+pub struct EvaluateLines<R> {
+    lines: SourceLines<R>,
+    context: Context,
+    options: ParserOptions,
+}
+
+pub fn evaluate_lines<R: BufRead>(reader: R) -> EvaluateLines<R> {
+    evaluate_lines_with(reader, Context::new(), ParserOptions::default())
+}
+
+// Each line is parsed with `options` and evaluated in `context`, in its
+// angle mode and with its variables, but does not change it.
+pub fn evaluate_lines_with<R: BufRead>(
+    reader: R,
+    context: Context,
+    options: ParserOptions,
+) -> EvaluateLines<R> {
+    EvaluateLines {
+        lines: source_lines(reader),
+        context,
+        options,
+    }
+}
+
+impl<R: BufRead> Iterator for EvaluateLines<R> {
+    type Item = io::Result<Line>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?.map(|(number, input)| Line {
+            number,
+            result: Parser::with_context_and_options(&input, &self.context, self.options)
+                .evaluate(),
+            input,
+        });
+
+        Some(line)
+    }
+}
+
+pub struct ParsedLines<'a> {
+    lines: Enumerate<Lines<'a>>,
+}
+
+impl Parser<'_> {
+    // Parses every line of `source` as an expression of its own, skipping
+    // blank lines and comments. A line that fails does not stop the ones
+    // after it, so every error in a document can be reported at once.
+    pub fn parse_all(source: &str) -> ParsedLines<'_> {
+        ParsedLines {
+            lines: source.lines().enumerate(),
+        }
+    }
+}
+
+impl Iterator for ParsedLines<'_> {
+    type Item = Result<Node, LineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, line) = self.lines.find(|(_, line)| !is_skipped(line.trim()))?;
+
+        Some(Parser::new(line).parse().map_err(|error| LineError {
+            line: index + 1,
+            error,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_math::context::AngleMode;
+
+    #[test]
+    fn skips_blank_and_comment_lines() {
+        let lines: Vec<Line> = evaluate_lines("# header\n1+2\n\n  \n2*(3\n# note\n4".as_bytes())
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                Line {
+                    number: 2,
+                    input: "1+2".into(),
+                    result: Ok(3.),
+                },
+                Line {
+                    number: 5,
+                    input: "2*(3".into(),
+                    result: Err(ParseError::UnexpectedEndOfInput {
+                        expected: "')'",
+                        position: 4,
+                    }),
+                },
+                Line {
+                    number: 7,
+                    input: "4".into(),
+                    result: Ok(4.),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_all() {
+        let document = "1 + 2\n  x * (y - 1)\n\n# a comment\n2 * (3 +\nsqrt(x)\n\r\n-x^2\r\n";
+        let (nodes, errors): (Vec<_>, Vec<_>) =
+            Parser::parse_all(document).partition(Result::is_ok);
+        let nodes: Vec<Node> = nodes.into_iter().map(Result::unwrap).collect();
+        let errors: Vec<LineError> = errors.into_iter().map(Result::unwrap_err).collect();
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(nodes[1], Parser::new("x * (y - 1)").parse().unwrap());
+        assert_eq!(nodes[3].to_string(), "-x ^ 2");
+        // The position is the column in the line, leading spaces included.
+        assert_eq!(
+            errors,
+            vec![LineError {
+                line: 5,
+                error: ParseError::UnexpectedEndOfInput {
+                    expected: "a number or '('",
+                    position: 8,
+                },
+            }]
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "line 5: Unexpected end of input at position 8, expected a number or '('"
+        );
+
+        assert_eq!(Parser::parse_all("").count(), 0);
+        let lines: Vec<usize> = Parser::parse_all("1 +\n 2 2\n3")
+            .filter_map(Result::err)
+            .map(|error| error.line)
+            .collect();
+        assert_eq!(lines, vec![1, 2]);
+        let all: Result<Vec<Node>, LineError> = Parser::parse_all("1\n# two\n3").collect();
+        assert_eq!(all.map(|nodes| nodes.len()), Ok(2));
+    }
+
+    #[test]
+    fn lines_in_a_context() {
+        let mut context = Context::new();
+        context.set_angle_mode(AngleMode::Degrees);
+        context.set("r", 2.);
+        let results: Vec<Result<f64, ParseError>> = evaluate_lines_with(
+            "sin(90) * r\nr = 3\n2r\n".as_bytes(),
+            context.clone(),
+            ParserOptions::default(),
+        )
+        .map(|line| line.unwrap().result)
+        .collect();
+        assert_eq!(results[0], Ok(2.));
+        assert!(results[1].is_err());
+        assert_eq!(results[2], Ok(4.));
+
+        let options = ParserOptions::default().implicit_multiplication(false);
+        let mut lines = evaluate_lines_with("2r\n".as_bytes(), context, options);
+        assert!(lines.next().unwrap().unwrap().result.is_err());
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let results: Vec<Result<f64, ParseError>> = evaluate_lines("1+1\r\n2^3\r\n".as_bytes())
+            .map(|line| line.unwrap().result)
+            .collect();
+
+        assert_eq!(results, vec![Ok(2.), Ok(8.)]);
+    }
+}
@@ -0,0 +1,245 @@
+use super::errors::EvalError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    columns: usize,
+    values: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Result<Matrix, EvalError> {
+        let columns = rows.first().map_or(0, Vec::len);
+        if let Some((index, row)) = rows
+            .iter()
+            .enumerate()
+            .find(|(_, row)| row.len() != columns)
+        {
+            return Err(EvalError::RaggedMatrix {
+                row: index + 1,
+                expected: columns,
+                found: row.len(),
+            });
+        }
+
+        Ok(Matrix {
+            rows: rows.len(),
+            columns,
+            values: rows.into_iter().flatten().collect(),
+        })
+    }
+
+    pub fn identity(size: usize) -> Matrix {
+        let mut values = vec![0.; size * size];
+        for index in 0..size {
+            values[index * size + index] = 1.;
+        }
+
+        Matrix {
+            rows: size,
+            columns: size,
+            values,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn shape(&self) -> Vec<usize> {
+        vec![self.rows, self.columns]
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        self.values[row * self.columns + column]
+    }
+
+    pub fn row(&self, row: usize) -> &[f64] {
+        &self.values[row * self.columns..(row + 1) * self.columns]
+    }
+
+    pub fn map<F: Fn(f64) -> f64>(&self, apply: F) -> Matrix {
+        Matrix {
+            values: self.values.iter().map(|value| apply(*value)).collect(),
+            ..*self
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let values = (0..self.columns)
+            .flat_map(|column| (0..self.rows).map(move |row| (row, column)))
+            .map(|(row, column)| self.get(row, column))
+            .collect();
+
+        Matrix {
+            rows: self.columns,
+            columns: self.rows,
+            values,
+        }
+    }
+
+    pub fn sum(&self, other: &Matrix) -> Result<Matrix, EvalError> {
+        self.elementwise("addition", other, |a, b| a + b)
+    }
+
+    pub fn difference(&self, other: &Matrix) -> Result<Matrix, EvalError> {
+        self.elementwise("subtraction", other, |a, b| a - b)
+    }
+
+    pub fn product(&self, other: &Matrix) -> Result<Matrix, EvalError> {
+        if self.columns != other.rows {
+            return Err(self.shape_mismatch("matrix product", other));
+        }
+
+        let values = (0..self.rows)
+            .flat_map(|row| (0..other.columns).map(move |column| (row, column)))
+            .map(|(row, column)| {
+                (0..self.columns)
+                    .map(|k| self.get(row, k) * other.get(k, column))
+                    .sum()
+            })
+            .collect();
+
+        Ok(Matrix {
+            rows: self.rows,
+            columns: other.columns,
+            values,
+        })
+    }
+
+    // Gaussian elimination with partial pivoting, so the result carries the
+    // rounding of the elimination rather than being exact for integers.
+    pub fn determinant(&self) -> Result<f64, EvalError> {
+        if self.rows != self.columns {
+            return Err(EvalError::NotSquare(self.rows, self.columns));
+        }
+
+        let size = self.rows;
+        let mut values = self.values.clone();
+        let mut determinant = 1.;
+
+        for column in 0..size {
+            let pivot = (column..size)
+                .max_by(|&a, &b| {
+                    let (a, b) = (values[a * size + column], values[b * size + column]);
+                    a.abs().total_cmp(&b.abs())
+                })
+                .unwrap_or(column);
+            if values[pivot * size + column] == 0. {
+                return Ok(0.);
+            }
+            if pivot != column {
+                for k in 0..size {
+                    values.swap(pivot * size + k, column * size + k);
+                }
+                determinant = -determinant;
+            }
+
+            let diagonal = values[column * size + column];
+            determinant *= diagonal;
+            for row in column + 1..size {
+                let factor = values[row * size + column] / diagonal;
+                for k in column..size {
+                    values[row * size + k] -= factor * values[column * size + k];
+                }
+            }
+        }
+
+        Ok(determinant)
+    }
+
+    fn elementwise<F>(
+        &self,
+        operation: &'static str,
+        other: &Matrix,
+        apply: F,
+    ) -> Result<Matrix, EvalError>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        if self.rows != other.rows || self.columns != other.columns {
+            return Err(self.shape_mismatch(operation, other));
+        }
+
+        let values = self
+            .values
+            .iter()
+            .zip(&other.values)
+            .map(|(a, b)| apply(*a, *b))
+            .collect();
+
+        Ok(Matrix { values, ..*self })
+    }
+
+    fn shape_mismatch(&self, operation: &'static str, other: &Matrix) -> EvalError {
+        EvalError::ShapeMismatch {
+            operation,
+            left: self.shape(),
+            right: other.shape(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[&[f64]]) -> Matrix {
+        Matrix::from_rows(rows.iter().map(|row| row.to_vec()).collect()).unwrap()
+    }
+
+    #[test]
+    fn product() {
+        let a = matrix(&[&[1., 2.], &[3., 4.]]);
+        let b = matrix(&[&[5., 6.], &[7., 8.]]);
+        assert_eq!(a.product(&b), Ok(matrix(&[&[19., 22.], &[43., 50.]])));
+        assert_eq!(a.product(&Matrix::identity(2)), Ok(a.clone()));
+
+        let column = matrix(&[&[1.], &[1.]]);
+        assert_eq!(a.product(&column), Ok(matrix(&[&[3.], &[7.]])));
+    }
+
+    #[test]
+    fn shape_errors() {
+        let a = matrix(&[&[1., 2., 3.], &[4., 5., 6.]]);
+        let error = a.product(&a).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Shape mismatch: cannot apply matrix product to 2x3 and 2x3"
+        );
+        assert_eq!(error.code(), "shape_mismatch");
+        assert_eq!(
+            a.sum(&a.transpose()).unwrap_err().to_string(),
+            "Shape mismatch: cannot apply addition to 2x3 and 3x2"
+        );
+        assert_eq!(
+            Matrix::from_rows(vec![vec![1., 2.], vec![3.]]),
+            Err(EvalError::RaggedMatrix {
+                row: 2,
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(a.determinant(), Err(EvalError::NotSquare(2, 3)));
+    }
+
+    #[test]
+    fn determinant() {
+        let a = matrix(&[&[6., 1., 1.], &[4., -2., 5.], &[2., 8., 7.]]);
+        assert!((a.determinant().unwrap() + 306.).abs() < 1e-9);
+        assert_eq!(matrix(&[&[0., 1.], &[1., 0.]]).determinant(), Ok(-1.));
+        assert_eq!(matrix(&[&[1., 2.], &[2., 4.]]).determinant(), Ok(0.));
+        assert_eq!(Matrix::identity(4).determinant(), Ok(1.));
+    }
+
+    #[test]
+    fn transpose() {
+        let a = matrix(&[&[1., 2., 3.], &[4., 5., 6.]]);
+        assert_eq!(a.transpose(), matrix(&[&[1., 4.], &[2., 5.], &[3., 6.]]));
+        assert_eq!(a.transpose().transpose(), a);
+    }
+}
@@ -1,4 +1,26 @@
-mod ast;
-mod errors;
+pub mod ast;
+pub mod calculus;
+pub mod context;
+#[cfg(all(test, feature = "differential"))]
+mod differential;
+mod display;
+pub mod equation;
+pub mod equivalence;
+pub mod errors;
+pub mod eval;
+pub mod format;
+pub mod function;
+pub mod incremental;
+pub mod lines;
+pub mod matrix;
+pub mod operator;
 pub mod parser;
-mod token;
+mod random;
+mod simplify;
+pub mod stream;
+#[cfg(test)]
+mod testing;
+pub mod token;
+pub mod trace;
+pub mod value;
+pub mod vector;
@@ -0,0 +1,338 @@
+use super::errors::OperatorError;
+use super::token::{OperationPrecedence, Token, Tokenizer};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+// Whether a unary operator goes before or after its operand.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fixity {
+    Prefix,
+    Postfix,
+}
+
+// An operator defined by the caller, written between its operands as the
+// built-in ones are.
+#[derive(Clone, Copy, Debug)]
+pub struct BinaryOperator {
+    pub symbol: char,
+    pub precedence: OperationPrecedence,
+    pub associativity: Associativity,
+    apply: fn(f64, f64) -> f64,
+}
+
+impl BinaryOperator {
+    pub fn apply(&self, left: f64, right: f64) -> f64 {
+        (self.apply)(left, right)
+    }
+
+    // How tightly the right operand holds on to what follows it: as tightly
+    // as the operator itself when it is left-associative, so that a @ b @ c
+    // is (a @ b) @ c, and one level less when it is right-associative.
+    pub(crate) fn right_precedence(&self) -> OperationPrecedence {
+        match self.associativity {
+            Associativity::Left => self.precedence,
+            Associativity::Right => match self.precedence {
+                OperationPrecedence::Postfix => OperationPrecedence::Power,
+                OperationPrecedence::Power => OperationPrecedence::Juxtaposition,
+                OperationPrecedence::Juxtaposition => OperationPrecedence::MulDiv,
+                OperationPrecedence::MulDiv => OperationPrecedence::AddSub,
+                OperationPrecedence::AddSub => OperationPrecedence::Comparison,
+                OperationPrecedence::Comparison => OperationPrecedence::And,
+                OperationPrecedence::And => OperationPrecedence::Or,
+                OperationPrecedence::Or | OperationPrecedence::Default => {
+                    OperationPrecedence::Default
+                }
+            },
+        }
+    }
+}
+
+// An operator defined by the caller with a single operand. A prefix one
+// takes in the operators after its operand that bind tighter than its
+// precedence, as - takes in ^, and a postfix one applies to as much of
+// what comes before it as binds tighter.
+#[derive(Clone, Copy, Debug)]
+pub struct UnaryOperator {
+    pub symbol: char,
+    pub fixity: Fixity,
+    pub precedence: OperationPrecedence,
+    apply: fn(f64) -> f64,
+}
+
+impl UnaryOperator {
+    pub fn apply(&self, operand: f64) -> f64 {
+        (self.apply)(operand)
+    }
+}
+
+// The operators a Context holds. The parser reads them through it, and
+// evaluating with the context applies them.
+#[derive(Clone, Debug, Default)]
+pub struct Operators {
+    binary: Vec<BinaryOperator>,
+    unary: Vec<UnaryOperator>,
+}
+
+impl Operators {
+    pub fn new() -> Self {
+        Operators::default()
+    }
+
+    // Only a character the tokenizer has no use for can be an operator, so
+    // that nothing that parses now changes meaning. Precedence is one of
+    // the levels between || and ^; Default and Postfix are for operands
+    // and postfix operators.
+    pub fn define_binary(
+        &mut self,
+        symbol: char,
+        precedence: OperationPrecedence,
+        associativity: Associativity,
+        apply: fn(f64, f64) -> f64,
+    ) -> Result<(), OperatorError> {
+        if !is_free(symbol) {
+            return Err(OperatorError::Reserved(symbol));
+        }
+        if self.binary(symbol).is_some() {
+            return Err(OperatorError::AlreadyDefined(symbol));
+        }
+        if self.unary(Fixity::Postfix, symbol).is_some() {
+            return Err(OperatorError::Ambiguous(symbol));
+        }
+        if matches!(
+            precedence,
+            OperationPrecedence::Default | OperationPrecedence::Postfix
+        ) {
+            return Err(OperatorError::Precedence(symbol));
+        }
+
+        self.binary.push(BinaryOperator {
+            symbol,
+            precedence,
+            associativity,
+            apply,
+        });
+        Ok(())
+    }
+
+    // A symbol may be both a prefix and a binary operator, as - is, since
+    // where it stands tells them apart. A postfix operator stands where a
+    // binary one would, and after an operand a prefix one could as well
+    // start a factor multiplied in, so it shares its symbol with neither.
+    pub fn define_unary(
+        &mut self,
+        symbol: char,
+        fixity: Fixity,
+        precedence: OperationPrecedence,
+        apply: fn(f64) -> f64,
+    ) -> Result<(), OperatorError> {
+        if !is_free(symbol) {
+            return Err(OperatorError::Reserved(symbol));
+        }
+        if self.unary(fixity, symbol).is_some() {
+            return Err(OperatorError::AlreadyDefined(symbol));
+        }
+        let postfix = self.unary(Fixity::Postfix, symbol).is_some();
+        let other = match fixity {
+            Fixity::Prefix => postfix,
+            Fixity::Postfix => {
+                self.binary(symbol).is_some() || self.unary(Fixity::Prefix, symbol).is_some()
+            }
+        };
+        if other {
+            return Err(OperatorError::Ambiguous(symbol));
+        }
+        // A postfix operator that binds no tighter than anything would
+        // never apply.
+        if fixity == Fixity::Postfix && precedence == OperationPrecedence::Default {
+            return Err(OperatorError::Precedence(symbol));
+        }
+
+        self.unary.push(UnaryOperator {
+            symbol,
+            fixity,
+            precedence,
+            apply,
+        });
+        Ok(())
+    }
+
+    pub fn binary(&self, symbol: char) -> Option<&BinaryOperator> {
+        self.binary
+            .iter()
+            .find(|operator| operator.symbol == symbol)
+    }
+
+    pub fn unary(&self, fixity: Fixity, symbol: char) -> Option<&UnaryOperator> {
+        self.unary
+            .iter()
+            .find(|operator| operator.fixity == fixity && operator.symbol == symbol)
+    }
+
+    // How tightly `symbol` binds where it follows an operand, as a binary
+    // or a postfix operator.
+    pub(crate) fn after_operand(&self, symbol: char) -> Option<OperationPrecedence> {
+        match self.binary(symbol) {
+            Some(operator) => Some(operator.precedence),
+            None => self
+                .unary(Fixity::Postfix, symbol)
+                .map(|operator| operator.precedence),
+        }
+    }
+
+    pub fn symbols(&self) -> Vec<char> {
+        let binary = self.binary.iter().map(|operator| operator.symbol);
+        let mut symbols: Vec<char> = binary
+            .chain(self.unary.iter().map(|operator| operator.symbol))
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        symbols
+    }
+}
+
+// $, & and the decimal point are left out even though they are not tokens
+// on their own, since $1, && and .5 are.
+fn is_free(symbol: char) -> bool {
+    let text = symbol.to_string();
+    !symbol.is_alphanumeric()
+        && !"$&.".contains(symbol)
+        && Tokenizer::new(&text).next() == Some(Token::Invalid(symbol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registration() {
+        let mut operators = Operators::new();
+        let difference = |a: f64, b: f64| (a - b) / b * 100.;
+        assert_eq!(
+            operators.define_binary(
+                '@',
+                OperationPrecedence::MulDiv,
+                Associativity::Left,
+                difference
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            operators.binary('@').map(|o| o.apply(150., 100.)),
+            Some(50.)
+        );
+        assert_eq!(operators.symbols(), vec!['@']);
+        assert!(operators.binary('?').is_none());
+
+        assert_eq!(
+            operators.define_binary(
+                '@',
+                OperationPrecedence::AddSub,
+                Associativity::Left,
+                difference
+            ),
+            Err(OperatorError::AlreadyDefined('@'))
+        );
+        // Anything the tokenizer reads already, normalized forms included.
+        for symbol in [
+            '+', '^', '%', '!', '=', '<', '|', '(', ',', ';', '#', '×', '−', '$', '&', '.', 'x',
+            '7', ' ',
+        ]
+        .iter()
+        {
+            assert_eq!(
+                operators.define_binary(
+                    *symbol,
+                    OperationPrecedence::MulDiv,
+                    Associativity::Left,
+                    difference
+                ),
+                Err(OperatorError::Reserved(*symbol)),
+                "{:?}",
+                symbol
+            );
+        }
+        assert_eq!(
+            operators.define_binary(
+                '?',
+                OperationPrecedence::Postfix,
+                Associativity::Left,
+                difference
+            ),
+            Err(OperatorError::Precedence('?'))
+        );
+        assert_eq!(
+            OperatorError::Reserved('+').to_string(),
+            "Cannot define an operator '+': the character already has a meaning"
+        );
+    }
+
+    #[test]
+    fn unary_registration() {
+        let mut operators = Operators::new();
+        let round = |x: f64| x.round();
+        let precedence = OperationPrecedence::Power;
+        operators
+            .define_binary(
+                '~',
+                OperationPrecedence::AddSub,
+                Associativity::Left,
+                f64::max,
+            )
+            .unwrap();
+        assert_eq!(
+            operators.define_unary('~', Fixity::Prefix, precedence, round),
+            Ok(())
+        );
+        assert_eq!(
+            operators.define_unary('°', Fixity::Postfix, precedence, f64::to_radians),
+            Ok(())
+        );
+        assert_eq!(
+            operators.unary(Fixity::Prefix, '~').map(|o| o.apply(2.5)),
+            Some(3.)
+        );
+        assert!(operators.unary(Fixity::Postfix, '~').is_none());
+        assert_eq!(
+            operators.after_operand('~'),
+            Some(OperationPrecedence::AddSub)
+        );
+        assert_eq!(operators.after_operand('°'), Some(precedence));
+        assert_eq!(operators.symbols(), vec!['~', '°']);
+
+        for (symbol, fixity, error) in [
+            ('~', Fixity::Prefix, OperatorError::AlreadyDefined('~')),
+            ('~', Fixity::Postfix, OperatorError::Ambiguous('~')),
+            ('°', Fixity::Prefix, OperatorError::Ambiguous('°')),
+            ('!', Fixity::Postfix, OperatorError::Reserved('!')),
+            ('%', Fixity::Postfix, OperatorError::Reserved('%')),
+            ('-', Fixity::Prefix, OperatorError::Reserved('-')),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                operators.define_unary(*symbol, *fixity, precedence, round),
+                Err(error.clone()),
+                "{} {:?}",
+                symbol,
+                fixity
+            );
+        }
+        assert_eq!(
+            operators.define_binary('°', precedence, Associativity::Left, f64::max),
+            Err(OperatorError::Ambiguous('°'))
+        );
+        assert_eq!(
+            operators.define_unary('?', Fixity::Postfix, OperationPrecedence::Default, round),
+            Err(OperatorError::Precedence('?'))
+        );
+        assert_eq!(
+            OperatorError::Ambiguous('°').to_string(),
+            "The operator '°' cannot be postfix as well as prefix or binary"
+        );
+    }
+}
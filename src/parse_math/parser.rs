@@ -1,127 +1,3170 @@
-use super::ast::Node;
-use super::errors::ParseError;
-use super::token::{OperationPrecedence, Token, Tokenizer};
-use std::iter::Peekable;
+use super::ast::{self, Comparison, Node, Series, Statement, UserFunction};
+use super::context::{self, Context};
+use super::equation::Equation;
+use super::errors::{Diagnostic, ParseError};
+use super::function::{self, Arity};
+use super::operator::{Fixity, Operators};
+use super::stream::StreamingTokenizer;
+use super::token::{
+    DecimalSeparator, OperationPrecedence, SpannedToken, Token, Tokenizer, TokenizerOptions,
+};
+use super::trace;
+use super::value::Value;
+use std::borrow::Cow;
+use std::io::BufRead;
+use std::ops::Range;
 
-pub struct Parser<'a> {
-    tokenizer: Peekable<Tokenizer<'a>>,
+pub struct Parser<'a, S = Tokenizer<'a>> {
+    tokens: Tokens<S>,
+    // Empty when the tokens come from a stream.
+    source: &'a str,
+    // Borrowed from the caller, or a copy when statements are parsed one
+    // at a time, each with the context as the ones before it left it.
+    context: Option<Cow<'a, Context>>,
+    spans: Option<Vec<Range<usize>>>,
+    // Names missing from the context stay variables, to be solved for.
+    unknowns: bool,
+    // Whether a semicolon ends the statement, as in a = 3; b = 4; a * b.
+    sequence: bool,
+    // Whether the innermost group being parsed is a pair of bars.
+    in_bars: bool,
+    // The function whose body is being parsed and its parameters, which
+    // stay variables whatever the context holds.
+    definition: Option<(String, Vec<String>)>,
+    // The variables of the sums and products being parsed, which stay
+    // variables in their bodies as parameters do.
+    bound: Vec<String>,
+    options: ParserOptions,
+    // When parsing partially, where the longest expression read so far
+    // that is complete on its own ends.
+    complete: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParserOptions {
+    pub tokenizer: TokenizerOptions,
+    // Square brackets group like parentheses instead of making lists, so
+    // [(1 + 2) * {3 - 1}]^2 reads as textbooks write it. Off by default,
+    // since then there are no list literals: by default 2[3+1] is an error
+    // and [(1 + 2) * {3 - 1}]^2 the power of a one-element list.
+    pub bracket_groups: bool,
+    // Whether an operand straight before a group, bars or a name multiplies
+    // it, as in 2(x + 1), (10)(20) or 2x. Without it those are missing an
+    // operator, which catches a dropped + or - in typed input.
+    pub implicit_multiplication: bool,
+    // Whether implicit multiplication binds tighter than * and /, so that
+    // 1/2x is 1/(2x) and 6/2(1 + 2) is 1, or the same as them, so that
+    // those are (1/2)x and 9. Either way it takes in powers: 2x^2 is
+    // 2(x^2).
+    pub juxtaposition_binds_tight: bool,
+    // Formulas as spreadsheets write them: a leading = is skipped, = and <>
+    // compare, function names are not case-sensitive, and a sign binds
+    // tighter than ^, which is left-associative, so -2^2 is 4 and 2^3^2 is
+    // 64.
+    pub excel_compat: bool,
+    // Names made of letters and then digits, as in A1 or ab12, are cells
+    // of a spreadsheet rather than variables, to be looked up when the
+    // tree is evaluated with Node::eval_with_cells.
+    pub cell_references: bool,
+    // Whether a call has to name a function there is and pass it as many
+    // arguments as it takes. Without it any name before a parenthesis is a
+    // call with any number of arguments, which is all a syntax check needs;
+    // if and the series still take their own.
+    pub check_calls: bool,
+    // How deeply operands may nest, as in ((((...)))). The parser itself
+    // has no limit, but printing, simplifying or comparing a tree recurses
+    // once per level; the default leaves room to spare in the 2 MiB a
+    // spawned thread gets.
+    pub max_depth: usize,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            tokenizer: TokenizerOptions::default(),
+            bracket_groups: false,
+            implicit_multiplication: true,
+            juxtaposition_binds_tight: true,
+            excel_compat: false,
+            cell_references: false,
+            check_calls: true,
+            max_depth: 128,
+        }
+    }
+}
+
+// A grammar policy to keep and reuse, built up from the defaults as in
+// ParserOptions::default().implicit_multiplication(false).max_depth(64).
+impl ParserOptions {
+    pub fn decimal_separator(mut self, separator: DecimalSeparator) -> Self {
+        self.tokenizer.decimal_separator = separator;
+        self
+    }
+
+    pub fn allow_grouping(mut self, grouping: bool) -> Self {
+        self.tokenizer.grouping = grouping;
+        self
+    }
+
+    pub fn allow_magnitude_suffixes(mut self, suffixes: bool) -> Self {
+        self.tokenizer.magnitude_suffixes = suffixes;
+        self
+    }
+
+    pub fn allow_bracket_groups(mut self, bracket_groups: bool) -> Self {
+        self.bracket_groups = bracket_groups;
+        self
+    }
+
+    pub fn implicit_multiplication(mut self, implicit_multiplication: bool) -> Self {
+        self.implicit_multiplication = implicit_multiplication;
+        self
+    }
+
+    pub fn juxtaposition_binds_tight(mut self, tight: bool) -> Self {
+        self.juxtaposition_binds_tight = tight;
+        self
+    }
+
+    pub fn excel_compat(mut self, excel_compat: bool) -> Self {
+        self.excel_compat = excel_compat;
+        self.tokenizer.angle_not_equals = excel_compat;
+        self
+    }
+
+    pub fn cell_references(mut self, cell_references: bool) -> Self {
+        self.cell_references = cell_references;
+        self
+    }
+
+    pub fn check_calls(mut self, check_calls: bool) -> Self {
+        self.check_calls = check_calls;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    fn precedence(&self, token: &Token) -> OperationPrecedence {
+        match token.operation_precedence() {
+            OperationPrecedence::Juxtaposition if !self.juxtaposition_binds_tight => {
+                OperationPrecedence::MulDiv
+            }
+            OperationPrecedence::Default if self.excel_compat && token == &Token::Equals => {
+                OperationPrecedence::Comparison
+            }
+            precedence => precedence,
+        }
+    }
+
+    // How tightly a sign binds its operand, and the right operand of ^.
+    fn sign_precedence(&self) -> OperationPrecedence {
+        match self.excel_compat {
+            true => OperationPrecedence::Power,
+            false => OperationPrecedence::Juxtaposition,
+        }
+    }
+}
+
+// The spans of every node of a tree in post-order, children before their
+// parent and left before right, so the root's span comes last.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SpannedAst {
+    pub node: Node,
+    pub spans: Vec<Range<usize>>,
+}
+
+impl SpannedAst {
+    pub fn span(&self) -> Range<usize> {
+        self.spans.last().cloned().unwrap_or(0..0)
+    }
+}
+
+// Where a parser takes its tokens from: a Tokenizer over a string, or a
+// StreamingTokenizer, which can also fail to read.
+pub trait TokenSource {
+    fn next_token(&mut self) -> Result<SpannedToken, ParseError>;
+}
+
+impl TokenSource for Tokenizer<'_> {
+    fn next_token(&mut self) -> Result<SpannedToken, ParseError> {
+        Ok(self.spanned_token())
+    }
 }
 
 impl<'a> Parser<'a> {
     pub fn new(value: &'a str) -> Self {
-        let tokenizer = Tokenizer::new(value).peekable();
+        Parser::new_with_options(value, ParserOptions::default())
+    }
+
+    pub fn new_with_options(value: &'a str, options: ParserOptions) -> Self {
+        let tokenizer = Tokenizer::new_with_options(value, options.tokenizer);
+        let mut parser = Parser {
+            tokens: Tokens::new(tokenizer),
+            source: value,
+            context: None,
+            spans: None,
+            unknowns: false,
+            sequence: false,
+            in_bars: false,
+            definition: None,
+            bound: Vec::new(),
+            options,
+            complete: None,
+        };
+        if options.excel_compat && parser.tokens.peek() == Ok(&Token::Equals) {
+            let _ = parser.tokens.next();
+        }
+
+        parser
+    }
+
+    pub fn with_options(value: &'a str, options: ParserOptions) -> Self {
+        Parser::new_with_options(value, options)
+    }
+
+    // Parses as much as it can of text with mistakes in it, to show every
+    // one at once. After an error, the text up to the next binary operator
+    // is skipped, closing parentheses included, and parsing goes on with
+    // the operand after it. The parts that parse are joined by those
+    // operators, each as if it were parenthesized. Without mistakes, this
+    // gives what parse does.
+    pub fn parse_with_recovery(&mut self) -> (Option<Node>, Vec<Diagnostic>) {
+        let mut node = None;
+        let mut diagnostics = Vec::new();
+        let mut operator: Option<Combine> = None;
+        let mut position = 0;
+
+        loop {
+            // The text before `position` is blanked out rather than cut off,
+            // so that positions stay those in the whole input.
+            let rest = format!("{}{}", " ".repeat(position), &self.source[position..]);
+            let mut parser = self.resumed(&rest);
+            parser.complete = Some(position);
+            let (part, error) = match parser.ast(OperationPrecedence::Default) {
+                Ok(part) => (Some(part), parser.expect_end().err()),
+                Err(error) => {
+                    let part = match parser.complete {
+                        Some(end) if end > position => self.resumed(&rest[..end]).parse().ok(),
+                        _ => None,
+                    };
+                    (part, Some(error))
+                }
+            };
+
+            if let Some(part) = part {
+                node = Some(match (node, operator) {
+                    (Some(left), Some(combine)) => combine(Box::new(left), Box::new(part)),
+                    _ => part,
+                });
+            }
+            let error = match error {
+                Some(error) => error,
+                None => break,
+            };
+            let span = parser.tokens.looking_at();
+            diagnostics.push(Diagnostic {
+                message: error.to_string(),
+                span: span.clone(),
+            });
+
+            let next = Tokenizer::new_with_options(&rest, self.options.tokenizer)
+                .spanned()
+                .skip_while(|spanned| spanned.span.start < span.start)
+                .find_map(|spanned| Some((binary(&spanned.token)?, spanned.span.end)));
+            match next {
+                Some((combine, end)) => {
+                    operator = Some(combine);
+                    position = end;
+                }
+                None => break,
+            }
+        }
+
+        (node, diagnostics)
+    }
+
+    // A parser with the options and context of this one, for `text`.
+    fn resumed<'b>(&self, text: &'b str) -> Parser<'b>
+    where
+        'a: 'b,
+    {
+        let mut parser = Parser {
+            context: self.context.clone(),
+            ..Parser::new_with_options(text, self.options)
+        };
+        parser.tokens.custom = self.tokens.custom.clone();
+
+        parser
+    }
+
+    // Besides the values and functions it holds, the context's operators
+    // can be used.
+    pub fn with_context(value: &'a str, context: &'a Context) -> Self {
+        Parser::with_context_and_options(value, context, ParserOptions::default())
+    }
+
+    pub fn with_context_and_options(
+        value: &'a str,
+        context: &'a Context,
+        options: ParserOptions,
+    ) -> Self {
+        let mut parser = Parser {
+            context: Some(Cow::Borrowed(context)),
+            ..Parser::new_with_options(value, options)
+        };
+        parser.tokens.custom = context.operators().symbols();
+
+        parser
+    }
+
+    // Keeps `variable` a Variable in the tree even where the context has a
+    // value for it, so that the tree can be evaluated at many values of it.
+    pub fn bind(mut self, variable: &str) -> Self {
+        self.bound.push(variable.to_string());
+        self
+    }
+
+    // The expression at the start of `source` and the offset just past its
+    // last token, for text that goes on with something else. Parsing stops
+    // before the first token that cannot continue the expression, and an
+    // operator without its operand, as the + of 3 + , is left unread too.
+    // Errors that are not about where the expression ends, such as a call
+    // with the wrong number of arguments, are still errors.
+    pub fn parse_partial(source: &str) -> Result<(Node, usize), ParseError> {
+        let mut parser = Parser::new(source);
+        parser.complete = Some(0);
+        let error = match parser.ast(OperationPrecedence::Default) {
+            Ok(node) => return Ok((node, parser.tokens.end)),
+            Err(error) => error,
+        };
+
+        match (&error, parser.complete) {
+            (
+                ParseError::UnknownReference(_)
+                | ParseError::UnknownIdentifier { .. }
+                | ParseError::UnknownFunction { .. }
+                | ParseError::ArgumentCount { .. }
+                | ParseError::TooDeep { .. }
+                | ParseError::RecursiveFunction(_)
+                | ParseError::DuplicateParameter(_)
+                | ParseError::Evaluation(_)
+                | ParseError::Io(_),
+                _,
+            )
+            | (_, None | Some(0)) => Err(error),
+            (_, Some(end)) => Ok((Parser::new(&source[..end]).parse()?, end)),
+        }
+    }
+
+    pub fn is_incomplete(input: &str) -> bool {
+        let mut depth = 0;
+        let mut bars = 0;
+        let mut last = Token::EOF;
+
+        for SpannedToken { token, .. } in Tokenizer::new(input).spanned() {
+            match token {
+                Token::LeftParenthesis | Token::LeftBracket | Token::LeftBrace => depth += 1,
+                Token::RightParenthesis | Token::RightBracket | Token::RightBrace if depth == 0 => {
+                    return false
+                }
+                Token::RightParenthesis | Token::RightBracket | Token::RightBrace => depth -= 1,
+                Token::Pipe => bars += 1,
+                Token::Invalid(_) => return false,
+                Token::EOF => break,
+                _ => {}
+            }
+            last = token;
+        }
+
+        let dangling_operator = matches!(
+            last,
+            Token::Plus
+                | Token::Minus
+                | Token::Asterisk
+                | Token::Slash
+                | Token::Caret
+                | Token::DoubleAsterisk
+                | Token::Equals
+                | Token::DoubleEquals
+                | Token::NotEquals
+                | Token::Less
+                | Token::LessEquals
+                | Token::Greater
+                | Token::GreaterEquals
+                | Token::AndAnd
+                | Token::OrOr
+                | Token::Comma
+        );
+        depth > 0 || bars % 2 == 1 || dangling_operator
+    }
+}
+
+impl<R: BufRead> Parser<'static, StreamingTokenizer<R>> {
+    // Parses input too large to hold as one string. Statements are read as
+    // expressions, since telling an equation apart needs the whole line.
+    pub fn from_reader(reader: R) -> Self {
+        Parser {
+            tokens: Tokens::new(StreamingTokenizer::new(reader)),
+            source: "",
+            context: None,
+            spans: None,
+            unknowns: false,
+            sequence: false,
+            in_bars: false,
+            definition: None,
+            bound: Vec::new(),
+            options: ParserOptions::default(),
+            complete: None,
+        }
+    }
+}
+
+impl<S: TokenSource> Parser<'_, S> {
+    // Names are looked up as they are read, in a context of their own if
+    // none was given, so that an unknown one is reported where it is. The
+    // expression is evaluated with types; a boolean result reads as 1 or 0.
+    pub fn evaluate(&mut self) -> Result<f64, ParseError> {
+        if self.context.is_none() {
+            self.context = Some(Cow::Owned(Context::new()));
+        }
+        let node = self.parse()?;
+        let context = self.context.as_deref().expect("set above");
+
+        match trace::evaluate(&node, context)? {
+            Value::Bool(holds) => Ok(ast::truth(holds)),
+            value => Ok(value.number("scalar evaluation")?),
+        }
+    }
+
+    // Unlike with_context, which puts values in while parsing, this looks
+    // names up in `context` once the whole text is parsed. To evaluate the
+    // same text for other values, keep the tree from parse and call
+    // Node::eval_with on it instead.
+    pub fn evaluate_with(&mut self, context: &Context) -> Result<f64, ParseError> {
+        let node = self.parse()?;
+
+        Ok(node.eval_with(context)?)
+    }
+
+    pub fn parse(&mut self) -> Result<Node, ParseError> {
+        let _span = trace::parse_span(self.source.len());
+
+        self.expression().inspect_err(trace::parse_error)
+    }
+
+    pub fn parse_spanned(&mut self) -> Result<SpannedAst, ParseError> {
+        self.spans = Some(Vec::new());
+        let node = self.parse()?;
+
+        Ok(SpannedAst {
+            node,
+            spans: self.spans.take().unwrap_or_default(),
+        })
+    }
+
+    pub fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        let _span = trace::parse_span(self.source.len());
+
+        self.statement().inspect_err(trace::parse_error)
+    }
+
+    // The next of a sequence of statements separated by semicolons, or None
+    // after the last. Names are looked up in `context` when the statement
+    // is parsed, so whatever the statements before it assigned has to be
+    // in there by then.
+    pub fn next_statement(&mut self, context: &Context) -> Option<Result<Statement, ParseError>> {
+        if self.at_end() {
+            return None;
+        }
+        self.context = Some(Cow::Owned(context.clone()));
+        self.tokens.custom = context.operators().symbols();
+        self.unknowns = false;
+        self.sequence = true;
+
+        Some(self.parse_statement())
+    }
+
+    // Runs a sequence such as a = 3; b = 4; sqrt(a^2 + b^2) statement by
+    // statement and returns the value of the last one that has a value,
+    // which a definition does not. Assignments and definitions are made in
+    // `context`, those before an error included.
+    pub fn evaluate_statements(&mut self, context: &mut Context) -> Result<f64, ParseError> {
+        let mut last = None;
+        while let Some(statement) = self.next_statement(context) {
+            let value = match statement? {
+                Statement::Expression(node) => node.eval_with(context)?,
+                Statement::Assignment(name, node) => {
+                    let value = node.eval_with(context)?;
+                    context.set(&name, value);
+                    value
+                }
+                Statement::Equation(_) => {
+                    return Err(ParseError::UnableToParse("an equation".into()))
+                }
+                Statement::Definition(name, function) => {
+                    context.define(&name, function);
+                    continue;
+                }
+            };
+            last = Some(value);
+        }
+
+        match last {
+            Some(value) => Ok(value),
+            None => Err(ParseError::UnexpectedEndOfInput {
+                expected: "a number or '('",
+                position: self.tokens.start(),
+            }),
+        }
+    }
+
+    pub fn parse_equation(&mut self) -> Result<Equation, ParseError> {
+        let _span = trace::parse_span(self.source.len());
+        self.unknowns = true;
+
+        self.equation().inspect_err(trace::parse_error)
+    }
+
+    // A parenthesized, comma-separated list such as (1, x + 2), which may be
+    // empty. Every comma has to have an argument on both sides.
+    pub fn argument_list(&mut self) -> Result<Vec<Node>, ParseError> {
+        if self.tokens.next()? != Token::LeftParenthesis {
+            return Err(ParseError::ParenthesisNotBalanced);
+        }
+        if self.tokens.peek()? == &Token::RightParenthesis {
+            self.tokens.next()?;
+            return Ok(Vec::new());
+        }
+
+        let mut arguments = Vec::new();
+        loop {
+            let start = self.tokens.start();
+            if let Token::Comma | Token::RightParenthesis = self.tokens.peek()? {
+                return Err(ParseError::MissingArgument(start));
+            }
+            arguments.push(self.ast(OperationPrecedence::Default)?);
+
+            let end = self.tokens.start();
+            match self.tokens.next()? {
+                Token::Comma => {}
+                Token::RightParenthesis => return Ok(arguments),
+                token => return Err(unclosed(&Token::LeftParenthesis, &token, end)),
+            }
+        }
+    }
+
+    // Whether all of the input has been read.
+    pub fn at_end(&mut self) -> bool {
+        matches!(self.tokens.peek(), Ok(Token::EOF))
+    }
+}
+
+impl<S: TokenSource> Parser<'_, S> {
+    fn expression(&mut self) -> Result<Node, ParseError> {
+        let node = self.ast(OperationPrecedence::Default)?;
+        self.expect_end()?;
+
+        Ok(node)
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        let tokens = self.lookahead();
+        if self.is_definition(&tokens) {
+            return self.definition();
+        }
+        if is_equation(&tokens) {
+            self.unknowns = true;
+            return self.equation().map(Statement::Equation);
+        }
+
+        let start = self.tokens.start();
+        let name = match self.tokens.peek()? {
+            Token::Identifier(name) => name.clone(),
+            _ => return self.expression().map(Statement::Expression),
+        };
+        self.tokens.next()?;
+
+        if self.tokens.peek()? == &Token::Equals {
+            self.tokens.next()?;
+            let value = self.expression()?;
+            return Ok(Statement::Assignment(name, value));
+        }
+
+        let mut frames = vec![Frame::Operators {
+            start,
+            precedence: OperationPrecedence::Default,
+            depth: 0,
+        }];
+        let step = self.name(&mut frames, start, name)?;
+        let node = self.run(frames, step)?;
+        self.expect_end()?;
+
+        Ok(Statement::Expression(node))
+    }
+
+    // The expression ends wherever the next token cannot continue it, so
+    // without this check the rest of 1+2) or 3,4 would be dropped.
+    fn expect_end(&mut self) -> Result<(), ParseError> {
+        let position = self.tokens.start();
+        match self.tokens.peek()? {
+            Token::EOF => Ok(()),
+            Token::Semicolon if self.sequence => {
+                self.tokens.next()?;
+                Ok(())
+            }
+            token => Err(ParseError::TrailingInput {
+                token: token.clone(),
+                position,
+            }),
+        }
+    }
+
+    // The tokens of the statement ahead.
+    fn lookahead(&mut self) -> Vec<Token> {
+        let start = self.tokens.start();
+        let sequence = self.sequence;
+        Tokenizer::new(self.source.get(start..).unwrap_or_default())
+            .spanned()
+            .map(|spanned| spanned.token)
+            .take_while(|token| !(sequence && token == &Token::Semicolon))
+            .collect()
+    }
+
+    // A name, a parenthesized list of parameter names and an =. A built-in
+    // function or a name with a value starts an equation instead, as in
+    // sqrt(x) = 3 or a(x + 1) = 4 when a is a variable.
+    fn is_definition(&self, tokens: &[Token]) -> bool {
+        let (name, rest) = match tokens {
+            [Token::Identifier(name), Token::LeftParenthesis, rest @ ..] => (name, rest),
+            _ => return false,
+        };
+        let close = match rest
+            .iter()
+            .position(|token| token == &Token::RightParenthesis)
+        {
+            Some(close) if rest.get(close + 1) == Some(&Token::Equals) => close,
+            _ => return false,
+        };
+        let parameters = &rest[..close];
+        let listed = parameters.is_empty() || parameters.len() % 2 == 1;
+        let alternating = parameters.iter().enumerate().all(|(index, token)| {
+            if index % 2 == 0 {
+                matches!(token, Token::Identifier(_))
+            } else {
+                token == &Token::Comma
+            }
+        });
+
+        listed && alternating && built_in(name).is_none() && !self.has_value(name)
+    }
+
+    fn has_value(&self, name: &str) -> bool {
+        match self.context.as_deref() {
+            Some(context) => context.get(name).is_some(),
+            None => context::constant(name).is_some(),
+        }
+    }
+
+    // Names in the body other than the parameters are looked up as in any
+    // other statement. The body may not call the function, not even
+    // through another one, since a call could then never finish.
+    fn definition(&mut self) -> Result<Statement, ParseError> {
+        let name = match self.tokens.next()? {
+            Token::Identifier(name) => name,
+            _ => unreachable!("is_definition checks the name"),
+        };
+        self.tokens.next()?;
+        let mut parameters = Vec::new();
+        loop {
+            match self.tokens.next()? {
+                Token::Identifier(parameter) if parameters.contains(&parameter) => {
+                    return Err(ParseError::DuplicateParameter(parameter))
+                }
+                Token::Identifier(parameter) => parameters.push(parameter),
+                Token::Comma => {}
+                _ => break,
+            }
+        }
+        self.tokens.next()?;
+
+        self.definition = Some((name, parameters));
+        let body = self.expression();
+        let (name, parameters) = self.definition.take().expect("set above");
+        let body = body?;
+        if self.calls(&body, &name) {
+            return Err(ParseError::RecursiveFunction(name));
+        }
+
+        Ok(Statement::Definition(
+            name,
+            UserFunction { parameters, body },
+        ))
+    }
+
+    // Whether `node` calls `name`, directly or through the functions of
+    // the context it calls.
+    fn calls(&self, node: &Node, name: &str) -> bool {
+        let mut pending = vec![node];
+        let mut visited: Vec<&str> = Vec::new();
+        while let Some(node) = pending.pop() {
+            if let Node::Function(callee, _) = node {
+                if callee == name {
+                    return true;
+                }
+                let function = self
+                    .context
+                    .as_deref()
+                    .and_then(|context| context.function(callee));
+                if let Some(function) = function.filter(|_| !visited.contains(&callee.as_str())) {
+                    visited.push(callee);
+                    pending.push(&function.body);
+                }
+            }
+            pending.extend(node.children());
+        }
+
+        false
+    }
+
+    fn is_parameter(&self, name: &str) -> bool {
+        if self.bound.iter().any(|variable| variable == name) {
+            return true;
+        }
+        match &self.definition {
+            Some((_, parameters)) => parameters.iter().any(|parameter| parameter == name),
+            None => false,
+        }
+    }
+
+    fn equation(&mut self) -> Result<Equation, ParseError> {
+        let left = self.ast(OperationPrecedence::Default)?;
+        let position = self.tokens.start();
+        match self.tokens.next()? {
+            Token::Equals => {}
+            found => {
+                return Err(ParseError::UnexpectedToken {
+                    found,
+                    expected: vec!["'='"],
+                    position,
+                })
+            }
+        }
+        let right = self.ast(OperationPrecedence::Default)?;
+        let position = self.tokens.start();
+        match self.tokens.next()? {
+            Token::EOF => Ok(Equation { left, right }),
+            Token::Semicolon if self.sequence => Ok(Equation { left, right }),
+            found => Err(ParseError::UnexpectedToken {
+                found,
+                expected: vec!["the end of the equation"],
+                position,
+            }),
+        }
+    }
+
+    fn ast(&mut self, operation_precedence: OperationPrecedence) -> Result<Node, ParseError> {
+        self.run(Vec::new(), Step::Operand(operation_precedence))
+    }
+
+    // The parser proper. Whatever waits for an operand, an operator for its
+    // right side or a group for its content, goes on `frames` rather than
+    // the native stack, so neither long chains of operators nor deep nesting
+    // can overflow it.
+    fn run(&mut self, mut frames: Vec<Frame>, mut step: Step) -> Result<Node, ParseError> {
+        let in_bars = self.in_bars;
+        let bound = self.bound.len();
+        let result = loop {
+            let next = match step {
+                Step::Operand(precedence) => self.operand(&mut frames, precedence),
+                Step::Done(node) => match frames.pop() {
+                    Some(frame) => self.resume(&mut frames, frame, node),
+                    None => break Ok(node),
+                },
+            };
+            step = match next {
+                Ok(next) => next,
+                Err(error) => break Err(error),
+            };
+        };
+        self.in_bars = in_bars;
+        self.bound.truncate(bound);
+
+        result
+    }
+
+    // An operand and the operators after it that bind tighter than
+    // `precedence`. Every operand nested in another, by a group, a sign,
+    // an argument or the right side of an operator, starts here.
+    fn operand(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        precedence: OperationPrecedence,
+    ) -> Result<Step, ParseError> {
+        let start = self.tokens.start();
+        let depth = frames
+            .iter()
+            .rev()
+            .find_map(|frame| match frame {
+                Frame::Operators { depth, .. } => Some(*depth),
+                _ => None,
+            })
+            .unwrap_or(0)
+            + 1;
+        if depth > self.options.max_depth {
+            return Err(ParseError::TooDeep {
+                limit: self.options.max_depth,
+                position: start,
+            });
+        }
+        frames.push(Frame::Operators {
+            start,
+            precedence,
+            depth,
+        });
+
+        // A unary plus changes nothing, however many there are.
+        while self.tokens.peek()? == &Token::Plus {
+            self.tokens.next()?;
+        }
+        let start = self.tokens.start();
+        let current_token = self.tokens.next()?;
+
+        // A prefix operator the context defines takes in what binds tighter
+        // than it, as a sign does.
+        if let Token::Custom(symbol) = current_token {
+            let prefix = self
+                .custom_operators()
+                .and_then(|operators| operators.unary(Fixity::Prefix, symbol));
+            if let Some(operator) = prefix {
+                let precedence = operator.precedence;
+                frames.push(Frame::Prefix { start, symbol });
+                return Ok(Step::Operand(precedence));
+            }
+        }
+
+        let node = match current_token {
+            // Negation takes in powers and postfix operators, so -2^2 is
+            // -(2^2), but stops at * and / and at implicit multiplication.
+            Token::Minus => {
+                frames.push(Frame::Negative { start });
+                return Ok(Step::Operand(self.options.sign_precedence()));
+            }
+            // A ! before an operand is logical not, which binds as a sign
+            // does, so !x^2 is !(x^2) and !x > 0 is (!x) > 0.
+            Token::Exclamation => {
+                frames.push(Frame::Not { start });
+                return Ok(Step::Operand(self.options.sign_precedence()));
+            }
+            Token::Number(number) => Node::Element(number),
+            Token::InvalidNumber(literal) => {
+                return Err(ParseError::InvalidNumber {
+                    literal,
+                    span: start..self.tokens.end,
+                })
+            }
+            Token::Identifier(name) => return self.name(frames, start, name),
+            Token::History(number) => self
+                .context
+                .as_deref()
+                .and_then(|context| context.history(number))
+                .map(Node::Element)
+                .ok_or_else(|| ParseError::UnknownReference(format!("${}", number)))?,
+            open @ (Token::LeftParenthesis | Token::LeftBrace) => {
+                return Ok(self.group(frames, open))
+            }
+            open @ Token::LeftBracket if self.options.bracket_groups => {
+                return Ok(self.group(frames, open))
+            }
+            Token::LeftBracket => {
+                let in_bars = self.enter(false);
+                frames.push(Frame::List {
+                    start,
+                    items: Vec::new(),
+                    in_bars,
+                });
+                return Ok(Step::Operand(OperationPrecedence::Default));
+            }
+            Token::Pipe => return Ok(self.bars(frames, start)),
+            // Where an operand is expected, || can only open two pairs.
+            Token::OrOr => {
+                self.tokens.split_bars();
+                return Ok(self.bars(frames, start));
+            }
+            Token::EOF => {
+                return Err(ParseError::UnexpectedEndOfInput {
+                    expected: "a number or '('",
+                    position: start,
+                })
+            }
+            found => {
+                return Err(ParseError::UnexpectedToken {
+                    found,
+                    expected: vec!["a number", "'-'", "'('"],
+                    position: start,
+                });
+            }
+        };
+
+        Ok(Step::Done(self.record(start, node)))
+    }
+
+    fn custom_operators(&self) -> Option<&Operators> {
+        self.context.as_deref().map(Context::operators)
+    }
+
+    // A name followed by a parenthesis calls the built-in or defined
+    // function of that name, with as many arguments as it takes. Otherwise
+    // only a name with a value, or a parameter, may be multiplied by a
+    // group that way, as in x(2); any other name is taken for a function
+    // that does not exist.
+    fn name(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        start: usize,
+        name: String,
+    ) -> Result<Step, ParseError> {
+        let span = start..self.tokens.end;
+        // A cell is a value even before a parenthesis, so A1(2) is A1 * 2.
+        if self.options.cell_references && is_cell(&name) {
+            return Ok(Step::Done(self.record(start, Node::CellRef(name))));
+        }
+        if self.tokens.peek()? != &Token::LeftParenthesis {
+            let node = self.reference(name, span)?;
+            return Ok(Step::Done(self.record(start, node)));
+        }
+        let name = match self.options.excel_compat {
+            true => spreadsheet_name(name),
+            false => name,
+        };
+        let defined = self
+            .context
+            .as_deref()
+            .and_then(|context| context.function(&name))
+            .map(|function| Arity::Exactly(function.parameters.len()));
+        let arity = match built_in(&name) {
+            Some(arity) => Some(arity),
+            None => defined,
+        };
+        let arity = match arity {
+            Some(arity) => arity,
+            None if self.is_parameter(&name) => {
+                return Ok(Step::Done(self.record(start, Node::Variable(name))));
+            }
+            None if matches!(&self.definition, Some((defining, _)) if *defining == name) => {
+                return Err(ParseError::RecursiveFunction(name));
+            }
+            // Without a context a constant stays a name, which multiplies
+            // the group after it as a value would.
+            None if self.context.is_none() && context::constant(&name).is_some() => {
+                return Ok(Step::Done(self.record(start, Node::Variable(name))));
+            }
+            None if !self.options.check_calls => Arity::AtLeast(0),
+            None => {
+                return match self
+                    .context
+                    .as_deref()
+                    .and_then(|context| context.get(&name))
+                {
+                    Some(value) => Ok(Step::Done(self.record(start, Node::Element(value)))),
+                    None => Err(ParseError::UnknownFunction { name, span }),
+                }
+            }
+        };
+
+        self.tokens.next()?;
+        if self.tokens.peek()? == &Token::RightParenthesis {
+            self.tokens.next()?;
+            return self.call(start, name, arity, Vec::new());
+        }
+        let mut arguments = Vec::new();
+        if series(&name).is_some() {
+            let variable = self.index()?;
+            if self.tokens.peek()? == &Token::RightParenthesis {
+                self.tokens.next()?;
+                return self.call(start, name, arity, vec![Node::Variable(variable)]);
+            }
+            let position = self.tokens.start();
+            match self.tokens.next()? {
+                Token::Comma => {}
+                found => {
+                    return Err(ParseError::UnexpectedToken {
+                        found,
+                        expected: vec!["','"],
+                        position,
+                    })
+                }
+            }
+            self.bound.push(variable.clone());
+            arguments.push(Node::Variable(variable));
+        }
+        let in_bars = self.enter(false);
+        self.argument(
+            frames,
+            Frame::Call {
+                start,
+                name,
+                arity,
+                arguments,
+                in_bars,
+            },
+        )
+    }
+
+    // The variable a sum or product runs over, which is only a name and so
+    // has no span of its own.
+    fn index(&mut self) -> Result<String, ParseError> {
+        let position = self.tokens.start();
+        match self.tokens.next()? {
+            Token::Identifier(name) => Ok(name),
+            found => Err(ParseError::UnexpectedToken {
+                found,
+                expected: vec!["a variable"],
+                position,
+            }),
+        }
+    }
+
+    // Every comma has to have an argument on both sides.
+    fn argument(&mut self, frames: &mut Vec<Frame>, call: Frame) -> Result<Step, ParseError> {
+        let start = self.tokens.start();
+        if let Token::Comma | Token::RightParenthesis = self.tokens.peek()? {
+            return Err(ParseError::MissingArgument(start));
+        }
+        frames.push(call);
+
+        Ok(Step::Operand(OperationPrecedence::Default))
+    }
+
+    fn call(
+        &mut self,
+        start: usize,
+        name: String,
+        arity: Arity,
+        arguments: Vec<Node>,
+    ) -> Result<Step, ParseError> {
+        let structural = name == CONDITIONAL || series(&name).is_some();
+        if !arity.accepts(arguments.len()) && (self.options.check_calls || structural) {
+            return Err(ParseError::ArgumentCount {
+                function: name,
+                expected: arity,
+                found: arguments.len(),
+                span: start..self.tokens.end,
+            });
+        }
+
+        let node = if name == CONDITIONAL {
+            let mut parts = arguments.into_iter().map(Box::new);
+            let mut part = || parts.next().expect("if takes three arguments");
+            Node::Conditional(part(), part(), part())
+        } else if let Some(series) = series(&name) {
+            self.bound.pop();
+            let mut parts = arguments.into_iter();
+            let variable = match parts.next().as_ref() {
+                Some(Node::Variable(variable)) => variable.clone(),
+                _ => unreachable!("name reads the variable first"),
+            };
+            let mut part = || Box::new(parts.next().expect("a series takes four arguments"));
+            Node::Series(series, variable, part(), part(), part())
+        } else {
+            Node::Function(name, arguments)
+        };
+        Ok(Step::Done(self.record(start, node)))
+    }
+
+    fn reference(&self, name: String, span: Range<usize>) -> Result<Node, ParseError> {
+        if self.is_parameter(&name) {
+            return Ok(Node::Variable(name));
+        }
+        match self.context.as_deref() {
+            Some(context) => match context.get(&name) {
+                Some(value) => Ok(Node::Element(value)),
+                None if self.unknowns => Ok(Node::Variable(name)),
+                None => Err(ParseError::UnknownIdentifier { name, span }),
+            },
+            None => Ok(Node::Variable(name)),
+        }
+    }
+
+    // Takes in the operators after `left` that bind tighter than
+    // `precedence`, until one needs an operand parsed first.
+    fn operators(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        start: usize,
+        precedence: OperationPrecedence,
+        depth: usize,
+        mut left: Node,
+    ) -> Result<Step, ParseError> {
+        loop {
+            // Outside of any group, everything read so far makes a whole
+            // expression once the operators waiting are applied.
+            if let Some(complete) = &mut self.complete {
+                if frames.iter().all(|frame| {
+                    matches!(
+                        frame,
+                        Frame::Operators { .. }
+                            | Frame::Negative { .. }
+                            | Frame::Not { .. }
+                            | Frame::Prefix { .. }
+                            | Frame::Binary { .. }
+                            | Frame::Custom { .. }
+                            | Frame::Product { .. }
+                    )
+                }) {
+                    *complete = self.tokens.end;
+                }
+            }
+            let factor_follows = self.tokens.factor_follows;
+            let group_closed = self.tokens.group_closed;
+            match self.tokens.peek()? {
+                Token::EOF => break,
+                // Two numbers in a row, as in 1 2, would otherwise leave the
+                // second one unread and the first one as the result.
+                number @ Token::Number(_) if !group_closed => {
+                    let found = number.clone();
+                    return Err(ParseError::UnexpectedToken {
+                        found,
+                        expected: vec!["an operator"],
+                        position: self.tokens.start(),
+                    });
+                }
+                Token::Identifier(_) if !factor_follows => break,
+                Token::Pipe | Token::OrOr if self.in_bars => break,
+                // A list after an operand is not multiplied in.
+                Token::LeftBracket if !self.options.bracket_groups => break,
+                Token::Custom(symbol) => {
+                    let bound = self
+                        .context
+                        .as_deref()
+                        .and_then(|context| context.operators().after_operand(*symbol));
+                    if bound.is_none_or(|bound| precedence >= bound) {
+                        break;
+                    }
+                }
+                operation if precedence >= self.options.precedence(operation) => break,
+                _ => {}
+            }
+
+            let operator_start = self.tokens.start();
+            let operator = self.tokens.next()?;
+            let operation_precedence = self.options.precedence(&operator);
+            let (combine, right): (Combine, _) = match operator {
+                // Right-associative, so 2^3^2 is 2^(3^2): the right operand
+                // may itself contain a power.
+                Token::Caret | Token::DoubleAsterisk => {
+                    (Node::Power, self.options.sign_precedence())
+                }
+                // Only gets here in spreadsheet formulas.
+                Token::Equals => (
+                    |left, right| Node::Compare(Comparison::Equal, left, right),
+                    operation_precedence,
+                ),
+                // Postfix, so it applies to the operand just parsed. A
+                // second % straight after is a typo rather than a percent of
+                // a percent.
+                Token::Percent => {
+                    if self.tokens.peek()? == &Token::Percent {
+                        return Err(ParseError::UnexpectedToken {
+                            found: Token::Percent,
+                            expected: vec!["an operand", "an operator"],
+                            position: self.tokens.start(),
+                        });
+                    }
+
+                    left = self.record(start, Node::Percent(Box::new(left)));
+                    continue;
+                }
+                Token::Exclamation => {
+                    left = self.record(start, Node::Factorial(Box::new(left)));
+                    continue;
+                }
+                // Implicit multiplication by a group, by bars, by a name or,
+                // after a group, by a number, which like a parenthesized
+                // factor takes in the powers after it: 2pi^2 is 2*(pi^2) and
+                // (1 + 1)2^3 is (1 + 1)*(2^3). Bars only get here outside of
+                // bars, where a | after an operand opens a pair instead of
+                // closing one: 2|x| is 2*|x|.
+                factor @ (Token::LeftParenthesis
+                | Token::LeftBracket
+                | Token::LeftBrace
+                | Token::Pipe
+                | Token::Identifier(_)
+                | Token::Number(_)) => {
+                    if !self.options.implicit_multiplication {
+                        return Err(ParseError::MissingOperator(operator_start));
+                    }
+                    frames.push(Frame::Operators {
+                        start,
+                        precedence,
+                        depth,
+                    });
+                    frames.push(Frame::Product { start, left });
+                    frames.push(Frame::Operators {
+                        start: operator_start,
+                        precedence: operation_precedence,
+                        depth,
+                    });
+
+                    return match factor {
+                        Token::Number(number) => {
+                            let node = self.record(operator_start, Node::Element(number));
+                            Ok(Step::Done(node))
+                        }
+                        Token::Pipe => Ok(self.bars(frames, operator_start)),
+                        Token::Identifier(name) => self.name(frames, operator_start, name),
+                        open => Ok(self.group(frames, open)),
+                    };
+                }
+                // Either binary or postfix, whichever the context defines.
+                Token::Custom(symbol) => {
+                    let binary = self
+                        .custom_operators()
+                        .and_then(|operators| operators.binary(symbol))
+                        .map(|operator| operator.right_precedence());
+                    let right = match binary {
+                        Some(right) => right,
+                        None => {
+                            let operand = Box::new(left);
+                            let node = Node::CustomUnary(Fixity::Postfix, symbol.into(), operand);
+                            left = self.record(start, node);
+                            continue;
+                        }
+                    };
+                    frames.push(Frame::Operators {
+                        start,
+                        precedence,
+                        depth,
+                    });
+                    frames.push(Frame::Custom {
+                        start,
+                        left,
+                        symbol,
+                    });
+                    return Ok(Step::Operand(right));
+                }
+                token => match binary(&token) {
+                    Some(combine) => (combine, operation_precedence),
+                    None => {
+                        return Err(ParseError::UnexpectedToken {
+                            found: token,
+                            expected: vec!["an operator"],
+                            position: operator_start,
+                        })
+                    }
+                },
+            };
+
+            frames.push(Frame::Operators {
+                start,
+                precedence,
+                depth,
+            });
+            frames.push(Frame::Binary {
+                start,
+                left,
+                combine,
+            });
+            return Ok(Step::Operand(right));
+        }
+
+        Ok(Step::Done(left))
+    }
+
+    // Carries on with what was waiting for `node`.
+    fn resume(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        frame: Frame,
+        node: Node,
+    ) -> Result<Step, ParseError> {
+        let node = match frame {
+            Frame::Operators {
+                start,
+                precedence,
+                depth,
+            } => return self.operators(frames, start, precedence, depth, node),
+            Frame::Negative { start } => self.record(start, Node::Negative(Box::new(node))),
+            Frame::Not { start } => self.record(start, Node::Not(Box::new(node))),
+            // The right operand of a comparison stops at the next one, so
+            // 1 < x < 3 is caught here rather than read as (1 < x) < 3.
+            Frame::Binary {
+                start,
+                left,
+                combine,
+            } => {
+                let node = combine(Box::new(left), Box::new(node));
+                let next = self.options.precedence(self.tokens.peek()?);
+                if matches!(node, Node::Compare(..)) && next == OperationPrecedence::Comparison {
+                    return Err(ParseError::ChainedComparison(self.tokens.start()));
+                }
+                self.record(start, node)
+            }
+            Frame::Product { start, left } => {
+                self.record(start, Node::Multiply(Box::new(left), Box::new(node)))
+            }
+            Frame::Prefix { start, symbol } => {
+                let node = Node::CustomUnary(Fixity::Prefix, symbol.into(), Box::new(node));
+                self.record(start, node)
+            }
+            Frame::Custom {
+                start,
+                left,
+                symbol,
+            } => self.record(
+                start,
+                Node::CustomBinary(symbol.to_string(), Box::new(left), Box::new(node)),
+            ),
+            // Parentheses, braces and, as an option, brackets group a single
+            // expression and have to be closed by their own kind. A comma in
+            // them would only make sense in an argument list.
+            Frame::Group { open, in_bars } => {
+                self.in_bars = in_bars;
+                let end = self.tokens.start();
+                match self.tokens.next()? {
+                    Token::Comma => return Err(ParseError::UnexpectedComma(end)),
+                    token if token == closing(&open) => node,
+                    token => return Err(unclosed(&open, &token, end)),
+                }
+            }
+            Frame::List {
+                start,
+                mut items,
+                in_bars,
+            } => {
+                items.push(node);
+                let end = self.tokens.start();
+                match self.tokens.next()? {
+                    Token::Comma => {
+                        frames.push(Frame::List {
+                            start,
+                            items,
+                            in_bars,
+                        });
+                        return Ok(Step::Operand(OperationPrecedence::Default));
+                    }
+                    Token::RightBracket => {
+                        self.in_bars = in_bars;
+                        self.record(start, Node::List(items))
+                    }
+                    token => return Err(unclosed(&Token::LeftBracket, &token, end)),
+                }
+            }
+            Frame::Call {
+                start,
+                name,
+                arity,
+                mut arguments,
+                in_bars,
+            } => {
+                arguments.push(node);
+                let end = self.tokens.start();
+                return match self.tokens.next()? {
+                    Token::Comma => self.argument(
+                        frames,
+                        Frame::Call {
+                            start,
+                            name,
+                            arity,
+                            arguments,
+                            in_bars,
+                        },
+                    ),
+                    Token::RightParenthesis => {
+                        self.in_bars = in_bars;
+                        self.call(start, name, arity, arguments)
+                    }
+                    token => Err(unclosed(&Token::LeftParenthesis, &token, end)),
+                };
+            }
+            // Inside bars, || after an operand closes the pair and leaves a
+            // bar to close or open the next one, as two bars would.
+            Frame::Bars { start, in_bars } => {
+                self.in_bars = in_bars;
+                let end = self.tokens.start();
+                match self.tokens.next()? {
+                    Token::Pipe => self.record(start, Node::Function("abs".into(), vec![node])),
+                    Token::OrOr => {
+                        self.tokens.split_bars();
+                        self.record(start, Node::Function("abs".into(), vec![node]))
+                    }
+                    Token::EOF => {
+                        return Err(ParseError::UnexpectedEndOfInput {
+                            expected: "'|'",
+                            position: end,
+                        })
+                    }
+                    _ => return Err(ParseError::BarNotBalanced),
+                }
+            }
+        };
+
+        Ok(Step::Done(node))
+    }
+
+    // The content of a group, read once its opening token is.
+    fn group(&mut self, frames: &mut Vec<Frame>, open: Token) -> Step {
+        let in_bars = self.enter(false);
+        frames.push(Frame::Group { open, in_bars });
+
+        Step::Operand(OperationPrecedence::Default)
+    }
+
+    // The absolute value |x|, read once the opening bar is. A bar where an
+    // operand is expected opens a pair, and one after an operand closes the
+    // innermost pair if that is the innermost group, so ||x| - 1| nests and
+    // (|x|) keeps its bars inside the parentheses.
+    fn bars(&mut self, frames: &mut Vec<Frame>, start: usize) -> Step {
+        let in_bars = self.enter(true);
+        frames.push(Frame::Bars { start, in_bars });
+
+        Step::Operand(OperationPrecedence::Default)
+    }
+
+    // Tells the parser whether the group it enters is a pair of bars, and
+    // returns whether the one it was in is, to go back to at its end.
+    fn enter(&mut self, bars: bool) -> bool {
+        std::mem::replace(&mut self.in_bars, bars)
+    }
+
+    // A node spans from the first token of its construct, including any
+    // opening parenthesis, to the last token consumed.
+    fn record(&mut self, start: usize, node: Node) -> Node {
+        if let Some(spans) = &mut self.spans {
+            spans.push(start..self.tokens.end);
+        }
+
+        node
+    }
+}
+
+// What the parser does next: read an operand, or hand the node just parsed
+// to the frame on top of the stack.
+enum Step {
+    Operand(OperationPrecedence),
+    Done(Node),
+}
+
+// Builds the node of a binary operator from its operands.
+type Combine = fn(Box<Node>, Box<Node>) -> Node;
+
+// Something waiting for the node being parsed. Groups, lists, calls and
+// bars keep whether the parser was inside bars before them.
+enum Frame {
+    // The operators after an operand that started at `start`, as far as
+    // they bind tighter than `precedence`. `depth` counts the operands
+    // this one is nested in, itself included.
+    Operators {
+        start: usize,
+        precedence: OperationPrecedence,
+        depth: usize,
+    },
+    Negative {
+        start: usize,
+    },
+    Not {
+        start: usize,
+    },
+    Binary {
+        start: usize,
+        left: Node,
+        combine: Combine,
+    },
+    // Operators the context defines.
+    Prefix {
+        start: usize,
+        symbol: char,
+    },
+    Custom {
+        start: usize,
+        left: Node,
+        symbol: char,
+    },
+    // Implicit multiplication, as in 2(x + 1) or 2x.
+    Product {
+        start: usize,
+        left: Node,
+    },
+    Group {
+        open: Token,
+        in_bars: bool,
+    },
+    List {
+        start: usize,
+        items: Vec<Node>,
+        in_bars: bool,
+    },
+    Call {
+        start: usize,
+        name: String,
+        arity: Arity,
+        arguments: Vec<Node>,
+        in_bars: bool,
+    },
+    Bars {
+        start: usize,
+        in_bars: bool,
+    },
+}
+
+// if(condition, then, otherwise) is called like a function, but it is not
+// one, since it leaves a branch unevaluated.
+const CONDITIONAL: &str = "if";
+
+// Neither are sum(i, from, to, body) and prod, whose first argument names
+// a variable rather than giving a value.
+fn series(name: &str) -> Option<Series> {
+    match name {
+        "sum" => Some(Series::Sum),
+        "prod" => Some(Series::Product),
+        _ => None,
+    }
+}
+
+// What a binary operator makes of its operands.
+fn binary(operator: &Token) -> Option<Combine> {
+    Some(match operator {
+        Token::Plus => Node::Sum,
+        Token::Minus => Node::Subtract,
+        Token::Asterisk => Node::Multiply,
+        Token::Slash => Node::Divide,
+        Token::DoubleSlash => Node::FloorDivide,
+        Token::Modulo => Node::Modulo,
+        Token::Caret | Token::DoubleAsterisk => Node::Power,
+        Token::AndAnd => Node::And,
+        Token::OrOr => Node::Or,
+        Token::DoubleEquals => |left, right| Node::Compare(Comparison::Equal, left, right),
+        Token::NotEquals => |left, right| Node::Compare(Comparison::NotEqual, left, right),
+        Token::Less => |left, right| Node::Compare(Comparison::Less, left, right),
+        Token::LessEquals => |left, right| Node::Compare(Comparison::LessOrEqual, left, right),
+        Token::Greater => |left, right| Node::Compare(Comparison::Greater, left, right),
+        Token::GreaterEquals => {
+            |left, right| Node::Compare(Comparison::GreaterOrEqual, left, right)
+        }
+        _ => return None,
+    })
+}
+
+// The name a spreadsheet function is known by here, whatever its case.
+fn spreadsheet_name(name: String) -> String {
+    if name.eq_ignore_ascii_case(CONDITIONAL) {
+        return CONDITIONAL.to_string();
+    }
+
+    match function::lookup_ignoring_case(&name) {
+        Some(function) => function.name.to_string(),
+        None => name,
+    }
+}
+
+// One to three letters for the column, as far as XFD goes, and a row from
+// 1 on, so that a name such as atan2 or x0 is not taken for a cell.
+fn is_cell(name: &str) -> bool {
+    let column = name.len()
+        - name
+            .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+            .len();
+    let row = &name[column..];
+
+    (1..=3).contains(&column)
+        && !row.starts_with('0')
+        && !row.is_empty()
+        && row.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn built_in(name: &str) -> Option<Arity> {
+    match name {
+        CONDITIONAL => Some(Arity::Exactly(3)),
+        name if series(name).is_some() => Some(Arity::Exactly(4)),
+        name => function::arity(name),
+    }
+}
+
+// An = anywhere in the statement but straight after a leading name, which
+// would make it an assignment.
+fn is_equation(tokens: &[Token]) -> bool {
+    match tokens {
+        [Token::Identifier(_), Token::Equals, ..] => false,
+        tokens => tokens.contains(&Token::Equals),
+    }
+}
+
+fn closing(open: &Token) -> Token {
+    match open {
+        Token::LeftBracket => Token::RightBracket,
+        Token::LeftBrace => Token::RightBrace,
+        _ => Token::RightParenthesis,
+    }
+}
+
+fn delimiter(token: &Token) -> Option<char> {
+    match token {
+        Token::LeftParenthesis => Some('('),
+        Token::RightParenthesis => Some(')'),
+        Token::LeftBracket => Some('['),
+        Token::RightBracket => Some(']'),
+        Token::LeftBrace => Some('{'),
+        Token::RightBrace => Some('}'),
+        _ => None,
+    }
+}
+
+// What a group opened by `open` and ended by `found` at `position` is
+// wrong with: closed by another kind, cut off, or not closed at all.
+fn unclosed(open: &Token, found: &Token, position: usize) -> ParseError {
+    if found == &Token::EOF {
+        return ParseError::UnexpectedEndOfInput {
+            expected: match open {
+                Token::LeftBracket => "']'",
+                Token::LeftBrace => "'}'",
+                _ => "')'",
+            },
+            position,
+        };
+    }
+
+    match (delimiter(open), delimiter(found)) {
+        (Some(open), Some(close)) if close != '(' && close != '[' && close != '{' => {
+            ParseError::MismatchedGroup {
+                open,
+                close,
+                position,
+            }
+        }
+        _ => ParseError::ParenthesisNotBalanced,
+    }
+}
+
+// A Peekable<Tokenizer> that also knows where its tokens are. A character
+// the tokenizer does not recognize is an error wherever it turns up.
+struct Tokens<S> {
+    source: S,
+    peeked: Option<SpannedToken>,
+    end: usize,
+    last: Range<usize>,
+    // Whether the last token read was a number or a closing parenthesis,
+    // which a name may follow as a factor, as in 2x or (a + b)c.
+    factor_follows: bool,
+    // Whether it closed a group or bars, which a number may follow as a
+    // factor too, as in (a + b)2.
+    group_closed: bool,
+    // The characters the context defines as operators, which come through
+    // as Custom instead of as errors.
+    custom: Vec<char>,
+}
+
+impl<S: TokenSource> Tokens<S> {
+    fn new(source: S) -> Self {
+        Tokens {
+            source,
+            peeked: None,
+            end: 0,
+            last: 0..0,
+            factor_follows: false,
+            group_closed: false,
+            custom: Vec::new(),
+        }
+    }
+
+    fn peek(&mut self) -> Result<&Token, ParseError> {
+        let spanned = match &mut self.peeked {
+            Some(spanned) => spanned,
+            peeked => peeked.insert(self.source.next_token()?),
+        };
+        if let Token::Invalid(character) = spanned.token {
+            if self.custom.contains(&character) {
+                spanned.token = Token::Custom(character);
+            }
+        }
+
+        match spanned.token {
+            Token::Invalid(character) => Err(ParseError::UnexpectedCharacter {
+                character,
+                position: spanned.span.start,
+            }),
+            ref token => Ok(token),
+        }
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        let mut spanned = match self.peeked.take() {
+            Some(spanned) => spanned,
+            None => self.source.next_token()?,
+        };
+        if let Token::Invalid(character) = spanned.token {
+            if self.custom.contains(&character) {
+                spanned.token = Token::Custom(character);
+            }
+        }
+        self.end = spanned.span.end;
+        self.last = spanned.span.clone();
+        self.group_closed = matches!(
+            spanned.token,
+            Token::RightParenthesis | Token::RightBracket | Token::RightBrace | Token::Pipe
+        );
+        self.factor_follows = self.group_closed || matches!(spanned.token, Token::Number(_));
+
+        match spanned.token {
+            Token::Invalid(character) => Err(ParseError::UnexpectedCharacter {
+                character,
+                position: spanned.span.start,
+            }),
+            token => Ok(token),
+        }
+    }
+
+    // Takes back the second bar of the || just read, so that only the
+    // first one is.
+    fn split_bars(&mut self) {
+        self.peeked = Some(SpannedToken {
+            token: Token::Pipe,
+            span: self.end - 1..self.end,
+        });
+        self.end -= 1;
+        self.last.end -= 1;
+        self.factor_follows = true;
+        self.group_closed = true;
+    }
+
+    // The token being looked at: the next one if it has been peeked at,
+    // otherwise the last one read.
+    fn looking_at(&self) -> Range<usize> {
+        self.peeked
+            .as_ref()
+            .map_or(self.last.clone(), |spanned| spanned.span.clone())
+    }
+
+    // Where the next token starts.
+    fn start(&mut self) -> usize {
+        let _ = self.peek();
+        self.peeked
+            .as_ref()
+            .map_or(self.end, |spanned| spanned.span.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_math::errors::EvalError;
+    use crate::parse_math::eval::EvalOptions;
+    use crate::parse_math::function::Arity;
+    use crate::parse_math::operator::{Associativity, Fixity};
+    use crate::parse_math::token::DecimalSeparator;
+    use crate::parse_math::value::Value;
+    use std::collections::HashMap;
+
+    fn operand_expected(found: Token, position: usize) -> ParseError {
+        ParseError::UnexpectedToken {
+            found,
+            expected: vec!["a number", "'-'", "'('"],
+            position,
+        }
+    }
+
+    fn operator_expected(found: Token, position: usize) -> ParseError {
+        ParseError::UnexpectedToken {
+            found,
+            expected: vec!["an operator"],
+            position,
+        }
+    }
+
+    fn unknown_identifier(name: &str, start: usize) -> ParseError {
+        ParseError::UnknownIdentifier {
+            name: name.into(),
+            span: start..start + name.len(),
+        }
+    }
+
+    fn unknown_function(name: &str, start: usize) -> ParseError {
+        ParseError::UnknownFunction {
+            name: name.into(),
+            span: start..start + name.len(),
+        }
+    }
+
+    fn invalid_number(literal: &str, start: usize) -> ParseError {
+        ParseError::InvalidNumber {
+            literal: literal.into(),
+            span: start..start + literal.len(),
+        }
+    }
+
+    #[test]
+    fn incomplete_input() {
+        for input in ["(1+2", "1+", "2*(3-", "((1)", "-", "2^", "[1, 2", "[[1],"].iter() {
+            assert!(Parser::is_incomplete(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn complete_or_invalid_input() {
+        for input in ["", "1+2", "(1)", "1+2)", "(1))+(", "1+$", "(1 $"].iter() {
+            assert!(!Parser::is_incomplete(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn partial_parses() {
+        let parse = |input| Parser::parse_partial(input);
+        let node = |input| Parser::new(input).parse().unwrap();
+
+        assert_eq!(parse("1+2)*x"), Ok((node("1+2"), 3)));
+        assert_eq!(parse("3 + , rest"), Ok((node("3"), 1)));
+        assert_eq!(parse(" 1 +  2  ; next"), Ok((node("1 + 2"), 7)));
+        assert_eq!(parse("2 * (3 + , x"), Ok((node("2"), 1)));
+        assert_eq!(parse("2x^2, y"), Ok((node("2x^2"), 4)));
+        assert_eq!(parse("1 2"), Ok((node("1"), 1)));
+        assert_eq!(parse("1 < 2 < 3"), Ok((node("1 < 2"), 5)));
+        assert_eq!(parse("-x! @ y"), Ok((node("-x!"), 3)));
+        assert_eq!(parse("10 % ]"), Ok((node("10%"), 4)));
+        assert_eq!(parse("max(1, 2) = 3"), Ok((node("max(1, 2)"), 9)));
+        assert_eq!(parse("x"), Ok((node("x"), 1)));
+
+        // Without a complete expression before it, or for an error that is
+        // not about where the expression ends, there is still an error.
+        assert_eq!(parse(", 1"), Err(operand_expected(Token::Comma, 0)));
+        assert!(parse("(1 + 2, x").is_err());
+        assert!(parse("").is_err());
+        assert_eq!(
+            parse("1 + sqrt(1, 2) + 1").unwrap_err().code(),
+            "argument_count"
+        );
+    }
+
+    #[test]
+    fn error_recovery() {
+        let (node, diagnostics) = Parser::new("1 + * 2 ) + $").parse_with_recovery();
+        let spans: Vec<Range<usize>> = diagnostics.iter().map(|d| d.span.clone()).collect();
+        assert_eq!(spans, vec![4..5, 8..9, 12..13]);
+        assert_eq!(
+            diagnostics[0].message,
+            Parser::new("1 + * 2").parse().unwrap_err().to_string()
+        );
+        assert_eq!(
+            diagnostics[2].message,
+            "Unexpected character '$' at position 12"
+        );
+        // The dangling + gives way to the *, and the + before $ has no
+        // operand to join.
+        assert_eq!(node, Parser::new("1 * 2").parse().ok());
+
+        let (node, diagnostics) = Parser::new("(1 + ) * 3 - 4 4").parse_with_recovery();
+        let spans: Vec<Range<usize>> = diagnostics.iter().map(|d| d.span.clone()).collect();
+        assert_eq!(spans, vec![5..6, 15..16]);
+        assert_eq!(node, Parser::new("3 - 4").parse().ok());
+
+        let (node, diagnostics) = Parser::new("max(1, 2, ) + 1 +").parse_with_recovery();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[1].span, 17..17);
+        assert_eq!(node, Parser::new("1").parse().ok());
+
+        let (node, diagnostics) = Parser::new("@").parse_with_recovery();
+        assert_eq!((node, diagnostics.len()), (None, 1));
+
+        // Without mistakes, nothing changes.
+        for input in ["2 * (x + 1)^2", "|x| - 1 < 2 && y", ""].iter() {
+            let (node, diagnostics) = Parser::new(input).parse_with_recovery();
+            assert_eq!(node, Parser::new(input).parse().ok(), "{}", input);
+            assert_eq!(diagnostics.is_empty(), Parser::new(input).parse().is_ok());
+        }
+        let mut context = Context::new();
+        context.set("x", 2.);
+        let (node, diagnostics) = Parser::with_context("x * ) + y", &context).parse_with_recovery();
+        assert_eq!(node, Some(Node::Element(2.)));
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages[1], "Unknown identifier: y");
+    }
+
+    #[test]
+    fn rendered_errors() {
+        let error = Parser::new("1 + $ 2").parse().unwrap_err();
+        assert_eq!(error.span("1 + $ 2"), Some(4..5));
+        assert_eq!(
+            error.render("1 + $ 2"),
+            "Unexpected character '$' at position 4\n  1 + $ 2\n      ^\n"
+        );
+
+        // The whole offending token is underlined, and only its line shown.
+        let source = "max(1, 2)\n(π + 1) 2.50 * 3";
+        let error = ParseError::TrailingInput {
+            token: Token::Number(2.5),
+            position: 19,
+        };
+        assert_eq!(error.span(source), Some(19..23));
+        assert_eq!(
+            error.render(source),
+            "Unexpected '2.5' at position 19\n  (π + 1) 2.50 * 3\n          ^~~~\n"
+        );
+
+        let error = Parser::new("2 *  ").parse().unwrap_err();
+        assert_eq!(error.span("2 *  "), Some(5..5));
+        assert!(error.render("2 *  ").ends_with("\n  2 *  \n       ^\n"));
+        let error = ParseError::ParenthesisNotBalanced;
+        assert_eq!(error.render("(1"), "Balance parenthesis error\n");
+
+        // Names, numbers and calls are underlined where they were read.
+        let error = Parser::with_context("1 + foo", &Context::new())
+            .parse()
+            .unwrap_err();
+        assert_eq!(error.position(), Some(4));
+        assert!(error
+            .render("1 + foo")
+            .ends_with("\n  1 + foo\n      ^~~\n"));
+        let error = Parser::new("1.2.3 + 1").parse().unwrap_err();
+        assert!(error
+            .render("1.2.3 + 1")
+            .ends_with("\n  1.2.3 + 1\n  ^~~~~\n"));
+        let error = Parser::new("2 * sqrt(1,2)").parse().unwrap_err();
+        assert_eq!(error.span("2 * sqrt(1,2)"), Some(4..13));
+        assert!(error
+            .render("2 * sqrt(1,2)")
+            .ends_with("\n  2 * sqrt(1,2)\n      ^~~~~~~~~\n"));
+    }
+
+    #[test]
+    fn history_references() {
+        let mut context = Context::new();
+        context.push_result(3.);
+        context.push_result(4.);
+
+        assert_eq!(Parser::with_context("$1*ans", &context).evaluate(), Ok(12.));
+        assert_eq!(
+            Parser::with_context("$3", &context).evaluate(),
+            Err(ParseError::UnknownReference("$3".into()))
+        );
+        assert_eq!(
+            Parser::new("$1").evaluate(),
+            Err(ParseError::UnknownReference("$1".into()))
+        );
+        assert_eq!(
+            Parser::with_context("ans", &Context::new()).evaluate(),
+            Err(unknown_identifier("ans", 0))
+        );
+    }
+
+    #[test]
+    fn unknown_identifiers() {
+        let error = Parser::new("foo + 1").evaluate().unwrap_err();
+        assert_eq!(error, unknown_identifier("foo", 0));
+        assert_eq!(error.to_string(), "Unknown identifier: foo");
+        assert_eq!(error.code(), "unknown_identifier");
+
+        assert_eq!(
+            Parser::with_context("2 * (x_1 - 1)", &Context::new()).parse(),
+            Err(unknown_identifier("x_1", 5))
+        );
+        // History references that are out of range are not identifiers.
+        assert_eq!(
+            Parser::with_context("$2", &Context::new()).parse(),
+            Err(ParseError::UnknownReference("$2".into()))
+        );
+    }
+
+    #[test]
+    fn context_variables() {
+        let mut context = Context::new();
+        context.set("x", 3.);
+
+        assert_eq!(Parser::with_context("x^2-1", &context).evaluate(), Ok(8.));
+        assert_eq!(
+            Parser::with_context("x*y", &context).evaluate(),
+            Err(unknown_identifier("y", 2))
+        );
+    }
+
+    #[test]
+    fn evaluate_with_a_context() {
+        let node = Parser::new("x^2 + 2*x + 1").parse().unwrap();
+        let mut context = Context::new();
+        for x in [-1., 0., 2.5].iter() {
+            context.set("x", *x);
+            assert_eq!(node.eval_with(&context), Ok((x + 1.) * (x + 1.)));
+        }
+        assert_eq!(
+            Parser::new("x^2 + 2*x + 1").evaluate_with(&context),
+            Ok(12.25)
+        );
+
+        let error = Parser::new("x * y").evaluate_with(&context).unwrap_err();
+        assert_eq!(
+            error,
+            ParseError::Evaluation(EvalError::UnknownVariable("y".into()))
+        );
+        assert_eq!(error.to_string(), "Unknown variable: y");
+        assert_eq!(error.code(), "unknown_variable");
+        assert_eq!(
+            Parser::new("x +")
+                .evaluate_with(&context)
+                .map_err(|e| e.code()),
+            Err("unexpected_end_of_input")
+        );
+    }
+
+    #[test]
+    fn context_shadows_names() {
+        // Variables hide the constants pi and e, and a variable called ans
+        // the last result; a function name is only a function when it is
+        // called.
+        let mut context = Context::new();
+        context.push_result(10.);
+        context.set("pi", 3.);
+        context.set("e", 2.);
+        context.set("ans", 1.);
+        context.set("sqrt", 5.);
+        assert_eq!(Parser::new("2pi + e").evaluate_with(&context), Ok(8.));
+        assert_eq!(Parser::new("ans").evaluate_with(&context), Ok(1.));
+        assert_eq!(
+            Parser::new("sqrt(16) * sqrt").evaluate_with(&context),
+            Ok(20.)
+        );
+    }
+
+    #[test]
+    fn constants() {
+        use std::f64::consts::{E, PI, TAU};
+
+        assert_eq!(Parser::new("2*pi").evaluate(), Ok(TAU));
+        assert_eq!(Parser::new("tau / 2").evaluate(), Ok(PI));
+        assert_eq!(Parser::new("e").evaluate(), Ok(E));
+        // pi(2) is a product, as with any other name that is not a function.
+        assert_eq!(Parser::new("pi(2)").evaluate(), Ok(TAU));
+        assert_eq!(Parser::new("pi(2)").parse(), Parser::new("pi*2").parse());
+        assert_eq!(
+            Parser::new("pie").evaluate(),
+            Err(unknown_identifier("pie", 0))
+        );
+        assert_eq!(
+            Parser::new("PI").evaluate(),
+            Err(unknown_identifier("PI", 0))
+        );
+
+        let mut context = Context::new();
+        assert_eq!(Parser::new("2pi").evaluate_with(&context), Ok(TAU));
+        assert_eq!(Parser::with_context("2pi", &context).evaluate(), Ok(TAU));
+        context.set("pi", 3.);
+        assert_eq!(Parser::new("2pi").evaluate_with(&context), Ok(6.));
+        assert_eq!(Parser::with_context("2pi", &context).evaluate(), Ok(6.));
+        assert_eq!(Parser::new("2pi").evaluate(), Ok(TAU));
+
+        let equation = Parser::new("2x = tau").parse_equation().unwrap();
+        assert_eq!(equation.unknowns(), vec!["x"]);
+        assert_eq!(
+            equation.solve_linear("x"),
+            Ok(crate::parse_math::equation::Solution::Unique(PI))
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let node = Parser::new("2 * if(x, 1, y / 0) + 1").parse().unwrap();
+        assert_eq!(
+            node,
+            Node::Sum(
+                Box::new(Node::Multiply(
+                    Box::new(Node::Element(2.)),
+                    Box::new(Node::Conditional(
+                        Box::new(Node::Variable("x".into())),
+                        Box::new(Node::Element(1.)),
+                        Box::new(Node::Divide(
+                            Box::new(Node::Variable("y".into())),
+                            Box::new(Node::Element(0.))
+                        ))
+                    ))
+                )),
+                Box::new(Node::Element(1.))
+            )
+        );
+        assert_eq!(node.to_string(), "2 * if(x, 1, y / 0) + 1");
+        assert_eq!(node.to_minified(), "2*if(x,1,y/0)+1");
+
+        let mut context = Context::new();
+        context.set("x", 4.);
+        assert_eq!(node.eval_with(&context), Ok(3.));
+        assert_eq!(
+            Parser::new("if(x - 4, 1, 2) * if(0, 1, 2)").evaluate_with(&context),
+            Ok(4.)
+        );
+        assert_eq!(
+            Parser::new("if(1, 2)").parse(),
+            Err(ParseError::ArgumentCount {
+                function: "if".into(),
+                expected: Arity::Exactly(3),
+                found: 2,
+                span: 0..8,
+            })
+        );
+        assert_eq!(
+            Parser::new("if(1, 2, 3)").parse().unwrap().simplify(),
+            Node::Element(2.)
+        );
+        // if cannot be defined, so this is an equation.
+        assert!(matches!(
+            Parser::new("if(x, 1, 2) = 1").parse_statement(),
+            Ok(Statement::Equation(_))
+        ));
+    }
+
+    #[test]
+    fn comparisons() {
+        let element = |number| Box::new(Node::Element(number));
+        assert_eq!(
+            Parser::new("1 + 2 < 4 * 1").parse(),
+            Ok(Node::Compare(
+                Comparison::Less,
+                Box::new(Node::Sum(element(1.), element(2.))),
+                Box::new(Node::Multiply(element(4.), element(1.)))
+            ))
+        );
+        assert_eq!(
+            Parser::new("-x^2 >= 2 - 3").parse().unwrap().to_string(),
+            "-x ^ 2 >= 2 - 3"
+        );
+        for (input, expected) in [
+            ("1 + 2 < 4 * 1", 1.),
+            ("3 <= 3", 1.),
+            ("2 > 3", 0.),
+            ("2 >= 3 - 1", 1.),
+            ("2 ^ 3 == 8", 1.),
+            ("1 != 1", 0.),
+            ("3! == 6", 1.),
+            // Equality is exact, with no tolerance for rounding.
+            ("0.1 + 0.2 == 0.3", 0.),
+            ("nan == nan", 0.),
+            ("nan != nan", 1.),
+        ]
+        .iter()
+        {
+            assert_eq!(Parser::new(input).evaluate(), Ok(*expected), "{}", input);
+        }
+
+        // Plain evaluation counts comparisons as 1 and 0; with types that
+        // takes the bool_arithmetic option.
+        let coerced = [
+            ("(1 < 2) + (2 < 1) + (3 > 2)", 2.),
+            ("(1 < 2) < 3", 1.),
+            ("10 * (4 >= 4)", 10.),
+        ];
+        let mut context = Context::new();
+        context.set_options(EvalOptions {
+            bool_arithmetic: true,
+            ..EvalOptions::default()
+        });
+        for (input, expected) in coerced.iter() {
+            let node = Parser::new(input).parse().unwrap();
+            assert_eq!(node.eval(), *expected, "{}", input);
+            assert_eq!(
+                Parser::with_context(input, &context).evaluate(),
+                Ok(*expected),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            Parser::new("(1 < 2) + 5").evaluate(),
+            Err(ParseError::Evaluation(EvalError::TypeMismatch {
+                operation: "addition",
+                operands: vec!["bool", "number"],
+            }))
+        );
+
+        let mut context = Context::new();
+        context.set("score", 93.);
+        context.set("bonus", 5.);
+        assert_eq!(
+            Parser::new("(score >= 90) * bonus").evaluate_with(&context),
+            Ok(5.)
+        );
+        assert_eq!(
+            Parser::new("if(score < 50, 0, score - 50)").evaluate_with(&context),
+            Ok(43.)
+        );
+
+        for (input, position) in
+            [("1 < 2 < 3", 6), ("x == y != z", 7), ("1 < 2 + 3 >= 4", 10)].iter()
+        {
+            let error = Parser::new(input).parse().unwrap_err();
+            assert_eq!(error, ParseError::ChainedComparison(*position), "{}", input);
+            assert_eq!(error.code(), "chained_comparison");
+        }
+        assert_eq!(
+            ParseError::ChainedComparison(6).to_string(),
+            "Chained comparison at position 6; compare one pair at a time"
+        );
+        assert!(Parser::is_incomplete("1 <"));
+    }
+
+    #[test]
+    fn logical_operators() {
+        let x = || Box::new(Node::Variable("x".into()));
+        let element = |number| Box::new(Node::Element(number));
+        assert_eq!(
+            Parser::new("x > 0 && x < 10").parse(),
+            Ok(Node::And(
+                Box::new(Node::Compare(Comparison::Greater, x(), element(0.))),
+                Box::new(Node::Compare(Comparison::Less, x(), element(10.)))
+            ))
+        );
+        assert_eq!(
+            Parser::new("x || 1 && 0").parse(),
+            Ok(Node::Or(x(), Box::new(Node::And(element(1.), element(0.)))))
+        );
+        assert_eq!(
+            Parser::new("!x > 0").parse(),
+            Ok(Node::Compare(
+                Comparison::Greater,
+                Box::new(Node::Not(x())),
+                element(0.)
+            ))
+        );
+        assert_eq!(
+            Parser::new("!x^2 + 1").parse(),
+            Ok(Node::Sum(
+                Box::new(Node::Not(Box::new(Node::Power(x(), element(2.))))),
+                element(1.)
+            ))
+        );
+        assert_eq!(
+            Parser::new("!3!").parse(),
+            Ok(Node::Not(Box::new(Node::Factorial(element(3.)))))
+        );
+
+        for (input, expected) in
+            [("1 + 2 > 2 && 3 * 2 == 6", 1.), ("3! == 6 && !(2 < 1)", 1.)].iter()
+        {
+            assert_eq!(Parser::new(input).evaluate(), Ok(*expected), "{}", input);
+        }
+        assert_eq!(
+            Parser::new("0 || 2 - 2").evaluate(),
+            Err(ParseError::Evaluation(EvalError::TypeMismatch {
+                operation: "||",
+                operands: vec!["number"],
+            }))
+        );
+
+        // Numbers only count as conditions in plain evaluation.
+        for (input, expected) in [
+            ("0 || 2 - 2", 0.),
+            ("0 || -4", 1.),
+            ("1 && 0 || 1", 1.),
+            ("1 || 0 && 0", 1.),
+            ("!0 + !5", 1.),
+            ("!!7", 1.),
+            ("(1 || 0) * 5", 5.),
+            ("nan && 1", 1.),
+        ]
+        .iter()
+        {
+            let node = Parser::new(input).parse().unwrap();
+            assert_eq!(node.eval(), *expected, "{}", input);
+        }
+
+        // The right side is only evaluated when the left one does not
+        // decide, so a failing one is never reached.
+        let mut context = Context::new();
+        context.set("x", 0.);
+        for (input, expected) in [
+            ("x != 0 && 1 / x > 2", Ok(0.)),
+            ("x != 0 && (x - 1)! > 2", Ok(0.)),
+            ("x == 0 || (x - 1)! > 2", Ok(1.)),
+            (
+                "x == 0 && (x - 1)! > 2",
+                Err(EvalError::Domain {
+                    operation: "factorial",
+                    value: -1.,
+                }),
+            ),
+        ]
+        .iter()
+        {
+            let node = Parser::with_context(input, &context).parse().unwrap();
+            assert_eq!(node.eval_with(&context), *expected, "{}", input);
+        }
+
+        let node = Parser::new("!(x > 0) || x == 2").parse().unwrap();
+        assert_eq!(node.to_string(), "!(x > 0) || x == 2");
+        assert_eq!(node.to_minified(), "!(x>0)||x==2");
+        let node = Parser::new("(x || 1) && !x!").parse().unwrap();
+        assert_eq!(node.to_minified(), "(x||1)&&!x!");
+        assert_eq!(Parser::new(&node.to_minified()).parse(), Ok(node));
+        assert!(Parser::is_incomplete("x > 0 &&"));
+        assert_eq!(
+            Parser::new("1 & 2").parse(),
+            Err(ParseError::UnexpectedCharacter {
+                character: '&',
+                position: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn names_are_case_sensitive() {
+        let mut context = Context::new();
+        context.set("x", 2.);
+        context.set("X", 3.);
+        assert_eq!(Parser::new("x + 10X").evaluate_with(&context), Ok(32.));
+
+        context.set("pi", 3.);
+        assert_eq!(
+            Parser::new("PI").evaluate_with(&context),
+            Err(ParseError::Evaluation(EvalError::UnknownVariable(
+                "PI".into()
+            )))
+        );
+        assert_eq!(
+            Parser::new("Sqrt(4)").evaluate_with(&context),
+            Err(unknown_function("Sqrt", 0))
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let mut context = Context::new();
+        context.set("x", 3.);
+
+        assert_eq!(
+            Parser::with_context("y = x*2", &context).parse_statement(),
+            Ok(Statement::Assignment(
+                "y".into(),
+                Node::Multiply(Box::new(Node::Element(3.)), Box::new(Node::Element(2.)))
+            ))
+        );
+        assert_eq!(
+            Parser::with_context("x(2)", &context).parse_statement(),
+            Ok(Statement::Expression(Node::Multiply(
+                Box::new(Node::Element(3.)),
+                Box::new(Node::Element(2.))
+            )))
+        );
+        assert_eq!(
+            Parser::new("2+3").parse_statement(),
+            Ok(Statement::Expression(Node::Sum(
+                Box::new(Node::Element(2.)),
+                Box::new(Node::Element(3.))
+            )))
+        );
+        assert_eq!(
+            Parser::with_context("y = z", &context).parse_statement(),
+            Err(unknown_identifier("z", 4))
+        );
+        assert!(Parser::is_incomplete("x ="));
+    }
+
+    #[test]
+    fn statement_sequences() {
+        let mut context = Context::new();
+        assert_eq!(
+            Parser::new("a = 3; b = 4; sqrt(a^2 + b^2)").evaluate_statements(&mut context),
+            Ok(5.)
+        );
+        assert_eq!(context.get("b"), Some(4.));
+        assert_eq!(
+            Parser::new("a = a + 1; a = a * 2; a;").evaluate_statements(&mut context),
+            Ok(8.)
+        );
+
+        // A statement sees only what the ones before it assigned, which
+        // stay assigned after an error.
+        let mut context = Context::new();
+        assert_eq!(
+            Parser::new("x = 1; y = z + 1; z = 2").evaluate_statements(&mut context),
+            Err(unknown_identifier("z", 11))
+        );
+        assert_eq!(context.get("x"), Some(1.));
+        assert_eq!(context.get("y"), None);
+
+        assert_eq!(
+            Parser::new("x = 1; y = (2").evaluate_statements(&mut context),
+            Err(ParseError::UnexpectedEndOfInput {
+                expected: "')'",
+                position: 13,
+            })
+        );
+        assert_eq!(
+            Parser::new("1 ; ").evaluate_statements(&mut context),
+            Ok(1.)
+        );
+        assert_eq!(
+            Parser::new("1; ;").evaluate_statements(&mut context),
+            Err(operand_expected(Token::Semicolon, 3))
+        );
+        assert_eq!(
+            Parser::new("x = 2; x + 1 = 3").evaluate_statements(&mut context),
+            Err(ParseError::UnableToParse("an equation".into()))
+        );
+        assert_eq!(
+            Parser::new("1; 2").parse_statement(),
+            Err(ParseError::TrailingInput {
+                token: Token::Semicolon,
+                position: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn user_functions() {
+        let mut context = Context::new();
+        context.set("a", 10.);
+        assert_eq!(
+            Parser::new("hyp(a, b) = sqrt(a^2 + b^2); hyp(3, 4)").evaluate_statements(&mut context),
+            Ok(5.)
+        );
+        // Parameters hide variables; other names in the body take the
+        // value they have when the function is defined.
+        assert_eq!(
+            Parser::new("f(x) = x^2 + a; a = 1; f(3) + hyp(a, 0)")
+                .evaluate_statements(&mut context),
+            Ok(20.)
+        );
+        assert_eq!(context.get("x"), None);
+        assert_eq!(
+            Parser::new("g(x, y) = hyp(x, y) * f(y); g(0, 1); k() = 4; k() + g(3, 4)")
+                .evaluate_statements(&mut context),
+            Ok(134.)
+        );
+        assert_eq!(
+            Parser::new("h(t) = t(t + 1); h(2)").evaluate_statements(&mut context),
+            Ok(6.)
+        );
+        assert_eq!(
+            Parser::with_context("hyp(1)", &context).parse(),
+            Err(ParseError::ArgumentCount {
+                function: "hyp".into(),
+                expected: Arity::Exactly(2),
+                found: 1,
+                span: 0..6,
+            })
+        );
+
+        // Without a context the body keeps its free names, which the call
+        // looks up in whatever context it is evaluated with.
+        let definition = Parser::new("scale(x) = x * c").parse_statement();
+        let function = match definition {
+            Ok(Statement::Definition(name, function)) if name == "scale" => function,
+            statement => panic!("{:?}", statement),
+        };
+        context.define("scale", function);
+        context.set("c", 3.);
+        let call = Parser::with_context("scale(2)", &context).parse().unwrap();
+        assert_eq!(call.eval_with(&context), Ok(6.));
+        let call = Node::Function("scale".into(), vec![]);
+        assert_eq!(
+            call.eval_with(&context).unwrap_err().to_string(),
+            "Wrong number of arguments: scale takes 1, got 0"
+        );
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        let mut context = Context::new();
+        assert_eq!(
+            Parser::new("f(x) = x * f(x - 1)").evaluate_statements(&mut context),
+            Err(ParseError::RecursiveFunction("f".into()))
+        );
+        assert_eq!(
+            Parser::new("f(x) = x; g(x) = f(x) + 1; f(x) = g(x)").evaluate_statements(&mut context),
+            Err(ParseError::RecursiveFunction("f".into()))
+        );
+        assert_eq!(
+            Parser::new("f(2)").evaluate_statements(&mut context),
+            Ok(2.)
+        );
+        let error = Parser::new("d(x, y, x) = 1").parse_statement().unwrap_err();
+        assert_eq!(error, ParseError::DuplicateParameter("x".into()));
+        assert_eq!(error.to_string(), "Parameter named twice: x");
+        assert_eq!(
+            Parser::new("d(x) = x + y").evaluate_statements(&mut context),
+            Err(unknown_identifier("y", 11))
+        );
+
+        // A built-in function or a name with a value makes an equation.
+        context.set("a", 2.);
+        for input in ["sqrt(x) = 3", "a(x + 1) = 4", "f(x) + 1 = 2", "pi(x) = 1"].iter() {
+            let statement = Parser::with_context(input, &context).parse_statement();
+            assert!(
+                matches!(statement, Ok(Statement::Equation(_))),
+                "{}: {:?}",
+                input,
+                statement
+            );
+        }
+        assert_eq!(
+            Parser::with_context("q(x, 1) = 2", &context).parse_statement(),
+            Err(unknown_function("q", 0))
+        );
+    }
+
+    #[test]
+    fn next_statement() {
+        let mut context = Context::new();
+        context.set("a", 1.);
+        let mut parser = Parser::new("a = 2; a*x = 4; a");
 
-        Parser { tokenizer }
+        assert_eq!(
+            parser.next_statement(&context),
+            Some(Ok(Statement::Assignment("a".into(), Node::Element(2.))))
+        );
+        context.set("a", 2.);
+        assert_eq!(
+            parser.next_statement(&context),
+            Some(Ok(Statement::Equation(Equation {
+                left: Node::Multiply(
+                    Box::new(Node::Element(2.)),
+                    Box::new(Node::Variable("x".into()))
+                ),
+                right: Node::Element(4.),
+            })))
+        );
+        assert_eq!(
+            parser.next_statement(&context),
+            Some(Ok(Statement::Expression(Node::Element(2.))))
+        );
+        assert_eq!(parser.next_statement(&context), None);
     }
 
-    pub fn evaluate(&mut self) -> Result<f64, ParseError> {
-        let result = self.parse()?.eval();
+    #[test]
+    fn unexpected_characters() {
+        let unexpected = |character, position| {
+            Err::<Node, _>(ParseError::UnexpectedCharacter {
+                character,
+                position,
+            })
+        };
+        assert_eq!(Parser::new("2 $ 3").parse(), unexpected('$', 2));
+        assert_eq!(Parser::new("§").parse(), unexpected('§', 0));
+        assert_eq!(Parser::new("(1 + 2)&").parse(), unexpected('&', 7));
+        assert_eq!(Parser::new("[1, é]").parse(), unexpected('é', 4));
+        assert_eq!(Parser::new("1 + (2 ~ 3)").parse(), unexpected('~', 7));
+        // Positions are byte offsets into the source.
+        assert_eq!(Parser::new("x × y ¤").parse(), unexpected('¤', 7));
+        assert_eq!(
+            Parser::new("y = 2 @").parse_statement().unwrap_err(),
+            unexpected('@', 6).unwrap_err()
+        );
 
-        Ok(result)
+        let error = Parser::new("2 $ 3").parse().unwrap_err();
+        assert_eq!(error.to_string(), "Unexpected character '$' at position 2");
+        assert_eq!(error.code(), "unexpected_character");
     }
 
-    pub fn parse(&mut self) -> Result<Node, ParseError> {
-        self.ast(OperationPrecedence::Default)
+    #[test]
+    fn equations() {
+        let mut context = Context::new();
+        context.set("a", 2.);
+
+        let x = || Box::new(Node::Variable("x".into()));
+        assert_eq!(
+            Parser::with_context("a*x = x + 1", &context).parse_statement(),
+            Ok(Statement::Equation(Equation {
+                left: Node::Multiply(Box::new(Node::Element(2.)), x()),
+                right: Node::Sum(x(), Box::new(Node::Element(1.))),
+            }))
+        );
+        assert_eq!(
+            Parser::with_context("x = 1", &context).parse_statement(),
+            Ok(Statement::Assignment("x".into(), Node::Element(1.)))
+        );
+        assert_eq!(
+            Parser::new("x + 1 = 2 = 3").parse_equation(),
+            Err(ParseError::UnexpectedToken {
+                found: Token::Equals,
+                expected: vec!["the end of the equation"],
+                position: 10,
+            })
+        );
+        assert_eq!(
+            Parser::new("x + 1").parse_equation(),
+            Err(ParseError::UnexpectedToken {
+                found: Token::EOF,
+                expected: vec!["'='"],
+                position: 5,
+            })
+        );
+        assert_eq!(
+            Parser::new("x + 1")
+                .parse_equation()
+                .unwrap_err()
+                .to_string(),
+            "expected '=' but found the end of input at position 5"
+        );
     }
-}
 
-impl<'a> Parser<'a> {
-    fn ast(&mut self, operation_precedence: OperationPrecedence) -> Result<Node, ParseError> {
-        let mut left = self.number()?;
+    #[test]
+    fn list_literals() {
+        let mut parser = Parser::new("[[1, 2], [3, -4]]");
+        let row = |a: Node, b: Node| Node::List(vec![a, b]);
+        let expected = row(
+            row(Node::Element(1.), Node::Element(2.)),
+            row(
+                Node::Element(3.),
+                Node::Negative(Box::new(Node::Element(4.))),
+            ),
+        );
+        assert_eq!(parser.parse(), Ok(expected));
 
-        loop {
-            match self.tokenizer.peek() {
-                Some(Token::EOF) => break,
-                Some(operation) => {
-                    if operation_precedence >= operation.operation_precedence() {
-                        break;
-                    }
+        assert_eq!(
+            Parser::new("[1, 2").parse(),
+            Err(ParseError::UnexpectedEndOfInput {
+                expected: "']'",
+                position: 5,
+            })
+        );
+        assert_eq!(
+            Parser::new("[]").parse(),
+            Err(operand_expected(Token::RightBracket, 1))
+        );
+    }
 
-                    left = self.operation(left)?;
-                }
-                None => {
-                    return Err(ParseError::UnableToParse("Unknown char".into()));
-                }
-            }
+    #[test]
+    fn matrix_functions() {
+        let evaluate = |input| {
+            Parser::new(input)
+                .parse()
+                .map(|node| node.eval_checked().map(|value| value.to_string()))
+        };
+        assert_eq!(evaluate("det([[1, 2], [3, 4]])"), Ok(Ok("-2".into())));
+        assert_eq!(
+            evaluate("transpose([[1, 2], [3, 4]]) * identity(2)"),
+            Ok(Ok("[1, 3]\n[2, 4]".into()))
+        );
+        assert_eq!(evaluate("det(identity(3)) + 1"), Ok(Ok("2".into())));
+        assert_eq!(
+            evaluate("det([[1, 2, 3], [4, 5, 6]])"),
+            Ok(Err(EvalError::NotSquare(2, 3)))
+        );
+        assert_eq!(
+            evaluate("det(2)").unwrap().unwrap_err().to_string(),
+            "Type mismatch: cannot apply det to number"
+        );
+        assert_eq!(
+            Parser::new("transpose([1], [2])").parse(),
+            Err(ParseError::ArgumentCount {
+                function: "transpose".into(),
+                expected: Arity::Exactly(1),
+                found: 2,
+                span: 0..19,
+            })
+        );
+    }
+
+    #[test]
+    fn vector_functions() {
+        let evaluate = |input| Parser::new(input).parse().unwrap().eval_checked();
+        assert_eq!(
+            evaluate("dot([1, 2, 3], [4, 5, 6])"),
+            Ok(Value::Number(32.))
+        );
+        assert_eq!(
+            evaluate("cross([1, 0, 0], [0, 1, 0])"),
+            Ok(Value::List(vec![0., 0., 1.]))
+        );
+        assert_eq!(evaluate("norm([3, 4]) + 1"), Ok(Value::Number(6.)));
+        assert_eq!(
+            evaluate("dot([1, 2], [1, 2, 3])"),
+            Err(EvalError::ShapeMismatch {
+                operation: "dot product",
+                left: vec![2],
+                right: vec![3],
+            })
+        );
+        for input in ["cross([1, 2], [3, 4])", "cross([1, 0, 0, 0], [1, 0, 0, 0])"].iter() {
+            assert_eq!(
+                evaluate(input).unwrap_err().code(),
+                "wrong_length",
+                "{}",
+                input
+            );
         }
-        Ok(left)
+        assert_eq!(
+            Parser::new("norm([1], [2])").parse().unwrap_err().code(),
+            "argument_count"
+        );
     }
 
-    fn number(&mut self) -> Result<Node, ParseError> {
-        let current_token = self
-            .tokenizer
-            .next()
-            .ok_or(ParseError::UnableToParse("Number parse error".into()))?;
+    #[test]
+    fn argument_lists() {
+        let arguments = |input| Parser::new(input).argument_list();
+        assert_eq!(
+            arguments("(1, x + 2, [3])"),
+            Ok(vec![
+                Node::Element(1.),
+                Node::Sum(
+                    Box::new(Node::Variable("x".into())),
+                    Box::new(Node::Element(2.))
+                ),
+                Node::List(vec![Node::Element(3.)]),
+            ])
+        );
+        assert_eq!(arguments("( )"), Ok(vec![]));
+        assert_eq!(arguments("((1, 2))"), Err(ParseError::UnexpectedComma(3)));
+
+        assert_eq!(arguments("(1,,2)"), Err(ParseError::MissingArgument(3)));
+        assert_eq!(arguments("(1, 2,)"), Err(ParseError::MissingArgument(6)));
+        assert_eq!(arguments("(,1)"), Err(ParseError::MissingArgument(1)));
+        assert_eq!(
+            arguments("(1, 2"),
+            Err(ParseError::UnexpectedEndOfInput {
+                expected: "')'",
+                position: 5,
+            })
+        );
+        assert_eq!(arguments("1, 2)"), Err(ParseError::ParenthesisNotBalanced));
+        assert_eq!(
+            arguments("(1,,2)").unwrap_err().to_string(),
+            "Missing argument at position 3"
+        );
 
-        let node = match current_token {
-            Token::Plus => self.number()?,
-            Token::Minus => Node::Negative(Box::new(self.number()?)),
-            Token::Number(number) => Node::Element(number),
-            Token::LeftParenthesis => {
-                let ast = self.ast(OperationPrecedence::Default)?;
+        let error = Parser::new("(1, 2)").parse().unwrap_err();
+        assert_eq!(error, ParseError::UnexpectedComma(2));
+        assert_eq!(error.to_string(), "Unexpected ',' at position 2");
+        assert_eq!(
+            Parser::new("3(4, 5)").parse(),
+            Err(ParseError::UnexpectedComma(3))
+        );
+    }
 
-                if self.tokenizer.next() != Some(Token::RightParenthesis) {
-                    return Err(ParseError::ParenthesisNotBalanced);
-                }
+    #[test]
+    fn scientific_notation() {
+        assert_eq!(Parser::new("1e3+1").evaluate(), Ok(1001.));
+        assert_eq!(Parser::new("2.5E-3 * 4").evaluate(), Ok(0.01));
+        assert_eq!(Parser::new("-6.02e+23").evaluate(), Ok(-6.02e23));
+        for input in ["2 * 1e+", "1E-", "(1e2.5)"].iter() {
+            assert!(
+                matches!(
+                    Parser::new(input).evaluate(),
+                    Err(ParseError::InvalidNumber { .. })
+                ),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            Parser::new("1e2.5").evaluate(),
+            Err(invalid_number("1e2.5", 0))
+        );
 
-                ast
-            }
-            token => {
-                return Err(ParseError::InvalidNumber(format!("{:?}", token).into()));
+        // Without exponent digits the e is a name.
+        assert_eq!(Parser::new("2e").evaluate(), Ok(2. * std::f64::consts::E));
+        assert_eq!(
+            Parser::new("2exp(1)").evaluate(),
+            Ok(2. * std::f64::consts::E)
+        );
+        assert_eq!(Parser::new("2e").parse().unwrap().to_string(), "2 * e");
+    }
+
+    #[test]
+    fn adjacent_numbers() {
+        assert_eq!(
+            Parser::new("1 2 + 3").evaluate(),
+            Err(operator_expected(Token::Number(2.), 2))
+        );
+        assert_eq!(
+            Parser::new("1\t\n2").evaluate(),
+            Err(operator_expected(Token::Number(2.), 3))
+        );
+        assert_eq!(
+            Parser::new("[1, 2^3 4]").parse(),
+            Err(operator_expected(Token::Number(4.), 8))
+        );
+        assert_eq!(
+            Parser::new("1 .5").evaluate(),
+            Err(operator_expected(Token::Number(0.5), 2))
+        );
+        assert_eq!(
+            Parser::new("1 2").evaluate().unwrap_err().to_string(),
+            "expected an operator but found '2' at position 2"
+        );
+        assert_eq!(Parser::new(" 12 +\t3 ").evaluate(), Ok(15.));
+    }
+
+    #[test]
+    fn unexpected_tokens() {
+        // An operator where an operand should be.
+        let error = Parser::new("3 + * 4").parse().unwrap_err();
+        assert_eq!(error, operand_expected(Token::Asterisk, 4));
+        assert_eq!(
+            error.to_string(),
+            "expected a number, '-', or '(' but found '*' at position 4"
+        );
+        assert_eq!(error.code(), "unexpected_token");
+        assert_eq!(error.span("3 + * 4"), Some(4..5));
+        assert_eq!(
+            Parser::new("(1 + >= 2)").parse(),
+            Err(operand_expected(Token::GreaterEquals, 5))
+        );
+
+        // An operand where an operator should be.
+        let error = Parser::new("2 * 3 4.5").parse().unwrap_err();
+        assert_eq!(error, operator_expected(Token::Number(4.5), 6));
+        assert_eq!(
+            error.to_string(),
+            "expected an operator but found '4.5' at position 6"
+        );
+        assert_eq!(error.span("2 * 3 4.5"), Some(6..9));
+    }
+
+    #[test]
+    fn leading_decimal_point() {
+        assert_eq!(Parser::new(".5+.25").evaluate(), Ok(0.75));
+        assert_eq!(Parser::new("-.5").evaluate(), Ok(-0.5));
+        assert_eq!(Parser::new("2*.5").evaluate(), Ok(1.));
+        assert_eq!(Parser::new(".5e1").evaluate(), Ok(5.));
+        assert_eq!(
+            Parser::new(".5.5").evaluate(),
+            Err(invalid_number(".5.5", 0))
+        );
+        for (input, position) in [(".", 0), ("2 * .", 4), ("1 + . 5", 4), (".x", 0)].iter() {
+            let error = Parser::new(input).evaluate().unwrap_err();
+            assert_eq!(
+                error,
+                ParseError::UnexpectedCharacter {
+                    character: '.',
+                    position: *position
+                },
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            Parser::new("2 * .").evaluate().unwrap_err().to_string(),
+            "Unexpected character '.' at position 4"
+        );
+    }
+
+    #[test]
+    fn fullwidth_input() {
+        assert_eq!(Parser::new("１＋２").evaluate(), Ok(3.));
+        assert_eq!(Parser::new("2\u{a0}*\u{2009}3").evaluate(), Ok(6.));
+        // Positions count bytes of the original text.
+        assert_eq!(
+            Parser::new("１２ § ３").evaluate(),
+            Err(ParseError::UnexpectedCharacter {
+                character: '§',
+                position: 7
+            })
+        );
+        assert_eq!(
+            Parser::new("１\u{a0}２").evaluate(),
+            Err(operator_expected(Token::Number(2.), 5))
+        );
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        for (input, literal) in [
+            ("1.2.3", "1.2.3"),
+            ("1..2", "1..2"),
+            ("2 * 1..", "1.."),
+            ("(3.4.5)", "3.4.5"),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Parser::new(input).evaluate(),
+                Err(invalid_number(literal, input.find(literal).unwrap())),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            Parser::new("..").evaluate(),
+            Err(ParseError::UnexpectedCharacter {
+                character: '.',
+                position: 0
+            })
+        );
+        assert_eq!(
+            Parser::new("1 + .e5").evaluate(),
+            Err(ParseError::UnexpectedCharacter {
+                character: '.',
+                position: 4
+            })
+        );
+        assert_eq!(Parser::new("1. + 2").evaluate(), Ok(3.));
+        assert_eq!(Parser::new("1 # .5").evaluate(), Ok(1.));
+
+        // Every short run of number characters is either a number or an
+        // error, never a panic.
+        let alphabet = ['1', '.', 'e', '_', '-'];
+        for length in 1..=4 {
+            for mut code in 0..alphabet.len().pow(length) {
+                let input: String = (0..length)
+                    .map(|_| {
+                        let c = alphabet[code % alphabet.len()];
+                        code /= alphabet.len();
+                        c
+                    })
+                    .collect();
+                let _ = Parser::new(&input).evaluate();
             }
+        }
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(Parser::new("1_000_000 * 12").evaluate(), Ok(12_000_000.));
+        assert_eq!(
+            Parser::new("1_.5").evaluate(),
+            Err(invalid_number("1_.5", 0))
+        );
+    }
+
+    #[test]
+    fn hexadecimal_literals() {
+        assert_eq!(Parser::new("0xFF + 0x10").evaluate(), Ok(271.));
+        assert_eq!(Parser::new("0x10 * 2.5").evaluate(), Ok(40.));
+        assert_eq!(Parser::new("(-0xa)^2").evaluate(), Ok(100.));
+        assert_eq!(
+            Parser::new("1 + 0x").evaluate(),
+            Err(ParseError::InvalidNumber {
+                literal: "0x (no digits)".into(),
+                span: 4..6,
+            })
+        );
+    }
+
+    #[test]
+    fn binary_literals() {
+        assert_eq!(Parser::new("0b1010 + 1").evaluate(), Ok(11.));
+        assert_eq!(Parser::new("0b10^0b11").evaluate(), Ok(8.));
+        assert_eq!(Parser::new("(0b11 + 1) * -0b1").evaluate(), Ok(-4.));
+        assert_eq!(Parser::new("-0b11^2").evaluate(), Ok(-9.));
+        assert_eq!(
+            Parser::new("2 * 0b12").evaluate(),
+            Err(ParseError::InvalidNumber {
+                literal: "0b12 (2 is not a binary digit)".into(),
+                span: 4..8,
+            })
+        );
+    }
+
+    #[test]
+    fn octal_literals() {
+        assert_eq!(Parser::new("0o755 - 0o022").evaluate(), Ok(493. - 18.));
+        assert_eq!(Parser::new("0o10 * 1.5 + 1").evaluate(), Ok(13.));
+        assert_eq!(
+            Parser::new("0o19").evaluate().unwrap_err().to_string(),
+            "Invalid number: 0o19 (9 is not an octal digit)"
+        );
+    }
+
+    #[test]
+    fn unicode_operators() {
+        assert_eq!(Parser::new("3 × (2 − 1) ÷ 2").evaluate(), Ok(1.5));
+        assert_eq!(
+            Parser::new("2·x − 1 ÷ 4").parse(),
+            Parser::new("2*x - 1 / 4").parse()
+        );
+        assert_eq!(Parser::new("−2^2").evaluate(), Ok(-4.));
+        assert_eq!(Parser::new("3 * (2 - 1) / 2").evaluate(), Ok(1.5));
+    }
+
+    #[test]
+    fn decimal_comma() {
+        let options = ParserOptions {
+            tokenizer: TokenizerOptions {
+                decimal_separator: DecimalSeparator::Comma,
+                ..TokenizerOptions::default()
+            },
+            ..ParserOptions::default()
         };
+        let evaluate = |input| Parser::new_with_options(input, options).evaluate();
 
-        Ok(node)
+        assert_eq!(evaluate("1,5+2,5"), Ok(4.));
+        assert_eq!(evaluate("2,25 * 2"), Ok(4.5));
+        assert_eq!(evaluate("1,2,3"), Err(invalid_number("1,2,3", 0)));
+        assert_eq!(
+            Parser::new_with_options("[1, 2,5]", options).parse(),
+            Ok(Node::List(vec![Node::Element(1.), Node::Element(2.5)]))
+        );
+        assert_eq!(
+            Parser::new("[1,5]").parse(),
+            Ok(Node::List(vec![Node::Element(1.), Node::Element(5.)]))
+        );
     }
 
-    fn operation(&mut self, left: Node) -> Result<Node, ParseError> {
-        let current_token = self
-            .tokenizer
-            .next()
-            .ok_or(ParseError::UnableToParse("Operator parse error".into()))?;
+    #[test]
+    fn grouping_separators() {
+        let options = ParserOptions::default().allow_grouping(true);
+        let evaluate = |input| Parser::new_with_options(input, options).evaluate();
 
-        let operation_precedence = current_token.operation_precedence();
-        let node = match current_token {
-            Token::Plus => {
-                let right = self.ast(operation_precedence)?;
-                Node::Sum(Box::new(left), Box::new(right))
-            }
-            Token::Minus => {
-                let right = self.ast(operation_precedence)?;
-                Node::Subtract(Box::new(left), Box::new(right))
-            }
-            Token::Asterisk => {
-                let right = self.ast(operation_precedence)?;
-                Node::Multiply(Box::new(left), Box::new(right))
-            }
-            Token::Slash => {
-                let right = self.ast(operation_precedence)?;
-                Node::Divide(Box::new(left), Box::new(right))
-            }
-            Token::Caret => {
-                let right = self.ast(operation_precedence)?;
-                Node::Power(Box::new(left), Box::new(right))
-            }
-            Token::LeftParenthesis => {
-                let right = self.ast(OperationPrecedence::Default)?;
-                if self.tokenizer.next() != Some(Token::RightParenthesis) {
-                    return Err(ParseError::ParenthesisNotBalanced);
-                }
+        assert_eq!(evaluate("1,000 + 1"), Ok(1001.));
+        assert_eq!(evaluate("1,234,567.89 * 0.2"), Ok(1234567.89 * 0.2));
+        assert_eq!(evaluate("1,,000"), Err(invalid_number("1,,000", 0)));
+        assert_eq!(
+            Parser::new_with_options("[1, 2,000]", options).parse(),
+            Ok(Node::List(vec![Node::Element(1.), Node::Element(2000.)]))
+        );
+    }
 
-                Node::Multiply(Box::new(left), Box::new(right))
-            }
-            token => {
-                return Err(ParseError::InvalidOperator(format!("{:?}", token).into()));
+    #[test]
+    fn combined_options() {
+        let options = ParserOptions::default()
+            .implicit_multiplication(false)
+            .max_depth(3)
+            .decimal_separator(DecimalSeparator::Comma);
+        let parse = |input| Parser::with_options(input, options).parse();
+
+        assert_eq!(parse("((1,5)) * 2"), Parser::new("((1.5)) * 2").parse());
+        // The depth limit is met before the missing operator is.
+        assert_eq!(
+            parse("(((1))(2))"),
+            Err(ParseError::TooDeep {
+                limit: 3,
+                position: 3,
+            })
+        );
+        assert_eq!(parse("((1))(2)"), Err(ParseError::MissingOperator(5)));
+        assert_eq!(
+            Parser::with_options("((1))(2)", options.implicit_multiplication(true)).evaluate(),
+            Ok(2.)
+        );
+        assert_eq!(
+            Parser::with_options("(((1)))", options.max_depth(4)).evaluate(),
+            Ok(1.)
+        );
+        assert_eq!(
+            Parser::with_options("[1,5, 2]", ParserOptions::default()).parse(),
+            Parser::new("[1,5, 2]").parse()
+        );
+    }
+
+    #[test]
+    fn magnitude_suffixes() {
+        let options = ParserOptions::default().allow_magnitude_suffixes(true);
+        let evaluate = |input| Parser::new_with_options(input, options).evaluate();
+
+        assert_eq!(evaluate("4.7k * 2"), Ok(9400.));
+        assert_eq!(evaluate("100m / 5"), Ok(0.02));
+        assert_eq!(evaluate("1e3k - 1M"), Ok(0.));
+        assert_eq!(evaluate("2kk"), Err(invalid_number("2kk", 0)));
+    }
+
+    #[test]
+    fn percent() {
+        let percent = |node| Node::Percent(Box::new(node));
+        assert_eq!(
+            Parser::new("50% * 200").parse(),
+            Ok(Node::Multiply(
+                Box::new(percent(Node::Element(50.))),
+                Box::new(Node::Element(200.))
+            ))
+        );
+        assert_eq!(
+            Parser::new("5%^2").parse(),
+            Ok(Node::Power(
+                Box::new(percent(Node::Element(5.))),
+                Box::new(Node::Element(2.))
+            ))
+        );
+        assert_eq!(Parser::new("50% * 200").evaluate(), Ok(100.));
+        assert_eq!(Parser::new("120 * (1 + 8.5%)").evaluate(), Ok(130.2));
+        assert_eq!(Parser::new("(50%)%").evaluate(), Ok(0.005));
+        assert_eq!(Parser::new("-5%").evaluate(), Ok(-0.05));
+
+        let error = Parser::new("50%%").parse().unwrap_err();
+        assert_eq!(
+            error,
+            ParseError::UnexpectedToken {
+                found: Token::Percent,
+                expected: vec!["an operand", "an operator"],
+                position: 3,
             }
-        };
+        );
+        assert_eq!(
+            error.to_string(),
+            "expected an operand or an operator but found '%' at position 3"
+        );
+        assert_eq!(
+            Parser::new("-%").parse(),
+            Err(operand_expected(Token::Percent, 1))
+        );
+    }
 
-        Ok(node)
+    #[test]
+    fn factorial() {
+        let factorial = |node| Node::Factorial(Box::new(node));
+        assert_eq!(
+            Parser::new("3!^2").parse(),
+            Ok(Node::Power(
+                Box::new(factorial(Node::Element(3.))),
+                Box::new(Node::Element(2.))
+            ))
+        );
+        assert_eq!(
+            Parser::new("3!!").parse(),
+            Ok(factorial(factorial(Node::Element(3.))))
+        );
+        assert_eq!(Parser::new("5! + 1").evaluate(), Ok(121.));
+        assert_eq!(Parser::new("0!").evaluate(), Ok(1.));
+        assert_eq!(Parser::new("3!!").evaluate(), Ok(720.));
+        assert_eq!(Parser::new("(1 + 2)! * 2").evaluate(), Ok(12.));
+        assert_eq!(Parser::new("2^3!").evaluate(), Ok(64.));
+        assert_eq!(
+            Parser::new("2.5!").evaluate(),
+            Err(ParseError::Evaluation(EvalError::Domain {
+                operation: "factorial",
+                value: 2.5
+            }))
+        );
+        assert_eq!(
+            Parser::new("(-2)!").parse().unwrap().eval_checked(),
+            Err(EvalError::Domain {
+                operation: "factorial",
+                value: -2.
+            })
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn modulo() {
+        let element = |number| Box::new(Node::Element(number));
+        assert_eq!(
+            Parser::new("1 + 10 % 3").parse(),
+            Ok(Node::Sum(
+                element(1.),
+                Box::new(Node::Modulo(element(10.), element(3.)))
+            ))
+        );
+        assert_eq!(
+            Parser::new("2 * 10 % 3").parse(),
+            Ok(Node::Modulo(
+                Box::new(Node::Multiply(element(2.), element(10.))),
+                element(3.)
+            ))
+        );
+        assert_eq!(Parser::new("10 % 3").evaluate(), Ok(1.));
+        assert_eq!(Parser::new("7.5 % 2").evaluate(), Ok(1.5));
+        assert_eq!(Parser::new("(0 - 7) % 3").evaluate(), Ok(-1.));
+        assert_eq!(Parser::new("7 % (0 - 3)").evaluate(), Ok(1.));
+        assert!(Parser::new("5 % 0").evaluate().unwrap().is_nan());
+        assert_eq!(Parser::new("50%%8").evaluate(), Ok(0.5));
+    }
+
+    #[test]
+    fn floor_division() {
+        let element = |number| Box::new(Node::Element(number));
+        assert_eq!(
+            Parser::new("20 // 3 // 2").parse(),
+            Ok(Node::FloorDivide(
+                Box::new(Node::FloorDivide(element(20.), element(3.))),
+                element(2.)
+            ))
+        );
+        assert_eq!(Parser::new("7 // 2").evaluate(), Ok(3.));
+        assert_eq!(Parser::new("-7 // 2").evaluate(), Ok(-4.));
+        assert_eq!(Parser::new("7 // -2").evaluate(), Ok(-4.));
+        assert_eq!(Parser::new("20 // 3 // 2").evaluate(), Ok(3.));
+        assert_eq!(Parser::new("1 + 7.5 // 2 * 2").evaluate(), Ok(7.));
+        assert_eq!(Parser::new("7 / 2").evaluate(), Ok(3.5));
+        assert_eq!(
+            Parser::new("7 / / 2").evaluate(),
+            Err(operand_expected(Token::Slash, 4))
+        );
+    }
 
     #[test]
     fn negative_test() {
@@ -131,6 +3174,19 @@ mod tests {
         assert_eq!(ast, Ok(expected))
     }
 
+    #[test]
+    fn negative_zero() {
+        let zero = Parser::new("-0").evaluate().unwrap();
+        assert_eq!(zero, 0.);
+        assert!(zero.is_sign_negative());
+        assert_eq!(Parser::new("1/(-0)").evaluate(), Ok(f64::NEG_INFINITY));
+        assert_eq!(Parser::new("1/0").evaluate(), Ok(f64::INFINITY));
+        assert!(Parser::new("-0 * -1")
+            .evaluate()
+            .unwrap()
+            .is_sign_positive());
+    }
+
     #[test]
     fn trim_plus() {
         let mut parser = Parser::new("+1");
@@ -219,11 +3275,1137 @@ mod tests {
     fn pow_many() {
         let mut parser = Parser::new("10^20^30");
         let ast = parser.parse();
-        let left = Node::Power(Box::new(Node::Element(10.)), Box::new(Node::Element(20.)));
-        let expected = Node::Power(Box::new(left), Box::new(Node::Element(30.)));
+        let right = Node::Power(Box::new(Node::Element(20.)), Box::new(Node::Element(30.)));
+        let expected = Node::Power(Box::new(Node::Element(10.)), Box::new(right));
+        assert_eq!(ast, Ok(expected))
+    }
+
+    #[test]
+    fn negation_binds_looser_than_pow() {
+        let element = |number| Box::new(Node::Element(number));
+        assert_eq!(
+            Parser::new("-2^2").parse(),
+            Ok(Node::Negative(Box::new(Node::Power(
+                element(2.),
+                element(2.)
+            ))))
+        );
+        assert_eq!(Parser::new("-2^2").evaluate(), Ok(-4.));
+        assert_eq!(Parser::new("(-2)^2").evaluate(), Ok(4.));
+        assert_eq!(Parser::new("2^-2").evaluate(), Ok(0.25));
+        assert_eq!(Parser::new("2^-1^2").evaluate(), Ok(0.5));
+        assert_eq!(Parser::new("-3!").evaluate(), Ok(-6.));
+        assert_eq!(Parser::new("-2 * 3").evaluate(), Ok(-6.));
+        assert_eq!(Parser::new("1 - -2^2").evaluate(), Ok(5.));
+        assert_eq!(
+            Parser::new("-2*3").parse(),
+            Ok(Node::Multiply(
+                Box::new(Node::Negative(element(2.))),
+                element(3.)
+            ))
+        );
+    }
+
+    #[test]
+    fn function_calls() {
+        assert_eq!(Parser::new("sqrt(2) + sin(0)").evaluate(), Ok(2f64.sqrt()));
+        assert_eq!(Parser::new("sqrt(abs(-16))").evaluate(), Ok(4.));
+        assert_eq!(Parser::new("ln(exp(2)) * cos(0)").evaluate(), Ok(2.));
+        assert_eq!(Parser::new("2sqrt(9)^2").evaluate(), Ok(18.));
+        assert_eq!(
+            Parser::new("sqrt(4)x").parse(),
+            Ok(Node::Multiply(
+                Box::new(Node::Function("sqrt".into(), vec![Node::Element(4.)])),
+                Box::new(Node::Variable("x".into()))
+            ))
+        );
+
+        let ast = Parser::new("1 + abs(x)").parse_spanned().unwrap();
+        assert_eq!(ast.spans, vec![0..1, 8..9, 4..10, 0..10]);
+
+        let error = Parser::new("2 * foo(1)").parse().unwrap_err();
+        assert_eq!(error, unknown_function("foo", 4));
+        assert_eq!(error.to_string(), "Unknown function: foo");
+        assert_eq!(error.code(), "unknown_function");
+        assert_eq!(
+            Parser::new("Sqrt(4)").parse(),
+            Err(unknown_function("Sqrt", 0))
+        );
+
+        // A name the context gives a value still multiplies a group.
+        let mut context = Context::new();
+        context.set("x", 3.);
+        assert_eq!(Parser::with_context("x(2)", &context).evaluate(), Ok(6.));
+        assert_eq!(
+            Parser::with_context("y(2)", &context).evaluate(),
+            Err(unknown_function("y", 0))
+        );
+    }
+
+    #[test]
+    fn multiple_arguments() {
+        assert_eq!(Parser::new("max(1, 2, 3)").evaluate(), Ok(3.));
+        assert_eq!(Parser::new("max(1, min(5, 2), 3)").evaluate(), Ok(3.));
+        assert_eq!(
+            Parser::new("min(max(1, 4), (2 + 3) * 2)").evaluate(),
+            Ok(4.)
+        );
+        assert_eq!(Parser::new("log(8, 2)").evaluate(), Ok(3.));
+        assert_eq!(Parser::new("log(max(2, 1)^10, 1 + 1)").evaluate(), Ok(10.));
+        assert_eq!(
+            Parser::new("atan2(1, 1) * 4").evaluate(),
+            Ok(std::f64::consts::PI)
+        );
+        assert_eq!(
+            Parser::new("max(x, 2y)").parse(),
+            Ok(Node::Function(
+                "max".into(),
+                vec![
+                    Node::Variable("x".into()),
+                    Node::Multiply(
+                        Box::new(Node::Element(2.)),
+                        Box::new(Node::Variable("y".into()))
+                    )
+                ]
+            ))
+        );
+
+        let ast = Parser::new("min(1, x)").parse_spanned().unwrap();
+        assert_eq!(ast.spans, vec![4..5, 7..8, 0..9]);
+
+        let error = Parser::new("atan2(1)").parse().unwrap_err();
+        assert_eq!(
+            error,
+            ParseError::ArgumentCount {
+                function: "atan2".into(),
+                expected: Arity::Exactly(2),
+                found: 1,
+                span: 0..8,
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Wrong number of arguments: atan2 takes 2, got 1"
+        );
+        assert_eq!(error.code(), "argument_count");
+        assert_eq!(
+            Parser::new("1 + max()").parse().unwrap_err().to_string(),
+            "Wrong number of arguments: max takes at least 1, got 0"
+        );
+        assert_eq!(
+            Parser::new("sqrt(1, 2)").parse().unwrap_err().to_string(),
+            "Wrong number of arguments: sqrt takes 1, got 2"
+        );
+        assert_eq!(
+            Parser::new("max(1,, 2)").parse(),
+            Err(ParseError::MissingArgument(6))
+        );
+        assert_eq!(
+            Parser::new("max(1, 2").parse(),
+            Err(ParseError::UnexpectedEndOfInput {
+                expected: "')'",
+                position: 8,
+            })
+        );
+    }
+
+    #[test]
+    fn absolute_value_bars() {
+        let abs = |node: Node| Node::Function("abs".into(), vec![node]);
+        let x = || Node::Variable("x".into());
+        let mut context = Context::new();
+        context.set("x", -3.);
+
+        assert_eq!(
+            Parser::with_context("|x - 3| + 1", &context).evaluate(),
+            Ok(7.)
+        );
+        assert_eq!(Parser::new("|-2| * |3 - 5|").evaluate(), Ok(4.));
+        assert_eq!(
+            Parser::new("||x| - 1|").parse(),
+            Ok(abs(Node::Subtract(
+                Box::new(abs(x())),
+                Box::new(Node::Element(1.))
+            )))
+        );
+        assert_eq!(
+            Parser::with_context("||x| - 1|", &context).evaluate(),
+            Ok(2.)
+        );
+        assert_eq!(
+            Parser::with_context("|2(1 - |x|)|", &context).evaluate(),
+            Ok(4.)
+        );
+        assert_eq!(Parser::with_context("(|x|)^2", &context).evaluate(), Ok(9.));
+        assert_eq!(Parser::with_context("-|x|", &context).evaluate(), Ok(-3.));
+
+        // Outside of bars, a bar after an operand opens a pair that is
+        // multiplied in.
+        assert_eq!(
+            Parser::new("2|x|").parse(),
+            Ok(Node::Multiply(
+                Box::new(Node::Element(2.)),
+                Box::new(abs(x()))
+            ))
+        );
+        assert_eq!(
+            Parser::new("|x|y").parse(),
+            Parser::new("abs(x) * y").parse()
+        );
+        assert_eq!(
+            Parser::new("|x|y|z|").parse(),
+            Parser::new("abs(x) * y * abs(z)").parse()
+        );
+
+        let ast = Parser::new("1 + |x|").parse_spanned().unwrap();
+        assert_eq!(ast.spans, vec![0..1, 5..6, 4..7, 0..7]);
+
+        // || is two bars wherever a bar opens or closes a pair, and an or
+        // only after an operand outside of bars.
+        assert_eq!(Parser::new("||x||").parse(), Ok(abs(abs(x()))));
+        assert_eq!(
+            Parser::new("|x||y|").parse(),
+            Parser::new("abs(x) * abs(y)").parse()
+        );
+        assert_eq!(
+            Parser::new("|x| || |y|").parse(),
+            Parser::new("abs(x) || abs(y)").parse()
+        );
+        let ast = Parser::new("||x||").parse_spanned().unwrap();
+        assert_eq!(ast.spans, vec![2..3, 1..4, 0..5]);
+
+        assert_eq!(Parser::new("|x)").parse(), Err(ParseError::BarNotBalanced));
+        for (input, expected, position) in [
+            ("|x", "'|'", 2),
+            ("|1 + |2|", "'|'", 8),
+            ("x|", "a number or '('", 2),
+            ("(x|", "a number or '('", 3),
+            ("||", "a number or '('", 2),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Parser::new(input).parse(),
+                Err(ParseError::UnexpectedEndOfInput {
+                    expected,
+                    position: *position,
+                }),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            ParseError::BarNotBalanced.to_string(),
+            "Balance absolute value bar error"
+        );
+        assert!(Parser::is_incomplete("|x - 1"));
+        assert!(!Parser::is_incomplete("|x - 1|"));
+    }
+
+    #[test]
+    fn juxtaposition_precedence() {
+        let loose = ParserOptions::default().juxtaposition_binds_tight(false);
+        let evaluate = |input, options| Parser::new_with_options(input, options).evaluate();
+
+        for (input, tight, by_position) in [
+            ("1/2(3)", 1. / 6., 1.5),
+            ("6/2(1+2)", 1., 9.),
+            ("6/2(1+2)^2", 6. / 18., 27.),
+            ("12 // 2(3)", 2., 18.),
+            ("-2(3)", -6., -6.),
+            ("2^3(2)", 16., 16.),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                evaluate(input, ParserOptions::default()),
+                Ok(*tight),
+                "{}",
+                input
+            );
+            assert_eq!(evaluate(input, loose), Ok(*by_position), "{}", input);
+        }
+
+        // Powers bind tighter either way.
+        for options in [ParserOptions::default(), loose].iter() {
+            let parse = |input| Parser::new_with_options(input, *options).parse();
+            assert_eq!(parse("2x^2"), parse("2 * (x^2)"));
+            assert_eq!(evaluate("2(3)^2", *options), Ok(18.));
+            assert_eq!(parse("1 + 2(3) * 4"), parse("1 + (2 * 3) * 4"));
+        }
+        assert_eq!(
+            Parser::new("1/2x").parse(),
+            Parser::new("1 / (2 * x)").parse()
+        );
+        assert_eq!(
+            Parser::new_with_options("1/2x", loose).parse(),
+            Parser::new("(1 / 2) * x").parse()
+        );
+    }
+
+    #[test]
+    fn excel_formulas() {
+        let excel = ParserOptions::default().excel_compat(true);
+        let evaluate = |input| Parser::new_with_options(input, excel).evaluate();
+
+        // Answers from Excel's documentation of its operators and of
+        // these functions.
+        for (formula, answer) in [
+            ("=5+2*3", 11.),
+            ("=(5+2)*3", 21.),
+            ("=-2^2", 4.),
+            ("=2^3^2", 64.),
+            ("=0-2^2", -4.),
+            ("=20%*50", 10.),
+            ("=SQRT(16)", 4.),
+            ("=ABS(-4)", 4.),
+            ("=POWER(5,2)", 25.),
+            ("=SUM(3, 2)", 5.),
+            ("=AVERAGE(10, 7, 9, 27, 2)", 11.),
+            ("=MAX(10, 7, 9, 27, 2)", 27.),
+            ("=IF(1<>1, 1, 0)", 0.),
+            ("=If(2=2, 10, 20)", 10.),
+            ("=LOG(8, 2)", 3.),
+            ("= Exp(0) + ln(1)", 1.),
+        ]
+        .iter()
+        {
+            assert_eq!(evaluate(formula), Ok(*answer), "{}", formula);
+        }
+        let pi = evaluate("=PI()*2^2").unwrap();
+        assert!((pi - 12.566_370_614_359_172).abs() < 1e-12);
+        assert!((evaluate("=POWER(98.6,3.2)").unwrap() - 2_401_077.222).abs() < 1e-3);
+
+        // Without the option, the same text means what it always has.
+        assert_eq!(Parser::new("-2^2").evaluate(), Ok(-4.));
+        assert_eq!(Parser::new("2^3^2").evaluate(), Ok(512.));
+        assert_eq!(evaluate("2^3^2 - 2^(3^2)"), Ok(64. - 512.));
+        assert!(Parser::new("=5+2*3").parse().is_err());
+        assert!(Parser::new("1 <> 2").parse().is_err());
+        assert_eq!(
+            Parser::new("SQRT(16)").parse(),
+            Err(unknown_function("SQRT", 0))
+        );
+        // Only a leading = is skipped.
+        assert!(evaluate("==1").is_err());
+        assert!(evaluate("1 + =2").is_err());
+    }
+
+    #[test]
+    fn custom_operators() {
+        let mut context = Context::new();
+        // The percent difference of a from b.
+        let difference = |a: f64, b: f64| (a - b) / b * 100.;
+        context
+            .define_operator(
+                '@',
+                OperationPrecedence::MulDiv,
+                Associativity::Left,
+                difference,
+            )
+            .unwrap();
+        context
+            .define_operator(
+                '↑',
+                OperationPrecedence::Power,
+                Associativity::Right,
+                f64::powf,
+            )
+            .unwrap();
+        context.set("x", 3.);
+        let parse = |input: &str| Parser::with_context(input, &context).parse().unwrap();
+        let element = |number| Box::new(Node::Element(number));
+        let custom = |symbol: &str, left, right| Node::CustomBinary(symbol.into(), left, right);
+
+        // @ binds as tightly as * and /, and from the left.
+        let node = parse("1 + 150 @ 100 * 2");
+        assert_eq!(
+            node,
+            Node::Sum(
+                element(1.),
+                Box::new(Node::Multiply(
+                    Box::new(custom("@", element(150.), element(100.))),
+                    element(2.)
+                ))
+            )
+        );
+        assert_eq!(node.eval_with(&context), Ok(101.));
+        assert_eq!(
+            parse("2x @ 4 @ 5"),
+            custom(
+                "@",
+                Box::new(custom(
+                    "@",
+                    Box::new(Node::Multiply(element(2.), element(3.))),
+                    element(4.)
+                )),
+                element(5.)
+            )
+        );
+        assert_eq!(
+            parse("2 ↑ 3 ↑ 2"),
+            custom(
+                "↑",
+                element(2.),
+                Box::new(custom("↑", element(3.), element(2.)))
+            )
+        );
+        assert_eq!(
+            Parser::with_context("-2↑3↑2 + 1", &context).evaluate_with(&context),
+            Ok(-511.)
+        );
+        assert_eq!(
+            Parser::with_context("(110 @ 100) ↑ 2 > 99", &context)
+                .parse()
+                .unwrap()
+                .eval_checked_with(&context),
+            Ok(Value::Bool(true))
+        );
+
+        // Printed with parentheses around the operands, the trees parse
+        // back the same.
+        for input in ["1 + 150 @ 100 * 2", "2 ↑ 3 ↑ 2", "-(1 + 3) @ 2 ↑ 2"].iter() {
+            let node = parse(input);
+            assert_eq!(parse(&node.to_string()), node, "{}", node);
+            assert_eq!(parse(&node.to_minified()), node, "{}", node);
+        }
+        assert_eq!(
+            parse("1 + 150 @ 100 * 2").to_string(),
+            "1 + (150 @ 100) * 2"
+        );
+
+        // Elsewhere the character means nothing, and the tree cannot be
+        // evaluated without the context that defines it.
+        assert_eq!(
+            Parser::new("150 @ 100").parse(),
+            Err(ParseError::UnexpectedCharacter {
+                character: '@',
+                position: 4
+            })
+        );
+        assert!(node.eval().is_nan());
+        assert_eq!(
+            node.eval_with(&Context::new()),
+            Err(EvalError::UnknownOperator("@".into()))
+        );
+        assert_eq!(
+            Parser::with_context("@ 1", &context).parse(),
+            Err(operand_expected(Token::Custom('@'), 0))
+        );
+    }
+
+    #[test]
+    fn custom_unary_operators() {
+        let mut context = Context::new();
+        let define = |context: &mut Context, symbol, fixity, precedence, apply| {
+            context
+                .define_unary_operator(symbol, fixity, precedence, apply)
+                .unwrap()
+        };
+        define(
+            &mut context,
+            '~',
+            Fixity::Prefix,
+            OperationPrecedence::Power,
+            f64::round,
+        );
+        define(
+            &mut context,
+            '°',
+            Fixity::Postfix,
+            OperationPrecedence::Postfix,
+            f64::to_radians,
+        );
+        let mut loose = Context::new();
+        define(
+            &mut loose,
+            '~',
+            Fixity::Prefix,
+            OperationPrecedence::Juxtaposition,
+            f64::round,
+        );
+        define(
+            &mut loose,
+            '°',
+            Fixity::Postfix,
+            OperationPrecedence::MulDiv,
+            f64::to_radians,
+        );
+
+        let element = |number| Box::new(Node::Element(number));
+        let prefix = |node| Node::CustomUnary(Fixity::Prefix, "~".into(), node);
+        let postfix = |node| Node::CustomUnary(Fixity::Postfix, "°".into(), node);
+        let parse = |input: &str, context| Parser::with_context(input, context).parse().unwrap();
+
+        // Binding tighter than ^, ~ rounds the base; binding as a sign does,
+        // it rounds the power.
+        let node = parse("~2.6^2", &context);
+        assert_eq!(
+            node,
+            Node::Power(Box::new(prefix(element(2.6))), element(2.))
+        );
+        assert_eq!(node.eval_with(&context), Ok(9.));
+        let node = parse("~2.6^2", &loose);
+        assert_eq!(
+            node,
+            prefix(Box::new(Node::Power(element(2.6), element(2.))))
+        );
+        assert_eq!(node.eval_with(&loose), Ok(7.));
+
+        // A postfix operator applies to as much before it as binds tighter.
+        assert_eq!(
+            parse("2^90°", &context),
+            Node::Power(element(2.), Box::new(postfix(element(90.))))
+        );
+        assert_eq!(
+            parse("90°^2", &context),
+            Node::Power(Box::new(postfix(element(90.))), element(2.))
+        );
+        assert_eq!(
+            parse("1 + 2 * 90°", &loose),
+            Node::Sum(
+                element(1.),
+                Box::new(postfix(Box::new(Node::Multiply(element(2.), element(90.)))))
+            )
+        );
+        let sine = parse("sin(30° * 3) + ~-0.4", &context);
+        assert!((sine.eval_with(&context).unwrap() - 1.).abs() < 1e-12);
+        assert_eq!(
+            Parser::with_context("~1.5°", &context).evaluate_with(&context),
+            Ok(0.)
+        );
+
+        for input in ["~2.6^2", "2^90°", "-~(1 + 2)°!", "~~2 * 3"].iter() {
+            let node = parse(input, &context);
+            assert_eq!(parse(&node.to_string(), &context), node, "{}", node);
+            assert_eq!(parse(&node.to_minified(), &context), node, "{}", node);
+        }
+        assert_eq!(parse("~2.6^2", &context).to_string(), "(~2.6) ^ 2");
+
+        assert_eq!(
+            Parser::with_context("2 ~ 3", &context).parse(),
+            Err(ParseError::TrailingInput {
+                token: Token::Custom('~'),
+                position: 2
+            })
+        );
+        assert_eq!(
+            Parser::with_context("° 3", &context).parse(),
+            Err(operand_expected(Token::Custom('°'), 0))
+        );
+        assert_eq!(
+            parse("90°", &context).eval_with(&Context::new()),
+            Err(EvalError::UnknownOperator("°".into()))
+        );
+    }
+
+    #[test]
+    fn bound_variables() {
+        let mut context = Context::new();
+        context.set("x", 2.);
+        context.set("k", 3.);
+        let node = Parser::with_context("k*x + sum(i, 1, 2, x)", &context)
+            .bind("x")
+            .parse()
+            .unwrap();
+        assert_eq!(node.variables(), vec!["x"]);
+        context.set("x", 5.);
+        assert_eq!(node.eval_with(&context), Ok(25.));
+
+        assert!(matches!(
+            Parser::with_context("x + y", &context).bind("x").parse(),
+            Err(ParseError::UnknownIdentifier { name, .. }) if name == "y"
+        ));
+    }
+
+    #[test]
+    fn unchecked_calls() {
+        let options = ParserOptions::default().check_calls(false);
+        let parse = |input| Parser::new_with_options(input, options).parse();
+
+        assert_eq!(
+            parse("foo(1, x) + sqrt(1, 2)"),
+            Ok(Node::Sum(
+                Box::new(Node::Function(
+                    "foo".into(),
+                    vec![Node::Element(1.), Node::Variable("x".into())]
+                )),
+                Box::new(Node::Function(
+                    "sqrt".into(),
+                    vec![Node::Element(1.), Node::Element(2.)]
+                )),
+            ))
+        );
+        assert_eq!(parse("bar()"), Ok(Node::Function("bar".into(), vec![])));
+        assert!(matches!(
+            parse("if(1, 2)"),
+            Err(ParseError::ArgumentCount { .. })
+        ));
+        assert!(matches!(
+            Parser::new("foo(1)").parse(),
+            Err(ParseError::UnknownFunction { .. })
+        ));
+    }
+
+    #[test]
+    fn cell_references() {
+        let options = ParserOptions::default().cell_references(true);
+        let parse = |input| Parser::new_with_options(input, options).parse().unwrap();
+        let cell = |name: &str| Box::new(Node::CellRef(name.into()));
+
+        let sheet: HashMap<&str, f64> = [("A1", 3.), ("B2", 4.), ("AB12", 0.5), ("C3", -1.)]
+            .iter()
+            .copied()
+            .collect();
+        let lookup = |cell: &str| sheet.get(cell).copied();
+        let context = Context::new();
+        for (formula, value) in [
+            ("A1 * 2 + B2", 10.),
+            ("sqrt(A1^2 + B2^2) / AB12", 10.),
+            ("2A1 - C3", 7.),
+            ("max(A1, B2, C3)", 4.),
+            ("A1(B2 + 1)", 15.),
+            ("if(C3 < 0, -C3, C3) * pi", std::f64::consts::PI),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                parse(formula).eval_with_cells(&context, &lookup),
+                Ok(*value),
+                "{}",
+                formula
+            );
+        }
+        assert_eq!(
+            parse("A1 + D4 * Z9").eval_with_cells(&context, &lookup),
+            Err(EvalError::UnknownCell("D4".into()))
+        );
+        assert_eq!(
+            Parser::new_with_options("B2 - 1", options).evaluate(),
+            Err(ParseError::Evaluation(EvalError::UnknownCell("B2".into())))
+        );
+        assert_eq!(
+            EvalError::UnknownCell("D4".into()).to_string(),
+            "Unknown cell: D4"
+        );
+        assert_eq!(parse("a1 * A1").to_string(), "a1 * A1");
+        assert_eq!(parse("C3 + A1 * (C3 - x)").cells(), vec!["A1", "C3"]);
+
+        // Names that are not cells, or without the option, stay variables
+        // and functions.
+        assert_eq!(
+            parse("x0 + ABCD1 + atan2(1, 1) + B02"),
+            Node::Sum(
+                Box::new(Node::Sum(
+                    Box::new(Node::Sum(
+                        Box::new(Node::Variable("x0".into())),
+                        Box::new(Node::Variable("ABCD1".into()))
+                    )),
+                    Box::new(Node::Function(
+                        "atan2".into(),
+                        vec![Node::Element(1.), Node::Element(1.)]
+                    ))
+                )),
+                Box::new(Node::Variable("B02".into()))
+            )
+        );
+        assert_eq!(*cell("XFD1048576"), parse("XFD1048576"));
+        assert_eq!(Parser::new("A1").parse(), Ok(Node::Variable("A1".into())));
+    }
+
+    #[test]
+    fn series() {
+        for (input, value) in [
+            ("sum(i, 1, 100, i^2)", 338350.),
+            ("sum(i, 1, 3, sum(j, 1, i, j))", 10.),
+            ("prod(k, 1, 5, k)", 120.),
+            ("2 * sum(n, 0, 3, 2^n) + 1", 31.),
+            ("sum(i, 5, 1, i)", 0.),
+            ("prod(i, 5, 1, i)", 1.),
+            ("sum(i, -2, 2, i) + sum(i, 1, 1, i)", 1.),
+        ]
+        .iter()
+        {
+            assert_eq!(Parser::new(input).evaluate(), Ok(*value), "{}", input);
+        }
+        let node = Parser::new("sum(i, 1, n, i * x)").parse().unwrap();
+        assert_eq!(node.variables(), vec!["n", "x"]);
+        assert_eq!(
+            node,
+            Node::Series(
+                Series::Sum,
+                "i".into(),
+                Box::new(Node::Element(1.)),
+                Box::new(Node::Variable("n".into())),
+                Box::new(Node::Multiply(
+                    Box::new(Node::Variable("i".into())),
+                    Box::new(Node::Variable("x".into()))
+                ))
+            )
+        );
+        assert_eq!(node.to_string(), "sum(i, 1, n, i * x)");
+        assert_eq!(node.to_minified(), "sum(i,1,n,i*x)");
+
+        // The index hides a variable of the same name in the body only.
+        let mut context = Context::new();
+        context.set("i", 10.);
+        context.set("x", 2.);
+        let input = "sum(i, 1, 3, i * x) + i";
+        assert_eq!(Parser::with_context(input, &context).evaluate(), Ok(22.));
+        assert_eq!(Parser::new(input).evaluate_with(&context), Ok(22.));
+        let mut steps = Context::new();
+        assert_eq!(
+            Parser::new("f(n) = prod(i, 1, n, i); f(4) + f(0)").evaluate_statements(&mut steps),
+            Ok(25.)
+        );
+
+        let evaluate = |input: &str| Parser::new(input).evaluate_with(&Context::new());
+        assert_eq!(
+            evaluate("sum(i, 1, 2.5, i)"),
+            Err(ParseError::Evaluation(EvalError::Bound {
+                function: "sum",
+                value: 2.5,
+            }))
+        );
+        assert_eq!(
+            EvalError::Bound {
+                function: "prod",
+                value: 0.5,
+            }
+            .to_string(),
+            "The bounds of prod have to be integers, not 0.5"
+        );
+        // Too many terms fail at once rather than after a long wait.
+        let error = evaluate("sum(i, 1, 1e18, i)").unwrap_err();
+        assert_eq!(error.code(), "too_many_terms");
+        assert_eq!(
+            error.to_string(),
+            "Too many terms: sum takes at most 1000000"
+        );
+        // Parser::evaluate reports them too, rather than giving NaN.
+        for input in ["sum(i, 1.5, 3, i)", "sum(i, 1, 1e9, i)"].iter() {
+            assert!(
+                matches!(
+                    Parser::new(input).evaluate(),
+                    Err(ParseError::Evaluation(
+                        EvalError::Bound { .. } | EvalError::TooManyTerms { .. }
+                    ))
+                ),
+                "{}",
+                input
+            );
+        }
+
+        assert_eq!(
+            Parser::new("sum(2, 1, 3, i)").parse(),
+            Err(ParseError::UnexpectedToken {
+                found: Token::Number(2.),
+                expected: vec!["a variable"],
+                position: 4,
+            })
+        );
+        for (input, found) in [("sum(i)", 1), ("prod(k, 1, 3)", 3), ("sum()", 0)].iter() {
+            assert!(
+                matches!(
+                    Parser::new(input).parse(),
+                    Err(ParseError::ArgumentCount { found: count, .. }) if count == *found
+                ),
+                "{}",
+                input
+            );
+        }
+        // Spreadsheets have a SUM of their own.
+        let excel = ParserOptions::default().excel_compat(true);
+        assert_eq!(
+            Parser::new_with_options("=sum(1, 2, 3)", excel).evaluate(),
+            Ok(6.)
+        );
+    }
+
+    #[test]
+    fn strict_multiplication() {
+        let strict = ParserOptions::default().implicit_multiplication(false);
+        let parse = |input| Parser::new_with_options(input, strict).parse();
+
+        for (input, position) in [
+            ("(1)(2)", 3),
+            ("2(3)", 1),
+            ("2x", 1),
+            ("2 x", 2),
+            ("(a + b)c", 7),
+            ("3|x|", 1),
+            ("2{3}", 1),
+            ("1 + 2 * sin(x) (4)", 15),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                parse(input),
+                Err(ParseError::MissingOperator(*position)),
+                "{}",
+                input
+            );
+            assert!(Parser::new(input).parse().is_ok(), "{}", input);
+        }
+        assert_eq!(Parser::new("(1)(2)").evaluate(), Ok(2.));
+        assert_eq!(Parser::new("2(3)").evaluate(), Ok(6.));
+        assert_eq!(Parser::new("2x").parse(), Parser::new("2 * x").parse());
+
+        // Calls, explicit operators, bars and postfix operators are not
+        // juxtaposition.
+        assert_eq!(
+            parse("2 * (3) + sqrt(4) - |x|! * x"),
+            Parser::new("2 * (3) + sqrt(4) - |x|! * x").parse()
+        );
+        let options = strict.allow_bracket_groups(true);
+        assert_eq!(
+            Parser::new_with_options("[1 + 1][2]", options).parse(),
+            Err(ParseError::MissingOperator(7))
+        );
+        assert_eq!(
+            Parser::new_with_options("[1, 2]", strict).parse(),
+            Parser::new("[1, 2]").parse()
+        );
+        assert!(ParserOptions::default().implicit_multiplication);
+    }
+
+    #[test]
+    fn brackets_and_braces() {
+        let options = ParserOptions::default().allow_bracket_groups(true);
+        let evaluate = |input| Parser::new_with_options(input, options).evaluate();
+
+        assert_eq!(evaluate("[ (1+2) * {3-1} ] ^ 2"), Ok(36.));
+        assert_eq!(evaluate("2[3+1]"), Ok(8.));
+        assert_eq!(evaluate("{[(2)]}{3}[4]"), Ok(24.));
+        assert_eq!(evaluate("[2]^{1 + 1}"), Ok(4.));
+        assert_eq!(evaluate("[1, 2]"), Err(ParseError::UnexpectedComma(2)));
+
+        // Braces group either way, while brackets make lists by default.
+        assert_eq!(
+            Parser::new("2[3+1]").parse(),
+            Err(ParseError::TrailingInput {
+                token: Token::LeftBracket,
+                position: 1,
+            })
+        );
+        assert_eq!(
+            Parser::new("[ (1+2) * {3-1} ] ^ 2").evaluate(),
+            Err(ParseError::Evaluation(EvalError::TypeMismatch {
+                operation: "power",
+                operands: vec!["list", "number"],
+            }))
+        );
+        assert_eq!(Parser::new("2{3+1}").evaluate(), Ok(8.));
+        assert_eq!(Parser::new("{x}y").parse(), Parser::new("x * y").parse());
+        assert_eq!(Parser::new("10 % {3}").evaluate(), Ok(1.));
+        assert_eq!(
+            Parser::new("[{1} + 1]").parse(),
+            Ok(Node::List(vec![Node::Sum(
+                Box::new(Node::Element(1.)),
+                Box::new(Node::Element(1.))
+            )]))
+        );
+
+        let error = Parser::new("(1+2]").parse().unwrap_err();
+        assert_eq!(
+            error,
+            ParseError::MismatchedGroup {
+                open: '(',
+                close: ']',
+                position: 4,
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Mismatched '(' closed by ']' at position 4"
+        );
+        assert_eq!(error.code(), "mismatched_group");
+        assert_eq!(
+            Parser::new_with_options("2[{1)]", options).parse(),
+            Err(ParseError::MismatchedGroup {
+                open: '{',
+                close: ')',
+                position: 4,
+            })
+        );
+        assert_eq!(
+            Parser::new("[1, 2}").parse(),
+            Err(ParseError::MismatchedGroup {
+                open: '[',
+                close: '}',
+                position: 5,
+            })
+        );
+        assert_eq!(
+            Parser::new("max(1, 2]").parse(),
+            Err(ParseError::MismatchedGroup {
+                open: '(',
+                close: ']',
+                position: 8,
+            })
+        );
+        assert_eq!(
+            Parser::new("{1 + 2").parse(),
+            Err(ParseError::UnexpectedEndOfInput {
+                expected: "'}'",
+                position: 6,
+            })
+        );
+        assert!(Parser::is_incomplete("{1 + [2"));
+        assert!(!Parser::is_incomplete("{1}"));
+    }
+
+    #[test]
+    fn implicit_multiplication_by_names() {
+        let variable = |name: &str| Box::new(Node::Variable(name.into()));
+        let element = |number| Box::new(Node::Element(number));
+        assert_eq!(
+            Parser::new("2pi^2").parse(),
+            Ok(Node::Multiply(
+                element(2.),
+                Box::new(Node::Power(variable("pi"), element(2.)))
+            ))
+        );
+        assert_eq!(
+            Parser::new("2 pi").parse(),
+            Ok(Node::Multiply(element(2.), variable("pi")))
+        );
+        assert_eq!(
+            Parser::new("(x + 1)y").parse(),
+            Parser::new("(x + 1) * y").parse()
+        );
+        assert_eq!(
+            Parser::new("3x + 1").parse(),
+            Parser::new("3*x + 1").parse()
+        );
+        assert_eq!(
+            Parser::new("6 / 2x").parse(),
+            Parser::new("6 / (2 * x)").parse()
+        );
+        assert_eq!(Parser::new("x2").parse(), Ok(Node::Variable("x2".into())));
+
+        let mut context = Context::new();
+        context.set("x", 4.);
+        assert_eq!(Parser::with_context("3x", &context).evaluate(), Ok(12.));
+        assert_eq!(Parser::with_context("2(x+1)", &context).evaluate(), Ok(10.));
+        assert_eq!(Parser::with_context("-2x!", &context).evaluate(), Ok(-48.));
+
+        // Only a number or a group multiplies a following name.
+        assert_eq!(
+            Parser::new("x y").parse(),
+            Err(ParseError::TrailingInput {
+                token: Token::Identifier("y".into()),
+                position: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn trailing_input() {
+        let trailing = |input| Parser::new(input).parse().unwrap_err();
+
+        let error = trailing("1+2)");
+        assert_eq!(
+            error,
+            ParseError::TrailingInput {
+                token: Token::RightParenthesis,
+                position: 3,
+            }
+        );
+        assert_eq!(error.to_string(), "Unexpected ')' at position 3");
+        assert_eq!(error.code(), "trailing_input");
+        assert_eq!(
+            trailing("3,4"),
+            ParseError::TrailingInput {
+                token: Token::Comma,
+                position: 1,
+            }
+        );
+        assert_eq!(trailing("1 2"), operator_expected(Token::Number(2.), 2));
+        assert_eq!(
+            trailing("[1] [2]"),
+            ParseError::TrailingInput {
+                token: Token::LeftBracket,
+                position: 4,
+            }
+        );
+        assert_eq!(
+            Parser::new("x = 1 + 2)").parse_statement(),
+            Err(ParseError::TrailingInput {
+                token: Token::RightParenthesis,
+                position: 9,
+            })
+        );
+        assert_eq!(
+            Parser::new("x + 1}").parse_statement(),
+            Err(ParseError::TrailingInput {
+                token: Token::RightBrace,
+                position: 5,
+            })
+        );
+        assert_eq!(
+            Parser::new("2 )").parse_spanned(),
+            Err(ParseError::TrailingInput {
+                token: Token::RightParenthesis,
+                position: 2,
+            })
+        );
+        assert_eq!(Parser::new("1+2 # done").evaluate(), Ok(3.));
+    }
+
+    #[test]
+    fn unexpected_end_of_input() {
+        let operand = "a number or '('";
+        for (input, expected, position) in [
+            ("1+", operand, 2),
+            ("2*", operand, 2),
+            ("-(", operand, 2),
+            ("(1+2", "')'", 4),
+            ("  ", operand, 2),
+            ("2^ ", operand, 3),
+            ("sqrt(4", "')'", 6),
+            ("{1", "'}'", 2),
+            ("|1", "'|'", 2),
+            ("[1,", operand, 3),
+            ("x = ", operand, 4),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Parser::new(input).parse_statement(),
+                Err(ParseError::UnexpectedEndOfInput {
+                    expected,
+                    position: *position,
+                }),
+                "{}",
+                input
+            );
+        }
+
+        let error = Parser::new("(1+2").parse().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected end of input at position 4, expected ')'"
+        );
+        assert_eq!(error.code(), "unexpected_end_of_input");
+        assert_eq!(
+            Parser::new("1 + 2 = ").parse_equation(),
+            Err(ParseError::UnexpectedEndOfInput {
+                expected: operand,
+                position: 8,
+            })
+        );
+    }
+
+    #[test]
+    fn nesting_too_deep() {
+        let too_deep = |position| {
+            Err(ParseError::TooDeep {
+                limit: 128,
+                position,
+            })
+        };
+        let parens = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(Parser::new(&parens).parse().map(drop), too_deep(128));
+        let signs = format!("{}1", "-".repeat(100_000));
+        assert_eq!(Parser::new(&signs).parse().map(drop), too_deep(128));
+        let powers = format!("{}2", "2^".repeat(100_000));
+        assert_eq!(Parser::new(&powers).parse().map(drop), too_deep(256));
+        let calls = format!("{}1{}", "abs(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(Parser::new(&calls).parse().map(drop), too_deep(512));
+
+        let error = Parser::new(&parens).parse().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Nested more than 128 levels deep at position 128"
+        );
+        assert_eq!(error.code(), "too_deep");
+
+        // Signs of no effect and operators chained left to right do not
+        // nest.
+        let plus = format!("{}1", "+".repeat(100_000));
+        assert_eq!(Parser::new(&plus).evaluate(), Ok(1.));
+        let sum = format!("{}1", "1 - ".repeat(100_000));
+        assert_eq!(Parser::new(&sum).evaluate(), Ok(-99_999.));
+
+        let options = ParserOptions::default().max_depth(3);
+        assert_eq!(
+            Parser::new_with_options("((1))", options).evaluate(),
+            Ok(1.)
+        );
+        assert_eq!(
+            Parser::new_with_options("2 * ((-1))", options).parse(),
+            Err(ParseError::TooDeep {
+                limit: 3,
+                position: 6,
+            })
+        );
+    }
+
+    #[test]
+    fn long_chains() {
+        let sum = format!("1{}", "+1".repeat(999_999));
+        assert_eq!(Parser::new(&sum).evaluate(), Ok(1_000_000.));
+
+        let unlimited = ParserOptions::default().max_depth(usize::MAX);
+        let parens = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(
+            Parser::new_with_options(&parens, unlimited).evaluate(),
+            Ok(1.)
+        );
+        let powers = format!("{}1", "1^".repeat(100_000));
+        assert_eq!(
+            Parser::new_with_options(&powers, unlimited).evaluate(),
+            Ok(1.)
+        );
+        let calls = format!("{}-1{}", "abs(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(
+            Parser::new_with_options(&calls, unlimited).evaluate(),
+            Ok(1.)
+        );
+    }
+
+    #[test]
+    fn pow_is_right_associative() {
+        assert_eq!(Parser::new("2^3^2").evaluate(), Ok(512.));
+        assert_eq!(Parser::new("(2^3)^2").evaluate(), Ok(64.));
+        assert_eq!(Parser::new("2**3^2").evaluate(), Ok(512.));
+        assert_eq!(Parser::new("2^3^2*2").evaluate(), Ok(1024.));
+        assert_eq!(Parser::new("2^2^3!").evaluate(), Ok(2f64.powi(64)));
+    }
+
+    #[test]
+    fn double_asterisk_power_two() {
+        let mut parser = Parser::new("1**2");
+        let ast = parser.parse();
+        let expected = Node::Power(Box::new(Node::Element(1.)), Box::new(Node::Element(2.)));
+        assert_eq!(ast, Ok(expected))
+    }
+
+    #[test]
+    fn double_asterisk_pow_many() {
+        let mut parser = Parser::new("10**20^30");
+        let ast = parser.parse();
+        let right = Node::Power(Box::new(Node::Element(20.)), Box::new(Node::Element(30.)));
+        let expected = Node::Power(Box::new(Node::Element(10.)), Box::new(right));
         assert_eq!(ast, Ok(expected))
     }
 
+    #[test]
+    fn double_asterisk() {
+        assert_eq!(Parser::new("2**10").evaluate(), Ok(1024.));
+        assert_eq!(Parser::new("3 * 2 ** 2").evaluate(), Ok(12.));
+        assert_eq!(
+            Parser::new("2 * *3").evaluate(),
+            Err(operand_expected(Token::Asterisk, 4))
+        );
+        assert_eq!(
+            Parser::new("2***3").evaluate(),
+            Err(operand_expected(Token::Asterisk, 3))
+        );
+        assert!(Parser::is_incomplete("2 **"));
+    }
+
     #[test]
     fn combine_pow() {
         let mut parser = Parser::new("3^2*2");
@@ -285,4 +4467,47 @@ mod tests {
         let expected = Node::Multiply(Box::new(left), Box::new(right));
         assert_eq!(ast, Ok(expected))
     }
+
+    #[test]
+    fn combine_parenthesis_multiply_number() {
+        let ast = Parser::new("(2)3").parse();
+        let expected = Node::Multiply(Box::new(Node::Element(2.)), Box::new(Node::Element(3.)));
+        assert_eq!(ast, Ok(expected));
+
+        let ast = Parser::new("(1+1)2^3").parse();
+        let left = Node::Sum(Box::new(Node::Element(1.)), Box::new(Node::Element(1.)));
+        let right = Node::Power(Box::new(Node::Element(2.)), Box::new(Node::Element(3.)));
+        let expected = Node::Multiply(Box::new(left), Box::new(right));
+        assert_eq!(ast, Ok(expected));
+
+        let ast = Parser::new("2(3)4").parse();
+        let left = Node::Multiply(Box::new(Node::Element(2.)), Box::new(Node::Element(3.)));
+        let expected = Node::Multiply(Box::new(left), Box::new(Node::Element(4.)));
+        assert_eq!(ast, Ok(expected));
+
+        assert_eq!(
+            Parser::new("|-2|3 + [1, 2]2").parse(),
+            Parser::new("|-2|*3 + [1, 2]*2").parse()
+        );
+        assert_eq!(Parser::new("6/(1)2").evaluate(), Ok(3.));
+        // Only after a group; two numbers in a row are still a mistake.
+        assert_eq!(
+            Parser::new("(2)3 4").parse(),
+            Err(operator_expected(Token::Number(4.), 5))
+        );
+        let strict = ParserOptions::default().implicit_multiplication(false);
+        assert_eq!(
+            Parser::new_with_options("(2)3", strict).parse(),
+            Err(ParseError::MissingOperator(3))
+        );
+    }
+
+    #[test]
+    fn combine_parenthesis_multiply_pow() {
+        let mut parser = Parser::new("2(3)^2");
+        let ast = parser.parse();
+        let right = Node::Power(Box::new(Node::Element(3.)), Box::new(Node::Element(2.)));
+        let expected = Node::Multiply(Box::new(Node::Element(2.)), Box::new(right));
+        assert_eq!(ast, Ok(expected))
+    }
 }
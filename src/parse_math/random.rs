@@ -0,0 +1,56 @@
+// SplitMix64: seeded, so a run can be repeated from its seed, and good
+// enough for sample points and generated test cases. Any seed works, 0
+// included.
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Below `bound`, which has to be positive. Only the generated test
+    // cases need it.
+    #[cfg(test)]
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    // In [0, 1).
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let sequence = |seed| {
+            let mut random = Random::new(seed);
+            (0..4).map(|_| random.next_u64()).collect::<Vec<_>>()
+        };
+        assert_eq!(sequence(0), sequence(0));
+        assert_ne!(sequence(0), sequence(1));
+        assert!(sequence(0).iter().all(|value| *value != 0));
+    }
+
+    #[test]
+    fn ranges() {
+        let mut random = Random::new(7);
+        for _ in 0..1000 {
+            assert!(random.below(3) < 3);
+            let unit = random.unit();
+            assert!((0. ..1.).contains(&unit), "{}", unit);
+        }
+    }
+}
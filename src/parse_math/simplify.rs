@@ -0,0 +1,237 @@
+use super::ast::{self, Node};
+use super::eval;
+
+impl Node {
+    // Folds constant subtrees and drops neutral elements bottom-up. Rules
+    // such as 0 * x = 0 assume x is finite, which is what symbolic results
+    // want even though x could evaluate to NaN.
+    pub fn simplify(&self) -> Node {
+        match self {
+            Node::Element(_) | Node::Variable(_) | Node::CellRef(_) => self.clone(),
+            Node::Negative(node) => match node.simplify() {
+                Node::Element(number) => Node::Element(-number),
+                node @ Node::Negative(_) => *negated(node),
+                node => Node::Negative(Box::new(node)),
+            },
+            Node::Percent(node) => match node.simplify() {
+                Node::Element(number) => Node::Element(number / 100.),
+                node => Node::Percent(Box::new(node)),
+            },
+            Node::Factorial(node) => match node.simplify() {
+                Node::Element(number) => match eval::factorial(number) {
+                    Ok(factorial) => Node::Element(factorial),
+                    Err(_) => Node::Factorial(Box::new(Node::Element(number))),
+                },
+                node => Node::Factorial(Box::new(node)),
+            },
+            Node::Sum(left, right) => match (left.simplify(), right.simplify()) {
+                (Node::Element(a), Node::Element(b)) => Node::Element(a + b),
+                (Node::Element(0.), node) | (node, Node::Element(0.)) => node,
+                (left, right @ Node::Negative(_)) => Node::Subtract(Box::new(left), negated(right)),
+                (left, right) => Node::Sum(Box::new(left), Box::new(right)),
+            },
+            Node::Subtract(left, right) => match (left.simplify(), right.simplify()) {
+                (Node::Element(a), Node::Element(b)) => Node::Element(a - b),
+                (node, Node::Element(0.)) => node,
+                (Node::Element(0.), node) => Node::Negative(Box::new(node)),
+                (left, right @ Node::Negative(_)) => Node::Sum(Box::new(left), negated(right)),
+                (left, right) if left == right => Node::Element(0.),
+                (left, right) => Node::Subtract(Box::new(left), Box::new(right)),
+            },
+            Node::Multiply(left, right) => match (left.simplify(), right.simplify()) {
+                (Node::Element(a), Node::Element(b)) => Node::Element(a * b),
+                (Node::Element(0.), _) | (_, Node::Element(0.)) => Node::Element(0.),
+                (Node::Element(1.), node) | (node, Node::Element(1.)) => node,
+                (Node::Element(-1.), node) | (node, Node::Element(-1.)) => {
+                    Node::Negative(Box::new(node))
+                }
+                (Node::Element(a), product @ Node::Multiply(_, _))
+                | (product @ Node::Multiply(_, _), Node::Element(a))
+                    if has_constant_factor(&product) =>
+                {
+                    let mut factors = product.into_children();
+                    let node = factors.pop().expect("a product has two factors");
+                    let b = factors[0].eval();
+                    Node::Multiply(Box::new(Node::Element(a * b)), Box::new(node))
+                }
+                (node, Node::Element(number)) => {
+                    Node::Multiply(Box::new(Node::Element(number)), Box::new(node))
+                }
+                (left, right) => Node::Multiply(Box::new(left), Box::new(right)),
+            },
+            Node::Divide(left, right) => match (left.simplify(), right.simplify()) {
+                (Node::Element(a), Node::Element(b)) if b != 0. => Node::Element(a / b),
+                (node, Node::Element(1.)) => node,
+                (Node::Element(0.), _) => Node::Element(0.),
+                (left, right) => Node::Divide(Box::new(left), Box::new(right)),
+            },
+            Node::FloorDivide(left, right) => match (left.simplify(), right.simplify()) {
+                (Node::Element(a), Node::Element(b)) if b != 0. => Node::Element((a / b).floor()),
+                (left, right) => Node::FloorDivide(Box::new(left), Box::new(right)),
+            },
+            Node::Modulo(left, right) => match (left.simplify(), right.simplify()) {
+                (Node::Element(a), Node::Element(b)) if b != 0. => Node::Element(a % b),
+                (left, right) => Node::Modulo(Box::new(left), Box::new(right)),
+            },
+            Node::Power(left, right) => match (left.simplify(), right.simplify()) {
+                (Node::Element(a), Node::Element(b)) => Node::Element(a.powf(b)),
+                (_, Node::Element(0.)) => Node::Element(1.),
+                (node, Node::Element(1.)) => node,
+                (Node::Element(1.), _) => Node::Element(1.),
+                (left, right) => Node::Power(Box::new(left), Box::new(right)),
+            },
+            Node::Compare(comparison, left, right) => match (left.simplify(), right.simplify()) {
+                (Node::Element(a), Node::Element(b)) => Node::Element(comparison.value(a, b)),
+                (left, right) => Node::Compare(*comparison, Box::new(left), Box::new(right)),
+            },
+            // A constant left side that decides the result drops the right
+            // one, as evaluation would; otherwise the right side still has
+            // to be made 0 or 1.
+            Node::And(left, right) | Node::Or(left, right) => {
+                let or = matches!(self, Node::Or(..));
+                match (left.simplify(), right.simplify()) {
+                    (Node::Element(a), _) if (a != 0.) == or => Node::Element(ast::truth(or)),
+                    (Node::Element(_), Node::Element(b)) => Node::Element(ast::truth(b != 0.)),
+                    (left, right) if or => Node::Or(Box::new(left), Box::new(right)),
+                    (left, right) => Node::And(Box::new(left), Box::new(right)),
+                }
+            }
+            Node::Not(node) => match node.simplify() {
+                Node::Element(number) => Node::Element(ast::truth(number == 0.)),
+                node => Node::Not(Box::new(node)),
+            },
+            Node::List(items) => Node::List(items.iter().map(Node::simplify).collect()),
+            // Calls are not folded, since trigonometric ones depend on the
+            // angle mode they are evaluated in.
+            Node::Function(name, arguments) => {
+                Node::Function(name.clone(), arguments.iter().map(Node::simplify).collect())
+            }
+            // Nor are custom operators, which only the context defines.
+            Node::CustomBinary(symbol, left, right) => Node::CustomBinary(
+                symbol.clone(),
+                Box::new(left.simplify()),
+                Box::new(right.simplify()),
+            ),
+            Node::CustomUnary(fixity, symbol, node) => {
+                Node::CustomUnary(*fixity, symbol.clone(), Box::new(node.simplify()))
+            }
+            Node::Conditional(condition, then, otherwise) => match condition.simplify() {
+                Node::Element(number) if number != 0. => then.simplify(),
+                Node::Element(_) => otherwise.simplify(),
+                condition => Node::Conditional(
+                    Box::new(condition),
+                    Box::new(then.simplify()),
+                    Box::new(otherwise.simplify()),
+                ),
+            },
+            // Folding a series would take as long as evaluating it.
+            Node::Series(series, variable, from, to, body) => Node::Series(
+                *series,
+                variable.clone(),
+                Box::new(from.simplify()),
+                Box::new(to.simplify()),
+                Box::new(body.simplify()),
+            ),
+        }
+    }
+}
+
+impl Node {
+    // The simplified tree with every constant -0 written as 0, so it prints
+    // as 0 as well. A constant divisor keeps its sign, since that decides
+    // whether dividing by it gives inf or -inf.
+    pub fn canonicalize(&self) -> Node {
+        let mut node = self.simplify();
+        node.unsign_zeros();
+        node
+    }
+
+    fn unsign_zeros(&mut self) {
+        match self {
+            Node::Element(number) if *number == 0. => *number = 0.,
+            Node::Divide(left, right) | Node::FloorDivide(left, right)
+                if matches!(**right, Node::Element(_)) =>
+            {
+                left.unsign_zeros()
+            }
+            node => node.children_mut().into_iter().for_each(Node::unsign_zeros),
+        }
+    }
+}
+
+// Simplified products keep a constant factor on the left.
+fn has_constant_factor(product: &Node) -> bool {
+    matches!(product, Node::Multiply(left, _) if matches!(**left, Node::Element(_)))
+}
+
+// The operand of a negation.
+fn negated(node: Node) -> Box<Node> {
+    Box::new(node.into_children().remove(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_math::ast::Node;
+    use crate::parse_math::parser::Parser;
+
+    fn simplified(input: &str) -> Node {
+        Parser::new(input).parse().unwrap().simplify()
+    }
+
+    #[test]
+    fn folds_constants() {
+        assert_eq!(simplified("2*3+4^2"), Node::Element(22.));
+        assert_eq!(simplified("-(1-3)"), Node::Element(2.));
+    }
+
+    #[test]
+    fn neutral_elements() {
+        let x = Node::Variable("x".into());
+        for input in [
+            "x+0",
+            "0+x",
+            "x-0",
+            "1*x",
+            "x*1",
+            "x/1",
+            "x^1",
+            "--x",
+            "(x*0+1)*x",
+        ]
+        .iter()
+        {
+            assert_eq!(simplified(input), x, "{}", input);
+        }
+        assert_eq!(simplified("x^0"), Node::Element(1.));
+        assert_eq!(simplified("0*x"), Node::Element(0.));
+        assert_eq!(simplified("x-x"), Node::Element(0.));
+        assert_eq!(simplified("0-x"), Node::Negative(Box::new(x.clone())));
+        assert_eq!(simplified("x*-1"), Node::Negative(Box::new(x)));
+    }
+
+    #[test]
+    fn constants_move_to_the_front() {
+        assert_eq!(simplified("x*2"), simplified("2*x"));
+        assert_eq!(simplified("5*(4*x^3)"), simplified("20*x^3"));
+        assert_eq!(simplified("(x*3)*2"), simplified("6*x"));
+        assert_eq!(simplified("x + -y"), simplified("x - y"));
+    }
+
+    #[test]
+    fn keeps_division_by_zero() {
+        assert_eq!(simplified("1/0").eval(), f64::INFINITY);
+    }
+
+    #[test]
+    fn canonical_zero_is_unsigned() {
+        let canonical = |input: &str| Parser::new(input).parse().unwrap().canonicalize();
+
+        assert!(simplified("-0").to_minified().starts_with('-'));
+        assert_eq!(canonical("-0").to_minified(), "0");
+        assert_eq!(canonical("x + 0*-1").to_minified(), "x");
+        assert_eq!(canonical("[-0, x - -0]").to_minified(), "[0,x]");
+        assert_eq!(canonical("1/(-0)").eval(), f64::NEG_INFINITY);
+        assert_eq!(canonical("1//-0").eval(), f64::NEG_INFINITY);
+        assert_eq!(canonical("-0/x").to_minified(), "0");
+    }
+}
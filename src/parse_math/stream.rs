@@ -0,0 +1,233 @@
+use super::errors::ParseError;
+use super::parser::TokenSource;
+use super::token::{SpannedToken, Token, Tokenizer, TokenizerOptions};
+use std::io::{BufRead, ErrorKind};
+use std::str;
+
+// Tokens read from a stream instead of a string held whole in memory. Only
+// the text from the start of the current token on is buffered, plus what
+// the reader hands over at a time; spans are still byte offsets from the
+// start of the stream.
+pub struct StreamingTokenizer<R> {
+    reader: R,
+    options: TokenizerOptions,
+    buffer: String,
+    // Where the unread text starts in `buffer`, and where `buffer` starts
+    // in the stream.
+    start: usize,
+    offset: usize,
+    // The first bytes of a character the reader split between two reads.
+    partial: Vec<u8>,
+    exhausted: bool,
+    finished: bool,
+}
+
+impl<R: BufRead> StreamingTokenizer<R> {
+    pub fn new(reader: R) -> Self {
+        StreamingTokenizer::new_with_options(reader, TokenizerOptions::default())
+    }
+
+    pub fn new_with_options(reader: R, options: TokenizerOptions) -> Self {
+        StreamingTokenizer {
+            reader,
+            options,
+            buffer: String::new(),
+            start: 0,
+            offset: 0,
+            partial: Vec::new(),
+            exhausted: false,
+            finished: false,
+        }
+    }
+
+    // The buffered text is tokenized again until the token found there could
+    // not change with more input: the tokenizer looks at most two characters
+    // past a token, or past any whitespace and comments after a %, so a
+    // token is final once that much and another token follow it.
+    pub fn spanned_token(&mut self) -> Result<SpannedToken, ParseError> {
+        loop {
+            let unread = &self.buffer[self.start..];
+            let spanned = Tokenizer::new_with_options(unread, self.options).spanned_token();
+            let after = &unread[spanned.span.end..];
+            let settled = after.chars().nth(1).is_some()
+                && Tokenizer::new_with_options(after, self.options)
+                    .spanned_token()
+                    .token
+                    != Token::EOF;
+
+            if settled || self.exhausted {
+                let position = self.offset + self.start;
+                self.start += spanned.span.end;
+                return Ok(SpannedToken {
+                    token: spanned.token,
+                    span: position + spanned.span.start..position + spanned.span.end,
+                });
+            }
+            self.fill()?;
+        }
+    }
+
+    fn fill(&mut self) -> Result<(), ParseError> {
+        let chunk = loop {
+            match self.reader.fill_buf() {
+                Ok(chunk) => break chunk,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(ParseError::Io(error.to_string())),
+            }
+        };
+        // Bytes left that do not make a character keep the stream from
+        // ending, so the error comes back however often it is read.
+        if chunk.is_empty() {
+            if !self.partial.is_empty() {
+                return Err(invalid_utf8());
+            }
+            self.exhausted = true;
+            return Ok(());
+        }
+
+        self.partial.extend_from_slice(chunk);
+        let length = chunk.len();
+        self.reader.consume(length);
+
+        self.offset += self.start;
+        self.buffer.drain(..self.start);
+        self.start = 0;
+
+        // Bytes that may still be completed by the next read wait in
+        // `partial`; anything else that is not UTF-8 is an error.
+        let valid = match str::from_utf8(&self.partial) {
+            Ok(_) => self.partial.len(),
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(_) => return Err(invalid_utf8()),
+        };
+        let rest = self.partial.split_off(valid);
+        self.buffer
+            .push_str(str::from_utf8(&self.partial).map_err(|_| invalid_utf8())?);
+        self.partial = rest;
+
+        Ok(())
+    }
+}
+
+fn invalid_utf8() -> ParseError {
+    ParseError::Io("stream did not contain valid UTF-8".into())
+}
+
+impl<R: BufRead> TokenSource for StreamingTokenizer<R> {
+    fn next_token(&mut self) -> Result<SpannedToken, ParseError> {
+        self.spanned_token()
+    }
+}
+
+impl<R: BufRead> Iterator for StreamingTokenizer<R> {
+    type Item = Result<SpannedToken, ParseError>;
+
+    // Ends after EOF or after the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let spanned = self.spanned_token();
+        self.finished = !matches!(&spanned, Ok(spanned) if spanned.token != Token::EOF);
+        Some(spanned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_math::parser::Parser;
+    use crate::parse_math::token::DecimalSeparator;
+    use std::io::{self, BufReader, Cursor, Read};
+
+    // Hands the stream over `size` bytes at a time, so that tokens and
+    // characters are split between reads.
+    fn chunked(text: &str, size: usize) -> BufReader<Cursor<Vec<u8>>> {
+        BufReader::with_capacity(size, Cursor::new(text.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn matches_the_string_tokenizer() {
+        let source = " 12.5e-3 *(x_1 //2)% 7 ÷ 0xff  + $3 - 1,5 × [ab, 2] § 9! ";
+        let expected: Vec<SpannedToken> = Tokenizer::new(source).spanned().collect();
+        for size in 1..=8 {
+            let tokens: Result<Vec<SpannedToken>, ParseError> =
+                StreamingTokenizer::new(chunked(source, size)).collect();
+            assert_eq!(tokens.as_ref(), Ok(&expected), "{}", size);
+        }
+    }
+
+    #[test]
+    fn lookahead_across_reads() {
+        let options = TokenizerOptions {
+            decimal_separator: DecimalSeparator::Comma,
+            ..TokenizerOptions::default()
+        };
+        for source in [
+            "[1,5, 1,x]",
+            "10 %   3",
+            "10 %    ",
+            "1e+7",
+            "2 //",
+            "1_0,25",
+            "10 % # note\n 3",
+            "2 # trailing",
+        ]
+        .iter()
+        {
+            let expected: Vec<SpannedToken> = Tokenizer::new_with_options(source, options)
+                .spanned()
+                .collect();
+            let tokens: Vec<SpannedToken> =
+                StreamingTokenizer::new_with_options(chunked(source, 1), options)
+                    .map(Result::unwrap)
+                    .collect();
+            assert_eq!(tokens, expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn parses_a_large_stream() {
+        let terms: Vec<String> = (0..2000).map(|i| format!("({} - {})", i + 1, i)).collect();
+        let source = terms.join(" + ");
+        assert!(source.len() > 20_000);
+
+        let mut parser = Parser::from_reader(Cursor::new(source.clone().into_bytes()));
+        assert_eq!(parser.evaluate(), Ok(2000.));
+
+        let mut parser = Parser::from_reader(chunked(&source, 64));
+        let streamed = parser.parse_spanned().unwrap();
+        assert_eq!(Parser::new(&source).parse_spanned(), Ok(streamed));
+    }
+
+    #[test]
+    fn read_errors() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk on fire"))
+            }
+        }
+
+        let mut tokens = StreamingTokenizer::new(BufReader::new(Failing));
+        assert_eq!(
+            tokens.next(),
+            Some(Err(ParseError::Io("disk on fire".into())))
+        );
+        assert_eq!(tokens.next(), None);
+
+        let error = Parser::from_reader(BufReader::new(Failing))
+            .evaluate()
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Read error: disk on fire");
+        assert_eq!(error.code(), "io");
+
+        for bytes in [&b"1 + \xff"[..], &b"2 * \xe2\x88"[..]].iter() {
+            assert_eq!(
+                Parser::from_reader(Cursor::new(bytes.to_vec())).evaluate(),
+                Err(invalid_utf8())
+            );
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use super::ast::Node;
+use super::random::Random;
+
+// Random trees for round-trip tests, reproducible from the seed. Elements
+// are non-negative, as the parser produces them, and only integers unless
+// decimals are asked for.
+pub struct Trees {
+    random: Random,
+    pub decimals: bool,
+}
+
+impl Trees {
+    pub fn new(seed: u64) -> Self {
+        Trees {
+            random: Random::new(seed),
+            decimals: true,
+        }
+    }
+
+    pub fn next(&mut self, bound: u64) -> u64 {
+        self.random.below(bound)
+    }
+
+    pub fn node(&mut self, depth: u32) -> Node {
+        let leaf = depth == 0 || self.next(4) == 0;
+        let pair = |trees: &mut Trees| {
+            (
+                Box::new(trees.node(depth - 1)),
+                Box::new(trees.node(depth - 1)),
+            )
+        };
+
+        let kind = if leaf {
+            self.next(3)
+        } else {
+            3 + self.next(11)
+        };
+        match kind {
+            0 => Node::Element(self.next(100) as f64),
+            1 if self.decimals => Node::Element(self.next(1000) as f64 / 8.),
+            1 => Node::Element(self.next(100_000) as f64),
+            2 => Node::Variable(["x", "y", "abc"][self.next(3) as usize].into()),
+            3 => Node::Negative(Box::new(self.node(depth - 1))),
+            10 => Node::Percent(Box::new(self.node(depth - 1))),
+            11 => Node::Factorial(Box::new(self.node(depth - 1))),
+            12 => {
+                let (left, right) = pair(self);
+                Node::Modulo(left, right)
+            }
+            13 => {
+                let (left, right) = pair(self);
+                Node::FloorDivide(left, right)
+            }
+            4 => {
+                let (left, right) = pair(self);
+                Node::Sum(left, right)
+            }
+            5 => {
+                let (left, right) = pair(self);
+                Node::Subtract(left, right)
+            }
+            6 => {
+                let (left, right) = pair(self);
+                Node::Multiply(left, right)
+            }
+            7 => {
+                let (left, right) = pair(self);
+                Node::Divide(left, right)
+            }
+            8 => {
+                let (left, right) = pair(self);
+                Node::Power(left, right)
+            }
+            _ => Node::List(
+                (0..1 + self.next(3))
+                    .map(|_| self.node(depth - 1))
+                    .collect(),
+            ),
+        }
+    }
+}
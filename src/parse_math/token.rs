@@ -1,105 +1,765 @@
-use std::iter::{Filter, Peekable};
-use std::str::Chars;
+use std::fmt;
+use std::iter::{Map, Peekable};
+use std::ops::Range;
+use std::str::CharIndices;
 
-#[derive(PartialEq, PartialOrd, Debug)]
+type Chars<'a> = Peekable<Map<CharIndices<'a>, fn((usize, char)) -> (usize, char)>>;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
 pub enum OperationPrecedence {
     Default,
+    Or,
+    And,
+    Comparison,
     AddSub,
     MulDiv,
+    // Implicit multiplication, unless ParserOptions puts it with * and /.
+    Juxtaposition,
     Power,
+    Postfix,
 }
 
-#[derive(PartialEq, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Token {
     Number(f64),
+    Identifier(String),
+    History(usize),
     Plus,
     Minus,
     Asterisk,
     Slash,
+    DoubleSlash,
     Caret,
+    DoubleAsterisk,
+    Percent,
+    Modulo,
+    Exclamation,
+    Equals,
+    DoubleEquals,
+    NotEquals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+    AndAnd,
+    OrOr,
     LeftParenthesis,
     RightParenthesis,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    Semicolon,
+    Pipe,
+    // A character defined as an operator in the parser's context, which
+    // would otherwise be Invalid.
+    Custom(char),
+    Invalid(char),
+    InvalidNumber(String),
     EOF,
 }
 
 impl Token {
     pub fn operation_precedence(&self) -> OperationPrecedence {
         match self {
+            Self::OrOr => OperationPrecedence::Or,
+            Self::AndAnd => OperationPrecedence::And,
+            Self::DoubleEquals
+            | Self::NotEquals
+            | Self::Less
+            | Self::LessEquals
+            | Self::Greater
+            | Self::GreaterEquals => OperationPrecedence::Comparison,
             Self::Plus | Self::Minus => OperationPrecedence::AddSub,
-            Self::Asterisk | Self::Slash | Self::LeftParenthesis => OperationPrecedence::MulDiv,
-            Self::Caret => OperationPrecedence::Power,
+            Self::Asterisk | Self::Slash | Self::DoubleSlash | Self::Modulo => {
+                OperationPrecedence::MulDiv
+            }
+            Self::Number(_)
+            | Self::LeftParenthesis
+            | Self::LeftBracket
+            | Self::LeftBrace
+            | Self::Identifier(_)
+            | Self::Pipe => OperationPrecedence::Juxtaposition,
+            Self::Caret | Self::DoubleAsterisk => OperationPrecedence::Power,
+            Self::Percent | Self::Exclamation => OperationPrecedence::Postfix,
             _ => OperationPrecedence::Default,
         }
     }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Number(_) => "Number",
+            Self::Identifier(_) => "Identifier",
+            Self::History(_) => "History",
+            Self::Plus => "Plus",
+            Self::Minus => "Minus",
+            Self::Asterisk => "Asterisk",
+            Self::Slash => "Slash",
+            Self::DoubleSlash => "DoubleSlash",
+            Self::Caret => "Caret",
+            Self::DoubleAsterisk => "DoubleAsterisk",
+            Self::Percent => "Percent",
+            Self::Modulo => "Modulo",
+            Self::Exclamation => "Exclamation",
+            Self::Equals => "Equals",
+            Self::DoubleEquals => "DoubleEquals",
+            Self::NotEquals => "NotEquals",
+            Self::Less => "Less",
+            Self::LessEquals => "LessEquals",
+            Self::Greater => "Greater",
+            Self::GreaterEquals => "GreaterEquals",
+            Self::AndAnd => "AndAnd",
+            Self::OrOr => "OrOr",
+            Self::LeftParenthesis => "LeftParenthesis",
+            Self::RightParenthesis => "RightParenthesis",
+            Self::LeftBracket => "LeftBracket",
+            Self::RightBracket => "RightBracket",
+            Self::LeftBrace => "LeftBrace",
+            Self::RightBrace => "RightBrace",
+            Self::Comma => "Comma",
+            Self::Semicolon => "Semicolon",
+            Self::Pipe => "Pipe",
+            Self::Custom(_) => "Custom",
+            Self::Invalid(_) => "Invalid",
+            Self::InvalidNumber(_) => "InvalidNumber",
+            Self::EOF => "EOF",
+        }
+    }
+}
+
+// The token as it is written, for messages; × and ÷ print as the * and /
+// they are read as, and the end of the input in words.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Self::Number(number) => return write!(f, "{}", number),
+            Self::Identifier(name) => return write!(f, "{}", name),
+            Self::History(number) => return write!(f, "${}", number),
+            Self::Custom(character) | Self::Invalid(character) => {
+                return write!(f, "{}", character)
+            }
+            Self::InvalidNumber(literal) => return write!(f, "{}", literal),
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Asterisk => "*",
+            Self::Slash => "/",
+            Self::DoubleSlash => "//",
+            Self::Caret => "^",
+            Self::DoubleAsterisk => "**",
+            Self::Percent | Self::Modulo => "%",
+            Self::Exclamation => "!",
+            Self::Equals => "=",
+            Self::DoubleEquals => "==",
+            Self::NotEquals => "!=",
+            Self::Less => "<",
+            Self::LessEquals => "<=",
+            Self::Greater => ">",
+            Self::GreaterEquals => ">=",
+            Self::AndAnd => "&&",
+            Self::OrOr => "||",
+            Self::LeftParenthesis => "(",
+            Self::RightParenthesis => ")",
+            Self::LeftBracket => "[",
+            Self::RightBracket => "]",
+            Self::LeftBrace => "{",
+            Self::RightBrace => "}",
+            Self::Comma => ",",
+            Self::Semicolon => ";",
+            Self::Pipe => "|",
+            Self::EOF => "end of input",
+        };
+
+        write!(f, "{}", symbol)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DecimalSeparator {
+    #[default]
+    Point,
+    Comma,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TokenizerOptions {
+    pub decimal_separator: DecimalSeparator,
+    // Whether the other one of , and . may group the integer digits of a
+    // number in threes, as in 1,234,567.89.
+    pub grouping: bool,
+    // Whether a number may end in an SI prefix that scales it, as in 4.7k
+    // or 100m. Off by default, since 2m could as well be 2 times m.
+    pub magnitude_suffixes: bool,
+    // Whether <> is not-equal, as in spreadsheets.
+    pub angle_not_equals: bool,
+}
+
+impl DecimalSeparator {
+    fn char(self) -> char {
+        match self {
+            DecimalSeparator::Point => '.',
+            DecimalSeparator::Comma => ',',
+        }
+    }
+
+    fn grouping(self) -> char {
+        match self {
+            DecimalSeparator::Point => ',',
+            DecimalSeparator::Comma => '.',
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Range<usize>,
 }
 
 pub struct Tokenizer<'a> {
-    chars: Peekable<Filter<Chars<'a>, &'a dyn Fn(&char) -> bool>>,
+    chars: Chars<'a>,
+    length: usize,
+    options: TokenizerOptions,
+    finished: bool,
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(expression: &'a str) -> Self {
-        let chars = expression
-            .chars()
-            .filter((&|char: &char| !char.is_ascii_whitespace()) as &'a dyn Fn(&char) -> bool)
-            .peekable();
-        Tokenizer { chars }
+        Tokenizer::new_with_options(expression, TokenizerOptions::default())
+    }
+
+    pub fn new_with_options(expression: &'a str, options: TokenizerOptions) -> Self {
+        Tokenizer {
+            chars: expression
+                .char_indices()
+                .map(normalize as fn((usize, char)) -> (usize, char))
+                .peekable(),
+            length: expression.len(),
+            options,
+            finished: false,
+        }
+    }
+
+    pub fn spanned(self) -> Spanned<'a> {
+        Spanned { tokenizer: self }
+    }
+
+    fn next_spanned(&mut self) -> Option<SpannedToken> {
+        if self.finished {
+            return None;
+        }
+
+        let spanned = self.spanned_token();
+        self.finished = spanned.token == Token::EOF;
+        Some(spanned)
     }
 }
 
 impl<'a> Iterator for Tokenizer<'a> {
     type Item = Token;
 
+    // Unrecognized characters come through as Invalid, so the tokens after
+    // them are still read; the iteration ends after EOF.
     fn next(&mut self) -> Option<Self::Item> {
-        self.token()
+        self.next_spanned().map(|spanned| spanned.token)
+    }
+}
+
+pub struct Spanned<'a> {
+    tokenizer: Tokenizer<'a>,
+}
+
+impl<'a> Iterator for Spanned<'a> {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tokenizer.next_spanned()
     }
 }
 
 impl<'a> Tokenizer<'a> {
-    fn token(&mut self) -> Option<Token> {
-        let next_char = self.chars.next();
+    // Spans are byte offsets into the expression, and whitespace and
+    // comments between tokens belong to neither of them.
+    pub(super) fn spanned_token(&mut self) -> SpannedToken {
+        self.skip_whitespace_and_comments();
+        let (start, next_char) = match self.chars.next() {
+            Some(next) => next,
+            None => {
+                return SpannedToken {
+                    token: Token::EOF,
+                    span: self.length..self.length,
+                }
+            }
+        };
+        let mut end = self.position();
+
+        let token = match next_char {
+            // A fraction may leave out its leading zero, as in .5.
+            '0'..='9' | '.' if next_char != '.' || self.starts_fraction(end) => {
+                let (token, number_end) = self.number(next_char, end);
+                end = number_end;
+                token
+            }
+            '$' => match self.digits(end) {
+                Some((digits, digits_end)) => {
+                    end = digits_end;
+                    Token::History(digits.parse().unwrap_or(usize::MAX))
+                }
+                None => Token::Invalid('$'),
+            },
+            'a'..='z' | 'A'..='Z' | '_' => {
+                let mut identifier = next_char.to_string();
+
+                while let Some(&(index, next_char)) = self.chars.peek() {
+                    if index == end && (next_char.is_ascii_alphanumeric() || next_char == '_') {
+                        identifier.push(next_char);
+                        self.chars.next();
+                        end = self.position();
+                    } else {
+                        break;
+                    }
+                }
+
+                // The names f64 prints infinity and NaN with, in any case.
+                match identifier.to_ascii_lowercase().as_str() {
+                    "inf" | "infinity" => Token::Number(f64::INFINITY),
+                    "nan" => Token::Number(f64::NAN),
+                    _ => Token::Identifier(identifier),
+                }
+            }
+            // The typographic forms that text copied from documents uses.
+            '+' => Token::Plus,
+            '-' | '\u{2212}' => Token::Minus,
+            // ** is a power, as in Python.
+            '*' => match self.chars.peek() {
+                Some(&(index, '*')) if index == end => {
+                    self.chars.next();
+                    end = self.position();
+                    Token::DoubleAsterisk
+                }
+                _ => Token::Asterisk,
+            },
+            '×' | '·' => Token::Asterisk,
+            '/' => match self.chars.peek() {
+                Some(&(index, '/')) if index == end => {
+                    self.chars.next();
+                    end = self.position();
+                    Token::DoubleSlash
+                }
+                _ => Token::Slash,
+            },
+            '÷' => Token::Slash,
+            '^' => Token::Caret,
+            // A % with an operand after it is a remainder, any other is a
+            // percent, so 10 % 3 is 1 while 50% * 2 and 5% - 1 take a
+            // percent first.
+            '%' => {
+                self.skip_whitespace_and_comments();
+                match self.chars.peek() {
+                    Some((_, next)) if next.is_ascii_alphanumeric() || "_([{$.".contains(*next) => {
+                        Token::Modulo
+                    }
+                    _ => Token::Percent,
+                }
+            }
+            // != is always a comparison, so a factorial equation needs a
+            // space, as in 3! = 6.
+            '!' if self.next_is('=', &mut end) => Token::NotEquals,
+            '!' => Token::Exclamation,
+            '=' if self.next_is('=', &mut end) => Token::DoubleEquals,
+            '=' => Token::Equals,
+            '<' if self.next_is('=', &mut end) => Token::LessEquals,
+            '<' if self.options.angle_not_equals && self.next_is('>', &mut end) => Token::NotEquals,
+            '<' => Token::Less,
+            '>' if self.next_is('=', &mut end) => Token::GreaterEquals,
+            '>' => Token::Greater,
+            '&' if self.next_is('&', &mut end) => Token::AndAnd,
+            '(' => Token::LeftParenthesis,
+            ')' => Token::RightParenthesis,
+            '[' => Token::LeftBracket,
+            ']' => Token::RightBracket,
+            '{' => Token::LeftBrace,
+            '}' => Token::RightBrace,
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            // The parser reads || as two bars where a bar can only open or
+            // close a pair, as in ||x| - 1|.
+            '|' if self.next_is('|', &mut end) => Token::OrOr,
+            '|' => Token::Pipe,
+            char => Token::Invalid(char),
+        };
+
+        SpannedToken {
+            token,
+            span: start..end,
+        }
+    }
+
+    // Takes `second` if it comes straight after the character that ends at
+    // `end`, as the = of <= does.
+    fn next_is(&mut self, second: char, end: &mut usize) -> bool {
+        match self.chars.peek() {
+            Some(&(index, next)) if index == *end && next == second => {
+                self.chars.next();
+                *end = self.position();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Digits and decimal points, then an optional exponent written right
+    // after them: e or E, an optional sign and at least one digit. Anything
+    // that does not make a valid f64 comes out as InvalidNumber.
+    //
+    // An underscore may separate two digits of the mantissa, as in 1_000 or
+    // 0.000_1; next to anything else (100_, 1__0, 1_.5) the literal is
+    // invalid. A leading underscore starts an identifier instead.
+    fn number(&mut self, first: char, start: usize) -> (Token, usize) {
+        if first == '0' {
+            let radix = match self.chars.peek() {
+                Some(&(index, prefix)) if index == start => match prefix {
+                    'x' | 'X' => Some((prefix, 16)),
+                    'o' | 'O' => Some((prefix, 8)),
+                    'b' | 'B' => Some((prefix, 2)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some((prefix, radix)) = radix {
+                self.chars.next();
+                return self.radix_number(prefix, radix, start + 1);
+            }
+        }
+
+        let mut number = first.to_string();
+        let mut end = start;
 
-        let char = match next_char {
-            Some('0'..='9') => {
-                let mut number = next_char?.to_string();
+        while let Some(&(index, next_char)) = self.chars.peek() {
+            if index == end
+                && (next_char.is_ascii_digit()
+                    || next_char == '_'
+                    || self.is_decimal(next_char, index, end)
+                    || self.is_grouping(next_char, index, end))
+            {
+                number.push(next_char);
+                self.chars.next();
+                end = self.position();
+            } else {
+                break;
+            }
+        }
+
+        // An e with neither digits nor a sign after it is left for a name,
+        // so 2e and 2exp(1) read as products. 1e+ is still one malformed
+        // literal.
+        if let Some(&(index, exponent @ ('e' | 'E'))) = self.chars.peek() {
+            if index == end && self.exponent_follows() {
+                number.push(exponent);
+                self.chars.next();
+                end = self.position();
+                if let Some(&(index, sign @ ('+' | '-'))) = self.chars.peek() {
+                    if index == end {
+                        number.push(sign);
+                        self.chars.next();
+                        end = self.position();
+                    }
+                }
+                if let Some((digits, digits_end)) = self.digits(end) {
+                    number.push_str(&digits);
+                    end = digits_end;
+                }
+                // A fractional or separated exponent such as 1e2.5 is one
+                // malformed literal, not 1e2 followed by .5.
+                while let Some(&(index, next_char)) = self.chars.peek() {
+                    let decimal = self.is_decimal(next_char, index, end);
+                    if index == end && (next_char.is_ascii_digit() || next_char == '_' || decimal) {
+                        number.push(next_char);
+                        self.chars.next();
+                        end = self.position();
+                    } else {
+                        break;
+                    }
+                }
+            }
+        }
 
-                while let Some(next_char) = self.chars.peek() {
-                    if next_char.is_numeric() || next_char == &'.' {
-                        number.push(self.chars.next()?);
+        // One prefix, written right after the number; letters after it make
+        // the whole literal invalid, so 2kk is an error rather than 2k * k.
+        let mut suffix = String::new();
+        let mut magnitude = None;
+        if self.options.magnitude_suffixes {
+            if let Some(&(index, next_char)) = self.chars.peek() {
+                magnitude = magnitude_suffix(next_char).filter(|_| index == end);
+            }
+            if magnitude.is_some() {
+                while let Some(&(index, next_char)) = self.chars.peek() {
+                    if index == end && (next_char.is_alphanumeric() || next_char == '_') {
+                        suffix.push(next_char);
+                        self.chars.next();
+                        end = self.position();
                     } else {
                         break;
                     }
                 }
+            }
+        }
+
+        let bytes = number.as_bytes();
+        let exponent = number.find(['e', 'E']).unwrap_or(bytes.len());
+        let separated = (0..bytes.len()).filter(|&i| bytes[i] == b'_').all(|i| {
+            i < exponent
+                && bytes[i - 1].is_ascii_digit()
+                && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)
+        });
+        let separator = self.options.decimal_separator;
+        let grouped = !self.options.grouping || is_grouped(&number[..exponent], separator);
+        let digits = number
+            .replace(['_', separator.grouping()], "")
+            .replace(',', ".");
+        let token = match (digits.parse::<f64>(), magnitude) {
+            (Ok(value), None) if separated && grouped => Token::Number(value),
+            // Dividing by 1e3 rather than multiplying by 1e-3 keeps 100m at
+            // exactly 0.1.
+            (Ok(value), Some(power)) if separated && grouped && suffix.chars().count() == 1 => {
+                match power {
+                    0.. => Token::Number(value * 10f64.powi(power)),
+                    _ => Token::Number(value / 10f64.powi(-power)),
+                }
+            }
+            _ => Token::InvalidNumber(number + &suffix),
+        };
+
+        (token, end)
+    }
+
+    // Whether the decimal point just read, which ends at `end`, has a digit
+    // right after it.
+    fn starts_fraction(&mut self, end: usize) -> bool {
+        match self.chars.peek() {
+            Some(&(index, next_char)) if index == end => {
+                self.options.decimal_separator == DecimalSeparator::Point
+                    && next_char.is_ascii_digit()
+            }
+            _ => false,
+        }
+    }
 
-                Token::Number(number.parse::<f64>().unwrap())
+    // Whether `next_char`, at `index`, is the decimal separator of a number
+    // that so far ends at `end`. A decimal comma has to sit between two
+    // digits, so 1,5 is a number but [1, 5] and [1,x] are still lists.
+    fn is_decimal(&self, next_char: char, index: usize, end: usize) -> bool {
+        match (self.options.decimal_separator, next_char) {
+            (DecimalSeparator::Point, '.') => true,
+            (DecimalSeparator::Comma, ',') if index == end => match self.chars.clone().nth(1) {
+                Some((_, digit)) => digit.is_ascii_digit(),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    // A grouping separator is taken into the number whenever it follows a
+    // digit directly and a digit or another separator comes right after, so
+    // that 1,,000 and 1,00 are invalid numbers, while a comma followed by a
+    // space, as in [1, 2], still separates items.
+    fn is_grouping(&self, next_char: char, index: usize, end: usize) -> bool {
+        let grouping = self.options.decimal_separator.grouping();
+        if !self.options.grouping || next_char != grouping || index != end {
+            return false;
+        }
+
+        match self.chars.clone().nth(1) {
+            Some((_, next)) => next.is_ascii_digit() || next == grouping,
+            None => false,
+        }
+    }
+
+    // The integer after a 0x, 0o or 0b prefix. Every letter, digit or
+    // underscore written right after the prefix belongs to the literal, so
+    // 0xG1 and 0b102 are one invalid number rather than 0 times a name, and
+    // the token says what is wrong with it. Underscores separate digits as
+    // they do in decimal literals.
+    fn radix_number(&mut self, prefix: char, radix: u32, start: usize) -> (Token, usize) {
+        let mut digits = String::new();
+        let mut end = start;
+
+        while let Some(&(index, next_char)) = self.chars.peek() {
+            if index == end && (next_char.is_ascii_alphanumeric() || next_char == '_') {
+                digits.push(next_char);
+                self.chars.next();
+                end = self.position();
+            } else {
+                break;
             }
-            Some('+') => Token::Plus,
-            Some('-') => Token::Minus,
-            Some('*') => Token::Asterisk,
-            Some('/') => Token::Slash,
-            Some('^') => Token::Caret,
-            Some('(') => Token::LeftParenthesis,
-            Some(')') => Token::RightParenthesis,
-            Some(_) => {
-                return None;
+        }
+
+        let literal = format!("0{}{}", prefix, digits);
+        let name = match radix {
+            16 => "a hexadecimal",
+            8 => "an octal",
+            _ => "a binary",
+        };
+        let invalid = |reason: String| Token::InvalidNumber(format!("{} ({})", literal, reason));
+        let token = if digits.is_empty() {
+            invalid("no digits".into())
+        } else if let Some(digit) = digits
+            .chars()
+            .find(|&digit| digit != '_' && !digit.is_digit(radix))
+        {
+            invalid(format!("{} is not {} digit", digit, name))
+        } else if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+            invalid("_ must separate two digits".into())
+        } else {
+            match u64::from_str_radix(&digits.replace('_', ""), radix) {
+                Ok(value) => Token::Number(value as f64),
+                Err(_) => invalid("too large".into()),
             }
-            None => Token::EOF,
         };
-        Some(char)
+
+        (token, end)
+    }
+
+    // Where the next character starts, which is where the ones read so far
+    // end.
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.length, |&(index, _)| index)
+    }
+
+    // A # starts a comment that runs to the end of the line.
+    fn skip_whitespace_and_comments(&mut self) {
+        let mut comment = false;
+        while let Some(&(_, next_char)) = self.chars.peek() {
+            match next_char {
+                '\n' => comment = false,
+                '#' => comment = true,
+                _ if comment || next_char.is_ascii_whitespace() => {}
+                _ => break,
+            }
+            self.chars.next();
+        }
+    }
+
+    fn exponent_follows(&self) -> bool {
+        let mut ahead = self.chars.clone();
+        ahead.next();
+        matches!(ahead.next(), Some((_, '0'..='9' | '+' | '-')))
     }
+
+    fn digits(&mut self, start: usize) -> Option<(String, usize)> {
+        let mut digits = String::new();
+        let mut end = start;
+
+        while let Some(&(index, next_char)) = self.chars.peek() {
+            if index == end && next_char.is_ascii_digit() {
+                digits.push(next_char);
+                self.chars.next();
+                end = self.position();
+            } else {
+                break;
+            }
+        }
+
+        if digits.is_empty() {
+            None
+        } else {
+            Some((digits, end))
+        }
+    }
+}
+
+// Text from East Asian input methods and PDFs uses the fullwidth forms of
+// ASCII characters, as in １＋２, and spaces other than the plain one. The
+// tokenizer reads them as the ASCII they stand for, while the byte offsets
+// of spans stay those of the original text.
+fn normalize((index, char): (usize, char)) -> (usize, char) {
+    let char = match char {
+        '\u{ff01}'..='\u{ff5e}' => char::from_u32(char as u32 - 0xfee0).unwrap_or(char),
+        '\u{a0}' | '\u{2000}'..='\u{200a}' | '\u{202f}' | '\u{205f}' | '\u{3000}' => ' ',
+        char => char,
+    };
+
+    (index, char)
+}
+
+// The power of ten an SI prefix stands for.
+fn magnitude_suffix(suffix: char) -> Option<i32> {
+    match suffix {
+        'T' => Some(12),
+        'G' => Some(9),
+        'M' => Some(6),
+        'k' => Some(3),
+        'm' => Some(-3),
+        'u' | 'µ' | 'μ' => Some(-6),
+        'n' => Some(-9),
+        _ => None,
+    }
+}
+
+// The integer part of a mantissa with grouping separators has a first
+// group of one to three digits and then groups of exactly three; nothing
+// after the decimal separator is grouped.
+fn is_grouped(mantissa: &str, separator: DecimalSeparator) -> bool {
+    let (integer, fraction) = match mantissa.split_once(separator.char()) {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (mantissa, ""),
+    };
+    if fraction.contains(separator.grouping()) {
+        return false;
+    }
+    if !integer.contains(separator.grouping()) {
+        return true;
+    }
+
+    integer
+        .split(separator.grouping())
+        .enumerate()
+        .all(|(index, group)| {
+            let size = if index == 0 { 1..=3 } else { 3..=3 };
+            size.contains(&group.len()) && group.bytes().all(|digit| digit.is_ascii_digit())
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn display() {
+        let tokens: Vec<String> = Tokenizer::new("2.5 × x ** $1 <= (y) ÷ 1e3 && |z|")
+            .map(|token| token.to_string())
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                "2.5",
+                "*",
+                "x",
+                "**",
+                "$1",
+                "<=",
+                "(",
+                "y",
+                ")",
+                "/",
+                "1000",
+                "&&",
+                "|",
+                "z",
+                "|",
+                "end of input"
+            ]
+        );
+    }
+
     #[test]
     fn parse_single_number() {
         let mut tokenizer = Tokenizer::new("1").peekable();
         assert_eq!(tokenizer.peek(), Some(&Token::Number(1.)));
         assert_eq!(tokenizer.next(), Some(Token::Number(1.)));
-        assert_eq!(tokenizer.peek(), None);
-        assert_eq!(tokenizer.next(), None);
+        assert_eq!(tokenizer.peek(), Some(&Token::EOF));
+        assert_eq!(tokenizer.next(), Some(Token::EOF));
     }
 
     #[test]
@@ -108,14 +768,792 @@ mod tests {
 
         assert_eq!(tokenizer.peek(), Some(&Token::Number(1234567890.)));
         assert_eq!(tokenizer.next(), Some(Token::Number(1234567890.)));
-        assert_eq!(tokenizer.next(), None);
+        assert_eq!(tokenizer.next(), Some(Token::EOF));
     }
 
     #[test]
     fn parse_float_number() {
         let mut tokenizer = Tokenizer::new("1234567890.1234567890");
 
-        assert_eq!(tokenizer.next(), Some(Token::Number(1234567890.123456789)));
+        assert_eq!(tokenizer.next(), Some(Token::Number(1234567890.1234567)));
+        assert_eq!(tokenizer.next(), Some(Token::EOF));
+    }
+
+    #[test]
+    fn scientific_notation() {
+        for (input, expected) in [
+            ("1e5", 1e5),
+            ("2.5E-3", 2.5e-3),
+            ("6.02e+23", 6.02e23),
+            ("1.e2", 100.),
+            ("7e0", 7.),
+        ]
+        .iter()
+        {
+            let tokens: Vec<Token> = Tokenizer::new(input).take(2).collect();
+            assert_eq!(
+                tokens,
+                vec![Token::Number(*expected), Token::EOF],
+                "{}",
+                input
+            );
+        }
+
+        for input in ["1e+", "1E-", "1e2.5", "1e2.", "1.2.3"].iter() {
+            assert_eq!(
+                Tokenizer::new(input).next(),
+                Some(Token::InvalidNumber(input.to_string()))
+            );
+        }
+
+        for (input, name) in [("2e", "e"), ("2E", "E"), ("2exp(1)", "exp")].iter() {
+            let tokens: Vec<Token> = Tokenizer::new(input).take(2).collect();
+            assert_eq!(
+                tokens,
+                vec![Token::Number(2.), Token::Identifier(name.to_string())],
+                "{}",
+                input
+            );
+        }
+
+        let tokens: Vec<SpannedToken> = Tokenizer::new("2 e-1").spanned().collect();
+        assert_eq!(tokens[0].token, Token::Number(2.));
+        assert_eq!(tokens[1].token, Token::Identifier("e".into()));
+    }
+
+    #[test]
+    fn digit_separators() {
+        for (input, expected) in [
+            ("1_000_000", 1e6),
+            ("1_0", 10.),
+            ("2.718_5", 2.7185),
+            ("0.000_1e1", 0.001),
+        ]
+        .iter()
+        {
+            assert_eq!(Tokenizer::new(input).next(), Some(Token::Number(*expected)));
+        }
+
+        for input in ["100_", "1__0", "1_.5", "1._5", "1_e3", "1e1_0"].iter() {
+            assert!(
+                matches!(Tokenizer::new(input).next(), Some(Token::InvalidNumber(_))),
+                "{}",
+                input
+            );
+        }
+
+        let tokens: Vec<Token> = Tokenizer::new("_100").take(2).collect();
+        assert_eq!(tokens, vec![Token::Identifier("_100".into()), Token::EOF]);
+    }
+
+    #[test]
+    fn hexadecimal_literals() {
+        for (input, expected) in [("0xFF", 255.), ("0Xff", 255.), ("0x0", 0.), ("0x1e", 30.)].iter()
+        {
+            let tokens: Vec<Token> = Tokenizer::new(input).take(2).collect();
+            assert_eq!(
+                tokens,
+                vec![Token::Number(*expected), Token::EOF],
+                "{}",
+                input
+            );
+        }
+
+        for (input, reason) in [
+            ("0x", "no digits"),
+            ("0xG1", "G is not a hexadecimal digit"),
+            ("0x1g", "g is not a hexadecimal digit"),
+            ("0x10000000000000000", "too large"),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Tokenizer::new(input).next(),
+                Some(Token::InvalidNumber(format!("{} ({})", input, reason)))
+            );
+        }
+
+        let tokens: Vec<Token> = Tokenizer::new("0x10*2").take(3).collect();
+        assert_eq!(
+            tokens,
+            vec![Token::Number(16.), Token::Asterisk, Token::Number(2.)]
+        );
+    }
+
+    #[test]
+    fn binary_literals() {
+        for (input, expected) in [("0b1010", 10.), ("0B1", 1.), ("0b1111_0000", 240.)].iter() {
+            let tokens: Vec<Token> = Tokenizer::new(input).take(2).collect();
+            assert_eq!(
+                tokens,
+                vec![Token::Number(*expected), Token::EOF],
+                "{}",
+                input
+            );
+        }
+
+        for input in ["0b", "0b102", "0b2", "0b_1", "0b1_", "0b1__0", "0xF__F"].iter() {
+            assert!(
+                matches!(
+                    Tokenizer::new(input).next(),
+                    Some(Token::InvalidNumber(message)) if message.starts_with(&format!("{} (", input))
+                ),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            Tokenizer::new("0xdead_beef").next(),
+            Some(Token::Number(3735928559.))
+        );
+    }
+
+    #[test]
+    fn octal_literals() {
+        for (input, expected) in [("0o755", 493.), ("0O17", 15.), ("0o0", 0.)].iter() {
+            let tokens: Vec<Token> = Tokenizer::new(input).take(2).collect();
+            assert_eq!(
+                tokens,
+                vec![Token::Number(*expected), Token::EOF],
+                "{}",
+                input
+            );
+        }
+
+        assert_eq!(
+            Tokenizer::new("0o758").next(),
+            Some(Token::InvalidNumber(
+                "0o758 (8 is not an octal digit)".into()
+            ))
+        );
+        assert_eq!(
+            Tokenizer::new("0o9").next(),
+            Some(Token::InvalidNumber("0o9 (9 is not an octal digit)".into()))
+        );
+        assert_eq!(
+            Tokenizer::new("0o").next(),
+            Some(Token::InvalidNumber("0o (no digits)".into()))
+        );
+    }
+
+    #[test]
+    fn unicode_operators() {
+        let tokens: Vec<SpannedToken> = Tokenizer::new("2×3·4÷5\u{2212}1").spanned().collect();
+        let kinds: Vec<&str> = tokens.iter().map(|spanned| spanned.token.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "Number", "Asterisk", "Number", "Asterisk", "Number", "Slash", "Number", "Minus",
+                "Number", "EOF"
+            ]
+        );
+        assert_eq!(tokens[1].span, 1..3);
+        assert_eq!(tokens[7].span, 10..13);
+    }
+
+    #[test]
+    fn decimal_comma() {
+        let options = TokenizerOptions {
+            decimal_separator: DecimalSeparator::Comma,
+            ..TokenizerOptions::default()
+        };
+        let tokens = |input| -> Vec<Token> {
+            Tokenizer::new_with_options(input, options)
+                .spanned()
+                .map(|spanned| spanned.token)
+                .collect()
+        };
+
+        assert_eq!(
+            tokens("1,5+2,25"),
+            vec![
+                Token::Number(1.5),
+                Token::Plus,
+                Token::Number(2.25),
+                Token::EOF
+            ]
+        );
+        assert_eq!(
+            tokens("[1, 2]"),
+            vec![
+                Token::LeftBracket,
+                Token::Number(1.),
+                Token::Comma,
+                Token::Number(2.),
+                Token::RightBracket,
+                Token::EOF
+            ]
+        );
+        assert_eq!(tokens("1,2,3")[0], Token::InvalidNumber("1,2,3".into()));
+        assert_eq!(tokens("1,5e1")[0], Token::Number(15.));
+        assert_eq!(tokens("1.5")[1], Token::Invalid('.'));
+        assert_eq!(
+            tokens("1,x"),
+            vec![
+                Token::Number(1.),
+                Token::Comma,
+                Token::Identifier("x".into()),
+                Token::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn grouping_separators() {
+        let first = |input, decimal_separator| {
+            let options = TokenizerOptions {
+                decimal_separator,
+                grouping: true,
+                ..TokenizerOptions::default()
+            };
+            Tokenizer::new_with_options(input, options).next()
+        };
+        let point = |input| first(input, DecimalSeparator::Point);
+
+        assert_eq!(point("1,000"), Some(Token::Number(1000.)));
+        assert_eq!(point("1,234,567.89"), Some(Token::Number(1234567.89)));
+        assert_eq!(point("12,345e2"), Some(Token::Number(1234500.)));
+        assert_eq!(
+            first("1.234.567,5", DecimalSeparator::Comma),
+            Some(Token::Number(1234567.5))
+        );
+        for input in ["1,,000", "1,00", "1,0000", "1234,567", "1.000,5", "1,0_00"].iter() {
+            assert_eq!(
+                point(input),
+                Some(Token::InvalidNumber(input.to_string())),
+                "{}",
+                input
+            );
+        }
+
+        let tokens: Vec<Token> = Tokenizer::new("1,000").take(3).collect();
+        assert_eq!(
+            tokens,
+            vec![Token::Number(1.), Token::Comma, Token::Number(0.)]
+        );
+    }
+
+    #[test]
+    fn percent_or_modulo() {
+        let kinds = |input| -> Vec<&str> {
+            Tokenizer::new(input)
+                .spanned()
+                .map(|spanned| spanned.token.kind())
+                .collect()
+        };
+
+        assert_eq!(kinds("10 % 3"), vec!["Number", "Modulo", "Number", "EOF"]);
+        assert_eq!(
+            kinds("a%(b)"),
+            vec![
+                "Identifier",
+                "Modulo",
+                "LeftParenthesis",
+                "Identifier",
+                "RightParenthesis",
+                "EOF"
+            ]
+        );
+        assert_eq!(
+            kinds("5% - 1"),
+            vec!["Number", "Percent", "Minus", "Number", "EOF"]
+        );
+        assert_eq!(
+            kinds("5%%2"),
+            vec!["Number", "Percent", "Modulo", "Number", "EOF"]
+        );
+        assert_eq!(kinds("5 %"), vec!["Number", "Percent", "EOF"]);
+    }
+
+    #[test]
+    fn double_slash() {
+        let tokens: Vec<SpannedToken> = Tokenizer::new("7 // 2 / / 1").spanned().collect();
+        let kinds: Vec<&str> = tokens.iter().map(|spanned| spanned.token.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "Number",
+                "DoubleSlash",
+                "Number",
+                "Slash",
+                "Slash",
+                "Number",
+                "EOF"
+            ]
+        );
+        assert_eq!(tokens[1].span, 2..4);
+    }
+
+    #[test]
+    fn spanned_tokens() {
+        let tokens: Vec<SpannedToken> = Tokenizer::new(" 12.5 *(3)").spanned().collect();
+        let expected = vec![
+            SpannedToken {
+                token: Token::Number(12.5),
+                span: 1..5,
+            },
+            SpannedToken {
+                token: Token::Asterisk,
+                span: 6..7,
+            },
+            SpannedToken {
+                token: Token::LeftParenthesis,
+                span: 7..8,
+            },
+            SpannedToken {
+                token: Token::Number(3.),
+                span: 8..9,
+            },
+            SpannedToken {
+                token: Token::RightParenthesis,
+                span: 9..10,
+            },
+            SpannedToken {
+                token: Token::EOF,
+                span: 10..10,
+            },
+        ];
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn spans_around_whitespace() {
+        let source = "\tx_1  *\n(ab -  3.5)  %  2 ";
+        let tokens: Vec<SpannedToken> = Tokenizer::new(source).spanned().collect();
+        let texts: Vec<&str> = tokens
+            .iter()
+            .map(|spanned| &source[spanned.span.clone()])
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["x_1", "*", "(", "ab", "-", "3.5", ")", "%", "2", ""]
+        );
+        assert_eq!(tokens[5].span, 15..18);
+        assert_eq!(tokens[9].span, source.len()..source.len());
+
+        // A number ends at whitespace, so the digits after it are a second
+        // number rather than more of the first.
+        let tokens: Vec<Token> = Tokenizer::new("1 000 . 5").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(1.),
+                Token::Number(0.),
+                Token::Invalid('.'),
+                Token::Number(5.),
+                Token::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn double_asterisk() {
+        let tokens: Vec<SpannedToken> = Tokenizer::new("2**3 * *4***5").spanned().collect();
+        let kinds: Vec<&str> = tokens.iter().map(|spanned| spanned.token.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "Number",
+                "DoubleAsterisk",
+                "Number",
+                "Asterisk",
+                "Asterisk",
+                "Number",
+                "DoubleAsterisk",
+                "Asterisk",
+                "Number",
+                "EOF"
+            ]
+        );
+        assert_eq!(tokens[1].span, 1..3);
+    }
+
+    #[test]
+    fn pipes() {
+        let tokens: Vec<Token> = Tokenizer::new("||x|-1| | |").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::OrOr,
+                Token::Identifier("x".into()),
+                Token::Pipe,
+                Token::Minus,
+                Token::Number(1.),
+                Token::Pipe,
+                Token::Pipe,
+                Token::Pipe,
+                Token::EOF,
+            ]
+        );
+        assert_eq!(Token::Pipe.kind(), "Pipe");
+    }
+
+    #[test]
+    fn braces() {
+        let tokens: Vec<Token> = Tokenizer::new("{[1]}").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LeftBrace,
+                Token::LeftBracket,
+                Token::Number(1.),
+                Token::RightBracket,
+                Token::RightBrace,
+                Token::EOF,
+            ]
+        );
+        assert_eq!(Tokenizer::new("5 % {2}").nth(1), Some(Token::Modulo));
+    }
+
+    #[test]
+    fn semicolons() {
+        let tokens: Vec<Token> = Tokenizer::new("a=1;a").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("a".into()),
+                Token::Equals,
+                Token::Number(1.),
+                Token::Semicolon,
+                Token::Identifier("a".into()),
+                Token::EOF,
+            ]
+        );
+        assert_eq!(Token::Semicolon.kind(), "Semicolon");
+    }
+
+    #[test]
+    fn comparisons() {
+        let kinds = |source| -> Vec<&str> {
+            Tokenizer::new(source)
+                .map(|token| token.kind())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds("a<=b==c!=d>e>=f<g"),
+            vec![
+                "Identifier",
+                "LessEquals",
+                "Identifier",
+                "DoubleEquals",
+                "Identifier",
+                "NotEquals",
+                "Identifier",
+                "Greater",
+                "Identifier",
+                "GreaterEquals",
+                "Identifier",
+                "Less",
+                "Identifier",
+                "EOF"
+            ]
+        );
+        // A factorial is still one before an equals sign with a space.
+        assert_eq!(
+            kinds("3! = 6"),
+            vec!["Number", "Exclamation", "Equals", "Number", "EOF"]
+        );
+        assert_eq!(kinds("3!=6"), vec!["Number", "NotEquals", "Number", "EOF"]);
+        assert_eq!(kinds("x = = 1")[1..3], ["Equals", "Equals"]);
+        assert_eq!(
+            Tokenizer::new("1 <= 2").spanned().nth(1).unwrap().span,
+            2..4
+        );
+    }
+
+    #[test]
+    fn angle_not_equals() {
+        let options = TokenizerOptions {
+            angle_not_equals: true,
+            ..TokenizerOptions::default()
+        };
+        let tokens: Vec<Token> = Tokenizer::new_with_options("1<>2 <= 3 < >", options).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(1.),
+                Token::NotEquals,
+                Token::Number(2.),
+                Token::LessEquals,
+                Token::Number(3.),
+                Token::Less,
+                Token::Greater,
+                Token::EOF
+            ]
+        );
+        let tokens: Vec<Token> = Tokenizer::new("<>").collect();
+        assert_eq!(tokens, vec![Token::Less, Token::Greater, Token::EOF]);
+    }
+
+    #[test]
+    fn magnitude_suffixes() {
+        let options = TokenizerOptions {
+            magnitude_suffixes: true,
+            ..TokenizerOptions::default()
+        };
+        let tokens =
+            |input| -> Vec<Token> { Tokenizer::new_with_options(input, options).collect() };
+
+        for (input, expected) in [
+            ("4.7k", 4700.),
+            ("3.5M", 3.5e6),
+            ("2G", 2e9),
+            ("1T", 1e12),
+            ("100m", 0.1),
+            ("22u", 22e-6),
+            ("22µ", 22e-6),
+            ("22μ", 22e-6),
+            ("5n", 5e-9),
+            ("1e3k", 1e6),
+            ("2.5e-3M", 2500.),
+            ("1_000k", 1e6),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                tokens(input),
+                vec![Token::Number(*expected), Token::EOF],
+                "{}",
+                input
+            );
+        }
+        for input in ["2kk", "2km", "1e3k_", "2Mx"].iter() {
+            assert_eq!(tokens(input)[0], Token::InvalidNumber(input.to_string()));
+        }
+
+        assert_eq!(
+            tokens("2 k"),
+            vec![Token::Number(2.), Token::Identifier("k".into()), Token::EOF]
+        );
+        assert_eq!(
+            tokens("2x"),
+            vec![Token::Number(2.), Token::Identifier("x".into()), Token::EOF]
+        );
+        assert_eq!(
+            Tokenizer::new("4.7k").collect::<Vec<Token>>(),
+            vec![
+                Token::Number(4.7),
+                Token::Identifier("k".into()),
+                Token::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn infinity_and_nan() {
+        let tokens: Vec<Token> = Tokenizer::new("inf+Infinity-INF*infinite").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(f64::INFINITY),
+                Token::Plus,
+                Token::Number(f64::INFINITY),
+                Token::Minus,
+                Token::Number(f64::INFINITY),
+                Token::Asterisk,
+                Token::Identifier("infinite".into()),
+                Token::EOF
+            ]
+        );
+
+        // NaN is not equal to itself, so it is matched rather than compared.
+        for input in ["nan", "NaN", "NAN"].iter() {
+            let token = Tokenizer::new(input).next();
+            assert!(
+                matches!(token, Some(Token::Number(value)) if value.is_nan()),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(
+            Tokenizer::new("nan2").next(),
+            Some(Token::Identifier("nan2".into()))
+        );
+    }
+
+    #[test]
+    fn fullwidth_and_unusual_spaces() {
+        let source = "１２３＋ｘ＿１\u{a0}*\u{3000}（２．５）";
+        let tokens: Vec<SpannedToken> = Tokenizer::new(source).spanned().collect();
+        let kinds: Vec<&Token> = tokens.iter().map(|spanned| &spanned.token).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &Token::Number(123.),
+                &Token::Plus,
+                &Token::Identifier("x_1".into()),
+                &Token::Asterisk,
+                &Token::LeftParenthesis,
+                &Token::Number(2.5),
+                &Token::RightParenthesis,
+                &Token::EOF,
+            ]
+        );
+        let texts: Vec<&str> = tokens
+            .iter()
+            .map(|spanned| &source[spanned.span.clone()])
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["１２３", "＋", "ｘ＿１", "*", "（", "２．５", "）", ""]
+        );
+
+        let tokens: Vec<Token> = Tokenizer::new("７／／２＊＊３").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(7.),
+                Token::DoubleSlash,
+                Token::Number(2.),
+                Token::DoubleAsterisk,
+                Token::Number(3.),
+                Token::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let tokens = |input| -> Vec<Token> { Tokenizer::new(input).collect() };
+        assert_eq!(
+            tokens("2 * radius  # diameter"),
+            vec![
+                Token::Number(2.),
+                Token::Asterisk,
+                Token::Identifier("radius".into()),
+                Token::EOF
+            ]
+        );
+        assert_eq!(tokens("# only a note"), vec![Token::EOF]);
+        assert_eq!(tokens("#"), vec![Token::EOF]);
+        assert_eq!(
+            tokens("1 +# one\n# two\n 2"),
+            vec![
+                Token::Number(1.),
+                Token::Plus,
+                Token::Number(2.),
+                Token::EOF
+            ]
+        );
+        // A comment ends a token, so it splits what would be // or a number.
+        assert_eq!(tokens("7 /#\n/ 2")[1..3], [Token::Slash, Token::Slash]);
+        assert_eq!(
+            tokens("12#3\n4")[..2],
+            [Token::Number(12.), Token::Number(4.)]
+        );
+        assert_eq!(tokens("10 % # note\n 3")[1], Token::Modulo);
+        assert_eq!(tokens("10 % # 3")[1], Token::Percent);
+
+        let spanned: Vec<SpannedToken> = Tokenizer::new("# a\nx").spanned().collect();
+        assert_eq!(spanned[0].span, 4..5);
+    }
+
+    #[test]
+    fn spanned_invalid_character() {
+        let tokens: Vec<Token> = Tokenizer::new("1 § 2")
+            .spanned()
+            .map(|spanned| spanned.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(1.),
+                Token::Invalid('§'),
+                Token::Number(2.),
+                Token::EOF
+            ]
+        );
+
+        let mut tokenizer = Tokenizer::new("1 § 2");
+        assert_eq!(tokenizer.next(), Some(Token::Number(1.)));
+        assert_eq!(tokenizer.next(), Some(Token::Invalid('§')));
+        assert_eq!(tokenizer.next(), Some(Token::Number(2.)));
+        assert_eq!(tokenizer.next(), Some(Token::EOF));
         assert_eq!(tokenizer.next(), None);
     }
+
+    #[test]
+    fn history_references() {
+        let tokens: Vec<Token> = Tokenizer::new("ans*$12+$ 3").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("ans".into()),
+                Token::Asterisk,
+                Token::History(12),
+                Token::Plus,
+                Token::Invalid('$'),
+                Token::Number(3.),
+                Token::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn assignment() {
+        let tokens: Vec<Token> = Tokenizer::new("x_1 = 2")
+            .spanned()
+            .map(|spanned| spanned.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("x_1".into()),
+                Token::Equals,
+                Token::Number(2.),
+                Token::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        let tokens: Vec<Token> = Tokenizer::new("2foo+(bar_2)*Baz9é").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(2.),
+                Token::Identifier("foo".into()),
+                Token::Plus,
+                Token::LeftParenthesis,
+                Token::Identifier("bar_2".into()),
+                Token::RightParenthesis,
+                Token::Asterisk,
+                Token::Identifier("Baz9".into()),
+                Token::Invalid('é'),
+                Token::EOF,
+            ]
+        );
+
+        let tokens: Vec<Token> = Tokenizer::new("a b").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("a".into()),
+                Token::Identifier("b".into()),
+                Token::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn brackets_and_commas() {
+        let tokens: Vec<Token> = Tokenizer::new("[[1, 2]]")
+            .spanned()
+            .map(|spanned| spanned.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LeftBracket,
+                Token::LeftBracket,
+                Token::Number(1.),
+                Token::Comma,
+                Token::Number(2.),
+                Token::RightBracket,
+                Token::RightBracket,
+                Token::EOF,
+            ]
+        );
+    }
 }
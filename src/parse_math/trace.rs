@@ -0,0 +1,148 @@
+// Instrumentation hooks. With the `trace` feature these emit `tracing` spans
+// and events; without it they compile down to nothing.
+use super::ast::Node;
+use super::context::Context;
+use super::errors::{EvalError, ParseError};
+use super::value::Value;
+
+#[cfg(feature = "trace")]
+pub type Guard = tracing::span::EnteredSpan;
+
+#[cfg(not(feature = "trace"))]
+pub struct Guard;
+
+#[cfg(feature = "trace")]
+pub fn parse_span(input_length: usize) -> Guard {
+    tracing::info_span!("parse", input_length).entered()
+}
+
+#[cfg(not(feature = "trace"))]
+pub fn parse_span(_input_length: usize) -> Guard {
+    Guard
+}
+
+#[cfg(feature = "trace")]
+pub fn parse_error(error: &ParseError) {
+    tracing::debug!(code = error.code(), error = %error, "parse error");
+}
+
+#[cfg(not(feature = "trace"))]
+pub fn parse_error(_error: &ParseError) {}
+
+#[cfg(feature = "trace")]
+pub fn evaluate(node: &Node, context: &Context) -> Result<Value, EvalError> {
+    let span = tracing::info_span!(
+        "evaluate",
+        node_count = node.node_count(),
+        duration_us = tracing::field::Empty
+    );
+    let _guard = span.enter();
+    let start = std::time::Instant::now();
+    let result = node.eval_checked_with(context);
+    span.record("duration_us", start.elapsed().as_micros() as u64);
+
+    result
+}
+
+#[cfg(not(feature = "trace"))]
+pub fn evaluate(node: &Node, context: &Context) -> Result<Value, EvalError> {
+    node.eval_checked_with(context)
+}
+
+#[cfg(all(test, feature = "trace"))]
+mod tests {
+    use super::super::parser::Parser;
+    use std::fmt;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    #[derive(Debug, PartialEq)]
+    struct Recorded {
+        name: &'static str,
+        fields: Vec<(&'static str, String)>,
+    }
+
+    struct Fields<'a>(&'a mut Vec<(&'static str, String)>);
+
+    impl<'a> Visit for Fields<'a> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push((field.name(), format!("{:?}", value)));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push((field.name(), value.to_string()));
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<Recorded>>>,
+        events: Arc<Mutex<Vec<Recorded>>>,
+        next_id: Arc<AtomicU64>,
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Vec::new();
+            span.record(&mut Fields(&mut fields));
+            self.spans.lock().unwrap().push(Recorded {
+                name: span.metadata().name(),
+                fields,
+            });
+
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Vec::new();
+            event.record(&mut Fields(&mut fields));
+            self.events.lock().unwrap().push(Recorded {
+                name: event.metadata().name(),
+                fields,
+            });
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn parse_emits_span_with_input_length() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            Parser::new("1+2").evaluate().unwrap();
+        });
+
+        let spans = recorder.spans.lock().unwrap();
+        let parse = spans.iter().find(|span| span.name == "parse").unwrap();
+        assert_eq!(parse.fields, vec![("input_length", "3".to_string())]);
+        let evaluate = spans.iter().find(|span| span.name == "evaluate").unwrap();
+        assert_eq!(evaluate.fields, vec![("node_count", "3".to_string())]);
+    }
+
+    #[test]
+    fn error_event_carries_code() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            assert!(Parser::new("(1+2").parse().is_err());
+        });
+
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0]
+            .fields
+            .contains(&("code", "unexpected_end_of_input".to_string())));
+    }
+}
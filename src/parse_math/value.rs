@@ -0,0 +1,340 @@
+use super::errors::EvalError;
+use super::eval::EvalOptions;
+use super::matrix::Matrix;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    List(Vec<f64>),
+    Matrix(Matrix),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::List(_) => "list",
+            Value::Matrix(_) => "matrix",
+        }
+    }
+
+    // A list of numbers is a list, a list of equally long lists a matrix.
+    pub fn list(items: Vec<Value>) -> Result<Value, EvalError> {
+        let mut numbers = Vec::new();
+        let mut rows = Vec::new();
+
+        for item in items {
+            match item {
+                Value::Number(number) if rows.is_empty() => numbers.push(number),
+                Value::List(row) if numbers.is_empty() => rows.push(row),
+                item => {
+                    return Err(EvalError::TypeMismatch {
+                        operation: "list element",
+                        operands: vec![item.type_name()],
+                    })
+                }
+            }
+        }
+
+        if rows.is_empty() {
+            Ok(Value::List(numbers))
+        } else {
+            Matrix::from_rows(rows).map(Value::Matrix)
+        }
+    }
+
+    // The unchecked evaluation treats true as 1 and false as 0.
+    pub fn coerce(self) -> f64 {
+        match self {
+            Value::Number(number) => number,
+            Value::Bool(true) => 1.,
+            Value::Bool(false) => 0.,
+            Value::List(_) | Value::Matrix(_) => f64::NAN,
+        }
+    }
+
+    pub fn number(self, operation: &'static str) -> Result<f64, EvalError> {
+        match self {
+            Value::Number(number) => Ok(number),
+            _ => Err(EvalError::TypeMismatch {
+                operation,
+                operands: vec![self.type_name()],
+            }),
+        }
+    }
+
+    pub fn condition(self, operation: &'static str) -> Result<bool, EvalError> {
+        match self {
+            Value::Bool(condition) => Ok(condition),
+            _ => Err(EvalError::TypeMismatch {
+                operation,
+                operands: vec![self.type_name()],
+            }),
+        }
+    }
+
+    pub fn arithmetic<F>(
+        operation: &'static str,
+        left: Value,
+        right: Value,
+        apply: F,
+    ) -> Result<Value, EvalError>
+    where
+        F: FnOnce(f64, f64) -> f64,
+    {
+        let (left, right) = Value::numbers(operation, left, right)?;
+        Ok(Value::Number(apply(left, right)))
+    }
+
+    fn numbers(
+        operation: &'static str,
+        left: Value,
+        right: Value,
+    ) -> Result<(f64, f64), EvalError> {
+        match (&left, &right) {
+            (Value::Number(left), Value::Number(right)) => Ok((*left, *right)),
+            _ => Err(EvalError::TypeMismatch {
+                operation,
+                operands: vec![left.type_name(), right.type_name()],
+            }),
+        }
+    }
+
+    pub fn power(base: Value, exponent: Value, options: &EvalOptions) -> Result<Value, EvalError> {
+        let (base, exponent) = Value::numbers("power", base, exponent)?;
+        options.power(base, exponent).map(Value::Number)
+    }
+
+    pub fn sum(left: Value, right: Value) -> Result<Value, EvalError> {
+        match (left, right) {
+            (Value::Matrix(left), Value::Matrix(right)) => left.sum(&right).map(Value::Matrix),
+            (Value::List(left), Value::List(right)) => {
+                elementwise("addition", &left, &right, |a, b| a + b)
+            }
+            (left, right) => Value::arithmetic("addition", left, right, |a, b| a + b),
+        }
+    }
+
+    pub fn difference(left: Value, right: Value) -> Result<Value, EvalError> {
+        match (left, right) {
+            (Value::Matrix(left), Value::Matrix(right)) => {
+                left.difference(&right).map(Value::Matrix)
+            }
+            (Value::List(left), Value::List(right)) => {
+                elementwise("subtraction", &left, &right, |a, b| a - b)
+            }
+            (left, right) => Value::arithmetic("subtraction", left, right, |a, b| a - b),
+        }
+    }
+
+    // Matrices multiply as matrices; a number scales every element of a
+    // list or matrix on either side.
+    pub fn product(left: Value, right: Value) -> Result<Value, EvalError> {
+        match (left, right) {
+            (Value::Matrix(left), Value::Matrix(right)) => left.product(&right).map(Value::Matrix),
+            (Value::Number(factor), Value::Matrix(matrix))
+            | (Value::Matrix(matrix), Value::Number(factor)) => {
+                Ok(Value::Matrix(matrix.map(|value| factor * value)))
+            }
+            (Value::Number(factor), Value::List(list))
+            | (Value::List(list), Value::Number(factor)) => Ok(Value::List(
+                list.iter().map(|value| factor * value).collect(),
+            )),
+            (left, right) => Value::arithmetic("multiplication", left, right, |a, b| a * b),
+        }
+    }
+
+    pub fn quotient(left: Value, right: Value, options: &EvalOptions) -> Result<Value, EvalError> {
+        match (left, right) {
+            (Value::Matrix(matrix), Value::Number(divisor)) => {
+                let divisor = options.divisor(divisor)?;
+                Ok(Value::Matrix(matrix.map(|value| value / divisor)))
+            }
+            (Value::List(list), Value::Number(divisor)) => {
+                let divisor = options.divisor(divisor)?;
+                Ok(Value::List(
+                    list.iter().map(|value| value / divisor).collect(),
+                ))
+            }
+            (left, right) => Value::divided("division", left, right, options, |a, b| a / b),
+        }
+    }
+
+    // Division, floor division and remainder of two numbers, with a zero
+    // divisor handled as the options say.
+    pub fn divided<F>(
+        operation: &'static str,
+        left: Value,
+        right: Value,
+        options: &EvalOptions,
+        apply: F,
+    ) -> Result<Value, EvalError>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        let (left, right) = Value::numbers(operation, left, right)?;
+        Ok(Value::Number(apply(left, options.divisor(right)?)))
+    }
+
+    pub fn negate(self) -> Result<Value, EvalError> {
+        match self {
+            Value::List(list) => Ok(Value::List(list.iter().map(|value| -value).collect())),
+            Value::Matrix(matrix) => Ok(Value::Matrix(matrix.map(|value| -value))),
+            value => Ok(Value::Number(-value.number("negation")?)),
+        }
+    }
+}
+
+fn elementwise<F>(
+    operation: &'static str,
+    left: &[f64],
+    right: &[f64],
+    apply: F,
+) -> Result<Value, EvalError>
+where
+    F: Fn(f64, f64) -> f64,
+{
+    if left.len() != right.len() {
+        return Err(EvalError::ShapeMismatch {
+            operation,
+            left: vec![left.len()],
+            right: vec![right.len()],
+        });
+    }
+
+    Ok(Value::List(
+        left.iter().zip(right).map(|(a, b)| apply(*a, *b)).collect(),
+    ))
+}
+
+fn write_numbers(f: &mut fmt::Formatter, numbers: &[f64]) -> fmt::Result {
+    write!(f, "[")?;
+    for (index, number) in numbers.iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", Value::Number(*number))?;
+    }
+    write!(f, "]")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // -0 keeps its sign bit for arithmetic but reads as 0.
+            Value::Number(number) if *number == 0. => write!(f, "0"),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::List(list) => write_numbers(f, list),
+            // One row per line.
+            Value::Matrix(matrix) => {
+                for row in 0..matrix.rows() {
+                    if row > 0 {
+                        writeln!(f)?;
+                    }
+                    write_numbers(f, matrix.row(row))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!(Value::Bool(true).to_string(), "true");
+        assert_eq!(Value::Bool(false).to_string(), "false");
+        assert_eq!(Value::Number(2.5).to_string(), "2.5");
+        assert_eq!(Value::Number(-0.).to_string(), "0");
+    }
+
+    #[test]
+    fn arithmetic_on_bool_is_a_type_error() {
+        assert_eq!(
+            Value::arithmetic("addition", Value::Number(1.), Value::Number(2.), |a, b| a
+                + b),
+            Ok(Value::Number(3.))
+        );
+
+        let error = Value::arithmetic("addition", Value::Bool(true), Value::Number(5.), |a, b| {
+            a + b
+        })
+        .unwrap_err();
+        assert_eq!(
+            error,
+            EvalError::TypeMismatch {
+                operation: "addition",
+                operands: vec!["bool", "number"],
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Type mismatch: cannot apply addition to bool and number"
+        );
+        assert_eq!(error.code(), "type_mismatch");
+    }
+
+    #[test]
+    fn conditions_require_bool() {
+        assert_eq!(Value::Bool(false).condition("if"), Ok(false));
+        assert_eq!(
+            Value::Number(1.).condition("if").unwrap_err().to_string(),
+            "Type mismatch: cannot apply if to number"
+        );
+        assert_eq!(
+            Value::Bool(true)
+                .number("negation")
+                .unwrap_err()
+                .to_string(),
+            "Type mismatch: cannot apply negation to bool"
+        );
+    }
+
+    #[test]
+    fn scalars_broadcast_over_lists_and_matrices() {
+        let matrix =
+            Value::list(vec![Value::List(vec![1., 2.]), Value::List(vec![3., 4.])]).unwrap();
+        assert_eq!(
+            Value::product(Value::Number(2.), matrix.clone())
+                .unwrap()
+                .to_string(),
+            "[2, 4]\n[6, 8]"
+        );
+        assert_eq!(
+            Value::quotient(matrix.clone(), Value::Number(2.), &EvalOptions::default())
+                .unwrap()
+                .to_string(),
+            "[0.5, 1]\n[1.5, 2]"
+        );
+        assert_eq!(
+            Value::product(Value::List(vec![1., -2.]), Value::Number(3.)),
+            Ok(Value::List(vec![3., -6.]))
+        );
+        assert_eq!(
+            Value::sum(matrix, Value::Number(1.))
+                .unwrap_err()
+                .to_string(),
+            "Type mismatch: cannot apply addition to matrix and number"
+        );
+        assert_eq!(
+            Value::sum(Value::List(vec![1.]), Value::List(vec![1., 2.]))
+                .unwrap_err()
+                .to_string(),
+            "Shape mismatch: cannot apply addition to 1 and 2"
+        );
+    }
+
+    #[test]
+    fn coercion() {
+        assert_eq!(Value::Bool(true).coerce() + 5., 6.);
+        assert_eq!(Value::Bool(false).coerce(), 0.);
+        assert_eq!(Value::Number(-2.).coerce(), -2.);
+    }
+}
@@ -0,0 +1,88 @@
+use super::errors::EvalError;
+
+pub fn dot(a: &[f64], b: &[f64]) -> Result<f64, EvalError> {
+    if a.len() != b.len() {
+        return Err(EvalError::ShapeMismatch {
+            operation: "dot product",
+            left: vec![a.len()],
+            right: vec![b.len()],
+        });
+    }
+
+    Ok(a.iter().zip(b).map(|(a, b)| a * b).sum())
+}
+
+pub fn cross(a: &[f64], b: &[f64]) -> Result<Vec<f64>, EvalError> {
+    let (a, b) = match (a, b) {
+        ([a0, a1, a2], [b0, b1, b2]) => ([*a0, *a1, *a2], [*b0, *b1, *b2]),
+        ([_, _, _], b) | (b, _) => {
+            return Err(EvalError::WrongLength {
+                operation: "cross product",
+                expected: 3,
+                found: b.len(),
+            })
+        }
+    };
+
+    Ok(vec![
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ])
+}
+
+// hypot scales internally, so components near f64::MAX do not overflow
+// the way squaring them would.
+pub fn norm(a: &[f64]) -> f64 {
+    a.iter().fold(0., |norm, x| norm.hypot(*x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_product() {
+        assert_eq!(dot(&[1., 2., 3.], &[4., 5., 6.]), Ok(32.));
+        assert_eq!(dot(&[], &[]), Ok(0.));
+
+        let error = dot(&[1., 2., 3.], &[1., 2.]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Shape mismatch: cannot apply dot product to 3 and 2"
+        );
+    }
+
+    #[test]
+    fn cross_product() {
+        assert_eq!(cross(&[1., 0., 0.], &[0., 1., 0.]), Ok(vec![0., 0., 1.]));
+        assert_eq!(cross(&[2., 3., 4.], &[5., 6., 7.]), Ok(vec![-3., 6., -3.]));
+
+        let error = cross(&[1., 0., 0.], &[1., 0.]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Wrong length: cross product needs 3 elements, got 2"
+        );
+        assert_eq!(error.code(), "wrong_length");
+        assert_eq!(
+            cross(&[1., 0., 0., 0.], &[1., 0., 0.]),
+            Err(EvalError::WrongLength {
+                operation: "cross product",
+                expected: 3,
+                found: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn euclidean_norm() {
+        assert_eq!(norm(&[3., 4.]), 5.);
+        assert_eq!(norm(&[]), 0.);
+        assert_eq!(norm(&[-2.]), 2.);
+
+        let huge = norm(&[3e307, 4e307]);
+        assert!(huge.is_finite());
+        assert!((huge / 5e307 - 1.).abs() < 1e-15);
+        assert!((norm(&[3e-320, 4e-320]) / 5e-320 - 1.).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,171 @@
+use math_parser::parse_math::format;
+use std::ffi::OsString;
+
+pub const DEFAULT_WIDTH: usize = 60;
+pub const HEIGHT: usize = 15;
+const MIN_WIDTH: usize = 20;
+// Room for the y-axis labels, as wide as -1.234e-10, and the axis.
+const LABEL_WIDTH: usize = 12;
+
+// As many samples as fit the terminal COLUMNS says is there, or
+// DEFAULT_WIDTH when it is not set.
+pub fn width<F: Fn(&str) -> Option<OsString>>(var: F) -> usize {
+    var("COLUMNS")
+        .and_then(|columns| columns.to_str()?.trim().parse::<usize>().ok())
+        .map_or(DEFAULT_WIDTH, |columns| {
+            columns.saturating_sub(LABEL_WIDTH).max(MIN_WIDTH)
+        })
+}
+
+pub fn sample<F, E>(start: f64, end: f64, width: usize, mut function: F) -> Result<Vec<f64>, E>
+where
+    F: FnMut(f64) -> Result<f64, E>,
+{
+    let steps = width.max(2) - 1;
+    (0..width)
+        .map(|index| function(start + (end - start) * index as f64 / steps as f64))
+        .collect()
+}
+
+// Non-finite samples are left out of the scale and drawn as gaps.
+pub fn render(samples: &[f64], start: f64, end: f64, height: usize) -> String {
+    let finite = samples.iter().copied().filter(|y| y.is_finite());
+    let min = finite.clone().fold(f64::INFINITY, f64::min);
+    let max = finite.fold(f64::NEG_INFINITY, f64::max);
+    if min > max {
+        return "nothing to plot: no finite values in range\n".into();
+    }
+
+    let height = height.max(2);
+    let mut rows = vec![vec![' '; samples.len()]; height];
+    for (column, y) in samples.iter().enumerate() {
+        if !y.is_finite() {
+            continue;
+        }
+
+        let row = if max == min {
+            height / 2
+        } else {
+            ((max - y) / (max - min) * (height - 1) as f64).round() as usize
+        };
+        rows[row][column] = '*';
+    }
+
+    let top = format::format_with_precision(max, format::Precision::Significant(4));
+    let bottom = format::format_with_precision(min, format::Precision::Significant(4));
+    let margin = top.len().max(bottom.len());
+
+    // A flat line has one value, labelled where it is drawn.
+    let mut plot = String::new();
+    for (index, row) in rows.iter().enumerate() {
+        let label = match index {
+            index if max == min && index == height / 2 => &top,
+            _ if max == min => "",
+            0 => &top,
+            index if index + 1 == height => &bottom,
+            _ => "",
+        };
+        let line: String = row.iter().collect();
+        plot.push_str(&format!(
+            "{:>margin$} |{}\n",
+            label,
+            line.trim_end(),
+            margin = margin
+        ));
+    }
+
+    let left = format::format_with_precision(start, format::Precision::Significant(4));
+    let right = format::format_with_precision(end, format::Precision::Significant(4));
+    let gap = samples
+        .len()
+        .saturating_sub(left.len() + right.len())
+        .max(1);
+    plot.push_str(&format!(
+        "{:margin$}  {}{:gap$}{}\n",
+        "",
+        left,
+        "",
+        right,
+        margin = margin,
+        gap = gap
+    ));
+
+    plot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plot(start: f64, end: f64, function: fn(f64) -> f64) -> String {
+        let samples = sample::<_, ()>(start, end, 40, |x| Ok(function(x))).unwrap();
+        render(&samples, start, end, 10)
+    }
+
+    #[test]
+    fn samples_cover_the_range() {
+        let xs = sample::<_, ()>(-1., 1., 5, Ok).unwrap();
+        assert_eq!(xs, vec![-1., -0.5, 0., 0.5, 1.]);
+        assert_eq!(
+            sample(0., 1., 3, |x| if x > 0.6 { Err(x) } else { Ok(x) }),
+            Err(1.)
+        );
+    }
+
+    #[test]
+    fn parabola() {
+        let plotted = plot(-3., 3., |x| x * x - 2.);
+        let expected = [
+            "     7 |*                                      *",
+            "       | *                                    *",
+            "       |  *                                  *",
+            "       |   **                              **",
+            "       |     *                            *",
+            "       |      **                        **",
+            "       |        **                    **",
+            "       |          **                **",
+            "       |            ***          ***",
+            "-1.994 |               **********",
+            "        -3                                     3",
+        ];
+        assert_eq!(plotted.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn constant_is_a_flat_line() {
+        let plotted = plot(0., 1., |_| 2.);
+        let lines: Vec<&str> = plotted.lines().collect();
+        assert_eq!(lines[5], format!("2 |{}", "*".repeat(40)));
+        for (index, line) in lines.iter().take(10).enumerate() {
+            if index != 5 {
+                assert_eq!(*line, "  |", "{}", index);
+            }
+        }
+    }
+
+    #[test]
+    fn width_follows_the_terminal() {
+        let columns = |value: &'static str| {
+            move |name: &str| match name {
+                "COLUMNS" => Some(OsString::from(value)),
+                _ => None,
+            }
+        };
+        assert_eq!(width(|_| None), DEFAULT_WIDTH);
+        assert_eq!(width(columns("132")), 120);
+        assert_eq!(width(columns("24")), MIN_WIDTH);
+        assert_eq!(width(columns("wide")), DEFAULT_WIDTH);
+    }
+
+    #[test]
+    fn pole_leaves_a_gap() {
+        let samples = [1., 2., f64::INFINITY, f64::NAN, -2., -1.];
+        let plotted = render(&samples, -1., 1., 4);
+        let expected = [" 2 | *", "   |*", "   |     *", "-2 |    *", "    -1   1"];
+        assert_eq!(plotted.lines().collect::<Vec<_>>(), expected);
+        assert_eq!(
+            render(&[f64::NAN], 0., 1., 4),
+            "nothing to plot: no finite values in range\n"
+        );
+    }
+}
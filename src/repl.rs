@@ -0,0 +1,1129 @@
+use crate::command::{Command, HELP};
+use crate::diagnostic;
+use crate::output::{self, OutputFormat};
+use crate::plot;
+use crate::transcript::{self, Entry, Recorder};
+use math_parser::parse_math::ast::Statement;
+use math_parser::parse_math::context::{AngleMode, Context};
+use math_parser::parse_math::equation::{Equation, Solution};
+use math_parser::parse_math::errors::{EvalError, ParseError};
+use math_parser::parse_math::eval::{DivisionByZero, EvalOptions, ZeroPower};
+use math_parser::parse_math::format::{self, Notation, Precision};
+use math_parser::parse_math::parser::{Parser, ParserOptions};
+use math_parser::parse_math::trace;
+use math_parser::parse_math::value::Value;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+pub const DEFAULT_PROMPT: &str = "math> ";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Interactive,
+    Pipe,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Continue,
+    Exit,
+}
+
+pub struct Session<W: Write, E: Write> {
+    output: Recorder<W>,
+    errors: Recorder<E>,
+    mode: Mode,
+    line_number: usize,
+    show_ast: bool,
+    format: OutputFormat,
+    precision: Option<Precision>,
+    notation: Notation,
+    radix: u32,
+    echo: bool,
+    pending: String,
+    color: bool,
+    context: Context,
+    parser_options: ParserOptions,
+    entries: Vec<Entry>,
+}
+
+impl<W: Write, E: Write> Session<W, E> {
+    pub fn new(output: W, errors: E, mode: Mode) -> Self {
+        Session {
+            output: Recorder::new(output),
+            errors: Recorder::new(errors),
+            mode,
+            line_number: 0,
+            show_ast: false,
+            format: OutputFormat::Text,
+            precision: None,
+            notation: Notation::Auto,
+            radix: 10,
+            echo: mode == Mode::Interactive,
+            pending: String::new(),
+            color: false,
+            context: Context::new(),
+            parser_options: ParserOptions::default(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    #[cfg_attr(not(feature = "repl"), allow(dead_code))]
+    pub fn prompt(&self, prompt: &str) -> String {
+        if self.context.angle_mode() != AngleMode::Degrees {
+            return prompt.to_string();
+        }
+
+        match prompt.rfind('>') {
+            Some(index) => format!("{}[deg]{}", &prompt[..index], &prompt[index..]),
+            None => format!("[deg] {}", prompt),
+        }
+    }
+
+    #[cfg_attr(not(feature = "repl"), allow(dead_code))]
+    pub fn completion_names(&self) -> Vec<String> {
+        let mut names = self.context.names();
+        let functions = self.context.functions();
+        names.extend(functions.iter().map(|(name, _)| format!("{}(", name)));
+
+        names
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn cancel_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn set_precision(&mut self, precision: Option<Precision>) {
+        self.precision = precision;
+    }
+
+    // The variables and evaluation options the session starts with.
+    pub fn set_context(&mut self, context: Context) {
+        self.context = context;
+    }
+
+    pub fn set_parser_options(&mut self, parser_options: ParserOptions) {
+        self.parser_options = parser_options;
+    }
+
+    pub fn set_notation(&mut self, notation: Notation) {
+        self.notation = notation;
+    }
+
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    pub fn set_format(&mut self, format: OutputFormat) {
+        self.format = format;
+    }
+
+    pub fn set_show_ast(&mut self, show_ast: bool) {
+        self.show_ast = show_ast;
+    }
+
+    pub fn transcript(&self) -> String {
+        transcript::render(self.mode, &self.entries)
+    }
+
+    pub fn last_entry(&self) -> Option<&Entry> {
+        self.entries.last()
+    }
+
+    pub fn handle(&mut self, line: &str) -> io::Result<Action> {
+        let action = self.dispatch(line);
+        self.entries.push(Entry {
+            input: line.trim_end_matches(['\r', '\n']).to_string(),
+            output: self.output.take(),
+            errors: self.errors.take(),
+        });
+        action
+    }
+
+    fn dispatch(&mut self, line: &str) -> io::Result<Action> {
+        self.line_number += 1;
+
+        let mut line = line.trim();
+        let buffered;
+        if self.is_pending() {
+            if line.is_empty() {
+                self.cancel_pending();
+                return Ok(Action::Continue);
+            }
+
+            buffered = format!("{} {}", self.pending, line);
+            self.cancel_pending();
+            line = &buffered;
+        } else if let Some(command) = Command::parse(line) {
+            return self.execute(command);
+        }
+
+        if self.mode == Mode::Interactive && Parser::is_incomplete(line) {
+            self.pending = line.to_string();
+            return Ok(Action::Continue);
+        }
+
+        if self.echo && self.format == OutputFormat::Text {
+            writeln!(self.output, "Your input: {}", line)?;
+        } else if line.is_empty() {
+            return Ok(Action::Continue);
+        }
+
+        // Statements separated by semicolons run in turn, each seeing what
+        // the ones before it assigned; the first error ends the line.
+        let mut parser = Parser::new_with_options(line, self.parser_options);
+        // A line with no statement at all is reported as one cut short.
+        let mut next = match parser.next_statement(&self.context) {
+            None => Some(parser.parse_statement()),
+            first => first,
+        };
+        while let Some(statement) = next {
+            let last = parser.at_end();
+            let completed = match statement {
+                Ok(statement) => self.run_statement(line, statement, last)?,
+                Err(error) => {
+                    self.print_error(line, &error)?;
+                    false
+                }
+            };
+            if !completed {
+                break;
+            }
+            next = parser.next_statement(&self.context);
+        }
+
+        Ok(Action::Continue)
+    }
+
+    // Only the last statement of a line prints its result and adds it to
+    // the history; the ones before it just make their assignments. A
+    // definition prints nothing.
+    fn run_statement(&mut self, line: &str, statement: Statement, last: bool) -> io::Result<bool> {
+        let (name, node) = match statement {
+            Statement::Expression(node) => (None, node),
+            Statement::Assignment(name, node) => (Some(name), node),
+            Statement::Equation(equation) => {
+                self.solve(line, &equation)?;
+                return Ok(true);
+            }
+            Statement::Definition(name, function) => {
+                self.context.define(&name, function);
+                return Ok(true);
+            }
+        };
+        if self.show_ast && self.format == OutputFormat::Text {
+            write!(self.output, "{}", node.to_tree_string())?;
+        }
+        let result = match trace::evaluate(&node, &self.context) {
+            Ok(Value::Number(result)) => result,
+            Ok(value) if name.is_some() => {
+                let error = EvalError::TypeMismatch {
+                    operation: "assignment",
+                    operands: vec![value.type_name()],
+                };
+                self.print_eval_error(line, &error)?;
+                return Ok(false);
+            }
+            Ok(value) => {
+                if last {
+                    self.print_value(line, &value)?;
+                }
+                return Ok(true);
+            }
+            Err(error) => {
+                self.print_eval_error(line, &error)?;
+                return Ok(false);
+            }
+        };
+        if let Some(name) = name {
+            self.context.set(&name, result);
+        }
+        if last {
+            self.context.push_result(result);
+            self.print_result(line, result)?;
+        }
+
+        Ok(true)
+    }
+
+    fn print_result(&mut self, input: &str, result: f64) -> io::Result<()> {
+        let precision = self
+            .precision
+            .unwrap_or_else(|| self.format.default_precision());
+        let decimal = || format::format_with_notation(result, precision, self.notation);
+        let (formatted, integral) = match self.radix {
+            10 => (decimal(), true),
+            radix => match format::format_radix(result, radix) {
+                Some(formatted) => (formatted, true),
+                None => (decimal(), false),
+            },
+        };
+
+        if self.format == OutputFormat::Json {
+            return writeln!(
+                self.output,
+                "{}",
+                output::json_result(input, result, precision, self.notation)
+            );
+        }
+
+        let prefix = if self.echo { "Result: " } else { "" };
+        match (integral, self.mode) {
+            (true, _) => writeln!(self.output, "{}{}", prefix, formatted),
+            (false, Mode::Interactive) => writeln!(
+                self.output,
+                "{}{} (not an integer, shown in decimal)",
+                prefix, formatted
+            ),
+            (false, Mode::Pipe) => {
+                writeln!(
+                    self.errors,
+                    "{}: note: not an integer, shown in decimal",
+                    self.line_number
+                )?;
+                writeln!(self.output, "{}{}", prefix, formatted)
+            }
+        }
+    }
+
+    // Lists and matrices are not numbered results and cannot be recalled.
+    fn print_value(&mut self, input: &str, value: &Value) -> io::Result<()> {
+        let precision = self
+            .precision
+            .unwrap_or_else(|| self.format.default_precision());
+        if self.format == OutputFormat::Json {
+            return writeln!(
+                self.output,
+                "{}",
+                output::json_value(input, value, precision, self.notation)
+            );
+        }
+
+        let formatted = output::format_value(value, precision, self.notation);
+        if self.echo {
+            writeln!(self.output, "Result:\n{}", formatted)
+        } else {
+            writeln!(self.output, "{}", formatted)
+        }
+    }
+
+    // A unique solution becomes the next numbered result, so it can be
+    // recalled with ans.
+    fn solve(&mut self, input: &str, equation: &Equation) -> io::Result<()> {
+        let solved = equation
+            .unknown()
+            .and_then(|name| Ok((equation.solve_linear(&name)?, name)));
+        let (solution, name) = match solved {
+            Ok(solved) => solved,
+            Err(error) => {
+                return self.report(input, "Solve error", error.code(), &error.to_string(), None)
+            }
+        };
+        if let Solution::Unique(value) = solution {
+            self.context.push_result(value);
+        }
+
+        let precision = self
+            .precision
+            .unwrap_or_else(|| self.format.default_precision());
+        if self.format == OutputFormat::Json {
+            return writeln!(
+                self.output,
+                "{}",
+                output::json_solution(input, &name, solution, precision, self.notation)
+            );
+        }
+
+        let prefix = if self.echo { "Result: " } else { "" };
+        match solution {
+            Solution::Unique(value) => {
+                let value = format::format_with_notation(value, precision, self.notation);
+                writeln!(self.output, "{}{} = {}", prefix, name, value)
+            }
+            Solution::AllValues => writeln!(self.output, "{}every {} is a solution", prefix, name),
+            Solution::NoSolution => writeln!(self.output, "{}no {} is a solution", prefix, name),
+        }
+    }
+
+    // The expression is parsed once, with the variable left free, and
+    // evaluated at each sample.
+    fn plot(
+        &self,
+        expression: &str,
+        variable: &str,
+        start: f64,
+        end: f64,
+    ) -> Result<String, ParseError> {
+        let node = Parser::with_context_and_options(expression, &self.context, self.parser_options)
+            .bind(variable)
+            .parse()?;
+        let mut context = self.context.clone();
+        let width = plot::width(|name| env::var_os(name));
+        let samples = plot::sample(start, end, width, |x| {
+            context.set(variable, x);
+            node.eval_with(&context)
+        })?;
+
+        Ok(plot::render(&samples, start, end, plot::HEIGHT))
+    }
+
+    fn print_variables(&mut self) -> io::Result<()> {
+        let precision = self
+            .precision
+            .unwrap_or_else(|| self.format.default_precision());
+        let variables = self.context.variables();
+        let functions = self.context.functions();
+        if variables.is_empty() && functions.is_empty() {
+            return writeln!(self.output, "no variables");
+        }
+
+        for (name, value) in variables {
+            let value = format::format_with_notation(value, precision, self.notation);
+            writeln!(self.output, "{} = {}", name, value)?;
+        }
+        for (name, function) in functions {
+            let parameters = function.parameters.join(", ");
+            writeln!(self.output, "{}({}) = {}", name, parameters, function.body)?;
+        }
+
+        Ok(())
+    }
+
+    fn print_error(&mut self, input: &str, error: &ParseError) -> io::Result<()> {
+        let span = error.span(input);
+        self.report(input, "Parse error", error.code(), &error.to_string(), span)
+    }
+
+    fn print_eval_error(&mut self, input: &str, error: &EvalError) -> io::Result<()> {
+        self.report(
+            input,
+            "Evaluation error",
+            error.code(),
+            &error.to_string(),
+            None,
+        )
+    }
+
+    fn report(
+        &mut self,
+        input: &str,
+        kind: &str,
+        code: &str,
+        error: &str,
+        span: Option<Range<usize>>,
+    ) -> io::Result<()> {
+        match (self.format, self.mode) {
+            (OutputFormat::Json, _) => {
+                writeln!(
+                    self.output,
+                    "{}",
+                    output::json_error(input, code, error, span)
+                )
+            }
+            (OutputFormat::Text, Mode::Interactive) => {
+                let message = format!("{}: {}", kind, error);
+                let rendered = diagnostic::render(&message, input, span, self.color);
+                write!(self.output, "{}", rendered)
+            }
+            (OutputFormat::Text, Mode::Pipe) => {
+                writeln!(self.errors, "{}: error: {}", self.line_number, error)
+            }
+        }
+    }
+
+    fn execute(&mut self, command: Command) -> io::Result<Action> {
+        match command {
+            Command::Exit => return Ok(Action::Exit),
+            Command::Help => write!(self.output, "{}", HELP)?,
+            Command::Vars => self.print_variables()?,
+            Command::Clear {
+                only_variables: true,
+            } => self.context.clear_variables(),
+            Command::Clear {
+                only_variables: false,
+            } => self.context.clear(),
+            Command::Ast(Some(show_ast)) => self.show_ast = show_ast,
+            Command::Ast(None) => writeln!(
+                self.output,
+                "ast {}",
+                if self.show_ast { "on" } else { "off" }
+            )?,
+            Command::Tokens(expression) => {
+                write!(self.output, "{}", output::format_tokens(expression))?
+            }
+            Command::Mode(Some(angle_mode)) => self.context.set_angle_mode(angle_mode),
+            Command::Mode(None) => writeln!(
+                self.output,
+                "mode {}",
+                match self.context.angle_mode() {
+                    AngleMode::Degrees => "deg",
+                    AngleMode::Radians => "rad",
+                }
+            )?,
+            Command::ZeroPower(Some(zero_power)) => {
+                let options = self.context.options();
+                self.context.set_options(EvalOptions {
+                    zero_power,
+                    ..options
+                });
+            }
+            Command::ZeroPower(None) => writeln!(
+                self.output,
+                "zeropower {}",
+                match self.context.options().zero_power {
+                    ZeroPower::One => "one",
+                    ZeroPower::Error => "error",
+                    ZeroPower::NaN => "nan",
+                }
+            )?,
+            Command::DivZero(Some(division_by_zero)) => {
+                let options = self.context.options();
+                self.context.set_options(EvalOptions {
+                    division_by_zero,
+                    ..options
+                });
+            }
+            Command::DivZero(None) => writeln!(
+                self.output,
+                "divzero {}",
+                match self.context.options().division_by_zero {
+                    DivisionByZero::Infinity => "inf",
+                    DivisionByZero::Error => "error",
+                    DivisionByZero::NaN => "nan",
+                }
+            )?,
+            Command::Roots(Some(real_roots)) => {
+                let options = self.context.options();
+                self.context.set_options(EvalOptions {
+                    real_roots,
+                    ..options
+                });
+            }
+            Command::Roots(None) => writeln!(
+                self.output,
+                "roots {}",
+                if self.context.options().real_roots {
+                    "real"
+                } else {
+                    "principal"
+                }
+            )?,
+            Command::Format(Some(notation)) => self.notation = notation,
+            Command::Format(None) => writeln!(
+                self.output,
+                "format {}",
+                output::notation_name(self.notation)
+            )?,
+            Command::Output(Some(radix)) => self.radix = radix,
+            Command::Output(None) => {
+                writeln!(self.output, "output {}", output::radix_name(self.radix))?
+            }
+            Command::Echo(echo) => self.echo = echo,
+            Command::Plot {
+                expression,
+                variable,
+                start,
+                end,
+            } => match self.plot(expression, variable, start, end) {
+                Ok(plot) => write!(self.output, "{}", plot)?,
+                Err(error) => writeln!(self.errors, "cannot plot: {}", error)?,
+            },
+            Command::Save(path) => {
+                if let Err(error) = fs::write(path, self.transcript()) {
+                    writeln!(self.errors, "cannot save '{}': {}", path, error)?
+                }
+            }
+            Command::Invalid(usage) => writeln!(self.errors, "{}", usage)?,
+            Command::Unknown(name) => writeln!(
+                self.errors,
+                "unknown command ':{}', type :help for a list of commands",
+                name
+            )?,
+        }
+
+        Ok(Action::Continue)
+    }
+}
+
+pub fn run<R: BufRead, W: Write, E: Write>(
+    mut input: R,
+    session: &mut Session<W, E>,
+) -> io::Result<()> {
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        if session.handle(&line)? == Action::Exit {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(input: &str, mode: Mode) -> (String, String) {
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run(
+            input.as_bytes(),
+            &mut Session::new(&mut output, &mut errors, mode),
+        )
+        .unwrap();
+
+        (
+            String::from_utf8(output).unwrap(),
+            String::from_utf8(errors).unwrap(),
+        )
+    }
+
+    fn interactive(input: &str) -> String {
+        session(input, Mode::Interactive).0
+    }
+
+    #[test]
+    fn stops_at_eof() {
+        assert_eq!(interactive(""), "");
+        assert_eq!(interactive("1+2\n"), "Your input: 1+2\nResult: 3\n");
+    }
+
+    #[test]
+    fn evaluates_last_line_without_newline() {
+        assert_eq!(
+            interactive("1+2\n2*3"),
+            "Your input: 1+2\nResult: 3\nYour input: 2*3\nResult: 6\n"
+        );
+    }
+
+    #[test]
+    fn exit_commands() {
+        for command in ["exit", "quit", ":q"].iter() {
+            let input = format!("2^3\n{}\n1+1\n", command);
+            assert_eq!(interactive(&input), "Your input: 2^3\nResult: 8\n");
+        }
+    }
+
+    #[test]
+    fn handle_reports_exit() {
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Interactive);
+        assert_eq!(session.handle("1").unwrap(), Action::Continue);
+        assert_eq!(session.handle("  quit ").unwrap(), Action::Exit);
+    }
+
+    #[test]
+    fn ast_toggle() {
+        assert_eq!(
+            interactive(":ast on\n-2\n:ast off\n-2\n"),
+            "Your input: -2\nNegative\n└ Element(2)\nResult: -2\nYour input: -2\nResult: -2\n"
+        );
+        assert_eq!(interactive(":ast\n"), "ast off\n");
+    }
+
+    #[test]
+    fn mode_command() {
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Interactive);
+        assert_eq!(session.prompt(DEFAULT_PROMPT), "math> ");
+
+        session.handle(":mode deg").unwrap();
+        assert_eq!(session.context.angle_mode(), AngleMode::Degrees);
+        assert_eq!(session.prompt(DEFAULT_PROMPT), "math[deg]> ");
+        assert_eq!(session.prompt("calc"), "[deg] calc");
+        session.handle(":mode").unwrap();
+
+        session.handle(":mode rad").unwrap();
+        assert_eq!(session.context.angle_mode(), AngleMode::Radians);
+        session.handle(":mode").unwrap();
+        session.handle(":mode turns").unwrap();
+
+        assert_eq!(
+            String::from_utf8(session.output.into_inner()).unwrap(),
+            "mode deg\nmode rad\n"
+        );
+        assert_eq!(
+            String::from_utf8(session.errors.into_inner()).unwrap(),
+            "usage: :mode deg|rad\n"
+        );
+    }
+
+    #[test]
+    fn power_commands() {
+        let (output, errors) = session(
+            ":zeropower\n0^0\n:zeropower nan\n0^0\n:zeropower error\n0^0\n:roots\n(-8)^(1/3)\n:roots real\n(-8)^(1/3)\n:roots\n",
+            Mode::Pipe,
+        );
+        assert_eq!(
+            output,
+            "zeropower one\n1\nNaN\nroots principal\nNaN\n-2\nroots real\n"
+        );
+        assert!(errors.starts_with("6: error: "), "{}", errors);
+    }
+
+    #[test]
+    fn division_command() {
+        let (output, errors) = session(
+            ":divzero\n1/(-0)\n:divzero nan\n1/(-0)\n:divzero error\n7 % 0\n[1, 2] / 0\n:divzero\n",
+            Mode::Pipe,
+        );
+        assert_eq!(output, "divzero inf\n-inf\nNaN\ndivzero error\n");
+        assert_eq!(
+            errors,
+            "6: error: Division by zero\n7: error: Division by zero\n"
+        );
+    }
+
+    #[test]
+    fn trigonometry_follows_the_mode() {
+        assert_eq!(
+            session(":mode deg\nsin(90)\n:mode rad\ncos(0)\n", Mode::Pipe).0,
+            "1\n1\n"
+        );
+        assert_eq!(
+            session(
+                ":mode deg\natan(1)\nacos(0)\n:mode rad\nasin(0)\n",
+                Mode::Pipe
+            )
+            .0,
+            "45\n90\n0\n"
+        );
+    }
+
+    #[test]
+    fn format_command() {
+        let (output, errors) = session(
+            ":format sci\n6.022*10^23\n:format eng\n12500\n:format\n:format auto\n1000\n:format x\n",
+            Mode::Pipe,
+        );
+        assert_eq!(output, "6.022e23\n12.5e3\nformat eng\n1000\n");
+        assert_eq!(errors, "usage: :format auto|sci|eng|human|human-bin\n");
+
+        let (output, _) = session(
+            ":format human\n0.00042\n:format human-bin\n2^30\n:format\n",
+            Mode::Pipe,
+        );
+        assert_eq!(output, "420µ\n1Gi\nformat human-bin\n");
+    }
+
+    #[test]
+    fn output_command() {
+        let (output, _) = session(
+            ":output hex\n255\n:output bin\n255\n:output oct\n255\n:output dec\n255\n",
+            Mode::Pipe,
+        );
+        assert_eq!(output, "0xff\n0b11111111\n0o377\n255\n");
+
+        let (output, _) = session(":output hex\n-255\n:output\n", Mode::Pipe);
+        assert_eq!(output, "-0xff\noutput hex\n");
+    }
+
+    #[test]
+    fn output_falls_back_to_decimal() {
+        assert_eq!(
+            interactive(":output hex\n1/4\n"),
+            "Your input: 1/4\nResult: 0.25 (not an integer, shown in decimal)\n"
+        );
+
+        let (output, errors) = session(":output bin\n1/4\n", Mode::Pipe);
+        assert_eq!(output, "0.25\n");
+        assert_eq!(errors, "2: note: not an integer, shown in decimal\n");
+    }
+
+    #[test]
+    fn quiet_interactive() {
+        let mut output = Vec::new();
+        let mut session = Session::new(&mut output, Vec::new(), Mode::Interactive);
+        session.set_echo(false);
+        run("1+2\n\n1+)\n".as_bytes(), &mut session).unwrap();
+        assert_eq!(
+            output,
+            b"3\nParse error: expected a number, '-', or '(' but found ')' at position 2\n  1+)\n    ^\n"
+        );
+    }
+
+    #[test]
+    fn echo_command() {
+        assert_eq!(
+            interactive(":echo off\n2*3\n:echo on\n2*3\n"),
+            "6\nYour input: 2*3\nResult: 6\n"
+        );
+        let (output, errors) = session(":echo on\n1\n:echo maybe\n", Mode::Pipe);
+        assert_eq!(output, "Your input: 1\nResult: 1\n");
+        assert_eq!(errors, "usage: :echo on|off\n");
+    }
+
+    #[test]
+    fn plot_command() {
+        let (output, errors) = session(":plot x^2, x, -1..1\n", Mode::Pipe);
+        assert_eq!(errors, "");
+        assert_eq!(output.lines().count(), plot::HEIGHT + 1);
+        assert!(output.trim_start().starts_with("1 |*"));
+
+        let (output, errors) = session(":plot x*y, x, 0..1\n", Mode::Pipe);
+        assert_eq!(output, "");
+        assert_eq!(errors, "cannot plot: Unknown identifier: y\n");
+
+        // A variable of the session with the same name does not fix it.
+        let (output, errors) = session("x = 5\nk = 2\n:plot x*k, x, -1..1\n", Mode::Pipe);
+        assert_eq!(errors, "");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[..2], ["5", "2"]);
+        assert!(lines[2].starts_with(" 2 |"), "{}", output);
+        assert!(lines[plot::HEIGHT + 1].starts_with("-2 |"), "{}", output);
+
+        let (output, _) = session(":plot 3, x, 0..1\n", Mode::Pipe);
+        let labelled: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with('3'))
+            .collect();
+        assert_eq!(labelled.len(), 1, "{}", output);
+    }
+
+    #[test]
+    fn tokens_command() {
+        assert_eq!(interactive(":tokens 1+2\n"), output::format_tokens("1+2"));
+    }
+
+    #[test]
+    fn result_history() {
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Pipe);
+        for line in ["1+2", "2*5", "7"].iter() {
+            session.handle(line).unwrap();
+        }
+        session.handle("$2 + ans").unwrap();
+        assert_eq!(session.context.ans(), Some(17.));
+        assert_eq!(session.context.history(4), Some(17.));
+
+        session.handle("1+").unwrap();
+        session.handle("$9").unwrap();
+        assert_eq!(session.context.ans(), Some(17.));
+        assert_eq!(session.context.history(5), None);
+
+        session.handle("ans * 2").unwrap();
+        assert_eq!(session.context.history(5), Some(34.));
+        assert_eq!(
+            String::from_utf8(session.output.into_inner()).unwrap(),
+            "3\n10\n7\n17\n34\n"
+        );
+        assert_eq!(
+            String::from_utf8(session.errors.into_inner()).unwrap(),
+            "5: error: Unexpected end of input at position 2, expected a number or '('\n6: error: Unknown reference: $9\n"
+        );
+    }
+
+    #[test]
+    fn variables_persist() {
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Pipe);
+        for line in [
+            "x = 5",
+            "x * 2",
+            "x = x + 1",
+            "x(2)",
+            "x = y + 1",
+            "x = 2 *)",
+            "x",
+        ]
+        .iter()
+        {
+            session.handle(line).unwrap();
+        }
+        assert_eq!(session.context.get("x"), Some(6.));
+        assert_eq!(
+            String::from_utf8(session.output.into_inner()).unwrap(),
+            "5\n10\n6\n12\n6\n"
+        );
+        assert_eq!(
+            String::from_utf8(session.errors.into_inner()).unwrap(),
+            "5: error: Unknown identifier: y\n6: error: expected a number, '-', or '(' but found ')' at position 7\n"
+        );
+    }
+
+    #[test]
+    fn statement_sequences() {
+        let (output, errors) = session(
+            "a = 3; b = 4; sqrt(a^2 + b^2)\na = a + 1; a * b\nc = 1; d = g; f = 2\nc + a; f\n$1 + $2\n",
+            Mode::Pipe,
+        );
+        assert_eq!(output, "5\n16\n21\n");
+        assert_eq!(
+            errors,
+            "3: error: Unknown identifier: g\n4: error: Unknown identifier: f\n"
+        );
+    }
+
+    #[test]
+    fn matrices() {
+        let (output, errors) = session(
+            "[[1, 2], [3, 4]] * [[5], [6]]\n2 * [1, 2]\n[[1, 2]] + [[1], [2]]\nm = [1, 2]\n1\n",
+            Mode::Pipe,
+        );
+        assert_eq!(output, "[17]\n[39]\n[2, 4]\n1\n");
+        assert_eq!(
+            errors,
+            "3: error: Shape mismatch: cannot apply addition to 1x2 and 2x1\n\
+             4: error: Type mismatch: cannot apply assignment to list\n"
+        );
+
+        let (output, errors) = session(
+            "d = det([[2, 1], [1, 3]])\ntranspose([[1, 2]])\nidentity(2) * [[3], [4]]\nidentity(-1)\n",
+            Mode::Pipe,
+        );
+        assert_eq!(output, "5\n[1]\n[2]\n[3]\n[4]\n");
+        assert_eq!(
+            errors,
+            "4: error: Domain error: identity is not defined for -1\n"
+        );
+
+        let (output, errors) = session(
+            "dot([1, 2], [3, 4])\ncross([0, 1, 0], [0, 0, 1])\nnorm([6, 8])\ndot([1], [1, 2])\ncross([1, 2], [3, 4])\n",
+            Mode::Pipe,
+        );
+        assert_eq!(output, "11\n[1, 0, 0]\n10\n");
+        assert_eq!(
+            errors,
+            "4: error: Shape mismatch: cannot apply dot product to 1 and 2\n\
+             5: error: Wrong length: cross product needs 3 elements, got 2\n"
+        );
+
+        let (output, _) = session("[[1, 0.5], [0, 1]] / 2\n", Mode::Interactive);
+        assert_eq!(
+            output,
+            "Your input: [[1, 0.5], [0, 1]] / 2\nResult:\n[0.5, 0.25]\n[0, 0.5]\n"
+        );
+    }
+
+    #[test]
+    fn equations() {
+        let (output, errors) = session(
+            "a = 2\na*x + 3 = 7\nans * 10\nx + 1 = x\n2*(y - 1) = 2*y - 2\nx^2 = 4\nx + y = 1\n\
+             2x + 3 = 11\n5z - 2 = 3z + 8\n",
+            Mode::Pipe,
+        );
+        assert_eq!(
+            output,
+            "2\nx = 2\n20\nno x is a solution\nevery y is a solution\nx = 4\nz = 5\n"
+        );
+        assert_eq!(
+            errors,
+            "6: error: Cannot solve: the equation has degree 2 in x, only linear equations can be solved\n\
+             7: error: Cannot solve for several unknowns at once: x, y\n"
+        );
+    }
+
+    #[test]
+    fn vars_and_clear() {
+        assert_eq!(
+            interactive(":vars\nb = 2\na = 1/4\n:vars\n:clear vars\n:vars\n"),
+            "no variables\nYour input: b = 2\nResult: 2\nYour input: a = 1/4\nResult: 0.25\n\
+             a = 0.25\nb = 2\nno variables\n"
+        );
+
+        let (output, errors) = session("x = 3\n:clear\nx\nans\n:mode\n", Mode::Pipe);
+        assert_eq!(output, "3\nmode rad\n");
+        assert_eq!(
+            errors,
+            "3: error: Unknown identifier: x\n4: error: Unknown identifier: ans\n"
+        );
+    }
+
+    #[test]
+    fn user_functions() {
+        let (output, errors) = session(
+            "f(x) = x^2 + 1\nf(3)\nhyp(a, b) = sqrt(a^2 + b^2)\nhyp(3, 4) + f(0)\nhyp(1)\n\
+             f(x) = f(x) + 1\n:vars\n:clear vars\nf(1)\n",
+            Mode::Pipe,
+        );
+        assert_eq!(
+            output,
+            "10\n6\nf(x) = x ^ 2 + 1\nhyp(a, b) = sqrt(a ^ 2 + b ^ 2)\n"
+        );
+        assert_eq!(
+            errors,
+            "5: error: Wrong number of arguments: hyp takes 2, got 1\n\
+             6: error: Function calls itself: f\n\
+             9: error: Unknown function: f\n"
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        let (output, errors) = session(
+            "1 + 2 < 4 * 1\nx = 7\nx >= 7\nif(x != 7, 1, 2)\n1 < x < 9\n",
+            Mode::Pipe,
+        );
+        assert_eq!(output, "true\n7\ntrue\n2\n");
+        assert_eq!(
+            errors,
+            "5: error: Chained comparison at position 6; compare one pair at a time\n"
+        );
+    }
+
+    #[test]
+    fn booleans_count_as_numbers_with_bool_arithmetic() {
+        let input = "(1 < 2) + 5\nscore = 95\n(score >= 90) * 10\nif(1, 2, 3)\n!0 && 2\n";
+        let (output, errors) = session(input, Mode::Pipe);
+        assert_eq!(output, "95\n");
+        assert_eq!(
+            errors,
+            "1: error: Type mismatch: cannot apply addition to bool and number\n\
+             3: error: Type mismatch: cannot apply multiplication to bool and number\n\
+             4: error: Type mismatch: cannot apply if to number\n\
+             5: error: Type mismatch: cannot apply ! to number\n"
+        );
+
+        let mut output = Vec::new();
+        let mut session = Session::new(&mut output, Vec::new(), Mode::Pipe);
+        let mut context = Context::new();
+        context.set_options(EvalOptions {
+            bool_arithmetic: true,
+            ..EvalOptions::default()
+        });
+        session.set_context(context);
+        run(input.as_bytes(), &mut session).unwrap();
+        drop(session);
+        assert_eq!(String::from_utf8(output).unwrap(), "6\n95\n10\n2\ntrue\n");
+    }
+
+    #[test]
+    fn continuation_lines() {
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Interactive);
+        session.handle("(1 +").unwrap();
+        assert!(session.is_pending());
+        session.handle("2) *").unwrap();
+        assert!(session.is_pending());
+        session.handle("3").unwrap();
+        assert!(!session.is_pending());
+        assert_eq!(
+            String::from_utf8(session.output.into_inner()).unwrap(),
+            "Your input: (1 + 2) * 3\nResult: 9\n"
+        );
+    }
+
+    #[test]
+    fn blank_line_cancels_continuation() {
+        assert_eq!(interactive("(1 +\n\n4\n"), "Your input: 4\nResult: 4\n");
+
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Interactive);
+        session.handle("2 *").unwrap();
+        session.cancel_pending();
+        assert!(!session.is_pending());
+    }
+
+    #[test]
+    fn pipe_mode_does_not_continue() {
+        let (output, errors) = session("(1 +\n2\n", Mode::Pipe);
+        assert_eq!(output, "2\n");
+        assert_eq!(
+            errors,
+            "1: error: Unexpected end of input at position 4, expected a number or '('\n"
+        );
+    }
+
+    #[test]
+    fn colored_errors() {
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Interactive);
+        session.set_color(true);
+        session.handle("1+)").unwrap();
+        assert_eq!(
+            String::from_utf8(session.output.into_inner()).unwrap(),
+            "Your input: 1+)\n\x1b[31mParse error: expected a number, '-', or '(' but found ')' at position 2\x1b[0m\n  1+)\n    \x1b[1;31m^\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn help_command() {
+        assert_eq!(interactive(":help\n"), HELP);
+    }
+
+    #[test]
+    fn unknown_command_is_not_evaluated() {
+        let (output, errors) = session(":sin 1\n", Mode::Interactive);
+        assert_eq!(output, "");
+        assert_eq!(
+            errors,
+            "unknown command ':sin', type :help for a list of commands\n"
+        );
+    }
+
+    #[test]
+    fn invalid_command_prints_usage() {
+        let (output, errors) = session(":ast maybe\n", Mode::Interactive);
+        assert_eq!(output, "");
+        assert_eq!(errors, "usage: :ast on|off\n");
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut output = Vec::new();
+        let mut session = Session::new(&mut output, Vec::new(), Mode::Pipe);
+        session.set_format(OutputFormat::Json);
+        run("2*3\n\n1+\n".as_bytes(), &mut session).unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["input"], "2*3");
+        assert_eq!(lines[0]["result"], 6.0);
+        assert_eq!(lines[1]["ok"], false);
+        assert_eq!(lines[1]["error"]["code"], "unexpected_end_of_input");
+    }
+
+    #[test]
+    fn precision() {
+        let (output, _) = session("0.1+0.2\n-0\n", Mode::Pipe);
+        assert_eq!(output, "0.3\n0\n");
+
+        let mut output = Vec::new();
+        let mut session = Session::new(&mut output, Vec::new(), Mode::Pipe);
+        session.set_precision(Some(Precision::Fixed(2)));
+        run("2/3\n".as_bytes(), &mut session).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "0.67\n");
+    }
+
+    #[test]
+    fn interactive_errors_go_to_output() {
+        let (output, errors) = session("1+)\n", Mode::Interactive);
+        assert_eq!(
+            output,
+            "Your input: 1+)\nParse error: expected a number, '-', or '(' but found ')' at position 2\n  1+)\n    ^\n"
+        );
+        assert_eq!(errors, "");
+
+        let (output, _) = session("1 + $ 2\n", Mode::Interactive);
+        assert_eq!(
+            output,
+            "Your input: 1 + $ 2\nParse error: Unexpected character '$' at position 4\n  1 + $ 2\n      ^\n"
+        );
+    }
+
+    #[test]
+    fn pipe_prints_bare_results() {
+        let (output, errors) = session("1+2\n\n2*3", Mode::Pipe);
+        assert_eq!(output, "3\n6\n");
+        assert_eq!(errors, "");
+    }
+
+    #[test]
+    fn pipe_errors_carry_line_number() {
+        let (output, errors) = session("1+2\n(1\n\n4*\n5", Mode::Pipe);
+        assert_eq!(output, "3\n5\n");
+        assert_eq!(
+            errors,
+            "2: error: Unexpected end of input at position 2, expected ')'\n4: error: Unexpected end of input at position 2, expected a number or '('\n"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use crate::command::Command;
+use crate::repl::{Mode, Session, DEFAULT_PROMPT};
+use std::fmt;
+use std::io::{self, Write};
+
+const ERROR_PREFIX: &str = "! ";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entry {
+    pub input: String,
+    pub output: String,
+    pub errors: String,
+}
+
+pub struct Recorder<W> {
+    inner: W,
+    recorded: Vec<u8>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(inner: W) -> Self {
+        Recorder {
+            inner,
+            recorded: Vec::new(),
+        }
+    }
+
+    pub fn take(&mut self) -> String {
+        let recorded = String::from_utf8_lossy(&self.recorded).into_owned();
+        self.recorded.clear();
+        recorded
+    }
+
+    #[cfg(test)]
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for Recorder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.recorded.extend_from_slice(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Inputs are written after the default prompt, error lines after `! ` and
+// everything else is output. Colors are dropped so a transcript replays the
+// same whether or not it was recorded on a terminal.
+pub fn render(mode: Mode, entries: &[Entry]) -> String {
+    let mut transcript = format!("# mode: {}\n", mode_name(mode));
+
+    for entry in entries {
+        if let Some(Command::Save(_)) = Command::parse(entry.input.trim()) {
+            continue;
+        }
+
+        transcript.push_str(&format!("{}{}\n", DEFAULT_PROMPT, entry.input));
+        transcript.push_str(&strip_colors(&entry.output));
+        for line in strip_colors(&entry.errors).lines() {
+            transcript.push_str(&format!("{}{}\n", ERROR_PREFIX, line));
+        }
+    }
+
+    transcript
+}
+
+pub fn parse(transcript: &str) -> (Mode, Vec<(usize, Entry)>) {
+    let mut mode = Mode::Interactive;
+    let mut entries: Vec<(usize, Entry)> = Vec::new();
+
+    for (index, line) in transcript.lines().enumerate() {
+        if let Some(input) = line.strip_prefix(DEFAULT_PROMPT) {
+            let entry = Entry {
+                input: input.to_string(),
+                ..Entry::default()
+            };
+            entries.push((index + 1, entry));
+        } else if let Some((_, entry)) = entries.last_mut() {
+            match line.strip_prefix(ERROR_PREFIX) {
+                Some(error) => entry.errors.push_str(&format!("{}\n", error)),
+                None => entry.output.push_str(&format!("{}\n", line)),
+            }
+        } else if line == "# mode: pipe" {
+            mode = Mode::Pipe;
+        }
+    }
+
+    (mode, entries)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    pub line: usize,
+    pub input: String,
+    pub expected: Entry,
+    pub actual: Entry,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "line {}: {}", self.line, self.input)?;
+        writeln!(
+            f,
+            "  expected: {:?}",
+            format!("{}{}", self.expected.output, self.expected.errors)
+        )?;
+        write!(
+            f,
+            "  actual:   {:?}",
+            format!("{}{}", self.actual.output, self.actual.errors)
+        )
+    }
+}
+
+pub fn replay<F>(transcript: &str, configure: F) -> io::Result<Vec<Divergence>>
+where
+    F: FnOnce(&mut Session<io::Sink, io::Sink>),
+{
+    let (mode, entries) = parse(transcript);
+    let mut session = Session::new(io::sink(), io::sink(), mode);
+    configure(&mut session);
+    let mut divergences = Vec::new();
+
+    for (line, expected) in entries {
+        session.handle(&expected.input)?;
+        let actual = session.last_entry().cloned().unwrap_or_default();
+        let actual = Entry {
+            output: strip_colors(&actual.output),
+            errors: strip_colors(&actual.errors),
+            ..actual
+        };
+
+        if actual.output != expected.output || actual.errors != expected.errors {
+            divergences.push(Divergence {
+                line,
+                input: expected.input.clone(),
+                expected,
+                actual,
+            });
+        }
+    }
+
+    Ok(divergences)
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Interactive => "interactive",
+        Mode::Pipe => "pipe",
+    }
+}
+
+fn strip_colors(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(char) = chars.next() {
+        if char == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            stripped.push(char);
+        }
+    }
+
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repl;
+
+    const SCRIPT: &str = ":output hex\n255\n1+\n2*8\n";
+
+    fn recorded_transcript() -> String {
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Interactive);
+        session.set_color(true);
+        repl::run(SCRIPT.as_bytes(), &mut session).unwrap();
+        session.transcript()
+    }
+
+    #[test]
+    fn records_inputs_outputs_and_errors() {
+        assert_eq!(
+            recorded_transcript(),
+            "\
+# mode: interactive
+math> :output hex
+math> 255
+Your input: 255
+Result: 0xff
+math> 1+
+math> 2*8
+Your input: 1+ 2*8
+Result: 0x11
+"
+        );
+
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Pipe);
+        repl::run("1/\n3\n".as_bytes(), &mut session).unwrap();
+        assert_eq!(
+            session.transcript(),
+            "# mode: pipe\nmath> 1/\n! 1: error: Unexpected end of input at position 2, expected a number or '('\nmath> 3\n3\n"
+        );
+    }
+
+    #[test]
+    fn save_writes_the_transcript() {
+        let path =
+            std::env::temp_dir().join(format!("math-parser-{}-session.txt", std::process::id()));
+        let mut session = Session::new(Vec::new(), Vec::new(), Mode::Interactive);
+        let script = format!("{}:save {}\n", SCRIPT, path.display());
+        repl::run(script.as_bytes(), &mut session).unwrap();
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved, session.transcript());
+        assert!(!saved.contains(":save"));
+        assert_eq!(replay(&saved, |_| {}).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn replay_detects_divergence() {
+        let changed = recorded_transcript().replace(":output hex", ":output bin");
+        let divergences = replay(&changed, |_| {}).unwrap();
+
+        assert_eq!(divergences.len(), 2);
+        assert_eq!(divergences[0].line, 3);
+        assert_eq!(divergences[0].input, "255");
+        assert_eq!(
+            divergences[0].actual.output,
+            "Your input: 255\nResult: 0b11111111\n"
+        );
+        assert_eq!(divergences[1].input, "2*8");
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        let entries = vec![Entry {
+            input: "1/".into(),
+            output: "".into(),
+            errors: "1: error: Invalid number: EOF\n".into(),
+        }];
+        let (mode, parsed) = parse(&render(Mode::Pipe, &entries));
+        assert_eq!(mode, Mode::Pipe);
+        assert_eq!(parsed, vec![(2, entries[0].clone())]);
+    }
+
+    #[test]
+    fn strips_colors() {
+        assert_eq!(
+            strip_colors("\x1b[31mParse error\x1b[0m\n"),
+            "Parse error\n"
+        );
+    }
+}
@@ -0,0 +1,448 @@
+use std::process::{Command, Output};
+
+fn math_parser(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_math-parser"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn evaluates_argument() {
+    let output = math_parser(&["2^10 - 24"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "1000\n");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn evaluates_arguments_in_order() {
+    let output = math_parser(&["1+2", "2*3", "--", "-4"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "3\n6\n-4\n");
+}
+
+#[test]
+fn exact_flag_shows_raw_float() {
+    let output = math_parser(&["0.1+0.2", "1/3"]);
+    assert_eq!(stdout(&output), "0.3\n0.3333333333333333\n");
+
+    let output = math_parser(&["--exact", "0.1+0.2"]);
+    assert_eq!(stdout(&output), "0.30000000000000004\n");
+}
+
+#[test]
+fn parse_error_underlines_the_offending_token() {
+    let output = math_parser(&["1 + $ 2"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "error: Unexpected character '$' at position 4\n  1 + $ 2\n      ^\n"
+    );
+}
+
+#[test]
+fn parse_error_exits_nonzero() {
+    let output = math_parser(&["(1+2", "5"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "5\n");
+    assert_eq!(
+        stderr(&output),
+        "error: Unexpected end of input at position 4, expected ')'\n  (1+2\n      ^\n"
+    );
+}
+
+#[test]
+fn booleans_count_as_numbers_with_bool_arithmetic() {
+    let output = math_parser(&["--", "(1<2)+5"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "error: Type mismatch: cannot apply addition to bool and number\n"
+    );
+
+    let config = temp_file("bool-arithmetic.toml", "bool_arithmetic = true\n");
+    let output = math_parser(&[
+        "--config",
+        config.to_str().unwrap(),
+        "(1 < 2) + 5",
+        "if(1, 2, 3)",
+        "(3 >= 90) * 10",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "6\n2\n0\n");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn evaluates_lists_and_matrices_with_types() {
+    let output = math_parser(&["det([[1, 2], [3, 4]])", "transpose([[1, 2]])"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "-2\n[1]\n[2]\n");
+
+    let output = math_parser(&["[1,2]+[1,2,3]"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        "error: Shape mismatch: cannot apply addition to 2 and 3\n"
+    );
+}
+
+#[test]
+fn series_errors_exit_nonzero() {
+    let output = math_parser(&["sum(i, 1, 4, i)", "sum(i,1.5,3,i)", "sum(i,1,1e9,i)"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "10\n");
+    assert_eq!(
+        stderr(&output),
+        "error: The bounds of sum have to be integers, not 1.5\n\
+         error: Too many terms: sum takes at most 1000000\n"
+    );
+}
+
+#[test]
+fn division_by_zero_evaluates_to_infinity() {
+    let output = math_parser(&["1/0"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "inf\n");
+}
+
+#[test]
+fn unknown_option_exits_with_usage() {
+    let output = math_parser(&["-5"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("error: unknown option '-5'\nusage: math-parser"));
+    assert!(stderr(&output).contains("--history-size <n>"));
+}
+
+#[test]
+fn help_lists_every_option() {
+    let output = math_parser(&["--help"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stderr(&output), "");
+    for option in [
+        "--json",
+        "--precision",
+        "--fixed",
+        "--exact",
+        "--format",
+        "--tokens",
+        "--ast",
+        "--file",
+        "--check ",
+        "--check=strict",
+        "--config",
+        "--replay",
+        "--no-history",
+        "--history-size",
+        "-q, --quiet",
+        "--no-quiet",
+        "--prompt",
+        "\n  --  ",
+    ]
+    .iter()
+    {
+        assert!(stdout(&output).contains(option), "{}", option);
+    }
+    assert_eq!(math_parser(&["-h"]).stdout, output.stdout);
+}
+
+#[test]
+fn piped_stdin_prints_bare_results() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_math-parser"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"1+2\n(1\n2^3")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "3\n8\n");
+    assert_eq!(
+        stderr(&output),
+        "2: error: Unexpected end of input at position 2, expected ')'\n"
+    );
+}
+
+#[test]
+fn ast_flag_prints_tree() {
+    let output = math_parser(&["--ast", "(1+2)*3"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "Multiply\n├ Sum\n│ ├ Element(1)\n│ └ Element(2)\n└ Element(3)\n9\n"
+    );
+}
+
+#[test]
+fn json_flag_emits_json_lines() {
+    let output = math_parser(&["--json", "1+2", "2*"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let lines: Vec<serde_json::Value> = stdout(&output)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        lines[0],
+        serde_json::json!({"input": "1+2", "ok": true, "result": 3.0})
+    );
+    assert_eq!(lines[1]["ok"], false);
+    assert_eq!(
+        lines[1]["error"]["message"],
+        "Unexpected end of input at position 2, expected a number or '('"
+    );
+    assert_eq!(lines[1]["error"]["span"], serde_json::json!([2, 2]));
+}
+
+#[test]
+fn tokens_flag_prints_tokens_without_evaluating() {
+    let output = math_parser(&["--tokens", "1 $"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "Number           \"1\"        0..1\nerror            \"$\"        2..3\nEOF              \"\"         3..3\n"
+    );
+}
+
+fn temp_file(name: &str, contents: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("math-parser-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn file_evaluates_each_line() {
+    let path = temp_file("success.txt", "# totals\r\n1+2\r\n\r\n2^10\r\n0.1+0.2");
+    let output = math_parser(&["--file", path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "3\n1024\n0.3\n");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn file_reports_failing_lines_and_continues() {
+    let path = temp_file("partial.txt", "1+2\n(4\n\n5*\n6\n");
+    let path_str = path.to_str().unwrap();
+    let output = math_parser(&["--file", path_str]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "3\n6\n");
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "{0}:2: error: Unexpected end of input at position 2, expected ')'\n{0}:4: error: Unexpected end of input at position 2, expected a number or '('\n",
+            path_str
+        )
+    );
+}
+
+#[test]
+fn missing_file_is_a_clean_error() {
+    let output = math_parser(&["--file", "/nonexistent/formulas.txt"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).starts_with("error: cannot read '/nonexistent/formulas.txt': "));
+}
+
+#[test]
+fn check_accepts_valid_input_without_evaluating() {
+    let output = math_parser(&["--check", "2*(3+1)", "1/0"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn check_reports_syntax_errors() {
+    let output = math_parser(&["--check", "2*(3+1", "4+"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        "error: Unexpected end of input at position 6, expected ')'\n  2*(3+1\n        ^\nerror: Unexpected end of input at position 2, expected a number or '('\n  4+\n    ^\n"
+    );
+}
+
+#[test]
+fn check_validates_file_lines() {
+    let path = temp_file("check.txt", "1+2\n# comment\nfoo(y)\n(3\n");
+    let path_str = path.to_str().unwrap();
+    let syntax = math_parser(&["--check", "--file", path_str]);
+    let strict = math_parser(&["--check=strict", "--file", path_str]);
+    std::fs::remove_file(&path).unwrap();
+
+    let unclosed = format!(
+        "{}:4: error: Unexpected end of input at position 2, expected ')'\n  (3\n    ^\n",
+        path_str
+    );
+    assert_eq!(syntax.status.code(), Some(1));
+    assert_eq!(stdout(&syntax), "");
+    assert_eq!(stderr(&syntax), unclosed);
+
+    assert_eq!(strict.status.code(), Some(1));
+    assert_eq!(stdout(&strict), "");
+    assert_eq!(
+        stderr(&strict),
+        format!(
+            "{}:3: error: Unknown function: foo\n  foo(y)\n  ^~~\n{}",
+            path_str, unclosed
+        )
+    );
+}
+
+#[test]
+fn config_file_sets_defaults() {
+    let path = temp_file("config.toml", "precision = 3\nformat = \"sci\"\n");
+    let path_str = path.to_str().unwrap();
+    let output = math_parser(&["--config", path_str, "1/3"]);
+    let overridden = math_parser(&["--config", path_str, "--format", "auto", "1/3"]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(stdout(&output), "3.33e-1\n");
+    assert_eq!(stdout(&overridden), "0.333\n");
+}
+
+#[test]
+fn config_angle_mode_applies_to_expressions_and_files() {
+    let config = temp_file("degrees.toml", "angle_mode = \"deg\"\n");
+    let file = temp_file("degrees.txt", "cos(180)\n");
+    let config_str = config.to_str().unwrap();
+    let output = math_parser(&["--config", config_str, "sin(90)", "atan(1)"]);
+    let from_file = math_parser(&["--config", config_str, "--file", file.to_str().unwrap()]);
+    let radians = math_parser(&["sin(90)"]);
+    std::fs::remove_file(&config).unwrap();
+    std::fs::remove_file(&file).unwrap();
+
+    assert_eq!(stdout(&output), "1\n45\n");
+    assert_eq!(stdout(&from_file), "-1\n");
+    assert_eq!(stdout(&radians), "0.893996663600558\n");
+}
+
+#[test]
+fn config_evaluation_options_apply_to_expressions_and_the_repl() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let config = temp_file(
+        "powers.toml",
+        "zero_power = \"nan\"\nreal_roots = true\ndivision_by_zero = \"error\"\n",
+    );
+    let config_str = config.to_str().unwrap();
+    let output = math_parser(&["--config", config_str, "0^0", "(-8)^(1/3)", "1/0"]);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_math-parser"))
+        .args(["--config", config_str])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"0^0\n(-27)^(1/3)\n")
+        .unwrap();
+    let piped = child.wait_with_output().unwrap();
+    std::fs::remove_file(&config).unwrap();
+
+    assert_eq!(stdout(&output), "NaN\n-2\n");
+    assert!(stderr(&output).starts_with("error: Division by zero"));
+    assert_eq!(stdout(&piped), "NaN\n-3\n");
+    assert_eq!(
+        stdout(&math_parser(&["0^0", "(-8)^(1/3)", "1/(-0)"])),
+        "1\nNaN\n-inf\n"
+    );
+}
+
+#[test]
+fn config_variables_and_grammar_apply_everywhere() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let config = temp_file(
+        "variables.toml",
+        "implicit_multiplication = false\n\n[variables]\nrate = 0.25\n",
+    );
+    let file = temp_file("variables.txt", "100 * rate\n2rate\n");
+    let config_str = config.to_str().unwrap();
+    let output = math_parser(&["--config", config_str, "4 * rate", "2rate"]);
+    let from_file = math_parser(&["--config", config_str, "--file", file.to_str().unwrap()]);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_math-parser"))
+        .args(["--config", config_str])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"rate * 8\n2rate\n")
+        .unwrap();
+    let piped = child.wait_with_output().unwrap();
+    std::fs::remove_file(&config).unwrap();
+    std::fs::remove_file(&file).unwrap();
+
+    assert_eq!(stdout(&output), "1\n");
+    assert!(stderr(&output).starts_with("error: "));
+    assert_eq!(stdout(&from_file), "25\n");
+    assert!(stderr(&from_file).contains(":2: error: "));
+    assert_eq!(stdout(&piped), "2\n");
+    assert!(stderr(&piped).starts_with("2: error: "));
+    assert_eq!(stdout(&math_parser(&["2pi/pi"])), "2\n");
+}
+
+#[test]
+fn invalid_config_is_an_error() {
+    let path = temp_file("bad.toml", "precison = 3\n");
+    let output = math_parser(&["--config", path.to_str().unwrap(), "1"]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains("precison"));
+}
+
+#[test]
+fn replay_reports_divergences() {
+    let path = temp_file(
+        "transcript.txt",
+        "# mode: pipe\nmath> 2*3\n6\nmath> 1+\n! 2: error: Unexpected end of input at position 2, expected a number or '('\nmath> 2^3\n9\n",
+    );
+    let path_str = path.to_str().unwrap();
+    let output = math_parser(&["--replay", path_str]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "line 6: 2^3\n  expected: \"9\\n\"\n  actual:   \"8\\n\"\n1 line(s) diverged from '{}'\n",
+            path_str
+        )
+    );
+}